@@ -0,0 +1,38 @@
+//! Rendering a playing track alongside a dozen stopped stems, which should cost about as much as the playing track alone
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use soundyrust::prelude::*;
+
+const FRAMES: u32 = 44100;
+
+fn with_stopped_tracks(stopped: usize) -> MidiAudio {
+	let fray = include_bytes!("../assets/fray.mid");
+	let mut audio =
+		MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2")).with_logical_clock();
+	audio.add_track(MidiAudioTrack::from_bytes(fray, 1.0));
+	for _ in 0..stopped {
+		audio.add_track(MidiAudioTrack::from_bytes(fray, 1.0).stopped());
+	}
+	audio
+}
+
+fn stopped_tracks(c: &mut Criterion) {
+	let mut group = c.benchmark_group("one second of fray");
+	for stopped in [0, 12] {
+		group.bench_function(format!("with {stopped} stopped tracks"), |b| {
+			b.iter_batched(
+				|| with_stopped_tracks(stopped),
+				|mut audio| audio.advance_logical(FRAMES),
+				BatchSize::LargeInput,
+			)
+		});
+	}
+	group.finish();
+}
+
+criterion_group! {
+	name = benches;
+	config = Criterion::default().sample_size(10);
+	targets = stopped_tracks
+}
+criterion_main!(benches);
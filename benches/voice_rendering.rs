@@ -0,0 +1,38 @@
+//! Rendering a held chord of SoundFont voices. Compare runs with and without `--features fixed-point`.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use soundyrust::prelude::*;
+
+const FRAMES: u32 = 44100;
+
+/// Sixteen grand piano notes at once, with nothing but the voices to render
+fn chord() -> MidiAudio {
+	let mut audio =
+		MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2")).with_logical_clock();
+	for note in 48..64 {
+		audio.play_one_shot(0, 0, Note::from_midi_number(note), 100);
+	}
+	audio
+}
+
+fn voice_rendering(c: &mut Criterion) {
+	let math = if cfg!(feature = "fixed-point") {
+		"fixed point"
+	} else {
+		"floating point"
+	};
+	c.bench_function(&format!("one second of 16 voices in {math}"), |b| {
+		b.iter_batched(
+			chord,
+			|mut audio| audio.advance_logical(FRAMES),
+			BatchSize::LargeInput,
+		)
+	});
+}
+
+criterion_group! {
+	name = benches;
+	config = Criterion::default().sample_size(10);
+	targets = voice_rendering
+}
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use bevy::audio::AudioPlugin;
+use bevy::prelude::*;
+use soundyrust::prelude::*;
+
+fn main() {
+	let mut app = App::new();
+	app.add_plugins(DefaultPlugins.set(AudioPlugin {
+		global_volume: GlobalVolume::new(0.2),
+		..default()
+	}))
+	.add_plugins(SoundyPlugin)
+	.add_systems(Startup, setup)
+	.run();
+}
+
+fn setup(mut assets: ResMut<Assets<MidiAudio>>, mut commands: Commands) {
+	// No SoundFont or MIDI file needed, just a scale written out by hand
+	let ticks_per_beat = 96;
+	let events = [60, 62, 64, 65, 67, 69, 71, 72]
+		.into_iter()
+		.enumerate()
+		.flat_map(|(i, note)| {
+			let time = i as u64 * ticks_per_beat as u64;
+			[
+				MidiTrackAccumulateEvent {
+					time,
+					inner: MidiEvent::NoteOn {
+						channel: 0,
+						note,
+						velocity: 100,
+					},
+					source_track: 0,
+				},
+				MidiTrackAccumulateEvent {
+					time: time + ticks_per_beat as u64 / 2,
+					inner: MidiEvent::NoteOff { channel: 0, note },
+					source_track: 0,
+				},
+			]
+		})
+		.collect();
+	let midi_track = MidiTrack {
+		events,
+		ticks_per_beat,
+		source_tracks: [SourceTrack::default()].into(),
+	};
+
+	let audio_handle = assets.add(
+		MidiAudio::with_builtin_synth(Waveform::Square)
+			.with_track(MidiAudioTrack::new(midi_track, 4.0 / 4.0)),
+	);
+	commands.spawn((AudioPlayer(audio_handle),));
+}
@@ -0,0 +1,74 @@
+use bevy::audio::AudioPlugin;
+use bevy::prelude::*;
+use soundyrust::prelude::*;
+
+fn main() {
+	let mut app = App::new();
+	app.add_plugins(DefaultPlugins.set(AudioPlugin {
+		global_volume: GlobalVolume::new(0.2),
+		..default()
+	}))
+	.add_plugins(SoundyPlugin)
+	.add_systems(Startup, setup)
+	.run();
+}
+
+fn setup(mut assets: ResMut<Assets<MidiAudio>>, mut commands: Commands) {
+	let audio_handle = assets.add(
+		MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2")).with_track(
+			MidiAudioTrack::from_bytes(include_bytes!("../assets/octave.mid"), 4.0 / 4.0)
+				.with_channel_instrument(0, Chiptune),
+		),
+	);
+	commands.spawn((AudioPlayer(audio_handle),));
+}
+
+/// Plays every note as a short square wave blip
+struct Chiptune;
+
+impl Instrument for Chiptune {
+	fn create_voice(
+		&mut self,
+		note: u8,
+		velocity: u8,
+		_context: &InstrumentContext,
+	) -> Option<Box<dyn VoiceSource>> {
+		Some(Box::new(Blip {
+			period: 44100.0 / Note::from_midi_number(note).frequency,
+			volume: velocity as f32 / 127.0 * 4000.0,
+			frame: 0,
+		}))
+	}
+
+	fn fresh(&self) -> Box<dyn Instrument> {
+		Box::new(Chiptune)
+	}
+}
+
+struct Blip {
+	/// In frames
+	period: f32,
+	volume: f32,
+	frame: u32,
+}
+
+impl VoiceSource for Blip {
+	fn tick(&mut self) {
+		self.frame += 1;
+	}
+
+	fn is_finished(&self) -> bool {
+		self.frame > 44100 / 4
+	}
+
+	fn sample(&self, _audio_channel: u16) -> i32 {
+		let phase = (self.frame as f32 / self.period).fract();
+		let fade = 1.0 - self.frame as f32 / (44100.0 / 4.0);
+		let value = if phase < 0.5 { 1.0 } else { -1.0 };
+		(value * self.volume * fade.max(0.0)) as i32
+	}
+
+	fn detune(&mut self, ratio: f32) {
+		self.period /= ratio;
+	}
+}
@@ -0,0 +1,85 @@
+use bevy::audio::AudioPlugin;
+use bevy::prelude::*;
+use soundyrust::prelude::*;
+use soundyrust::{NoteExplanation, RegionMatch};
+
+fn main() {
+	let mut app = App::new();
+	app.add_plugins(DefaultPlugins.set(AudioPlugin {
+		global_volume: GlobalVolume::new(0.2),
+		..default()
+	}))
+	.add_plugins(SoundyPlugin)
+	.init_resource::<Music>()
+	.add_systems(Startup, setup)
+	.add_systems(Update, play_and_explain)
+	.run();
+}
+
+#[derive(Resource, Default)]
+struct Music {
+	audio: Handle<MidiAudio>,
+	track: Option<MidiAudioTrackHandle>,
+}
+
+fn setup(mut assets: ResMut<Assets<MidiAudio>>, mut music: ResMut<Music>, mut commands: Commands) {
+	let mut audio = MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2"));
+	music.track = Some(audio.add_track(
+		MidiAudioTrack::from_bytes(include_bytes!("../assets/octave.mid"), 4.0 / 4.0).stopped(),
+	));
+	music.audio = assets.add(audio);
+	commands.spawn((AudioPlayer(music.audio.clone()),));
+}
+
+fn play_and_explain(
+	mut assets: ResMut<Assets<MidiAudio>>,
+	music: Res<Music>,
+	input: Res<ButtonInput<KeyCode>>,
+) {
+	let notes = [
+		(Note::C4, KeyCode::KeyA),
+		(Note::E4, KeyCode::KeyS),
+		(Note::G4, KeyCode::KeyD),
+		(Note::C5, KeyCode::KeyF),
+		(Note::E5, KeyCode::KeyG),
+		(Note::G5, KeyCode::KeyH),
+	];
+	let (Some(audio), Some(track)) = (assets.get_mut(&music.audio), music.track) else {
+		return;
+	};
+
+	for (note, key) in notes {
+		if input.just_pressed(key) {
+			audio.start_playing_note(note).unwrap();
+			// Live notes play on channel 0 at full velocity
+			if let Some(explanation) = audio.explain_note(&track, 0, note.midi_number(), 127) {
+				print_explanation(note, &explanation);
+			}
+		} else if input.just_released(key) {
+			audio.stop_playing_note(note).unwrap();
+		}
+	}
+}
+
+fn print_explanation(note: Note, explanation: &NoteExplanation) {
+	info!(
+		"{note:?}: bank {} patch {} ({}){}",
+		explanation.bank_number,
+		explanation.patch_number,
+		explanation.preset_name.as_deref().unwrap_or("unnamed"),
+		if explanation.uses_fallback {
+			", played by the synth fallback"
+		} else {
+			""
+		}
+	);
+	for RegionMatch {
+		sample_name,
+		key_range,
+		vel_range,
+		..
+	} in &explanation.regions
+	{
+		info!("  {sample_name}, keys {key_range:?}, velocities {vel_range:?}");
+	}
+}
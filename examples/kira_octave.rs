@@ -0,0 +1,20 @@
+use bevy::prelude::*;
+use soundyrust::prelude::*;
+use soundyrust::{KiraMidiPlayer, SoundyKiraPlugin};
+
+fn main() {
+	let mut app = App::new();
+	app.add_plugins(DefaultPlugins)
+		.add_plugins((SoundyPlugin, SoundyKiraPlugin))
+		.add_systems(Startup, setup)
+		.run();
+}
+
+fn setup(mut assets: ResMut<Assets<MidiAudio>>, mut commands: Commands) {
+	let audio_handle = assets.add(
+		MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2")).with_track(
+			MidiAudioTrack::from_bytes(include_bytes!("../assets/octave.mid"), 4.0 / 4.0),
+		),
+	);
+	commands.spawn(KiraMidiPlayer(audio_handle));
+}
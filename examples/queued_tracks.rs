@@ -1,6 +1,6 @@
 use bevy::audio::AudioPlugin;
 use bevy::prelude::*;
-use soundyrust::*;
+use soundyrust::prelude::*;
 
 fn main() {
 	let mut app = App::new();
@@ -26,6 +26,7 @@ fn setup(mut assets: ResMut<Assets<MidiAudio>>, mut commands: Commands) {
 				MidiAudioTrack::from_bytes(include_bytes!("../assets/fray lead.mid"), 4.0 / 4.0)
 					.with_channel_patch(0, 0, 46)
 					.stopped()
+					.with_shared_timing()
 					.with_queue(MidiQueueEvent {
 						event: MidiQueueEventType::Play,
 						timing: MidiQueueTiming::Bar,
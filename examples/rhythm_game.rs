@@ -0,0 +1,108 @@
+use std::time::Instant;
+
+use bevy::audio::AudioPlugin;
+use bevy::prelude::*;
+use soundyrust::prelude::*;
+
+const MELODY_CHANNEL: u8 = 0;
+
+fn main() {
+	let mut app = App::new();
+	app.add_plugins(DefaultPlugins.set(AudioPlugin {
+		global_volume: GlobalVolume::new(0.2),
+		..default()
+	}))
+	.add_plugins(SoundyPlugin)
+	.init_resource::<Game>()
+	.add_systems(Startup, setup)
+	.add_systems(Update, (show_upcoming_notes, judge_input))
+	.run();
+}
+
+#[derive(Resource, Default)]
+struct Game {
+	audio: Handle<MidiAudio>,
+	track: Option<MidiAudioTrackHandle>,
+	judge: HitJudge,
+	score: Score,
+}
+
+fn setup(mut assets: ResMut<Assets<MidiAudio>>, mut game: ResMut<Game>, mut commands: Commands) {
+	let mut audio = MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2"));
+	let track = audio.add_track(MidiAudioTrack::from_bytes(
+		include_bytes!("../assets/octave.mid"),
+		4.0 / 4.0,
+	));
+	game.track = Some(track);
+	game.audio = assets.add(audio);
+	commands.spawn((AudioPlayer(game.audio.clone()),));
+}
+
+fn show_upcoming_notes(assets: Res<Assets<MidiAudio>>, game: Res<Game>) {
+	let (Some(audio), Some(track)) = (assets.get(&game.audio), game.track) else {
+		return;
+	};
+	let Some(beat) = audio.estimated_beat_at(&track, Instant::now()) else {
+		return;
+	};
+	let Some(next) = audio
+		.notes_between(&track, Some(MELODY_CHANNEL), beat, beat + 2.0)
+		.first()
+		.copied()
+	else {
+		return;
+	};
+	debug!("Next note {} in {:.2} beats", next.note, next.beat - beat);
+}
+
+fn judge_input(
+	mut assets: ResMut<Assets<MidiAudio>>,
+	mut game: ResMut<Game>,
+	input: Res<ButtonInput<KeyCode>>,
+) {
+	if !input.just_pressed(KeyCode::Space) {
+		return;
+	}
+	let Some(track) = game.track else {
+		return;
+	};
+	let Some(audio) = assets.get_mut(&game.audio) else {
+		return;
+	};
+	let Some(hit_beat) = audio.audible_beat(&track) else {
+		return;
+	};
+
+	let window = game.judge.good_window * 2.0;
+	let upcoming = audio.notes_between(
+		&track,
+		Some(MELODY_CHANNEL),
+		hit_beat - window,
+		hit_beat + 4.0,
+	);
+	let targets = upcoming.iter().map(|note| note.beat);
+	let Some((index, judgement)) = game.judge.judge_nearest(targets, hit_beat) else {
+		return;
+	};
+
+	match judgement.grade {
+		HitGrade::Miss => {
+			audio.set_channel_muted(&track, MELODY_CHANNEL, true);
+			if let Some(next) = upcoming.get(index + 1) {
+				audio.schedule_channel_unmute(&track, MELODY_CHANNEL, next.beat);
+			}
+		}
+		HitGrade::Perfect | HitGrade::Good => {
+			audio.play_one_shot(128, 0, Note::from_midi_number(76), 100);
+		}
+	}
+
+	game.score.record(judgement.grade);
+	info!(
+		"{:?} ({:+.3} beats) — score {}, combo {}",
+		judgement.grade,
+		judgement.error_beats,
+		game.score.points(),
+		game.score.combo
+	);
+}
@@ -0,0 +1,18 @@
+use bevy::audio::AudioPlugin;
+use bevy::prelude::*;
+use soundyrust::prelude::*;
+
+fn main() {
+	let mut app = App::new();
+	app.add_plugins(DefaultPlugins.set(AudioPlugin {
+		global_volume: GlobalVolume::new(0.2),
+		..default()
+	}))
+	.add_plugins(SoundyPlugin)
+	.add_systems(Startup, setup)
+	.run();
+}
+
+fn setup(asset_server: Res<AssetServer>, mut commands: Commands) {
+	commands.spawn(DynamicSceneRoot(asset_server.load("music.scn.ron")));
+}
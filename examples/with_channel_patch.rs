@@ -1,6 +1,6 @@
 use bevy::audio::AudioPlugin;
 use bevy::prelude::*;
-use soundyrust::*;
+use soundyrust::prelude::*;
 
 fn main() {
 	let mut app = App::new();
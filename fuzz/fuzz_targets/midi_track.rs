@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use soundyrust::{MidiTrack, ParseLimits};
+
+fuzz_target!(|bytes: &[u8]| {
+	let limits = ParseLimits {
+		max_file_bytes: 1024 * 1024,
+		..ParseLimits::default()
+	};
+	let _ = MidiTrack::try_from_bytes_with_report(bytes, &limits);
+});
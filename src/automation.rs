@@ -0,0 +1,203 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Beat-positioned breakpoints for track parameters, linearly interpolated between breakpoints
+///
+/// Beats are relative to the start of the track, so automation loops along with it.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Automation {
+	breakpoints: Vec<AutomationBreakpoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AutomationBreakpoint {
+	pub beat: f64,
+	pub parameter: AutomationParameter,
+	pub value: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AutomationParameter {
+	TrackVolume,
+	ChannelVolume(u8),
+	/// -1.0 is fully left, 1.0 is fully right
+	ChannelPan(u8),
+	TempoMultiplier,
+	/// In semitones, rounded to the nearest whole semitone
+	Transpose,
+}
+
+impl Automation {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with_breakpoint(
+		mut self,
+		beat: f64,
+		parameter: AutomationParameter,
+		value: f32,
+	) -> Self {
+		self.add_breakpoint(beat, parameter, value);
+		self
+	}
+
+	pub fn add_breakpoint(&mut self, beat: f64, parameter: AutomationParameter, value: f32) {
+		let index = self
+			.breakpoints
+			.partition_point(|breakpoint| breakpoint.beat <= beat);
+		self.breakpoints.insert(
+			index,
+			AutomationBreakpoint {
+				beat,
+				parameter,
+				value,
+			},
+		);
+	}
+
+	/// Removes every breakpoint for `parameter` at exactly `beat`, returning whether any were removed
+	pub fn remove_breakpoint(&mut self, beat: f64, parameter: AutomationParameter) -> bool {
+		let len = self.breakpoints.len();
+		self.breakpoints
+			.retain(|breakpoint| breakpoint.beat != beat || breakpoint.parameter != parameter);
+		self.breakpoints.len() != len
+	}
+
+	pub fn clear_parameter(&mut self, parameter: AutomationParameter) {
+		self.breakpoints
+			.retain(|breakpoint| breakpoint.parameter != parameter);
+	}
+
+	pub fn breakpoints(&self) -> &[AutomationBreakpoint] {
+		&self.breakpoints
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.breakpoints.is_empty()
+	}
+
+	/// Every parameter that has at least one breakpoint
+	pub fn parameters(&self) -> impl Iterator<Item = AutomationParameter> + '_ {
+		self.breakpoints
+			.iter()
+			.enumerate()
+			.filter(|(i, breakpoint)| {
+				!self.breakpoints[..*i]
+					.iter()
+					.any(|other| other.parameter == breakpoint.parameter)
+			})
+			.map(|(_, breakpoint)| breakpoint.parameter)
+	}
+
+	/// The value of `parameter` at `beat`, holding the first and last breakpoints outside their range
+	pub fn value_at(&self, parameter: AutomationParameter, beat: f64) -> Option<f32> {
+		let mut previous: Option<&AutomationBreakpoint> = None;
+		for breakpoint in self
+			.breakpoints
+			.iter()
+			.filter(|breakpoint| breakpoint.parameter == parameter)
+		{
+			if breakpoint.beat > beat {
+				return Some(match previous {
+					Some(previous) => {
+						let fraction =
+							((beat - previous.beat) / (breakpoint.beat - previous.beat)) as f32;
+						previous.value + (breakpoint.value - previous.value) * fraction
+					}
+					None => breakpoint.value,
+				});
+			}
+			previous = Some(breakpoint);
+		}
+		previous.map(|breakpoint| breakpoint.value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ramp() -> Automation {
+		Automation::new()
+			.with_breakpoint(4.0, AutomationParameter::TrackVolume, 0.0)
+			.with_breakpoint(2.0, AutomationParameter::TrackVolume, 1.0)
+			.with_breakpoint(1.0, AutomationParameter::Transpose, 12.0)
+	}
+
+	#[test]
+	fn interpolates_between_breakpoints() {
+		let automation = ramp();
+		assert_eq!(
+			automation.value_at(AutomationParameter::TrackVolume, 3.0),
+			Some(0.5)
+		);
+		assert_eq!(
+			automation.value_at(AutomationParameter::TrackVolume, 3.5),
+			Some(0.25)
+		);
+	}
+
+	#[test]
+	fn holds_outside_the_breakpoints() {
+		let automation = ramp();
+		assert_eq!(
+			automation.value_at(AutomationParameter::TrackVolume, 0.0),
+			Some(1.0)
+		);
+		assert_eq!(
+			automation.value_at(AutomationParameter::TrackVolume, 10.0),
+			Some(0.0)
+		);
+		assert_eq!(
+			automation.value_at(AutomationParameter::TempoMultiplier, 0.0),
+			None
+		);
+	}
+
+	#[test]
+	fn breakpoints_stay_sorted() {
+		let beats = ramp()
+			.breakpoints()
+			.iter()
+			.map(|breakpoint| breakpoint.beat)
+			.collect::<Vec<_>>();
+		assert_eq!(beats, [1.0, 2.0, 4.0]);
+		assert_eq!(
+			ramp().parameters().collect::<Vec<_>>(),
+			[
+				AutomationParameter::Transpose,
+				AutomationParameter::TrackVolume
+			]
+		);
+	}
+
+	#[test]
+	fn removes_breakpoints() {
+		let mut automation = ramp();
+		assert!(!automation.remove_breakpoint(4.0, AutomationParameter::Transpose));
+		assert!(automation.remove_breakpoint(4.0, AutomationParameter::TrackVolume));
+		assert_eq!(
+			automation.value_at(AutomationParameter::TrackVolume, 10.0),
+			Some(1.0)
+		);
+		automation.clear_parameter(AutomationParameter::TrackVolume);
+		assert_eq!(automation.parameters().count(), 1);
+		automation.clear_parameter(AutomationParameter::Transpose);
+		assert!(automation.is_empty());
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serde_round_trip() {
+		let automation = ramp();
+		let json = serde_json::to_string(&automation).unwrap();
+		assert_eq!(
+			serde_json::from_str::<Automation>(&json).unwrap(),
+			automation
+		);
+	}
+}
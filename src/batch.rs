@@ -0,0 +1,128 @@
+use crate::{ChannelPlaybackOptions, MidiQueueEvent};
+
+/// Control commands recorded together and applied to a track in one go,
+/// so no audio is rendered in between them
+///
+/// Batchable operations are channel mute, patch and playback option changes,
+/// queueing events, and starting or stopping playback.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControlBatch {
+	commands: Vec<ControlCommand>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+	SetChannelMuted {
+		channel: u8,
+		muted: bool,
+	},
+	SetChannelPatch {
+		channel: u8,
+		bank_number: u8,
+		patch_number: u8,
+	},
+	SetChannelOptions {
+		channel: u8,
+		options: ChannelPlaybackOptions,
+	},
+	Queue(MidiQueueEvent),
+	Play,
+	Stop,
+}
+
+impl ControlBatch {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn mute_channel(&mut self, channel: u8) -> &mut Self {
+		self.push(ControlCommand::SetChannelMuted {
+			channel,
+			muted: true,
+		})
+	}
+
+	pub fn unmute_channel(&mut self, channel: u8) -> &mut Self {
+		self.push(ControlCommand::SetChannelMuted {
+			channel,
+			muted: false,
+		})
+	}
+
+	pub fn set_channel_patch(
+		&mut self,
+		channel: u8,
+		bank_number: u8,
+		patch_number: u8,
+	) -> &mut Self {
+		self.push(ControlCommand::SetChannelPatch {
+			channel,
+			bank_number,
+			patch_number,
+		})
+	}
+
+	pub fn set_channel_options(
+		&mut self,
+		channel: u8,
+		options: ChannelPlaybackOptions,
+	) -> &mut Self {
+		self.push(ControlCommand::SetChannelOptions { channel, options })
+	}
+
+	pub fn queue(&mut self, event: MidiQueueEvent) -> &mut Self {
+		self.push(ControlCommand::Queue(event))
+	}
+
+	pub fn play(&mut self) -> &mut Self {
+		self.push(ControlCommand::Play)
+	}
+
+	pub fn stop(&mut self) -> &mut Self {
+		self.push(ControlCommand::Stop)
+	}
+
+	pub fn push(&mut self, command: ControlCommand) -> &mut Self {
+		self.commands.push(command);
+		self
+	}
+
+	pub fn commands(&self) -> &[ControlCommand] {
+		&self.commands
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.commands.is_empty()
+	}
+
+	pub(crate) fn into_commands(self) -> Vec<ControlCommand> {
+		self.commands
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn records_commands_in_order() {
+		let mut batch = ControlBatch::new();
+		assert!(batch.is_empty());
+		batch.mute_channel(3).set_channel_patch(3, 0, 40).play();
+		assert_eq!(
+			batch.commands(),
+			[
+				ControlCommand::SetChannelMuted {
+					channel: 3,
+					muted: true,
+				},
+				ControlCommand::SetChannelPatch {
+					channel: 3,
+					bank_number: 0,
+					patch_number: 40,
+				},
+				ControlCommand::Play,
+			]
+		);
+	}
+}
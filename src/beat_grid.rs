@@ -0,0 +1,347 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Gridlines to snap beats to, e.g. for placing notes in a sequencer
+///
+/// Gridlines restart at every bar, so each bar starts on a gridline even when the meter
+/// doesn't divide evenly. Track queue timings use the same grid, see [`crate::MidiAudio::beat_grid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BeatGrid {
+	/// Gridlines per beat, e.g. 2 for eighth notes when the beat is a quarter note
+	pub subdivision: u32,
+	/// Fits three gridlines where `subdivision` would fit two
+	pub triplet: bool,
+	/// Where the second gridline of each pair sits between its neighbours, from 0.5 to 1.0.
+	/// 0.5 is straight and 2/3 is a triplet shuffle. At 1.0 the second gridline lands on the next pair,
+	/// so only every other gridline is left. Ignored on triplet grids.
+	pub swing: f64,
+	pub beats_per_bar: f64,
+	pub bars_per_phrase: u32,
+}
+
+impl Default for BeatGrid {
+	fn default() -> Self {
+		Self::new(4.0)
+	}
+}
+
+impl BeatGrid {
+	/// A straight grid with a gridline on every beat, and four bar phrases
+	pub fn new(beats_per_bar: f64) -> Self {
+		Self {
+			subdivision: 1,
+			triplet: false,
+			swing: 0.5,
+			beats_per_bar,
+			bars_per_phrase: 4,
+		}
+	}
+
+	pub fn with_subdivision(mut self, subdivision: u32) -> Self {
+		self.subdivision = subdivision;
+		self
+	}
+
+	pub fn with_triplet(mut self, triplet: bool) -> Self {
+		self.triplet = triplet;
+		self
+	}
+
+	pub fn with_swing(mut self, swing: f64) -> Self {
+		self.swing = swing;
+		self
+	}
+
+	pub fn with_bars_per_phrase(mut self, bars_per_phrase: u32) -> Self {
+		self.bars_per_phrase = bars_per_phrase;
+		self
+	}
+
+	/// Beats between straight gridlines
+	pub fn step(&self) -> f64 {
+		1.0 / self.gridlines_per_beat()
+	}
+
+	pub fn phrase_beats(&self) -> f64 {
+		self.beats_per_bar * self.bars_per_phrase.max(1) as f64
+	}
+
+	/// The gridline closest to `beat`, preferring the earlier one when it's exactly between two
+	pub fn snap(&self, beat: f64) -> f64 {
+		let before = self.gridline_before(beat);
+		let after = self.gridline_at_or_after(beat);
+		if after - beat < beat - before {
+			after
+		} else {
+			before
+		}
+	}
+
+	pub fn next_gridline_after(&self, beat: f64) -> f64 {
+		self.first_gridline(beat, |gridline| gridline > beat)
+	}
+
+	pub fn next_bar_after(&self, beat: f64) -> f64 {
+		(self.bar_of(beat) + 1.0) * self.beats_per_bar
+	}
+
+	pub fn next_phrase_after(&self, beat: f64) -> f64 {
+		((beat / self.phrase_beats()).floor() + 1.0) * self.phrase_beats()
+	}
+
+	/// Every gridline from `start` up to but not including `end`, in order
+	pub fn gridlines_in_range(&self, start: f64, end: f64) -> impl Iterator<Item = f64> + use<> {
+		let grid = *self;
+		let first_bar = self.bar_of(start);
+		let last_bar = self.bar_of(end);
+		(0..=(last_bar - first_bar).max(0.0) as u64)
+			.flat_map(move |bar| grid.gridlines_in_bar(first_bar + bar as f64))
+			.skip_while(move |&gridline| gridline < start)
+			.take_while(move |&gridline| gridline < end)
+	}
+
+	pub(crate) fn gridline_at_or_after(&self, beat: f64) -> f64 {
+		self.first_gridline(beat, |gridline| gridline >= beat)
+	}
+
+	pub(crate) fn bar_at_or_after(&self, beat: f64) -> f64 {
+		(beat / self.beats_per_bar).ceil() * self.beats_per_bar
+	}
+
+	fn gridline_before(&self, beat: f64) -> f64 {
+		let bar = self.bar_of(beat);
+		self.gridlines_in_bar(bar)
+			.take_while(|&gridline| gridline < beat)
+			.last()
+			.unwrap_or_else(|| self.gridlines_in_bar(bar - 1.0).last().unwrap_or(beat))
+	}
+
+	/// The first gridline from the bar containing `beat` onwards that passes `filter`
+	fn first_gridline(&self, beat: f64, filter: impl Fn(f64) -> bool) -> f64 {
+		let bar = self.bar_of(beat);
+		// Swing only ever moves gridlines later, so start a pair early
+		let index = ((beat - bar * self.beats_per_bar) * self.gridlines_per_beat()).floor() as u64;
+		(index.saturating_sub(2)..self.gridlines_per_bar())
+			.filter_map(|index| self.gridline(bar, index))
+			.find(|&gridline| filter(gridline))
+			.unwrap_or_else(|| self.next_bar_after(beat))
+	}
+
+	fn gridlines_in_bar(&self, bar: f64) -> impl Iterator<Item = f64> + use<> {
+		let grid = *self;
+		(0..self.gridlines_per_bar()).filter_map(move |index| grid.gridline(bar, index))
+	}
+
+	/// The `index`th gridline of `bar`, if it's in the bar and hasn't been swung onto the next gridline
+	fn gridline(&self, bar: f64, index: u64) -> Option<f64> {
+		let swing = if self.triplet {
+			0.5
+		} else {
+			self.swing.clamp(0.5, 1.0)
+		};
+		let offset = if index % 2 == 1 {
+			if swing >= 1.0 {
+				return None;
+			}
+			(index - 1) as f64 + 2.0 * swing
+		} else {
+			index as f64
+		};
+		let offset = offset / self.gridlines_per_beat();
+		(offset < self.beats_per_bar).then_some(bar * self.beats_per_bar + offset)
+	}
+
+	fn bar_of(&self, beat: f64) -> f64 {
+		(beat / self.beats_per_bar).floor()
+	}
+
+	fn gridlines_per_beat(&self) -> f64 {
+		let subdivision = self.subdivision.max(1) as f64;
+		if self.triplet {
+			subdivision * 1.5
+		} else {
+			subdivision
+		}
+	}
+
+	fn gridlines_per_bar(&self) -> u64 {
+		(self.beats_per_bar * self.gridlines_per_beat() - 1e-9).ceil() as u64
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_near(actual: f64, expected: f64) {
+		assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+	}
+
+	fn assert_gridlines(actual: impl Iterator<Item = f64>, expected: &[f64]) {
+		let actual = actual.collect::<Vec<_>>();
+		assert_eq!(actual.len(), expected.len(), "{actual:?} != {expected:?}");
+		for (&actual, &expected) in actual.iter().zip(expected) {
+			assert_near(actual, expected);
+		}
+	}
+
+	fn eighths() -> BeatGrid {
+		BeatGrid::new(4.0).with_subdivision(2)
+	}
+
+	#[test]
+	fn snaps_to_the_nearest_gridline() {
+		let grid = eighths();
+		assert_near(grid.snap(1.2), 1.0);
+		assert_near(grid.snap(1.3), 1.5);
+		assert_near(grid.snap(1.5), 1.5);
+		assert_near(grid.snap(3.9), 4.0);
+	}
+
+	#[test]
+	fn snaps_halfway_to_the_earlier_gridline() {
+		assert_near(eighths().snap(1.25), 1.0);
+		assert_near(eighths().snap(3.75), 3.5);
+	}
+
+	#[test]
+	fn next_gridline_is_strictly_after() {
+		let grid = eighths();
+		assert_near(grid.next_gridline_after(1.5), 2.0);
+		assert_near(grid.next_gridline_after(1.4), 1.5);
+		assert_near(grid.next_gridline_after(3.5), 4.0);
+	}
+
+	#[test]
+	fn ranges_include_the_start_but_not_the_end() {
+		assert_gridlines(eighths().gridlines_in_range(1.0, 2.0), &[1.0, 1.5]);
+		assert_gridlines(eighths().gridlines_in_range(1.1, 2.1), &[1.5, 2.0]);
+		assert_gridlines(eighths().gridlines_in_range(2.0, 2.0), &[]);
+		assert_gridlines(eighths().gridlines_in_range(3.0, 1.0), &[]);
+	}
+
+	#[test]
+	fn half_swing_is_straight() {
+		assert_eq!(
+			eighths()
+				.with_swing(0.5)
+				.gridlines_in_range(0.0, 8.0)
+				.collect::<Vec<_>>(),
+			eighths().gridlines_in_range(0.0, 8.0).collect::<Vec<_>>()
+		);
+		assert_gridlines(
+			eighths().with_swing(0.5).gridlines_in_range(0.0, 2.0),
+			&[0.0, 0.5, 1.0, 1.5],
+		);
+	}
+
+	#[test]
+	fn swing_moves_every_second_gridline_later() {
+		let grid = eighths().with_swing(2.0 / 3.0);
+		assert_gridlines(
+			grid.gridlines_in_range(0.0, 2.0),
+			&[0.0, 2.0 / 3.0, 1.0, 5.0 / 3.0],
+		);
+		assert_near(grid.snap(0.55), 2.0 / 3.0);
+		assert_near(grid.snap(0.3), 0.0);
+		assert_near(grid.next_gridline_after(0.6), 2.0 / 3.0);
+	}
+
+	#[test]
+	fn full_swing_leaves_every_other_gridline() {
+		let grid = eighths().with_swing(1.0);
+		assert_gridlines(grid.gridlines_in_range(0.0, 4.0), &[0.0, 1.0, 2.0, 3.0]);
+		assert_near(grid.snap(0.6), 1.0);
+		assert_near(grid.snap(0.4), 0.0);
+		assert_near(grid.next_gridline_after(0.0), 1.0);
+	}
+
+	#[test]
+	fn swing_outside_its_range_is_clamped() {
+		assert_eq!(
+			eighths()
+				.with_swing(0.2)
+				.gridlines_in_range(0.0, 4.0)
+				.collect::<Vec<_>>(),
+			eighths().gridlines_in_range(0.0, 4.0).collect::<Vec<_>>()
+		);
+		assert_eq!(
+			eighths()
+				.with_swing(1.5)
+				.gridlines_in_range(0.0, 4.0)
+				.collect::<Vec<_>>(),
+			eighths()
+				.with_swing(1.0)
+				.gridlines_in_range(0.0, 4.0)
+				.collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn triplets_fit_three_where_two_would_go() {
+		let grid = BeatGrid::new(4.0).with_subdivision(2).with_triplet(true);
+		assert_near(grid.step(), 1.0 / 3.0);
+		assert_gridlines(
+			grid.gridlines_in_range(0.0, 1.0),
+			&[0.0, 1.0 / 3.0, 2.0 / 3.0],
+		);
+		assert_near(grid.snap(0.4), 1.0 / 3.0);
+	}
+
+	#[test]
+	fn triplets_ignore_swing() {
+		let triplets = BeatGrid::new(4.0).with_triplet(true);
+		assert_eq!(
+			triplets
+				.with_swing(0.75)
+				.gridlines_in_range(0.0, 8.0)
+				.collect::<Vec<_>>(),
+			triplets.gridlines_in_range(0.0, 8.0).collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn ranges_cross_bars() {
+		assert_gridlines(
+			BeatGrid::new(4.0).gridlines_in_range(2.5, 6.5),
+			&[3.0, 4.0, 5.0, 6.0],
+		);
+		assert_gridlines(eighths().gridlines_in_range(3.2, 4.6), &[3.5, 4.0, 4.5]);
+	}
+
+	#[test]
+	fn gridlines_restart_at_every_bar() {
+		// 7/8 with a gridline every quarter note, so the last beat of the bar is short
+		let grid = BeatGrid::new(3.5);
+		assert_gridlines(
+			grid.gridlines_in_range(0.0, 7.0),
+			&[0.0, 1.0, 2.0, 3.0, 3.5, 4.5, 5.5, 6.5],
+		);
+		assert_near(grid.snap(3.4), 3.5);
+		assert_near(grid.next_gridline_after(3.0), 3.5);
+
+		// Triplets in 3/4 leave half a gridline at the end of the bar
+		let grid = BeatGrid::new(3.0).with_triplet(true);
+		assert_gridlines(grid.gridlines_in_range(2.5, 3.5), &[8.0 / 3.0, 3.0]);
+	}
+
+	#[test]
+	fn bars_and_phrases() {
+		let grid = BeatGrid::new(3.0).with_bars_per_phrase(2);
+		assert_near(grid.phrase_beats(), 6.0);
+		assert_near(grid.next_bar_after(3.0), 6.0);
+		assert_near(grid.next_bar_after(2.9), 3.0);
+		assert_near(grid.bar_at_or_after(3.0), 3.0);
+		assert_near(grid.next_phrase_after(5.0), 6.0);
+		assert_near(grid.next_phrase_after(6.0), 12.0);
+	}
+
+	#[test]
+	fn phrases_are_at_least_a_bar() {
+		assert_near(
+			BeatGrid::new(4.0).with_bars_per_phrase(0).phrase_beats(),
+			4.0,
+		);
+	}
+}
@@ -0,0 +1,122 @@
+use std::f32::consts::TAU;
+
+use bevy::utils::Duration;
+
+const PULSE_SECONDS: f32 = 0.01;
+const AMPLITUDE: f32 = i16::MAX as f32 * 0.5;
+
+/// A train of short sine pulses at exact sample positions, for measuring output latency and pitch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationPulse {
+	pub frequency: f32,
+	pub interval_frames: u64,
+	pub pulse_frames: u64,
+	pub samples_per_second: f32,
+}
+
+impl CalibrationPulse {
+	pub fn new(frequency: f32, pulse_interval: Duration, samples_per_second: f32) -> Self {
+		let interval_frames = (pulse_interval.as_secs_f32() * samples_per_second).round() as u64;
+		Self {
+			frequency,
+			interval_frames: interval_frames.max(1),
+			pulse_frames: ((PULSE_SECONDS * samples_per_second) as u64)
+				.clamp(1, interval_frames.max(1)),
+			samples_per_second,
+		}
+	}
+
+	/// The pulse train's value at `frame`, in -1..1
+	pub fn value(&self, frame: u64) -> f32 {
+		let position = frame % self.interval_frames;
+		if position >= self.pulse_frames {
+			return 0.0;
+		}
+		// Hann windowed, so the correlation peak is unambiguous
+		let window = 0.5 - 0.5 * (TAU * position as f32 / self.pulse_frames as f32).cos();
+		let time = position as f32 / self.samples_per_second;
+		(TAU * self.frequency * time).sin() * window
+	}
+
+	pub(crate) fn sample(&self, frame: u64) -> i32 {
+		(self.value(frame) * AMPLITUDE) as i32
+	}
+
+	/// Estimates how far `captured` lags behind the pulse train, by cross-correlating against one pulse
+	///
+	/// `captured` should start at the same moment the pulse train started, e.g. when the calibration
+	/// track was added. Latencies longer than the pulse interval wrap around.
+	pub fn estimate_latency(&self, captured: &[f32], sample_rate: u32) -> Duration {
+		let scale = sample_rate as f32 / self.samples_per_second;
+		let template = (0..self.pulse_frames)
+			.map(|frame| self.value(frame))
+			.collect::<Vec<_>>();
+		let interval = (self.interval_frames as f32 * scale).round() as usize;
+		let interval = interval.max(1);
+
+		let mut best_lag = 0;
+		let mut best_score = f32::MIN;
+		for lag in 0..interval.min(captured.len()) {
+			let score = (lag..captured.len())
+				.step_by(interval)
+				.map(|start| {
+					template
+						.iter()
+						.enumerate()
+						.map(|(i, value)| {
+							let index = start + (i as f32 * scale) as usize;
+							captured.get(index).map_or(0.0, |sample| sample * value)
+						})
+						.sum::<f32>()
+				})
+				.sum::<f32>();
+			if score > best_score {
+				best_score = score;
+				best_lag = lag;
+			}
+		}
+		Duration::from_secs_f64(best_lag as f64 / sample_rate as f64)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pulse() -> CalibrationPulse {
+		CalibrationPulse::new(1000.0, Duration::from_millis(250), 44100.0)
+	}
+
+	#[test]
+	fn pulses_start_on_exact_frames() {
+		let pulse = pulse();
+		assert_eq!(pulse.interval_frames, 11025);
+		assert_eq!(pulse.pulse_frames, 441);
+		for start in [11025, 22050, 33075] {
+			assert_eq!(pulse.value(start - 1), 0.0);
+			assert_eq!(pulse.value(start), 0.0);
+			assert_ne!(pulse.value(start + 1), 0.0);
+			assert_eq!(pulse.value(start + pulse.pulse_frames), 0.0);
+		}
+	}
+
+	#[test]
+	fn recovers_a_known_latency() {
+		let pulse = pulse();
+		let latency_frames = (0.037 * 44100.0) as u64;
+		// Quieter and a little noisy, like a microphone would hear it
+		let captured = (0..44100_u64)
+			.map(|frame| {
+				let played = frame
+					.checked_sub(latency_frames)
+					.map_or(0.0, |frame| pulse.value(frame));
+				played * 0.3 + ((frame * 7919) % 13) as f32 * 0.001
+			})
+			.collect::<Vec<_>>();
+		let latency = pulse.estimate_latency(&captured, 44100);
+		assert!(
+			(latency.as_secs_f64() - 0.037).abs() < 0.0005,
+			"{latency:?}"
+		);
+	}
+}
@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+use bevy::render::RenderPlugin;
+use bevy::utils::hashbrown::HashMap;
+use bevy_egui::{EguiContexts, EguiPlugin, egui};
+
+use crate::source::{MidiAudio, MidiAudioTrackHandle, MidiQueueEvent, TrackInfo};
+
+/// Adds a window listing every [`MidiAudio`] with its tracks, queues and buffer, and controls for them.
+/// Added by [`crate::SoundyPlugin`] with the `debug-panel` feature.
+pub(crate) struct DebugPanelPlugin;
+
+impl Plugin for DebugPanelPlugin {
+	fn build(&self, app: &mut App) {
+		// egui needs the renderer, so headless apps go without the panel
+		if !app.is_plugin_added::<RenderPlugin>() {
+			return;
+		}
+		if !app.is_plugin_added::<EguiPlugin>() {
+			app.add_plugins(EguiPlugin);
+		}
+		app.add_systems(Update, draw_debug_panel);
+	}
+}
+
+/// What the debug panel shows for one [`MidiAudio`], taken fresh every frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiAudioPanel {
+	pub id: AssetId<MidiAudio>,
+	pub buffered_frames: usize,
+	/// The most frames that can be buffered, see [`crate::MidiAudioSettings::buffer_frames`]
+	pub buffer_frames: usize,
+	pub underruns: u64,
+	pub stalled_frames: u64,
+	pub tracks: Vec<TrackPanel>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPanel {
+	pub handle: MidiAudioTrackHandle,
+	pub info: TrackInfo,
+	pub queue: Vec<MidiQueueEvent>,
+}
+
+impl MidiAudioPanel {
+	pub fn new(id: AssetId<MidiAudio>, audio: &MidiAudio) -> Self {
+		Self {
+			id,
+			buffered_frames: audio.buffered_frames(),
+			buffer_frames: audio.settings().buffer_frames,
+			underruns: audio.underruns(),
+			stalled_frames: audio.stalled_frames(),
+			tracks: audio
+				.track_handles()
+				.into_iter()
+				.filter_map(|handle| {
+					Some(TrackPanel {
+						handle,
+						info: audio.track_info(&handle)?,
+						queue: audio.queued_events(&handle)?.to_vec(),
+					})
+				})
+				.collect(),
+		}
+	}
+
+	pub fn active_voices(&self) -> usize {
+		self.tracks
+			.iter()
+			.map(|track| track.info.active_voices)
+			.sum()
+	}
+}
+
+/// A change made in the panel, applied once it's drawn
+enum PanelAction {
+	SetVolume(MidiAudioTrackHandle, f32),
+	SetMuted(MidiAudioTrackHandle, bool),
+	SetSolo(MidiAudioTrackHandle, bool),
+	Play(MidiAudioTrackHandle),
+	Stop(MidiAudioTrackHandle),
+	SeekToBar(MidiAudioTrackHandle, u32),
+	StopAllNotes,
+}
+
+fn draw_debug_panel(
+	mut contexts: EguiContexts,
+	mut audios: ResMut<Assets<MidiAudio>>,
+	mut seek_bars: Local<HashMap<(AssetId<MidiAudio>, MidiAudioTrackHandle), u32>>,
+) {
+	let Some(context) = contexts.try_ctx_mut() else {
+		return;
+	};
+	let panels = audios
+		.iter()
+		.map(|(id, audio)| MidiAudioPanel::new(id, audio))
+		.collect::<Vec<_>>();
+
+	let mut actions = vec![];
+	egui::Window::new("Music").show(context, |ui| {
+		if panels.is_empty() {
+			ui.label("No MidiAudio assets");
+		}
+		for panel in &panels {
+			let mut panel_actions = vec![];
+			ui.collapsing(format!("{:?}", panel.id), |ui| {
+				draw_audio_panel(ui, panel, &mut seek_bars, &mut panel_actions);
+			});
+			actions.extend(panel_actions.into_iter().map(|action| (panel.id, action)));
+		}
+	});
+
+	for (id, action) in actions {
+		let Some(audio) = audios.get_mut(id) else {
+			continue;
+		};
+		match action {
+			PanelAction::SetVolume(handle, volume) => audio.set_track_volume(&handle, volume),
+			PanelAction::SetMuted(handle, muted) => audio.set_track_muted(&handle, muted),
+			PanelAction::SetSolo(handle, solo) => audio.set_track_solo(&handle, solo),
+			PanelAction::Play(handle) => audio.batch(&handle, |batch| {
+				batch.play();
+			}),
+			PanelAction::Stop(handle) => audio.batch(&handle, |batch| {
+				batch.stop();
+			}),
+			PanelAction::SeekToBar(handle, bar) => audio.seek_to_bar(&handle, bar),
+			PanelAction::StopAllNotes => audio.stop_all_notes_all_tracks(),
+		}
+	}
+}
+
+fn draw_audio_panel(
+	ui: &mut egui::Ui,
+	panel: &MidiAudioPanel,
+	seek_bars: &mut HashMap<(AssetId<MidiAudio>, MidiAudioTrackHandle), u32>,
+	actions: &mut Vec<PanelAction>,
+) {
+	ui.add(
+		egui::ProgressBar::new(panel.buffered_frames as f32 / panel.buffer_frames.max(1) as f32)
+			.text(format!(
+				"Buffer {} / {} frames",
+				panel.buffered_frames, panel.buffer_frames
+			)),
+	);
+	ui.label(format!(
+		"{} underruns, {} stalled frames, {} voices",
+		panel.underruns,
+		panel.stalled_frames,
+		panel.active_voices()
+	));
+	if ui.button("Stop all notes").clicked() {
+		actions.push(PanelAction::StopAllNotes);
+	}
+
+	for track in &panel.tracks {
+		let info = &track.info;
+		ui.separator();
+		ui.horizontal(|ui| {
+			ui.strong(info.name.as_deref().unwrap_or("Unnamed track"));
+			ui.label(format!("{:?}", track.handle));
+		});
+		ui.label(format!(
+			"Bar {} beat {:.2}, {:.1} BPM, {} voices",
+			info.bar,
+			info.beat,
+			info.beats_per_second * 60.0,
+			info.active_voices
+		));
+		ui.add(egui::ProgressBar::new(info.level.min(1.0)).text("Level"));
+
+		ui.horizontal(|ui| {
+			if info.is_playing {
+				if ui.button("Stop").clicked() {
+					actions.push(PanelAction::Stop(track.handle));
+				}
+			} else if ui.button("Play").clicked() {
+				actions.push(PanelAction::Play(track.handle));
+			}
+			let mut muted = info.muted;
+			if ui.checkbox(&mut muted, "Mute").changed() {
+				actions.push(PanelAction::SetMuted(track.handle, muted));
+			}
+			let mut solo = info.solo;
+			if ui.checkbox(&mut solo, "Solo").changed() {
+				actions.push(PanelAction::SetSolo(track.handle, solo));
+			}
+		});
+
+		let mut volume = info.volume;
+		if ui
+			.add(egui::Slider::new(&mut volume, 0.0..=2.0).text("Volume"))
+			.changed()
+		{
+			actions.push(PanelAction::SetVolume(track.handle, volume));
+		}
+
+		ui.horizontal(|ui| {
+			let bar = seek_bars.entry((panel.id, track.handle)).or_default();
+			ui.add(egui::DragValue::new(bar).prefix("Bar "));
+			if ui.button("Seek").clicked() {
+				actions.push(PanelAction::SeekToBar(track.handle, *bar));
+			}
+		});
+
+		if track.queue.is_empty() {
+			ui.label("Nothing queued");
+		}
+		for event in &track.queue {
+			ui.label(format!(
+				"{:?} on {:?}, {:?}",
+				event.event, event.timing, event.looping
+			));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::source::{MidiAudioTrack, MidiQueueEventType, MidiQueueLooping, MidiQueueTiming};
+
+	#[test]
+	fn panels_show_where_a_live_asset_is() {
+		let mut audio =
+			MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2")).with_logical_clock();
+		let handle = audio.add_track(
+			MidiAudioTrack::from_bytes(include_bytes!("../assets/fray.mid"), 1.0).with_name("fray"),
+		);
+		let stop = MidiQueueEvent {
+			event: MidiQueueEventType::Stop,
+			timing: MidiQueueTiming::Loop,
+			looping: MidiQueueLooping::Once,
+		};
+		let mut decoder = audio.decoder();
+		audio.advance_logical(44100 * 5);
+		audio.queue(handle, stop.clone());
+		for _ in 0..44100 * 4 {
+			decoder.next_frame();
+		}
+
+		let panel = MidiAudioPanel::new(AssetId::default(), &audio);
+		assert_eq!(panel.buffered_frames, 44100);
+		assert_eq!(panel.underruns, 0);
+		let [track] = &panel.tracks[..] else {
+			panic!("{:?}", panel.tracks);
+		};
+		assert_eq!(track.handle, handle);
+		assert_eq!(track.info.name.as_deref(), Some("fray"));
+		assert!(track.info.is_playing);
+		// Five seconds at fray's 159 bpm is 13.25 beats, into the fourth bar of 4/4
+		assert_eq!(track.info.bar, 3);
+		assert!(
+			(track.info.beat - 13.25).abs() < 1e-3,
+			"{}",
+			track.info.beat
+		);
+		assert_eq!(track.queue, vec![stop]);
+		assert!(panel.active_voices() > 0);
+	}
+}
@@ -0,0 +1,253 @@
+//! General MIDI names for programs and percussion keys
+
+/// The lowest key in the General MIDI percussion map
+pub const FIRST_DRUM_KEY: u8 = 35;
+/// The highest key in the General MIDI percussion map
+pub const LAST_DRUM_KEY: u8 = 81;
+
+const INSTRUMENT_NAMES: [&str; 128] = [
+	"Acoustic Grand Piano",
+	"Bright Acoustic Piano",
+	"Electric Grand Piano",
+	"Honky-tonk Piano",
+	"Electric Piano 1",
+	"Electric Piano 2",
+	"Harpsichord",
+	"Clavi",
+	"Celesta",
+	"Glockenspiel",
+	"Music Box",
+	"Vibraphone",
+	"Marimba",
+	"Xylophone",
+	"Tubular Bells",
+	"Dulcimer",
+	"Drawbar Organ",
+	"Percussive Organ",
+	"Rock Organ",
+	"Church Organ",
+	"Reed Organ",
+	"Accordion",
+	"Harmonica",
+	"Tango Accordion",
+	"Acoustic Guitar (nylon)",
+	"Acoustic Guitar (steel)",
+	"Electric Guitar (jazz)",
+	"Electric Guitar (clean)",
+	"Electric Guitar (muted)",
+	"Overdriven Guitar",
+	"Distortion Guitar",
+	"Guitar Harmonics",
+	"Acoustic Bass",
+	"Electric Bass (finger)",
+	"Electric Bass (pick)",
+	"Fretless Bass",
+	"Slap Bass 1",
+	"Slap Bass 2",
+	"Synth Bass 1",
+	"Synth Bass 2",
+	"Violin",
+	"Viola",
+	"Cello",
+	"Contrabass",
+	"Tremolo Strings",
+	"Pizzicato Strings",
+	"Orchestral Harp",
+	"Timpani",
+	"String Ensemble 1",
+	"String Ensemble 2",
+	"Synth Strings 1",
+	"Synth Strings 2",
+	"Choir Aahs",
+	"Voice Oohs",
+	"Synth Voice",
+	"Orchestra Hit",
+	"Trumpet",
+	"Trombone",
+	"Tuba",
+	"Muted Trumpet",
+	"French Horn",
+	"Brass Section",
+	"Synth Brass 1",
+	"Synth Brass 2",
+	"Soprano Sax",
+	"Alto Sax",
+	"Tenor Sax",
+	"Baritone Sax",
+	"Oboe",
+	"English Horn",
+	"Bassoon",
+	"Clarinet",
+	"Piccolo",
+	"Flute",
+	"Recorder",
+	"Pan Flute",
+	"Blown Bottle",
+	"Shakuhachi",
+	"Whistle",
+	"Ocarina",
+	"Lead 1 (square)",
+	"Lead 2 (sawtooth)",
+	"Lead 3 (calliope)",
+	"Lead 4 (chiff)",
+	"Lead 5 (charang)",
+	"Lead 6 (voice)",
+	"Lead 7 (fifths)",
+	"Lead 8 (bass + lead)",
+	"Pad 1 (new age)",
+	"Pad 2 (warm)",
+	"Pad 3 (polysynth)",
+	"Pad 4 (choir)",
+	"Pad 5 (bowed)",
+	"Pad 6 (metallic)",
+	"Pad 7 (halo)",
+	"Pad 8 (sweep)",
+	"FX 1 (rain)",
+	"FX 2 (soundtrack)",
+	"FX 3 (crystal)",
+	"FX 4 (atmosphere)",
+	"FX 5 (brightness)",
+	"FX 6 (goblins)",
+	"FX 7 (echoes)",
+	"FX 8 (sci-fi)",
+	"Sitar",
+	"Banjo",
+	"Shamisen",
+	"Koto",
+	"Kalimba",
+	"Bag pipe",
+	"Fiddle",
+	"Shanai",
+	"Tinkle Bell",
+	"Agogo",
+	"Steel Drums",
+	"Woodblock",
+	"Taiko Drum",
+	"Melodic Tom",
+	"Synth Drum",
+	"Reverse Cymbal",
+	"Guitar Fret Noise",
+	"Breath Noise",
+	"Seashore",
+	"Bird Tweet",
+	"Telephone Ring",
+	"Helicopter",
+	"Applause",
+	"Gunshot",
+];
+
+const DRUM_NAMES: [&str; (LAST_DRUM_KEY - FIRST_DRUM_KEY + 1) as usize] = [
+	"Acoustic Bass Drum",
+	"Bass Drum 1",
+	"Side Stick",
+	"Acoustic Snare",
+	"Hand Clap",
+	"Electric Snare",
+	"Low Floor Tom",
+	"Closed Hi Hat",
+	"High Floor Tom",
+	"Pedal Hi-Hat",
+	"Low Tom",
+	"Open Hi-Hat",
+	"Low-Mid Tom",
+	"Hi-Mid Tom",
+	"Crash Cymbal 1",
+	"High Tom",
+	"Ride Cymbal 1",
+	"Chinese Cymbal",
+	"Ride Bell",
+	"Tambourine",
+	"Splash Cymbal",
+	"Cowbell",
+	"Crash Cymbal 2",
+	"Vibraslap",
+	"Ride Cymbal 2",
+	"Hi Bongo",
+	"Low Bongo",
+	"Mute Hi Conga",
+	"Open Hi Conga",
+	"Low Conga",
+	"High Timbale",
+	"Low Timbale",
+	"High Agogo",
+	"Low Agogo",
+	"Cabasa",
+	"Maracas",
+	"Short Whistle",
+	"Long Whistle",
+	"Short Guiro",
+	"Long Guiro",
+	"Claves",
+	"Hi Wood Block",
+	"Low Wood Block",
+	"Mute Cuica",
+	"Open Cuica",
+	"Mute Triangle",
+	"Open Triangle",
+];
+
+/// The General MIDI name of a percussion key on channel 10 (index 9)
+pub fn drum_name(key: u8) -> Option<&'static str> {
+	(FIRST_DRUM_KEY..=LAST_DRUM_KEY)
+		.contains(&key)
+		.then(|| DRUM_NAMES[(key - FIRST_DRUM_KEY) as usize])
+}
+
+/// The General MIDI name of a melodic program, wrapping patches above 127
+pub fn instrument_name(patch: u8) -> &'static str {
+	INSTRUMENT_NAMES[(patch & 0x7F) as usize]
+}
+
+/// The percussion key with this name, ignoring case
+pub fn drum_key(name: &str) -> Option<u8> {
+	DRUM_NAMES
+		.iter()
+		.position(|drum| drum.eq_ignore_ascii_case(name))
+		.map(|index| index as u8 + FIRST_DRUM_KEY)
+}
+
+/// The melodic program with this name, ignoring case
+pub fn instrument_patch(name: &str) -> Option<u8> {
+	INSTRUMENT_NAMES
+		.iter()
+		.position(|instrument| instrument.eq_ignore_ascii_case(name))
+		.map(|index| index as u8)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_program_has_a_unique_name() {
+		for patch in 0..128 {
+			let name = instrument_name(patch);
+			assert!(!name.is_empty());
+			assert_eq!(instrument_patch(name), Some(patch), "{name}");
+		}
+		assert_eq!(instrument_name(0), "Acoustic Grand Piano");
+		assert_eq!(instrument_name(127), "Gunshot");
+		assert_eq!(instrument_name(128), instrument_name(0));
+	}
+
+	#[test]
+	fn every_standard_drum_has_a_unique_name() {
+		for key in FIRST_DRUM_KEY..=LAST_DRUM_KEY {
+			let name = drum_name(key).unwrap();
+			assert!(!name.is_empty());
+			assert_eq!(drum_key(name), Some(key), "{name}");
+		}
+		assert_eq!(drum_name(35), Some("Acoustic Bass Drum"));
+		assert_eq!(drum_name(37), Some("Side Stick"));
+		assert_eq!(drum_name(81), Some("Open Triangle"));
+		assert_eq!(drum_name(FIRST_DRUM_KEY - 1), None);
+		assert_eq!(drum_name(LAST_DRUM_KEY + 1), None);
+	}
+
+	#[test]
+	fn names_match_ignoring_case() {
+		assert_eq!(drum_key("acoustic snare"), Some(38));
+		assert_eq!(instrument_patch("VIOLIN"), Some(40));
+		assert_eq!(instrument_patch("Kazoo"), None);
+	}
+}
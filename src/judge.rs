@@ -0,0 +1,139 @@
+/// Grades player input against target beats, for rhythm games
+///
+/// Windows are in beats either side of the target, so they scale with tempo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitJudge {
+	pub perfect_window: f64,
+	pub good_window: f64,
+}
+
+impl Default for HitJudge {
+	fn default() -> Self {
+		Self {
+			perfect_window: 0.1,
+			good_window: 0.25,
+		}
+	}
+}
+
+impl HitJudge {
+	pub fn new(perfect_window: f64, good_window: f64) -> Self {
+		Self {
+			perfect_window,
+			good_window,
+		}
+	}
+
+	pub fn judge(&self, target_beat: f64, hit_beat: f64) -> Judgement {
+		let error_beats = hit_beat - target_beat;
+		let grade = if error_beats.abs() <= self.perfect_window {
+			HitGrade::Perfect
+		} else if error_beats.abs() <= self.good_window {
+			HitGrade::Good
+		} else {
+			HitGrade::Miss
+		};
+		Judgement { grade, error_beats }
+	}
+
+	/// Judges against whichever target is closest to the hit, returning its index
+	pub fn judge_nearest(
+		&self,
+		target_beats: impl IntoIterator<Item = f64>,
+		hit_beat: f64,
+	) -> Option<(usize, Judgement)> {
+		target_beats
+			.into_iter()
+			.enumerate()
+			.min_by(|(_, a), (_, b)| (a - hit_beat).abs().total_cmp(&(b - hit_beat).abs()))
+			.map(|(index, target_beat)| (index, self.judge(target_beat, hit_beat)))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Judgement {
+	pub grade: HitGrade,
+	/// Positive when the hit was late
+	pub error_beats: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HitGrade {
+	Perfect,
+	Good,
+	Miss,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+	pub perfect: u32,
+	pub good: u32,
+	pub miss: u32,
+	pub combo: u32,
+}
+
+impl Score {
+	pub fn record(&mut self, grade: HitGrade) {
+		match grade {
+			HitGrade::Perfect => self.perfect += 1,
+			HitGrade::Good => self.good += 1,
+			HitGrade::Miss => self.miss += 1,
+		}
+		self.combo = if grade == HitGrade::Miss {
+			0
+		} else {
+			self.combo + 1
+		};
+	}
+
+	pub fn points(&self) -> u32 {
+		self.perfect * 300 + self.good * 100
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn grades_by_window() {
+		let judge = HitJudge::default();
+		assert_eq!(judge.judge(4.0, 4.05).grade, HitGrade::Perfect);
+		assert_eq!(judge.judge(4.0, 3.8).grade, HitGrade::Good);
+		assert_eq!(judge.judge(4.0, 4.5).grade, HitGrade::Miss);
+		assert_eq!(judge.judge(4.0, 4.25).error_beats, 0.25);
+		assert_eq!(judge.judge(4.0, 3.75).error_beats, -0.25);
+	}
+
+	#[test]
+	fn judges_the_nearest_target() {
+		let judge = HitJudge::new(0.05, 0.2);
+		let (index, judgement) = judge.judge_nearest([1.0, 2.0, 3.0], 2.1).unwrap();
+		assert_eq!(index, 1);
+		assert_eq!(judgement.grade, HitGrade::Good);
+		assert_eq!(judge.judge_nearest([], 2.0), None);
+	}
+
+	#[test]
+	fn scores_scripted_inputs() {
+		let judge = HitJudge::default();
+		let targets = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+		// On time, a little late, way off, early, on time, on time
+		let hits = [0.02, 1.2, 2.45, 2.92, 4.0, 5.05];
+		let mut score = Score::default();
+		for hit in hits {
+			let (_, judgement) = judge.judge_nearest(targets, hit).unwrap();
+			score.record(judgement.grade);
+		}
+		assert_eq!(
+			score,
+			Score {
+				perfect: 4,
+				good: 1,
+				miss: 1,
+				combo: 3,
+			}
+		);
+		assert_eq!(score.points(), 1300);
+	}
+}
@@ -0,0 +1,254 @@
+//! Plays [`MidiAudio`] through kira, for games that use bevy_kira_audio instead of bevy_audio.
+//!
+//! bevy_kira_audio only plays its own static and streaming sounds and keeps its audio manager private,
+//! so [`SoundyKiraPlugin`] opens a kira audio manager of its own next to it.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy::audio::Decodable;
+use bevy::prelude::*;
+use kira::OutputDestination;
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::dsp::Frame;
+use kira::manager::backend::DefaultBackend;
+use kira::manager::{AudioManager, AudioManagerSettings};
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::sound::{Sound, SoundData};
+
+use crate::source::{MidiAudio, MidiDecoder};
+
+/// Plays [`MidiAudio`] through kira. Add it alongside [`crate::SoundyPlugin`], which still ticks the assets,
+/// and spawn a [`KiraMidiPlayer`] where you'd spawn an [`AudioPlayer`].
+pub struct SoundyKiraPlugin;
+
+impl Plugin for SoundyKiraPlugin {
+	fn build(&self, app: &mut App) {
+		let manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())
+			.inspect_err(|error| {
+				warn!("Couldn't open a kira audio device, MIDI won't play: {error}")
+			})
+			.ok();
+		app.insert_non_send_resource(KiraMidiOutput {
+			manager,
+			playing: HashMap::new(),
+		})
+		.add_systems(PostUpdate, (play_kira_midi, stop_kira_midi));
+	}
+}
+
+/// Plays a [`MidiAudio`] through [`SoundyKiraPlugin`]'s audio manager until it's despawned or removed
+#[derive(Component, Clone, Debug)]
+pub struct KiraMidiPlayer(pub Handle<MidiAudio>);
+
+struct KiraMidiOutput {
+	manager: Option<AudioManager<DefaultBackend>>,
+	playing: HashMap<Entity, MidiSoundHandle>,
+}
+
+fn play_kira_midi(
+	mut output: NonSendMut<KiraMidiOutput>,
+	players: Query<(Entity, &KiraMidiPlayer)>,
+	assets: Res<Assets<MidiAudio>>,
+) {
+	let KiraMidiOutput { manager, playing } = &mut *output;
+	let Some(manager) = manager else {
+		return;
+	};
+	for (entity, player) in &players {
+		if playing.contains_key(&entity) {
+			continue;
+		}
+		let Some(audio) = assets.get(&player.0) else {
+			continue;
+		};
+		match manager.play(audio.kira_sound()) {
+			Ok(handle) => {
+				playing.insert(entity, handle);
+			}
+			Err(error) => warn!("Couldn't play MIDI through kira: {error}"),
+		}
+	}
+}
+
+fn stop_kira_midi(
+	mut output: NonSendMut<KiraMidiOutput>,
+	mut removed: RemovedComponents<KiraMidiPlayer>,
+) {
+	for entity in removed.read() {
+		if let Some(handle) = output.playing.remove(&entity) {
+			handle.stop();
+		}
+	}
+}
+
+impl MidiAudio {
+	/// A kira sound playing this asset, for your own kira audio manager.
+	/// Like [`Decodable::decoder`], this locks in the sample rate and channels.
+	pub fn kira_sound(&self) -> MidiSoundData {
+		MidiSoundData {
+			samples_per_second: self.settings().sample_rate as f64,
+			decoder: self.decoder(),
+			output_destination: OutputDestination::MAIN_TRACK,
+		}
+	}
+}
+
+/// A [`MidiAudio`]'s rendered audio as kira [`SoundData`], from [`MidiAudio::kira_sound`]
+pub struct MidiSoundData {
+	decoder: MidiDecoder,
+	samples_per_second: f64,
+	output_destination: OutputDestination,
+}
+
+impl MidiSoundData {
+	/// Sends the audio to a kira mixer track instead of the main track
+	pub fn with_output_destination(
+		mut self,
+		output_destination: impl Into<OutputDestination>,
+	) -> Self {
+		self.output_destination = output_destination.into();
+		self
+	}
+}
+
+impl SoundData for MidiSoundData {
+	type Error = Infallible;
+
+	type Handle = MidiSoundHandle;
+
+	fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+		let stopped = Arc::new(AtomicBool::new(false));
+		let sound = MidiSound {
+			decoder: self.decoder,
+			samples_per_second: self.samples_per_second,
+			output_destination: self.output_destination,
+			stopped: stopped.clone(),
+			current: None,
+			next: None,
+			position: 0.0,
+		};
+		Ok((Box::new(sound), MidiSoundHandle { stopped }))
+	}
+}
+
+/// Controls a playing [`MidiSoundData`]
+#[derive(Clone, Debug)]
+pub struct MidiSoundHandle {
+	stopped: Arc<AtomicBool>,
+}
+
+impl MidiSoundHandle {
+	/// Stops pulling audio, letting kira unload the sound. The asset keeps rendering until it's dropped.
+	pub fn stop(&self) {
+		self.stopped.store(true, Ordering::Relaxed);
+	}
+}
+
+/// Pulls frames from the asset's buffer, interpolating linearly when kira's sample rate differs from the asset's.
+/// At matching rates each frame passes through untouched, exactly what bevy_audio would play.
+struct MidiSound {
+	decoder: MidiDecoder,
+	samples_per_second: f64,
+	output_destination: OutputDestination,
+	stopped: Arc<AtomicBool>,
+	/// The frame at or just before the playhead
+	current: Option<[f32; 2]>,
+	/// The frame after [`Self::current`], only pulled once the playhead sits between the two
+	next: Option<[f32; 2]>,
+	/// How far the playhead is from `current` towards `next`, in frames
+	position: f64,
+}
+
+impl Sound for MidiSound {
+	fn output_destination(&mut self) -> OutputDestination {
+		self.output_destination
+	}
+
+	fn process(&mut self, dt: f64, _: &ClockInfoProvider, _: &ModulatorValueProvider) -> Frame {
+		let current = match self.current {
+			None => *self.current.insert(self.decoder.next_frame()),
+			Some(_) => {
+				let mut step = dt * self.samples_per_second;
+				// kira's dt is 1 / its sample rate, which doesn't always round trip to exactly one frame
+				if (step - 1.0).abs() < 1e-9 {
+					step = 1.0;
+				}
+				self.position += step;
+				while self.position >= 1.0 {
+					let next = self
+						.next
+						.take()
+						.unwrap_or_else(|| self.decoder.next_frame());
+					self.current = Some(next);
+					self.position -= 1.0;
+				}
+				self.current.unwrap()
+			}
+		};
+		if self.position == 0.0 {
+			return Frame::new(current[0], current[1]);
+		}
+		let next = *self.next.get_or_insert_with(|| self.decoder.next_frame());
+		let t = self.position as f32;
+		Frame::new(
+			current[0] + (next[0] - current[0]) * t,
+			current[1] + (next[1] - current[1]) * t,
+		)
+	}
+
+	fn finished(&self) -> bool {
+		self.stopped.load(Ordering::Relaxed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use kira::clock::clock_info::MockClockInfoProviderBuilder;
+	use kira::modulator::value_provider::MockModulatorValueProviderBuilder;
+
+	use super::*;
+	use crate::source::MidiAudioTrack;
+
+	fn octave() -> MidiAudio {
+		MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2"))
+			.with_logical_clock()
+			.with_track(MidiAudioTrack::from_bytes(
+				include_bytes!("../assets/octave.mid"),
+				4.0 / 4.0,
+			))
+	}
+
+	#[test]
+	fn kira_plays_the_same_frames_as_bevy_audio() {
+		let mut rodio = octave();
+		let mut kira = octave();
+		let mut decoder = rodio.decoder();
+		let (mut sound, _) = kira.kira_sound().into_sound().unwrap();
+		let clock_info = MockClockInfoProviderBuilder::new(0).build();
+		let modulators = MockModulatorValueProviderBuilder::new(0).build();
+		let mut heard = false;
+		for _ in 0..20 {
+			rodio.advance_logical(4410);
+			kira.advance_logical(4410);
+			for _ in 0..4410 {
+				let left = decoder.next().unwrap() as f32 / i16::MAX as f32;
+				let right = decoder.next().unwrap() as f32 / i16::MAX as f32;
+				let frame = sound.process(1.0 / 44100.0, &clock_info, &modulators);
+				assert_eq!((frame.left, frame.right), (left, right));
+				heard |= left != 0.0;
+			}
+		}
+		assert!(heard);
+	}
+
+	#[test]
+	fn stopping_finishes_the_sound() {
+		let (sound, handle) = octave().kira_sound().into_sound().unwrap();
+		assert!(!sound.finished());
+		handle.stop();
+		assert!(sound.finished());
+	}
+}
@@ -1,24 +1,89 @@
 use bevy::audio::AddAudioSource;
 use bevy::prelude::*;
+use preload::preload_music;
+use setup::{build_music_setups, load_music_setups};
 
-pub use midi::MidiTrack;
-pub use notes::Note;
-pub use rustysynth::SoundFont;
+pub use automation::{Automation, AutomationBreakpoint, AutomationParameter};
+pub use batch::{ControlBatch, ControlCommand};
+pub use beat_grid::BeatGrid;
+pub use calibration::CalibrationPulse;
+#[cfg(feature = "debug-panel")]
+pub use debug_panel::{MidiAudioPanel, TrackPanel};
+pub use judge::{HitGrade, HitJudge, Judgement, Score};
+#[cfg(feature = "kira")]
+pub use kira_backend::{KiraMidiPlayer, MidiSoundData, MidiSoundHandle, SoundyKiraPlugin};
+pub use midi::{
+	BANK_SELECT_CONTROLLER, ClampedValue, ClampedValueKind, EXPRESSION_CONTROLLER,
+	IgnoredEventKind, LoadReport, MidiEvent, MidiLoadError, MidiTrack, MidiTrackAccumulateEvent,
+	PAN_CONTROLLER, ParseLimits, SUSTAIN_CONTROLLER, SourceTrack, VOLUME_CONTROLLER,
+};
+pub use musical_time::{BeatPeriod, MusicalTime, MusicalTimeListener};
+pub use notes::{Accidental, Interval, Note, NoteNameStyle, NoteParseError, NoteRange, Tuning};
+pub use preload::{MusicPreload, MusicReady};
+pub use rustysynth::{SoundFont, SoundFontError};
+pub use setup::{
+	MidiFileAsset, MidiFileLoader, MusicChannelPatch, MusicQueueSetup, MusicRuntime, MusicSetup,
+	MusicTrackSetup, SoundFontAsset, SoundFontLoader, SoundyLoadError,
+};
+pub use smoothed::SmoothedParam;
 pub use source::{
-	MidiAudio, MidiAudioTrack, MidiAudioTrackHandle, MidiBufferMessage, MidiQueueEvent,
-	MidiQueueEventType, MidiQueueLooping, MidiQueueTiming, SyncedMidiInfo,
+	ChannelPlaybackOptions, DispatchedEvent, DispatchedEventKind, Drift, EventBatch, HandoffHandle,
+	Instrument, InstrumentContext, LayerAlternation, MidiAudio, MidiAudioSettings, MidiAudioTrack,
+	MidiAudioTrackHandle, MidiDecoder, MidiQueueEvent, MidiQueueEventType, MidiQueueLooping,
+	MidiQueueTiming, MissingDrumBankPolicy, MpeConfig, NoTracksError, NoteExplanation,
+	PlaybackClock, QueueClock, RegionMatch, SeamReport, SettingsError, SongHandle, SoundFontBank,
+	SoundFontInstrument, SoundFontLoadError, StallPolicy, StrictPolicy, StrictViolation,
+	StrictViolationKind, SynthInstrument, TrackInfo, UpcomingNote, VoiceOrigin, VoiceSource,
 };
+pub use synth::Waveform;
+pub use theory::{Chord, Key, Scale};
+
+pub mod gm;
+pub mod prelude;
 
+mod automation;
+mod batch;
+mod beat_grid;
+mod calibration;
+#[cfg(feature = "debug-panel")]
+mod debug_panel;
+mod judge;
+#[cfg(feature = "kira")]
+mod kira_backend;
 mod midi;
+mod musical_time;
 mod notes;
+mod preload;
+mod rng;
+mod setup;
+mod smoothed;
 mod source;
+mod synth;
+mod theory;
 
 pub struct SoundyPlugin;
 
 impl Plugin for SoundyPlugin {
 	fn build(&self, app: &mut App) {
 		app.add_audio_source::<MidiAudio>()
-			.add_systems(PreUpdate, tick_sequencers);
+			.init_asset::<SoundFontAsset>()
+			.init_asset::<MidiFileAsset>()
+			.init_asset_loader::<SoundFontLoader>()
+			.init_asset_loader::<MidiFileLoader>()
+			.register_type::<MusicSetup>()
+			.register_type::<MusicRuntime>()
+			.register_type::<Note>()
+			.register_type::<MidiAudioTrackHandle>()
+			.register_type::<MidiQueueEvent>()
+			.register_type::<MidiQueueTiming>()
+			.register_type::<MidiQueueLooping>()
+			.init_resource::<MusicPreload>()
+			.add_event::<MusicReady>()
+			.add_systems(PreUpdate, tick_sequencers)
+			.add_systems(PostUpdate, preload_music)
+			.add_systems(Update, (load_music_setups, build_music_setups).chain());
+		#[cfg(feature = "debug-panel")]
+		app.add_plugins(debug_panel::DebugPanelPlugin);
 	}
 }
 
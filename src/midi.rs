@@ -1,22 +1,45 @@
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 use std::ops::Index;
+use std::sync::Arc;
 
 use augmented_midi::{
-	parse_midi_file, MIDIFile, MIDIFileChunk, MIDIFileDivision, MIDIMessage, MIDIMessageNote,
-	MIDITrackInner,
+	MIDIFile, MIDIFileChunk, MIDIFileDivision, MIDIMessage, MIDIMessageNote, MIDITrackInner,
+	parse_midi_file,
 };
+use bevy::log::warn;
 use itertools::Itertools;
 
+use crate::Key;
+
+/// What files timed in SMPTE frames are converted to, see [`smpte_to_beat_ticks`]
+const SMPTE_TICKS_PER_BEAT: u16 = 960;
+
 #[derive(Debug, Clone)]
 pub struct MidiTrackAccumulateEvent {
 	pub time: u64,
 	pub inner: MidiEvent,
+	/// Which of [`MidiTrack::source_tracks`] the event was merged in from
+	pub source_track: u16,
 }
 
 #[derive(Debug, Clone)]
 pub struct MidiTrack {
-	pub events: Vec<MidiTrackAccumulateEvent>,
+	/// Shared, so duplicated tracks don't copy the whole file
+	pub events: Arc<[MidiTrackAccumulateEvent]>,
 	pub ticks_per_beat: u16,
+	/// The file's tracks in the order they're in the file, which [`Self::events`] are merged from
+	pub source_tracks: Arc<[SourceTrack]>,
+}
+
+/// One of the tracks in a MIDI file, as named in the DAW that wrote it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceTrack {
+	/// From the track's first track name meta event
+	pub name: Option<String>,
+	/// From the track's first instrument name meta event
+	pub instrument_name: Option<String>,
 }
 
 impl MidiTrack {
@@ -26,16 +49,79 @@ impl MidiTrack {
 	>(
 		file: MIDIFile<StringRepr, Buffer>,
 	) -> Self {
-		let events = file
+		Self::from_midi_file_with_report(file).0
+	}
+
+	pub fn from_midi_file_with_report<
+		StringRepr: Borrow<str>,
+		Buffer: Borrow<[u8]> + Clone + Index<usize, Output = u8>,
+	>(
+		file: MIDIFile<StringRepr, Buffer>,
+	) -> (Self, LoadReport) {
+		Self::try_from_midi_file(file, &ParseLimits::unlimited())
+			.unwrap_or_else(|error| panic!("Invalid MIDI file: {error}"))
+	}
+
+	fn try_from_midi_file<
+		StringRepr: Borrow<str>,
+		Buffer: Borrow<[u8]> + Clone + Index<usize, Output = u8>,
+	>(
+		file: MIDIFile<StringRepr, Buffer>,
+		limits: &ParseLimits,
+	) -> Result<(Self, LoadReport), MidiLoadError> {
+		let (ticks_per_beat, smpte_ticks_per_second) =
+			match file.header().ok_or(MidiLoadError::MissingHeader)?.division {
+				MIDIFileDivision::TicksPerQuarterNote {
+					ticks_per_quarter_note,
+				} if ticks_per_quarter_note > 0 => (ticks_per_quarter_note, None),
+				MIDIFileDivision::SMPTE {
+					format,
+					ticks_per_frame,
+				} if ticks_per_frame > 0 => {
+					// `format` is the low 7 bits of the negated frame rate
+					let frames_per_second = match 128 - format as i32 {
+						24 => 24.0,
+						25 => 25.0,
+						29 => 30.0 * 1000.0 / 1001.0,
+						30 => 30.0,
+						_ => return Err(MidiLoadError::UnsupportedDivision),
+					};
+					(
+						SMPTE_TICKS_PER_BEAT,
+						Some(frames_per_second * ticks_per_frame as f64),
+					)
+				}
+				_ => return Err(MidiLoadError::UnsupportedDivision),
+			};
+		let tracks = file
 			.chunks
 			.iter()
 			.filter_map(|chunk| match chunk {
-				MIDIFileChunk::Track { events } => Some(events.clone()),
+				MIDIFileChunk::Track { events } => Some(events),
 				_ => None,
 			})
+			.collect::<Vec<_>>();
+		if tracks.len() > limits.max_tracks {
+			return Err(MidiLoadError::TooManyTracks);
+		}
+		if tracks.iter().map(|events| events.len()).sum::<usize>() > limits.max_events {
+			return Err(MidiLoadError::TooManyEvents);
+		}
+		if tracks.iter().flat_map(|events| events.iter()).any(
+			|event| matches!(&event.inner, MIDITrackInner::Message(message) if has_bad_data_byte(message)),
+		) {
+			return Err(MidiLoadError::BadMessage);
+		}
+
+		let mut report = LoadReport::default();
+		let mut source_tracks = vec![SourceTrack::default(); tracks.len()];
+
+		let mut events = tracks
+			.into_iter()
 			.enumerate()
 			.flat_map(|(i, track)| {
 				let mut time = 0;
+				let track_channel = i.min(15) as u8;
 				track
 					.iter()
 					.filter_map(|event| {
@@ -46,7 +132,7 @@ impl MidiTrack {
 								note,
 								velocity,
 							})) => MidiEvent::NoteOn {
-								channel: (*channel).max(i as u8), // Workaround for DAWs that don't set the channel
+								channel: report.clamp_channel((*channel).max(track_channel), time), // Workaround for DAWs that don't set the channel
 								note: *note,
 								velocity: *velocity,
 							},
@@ -55,55 +141,906 @@ impl MidiTrack {
 								note,
 								velocity: _,
 							})) => MidiEvent::NoteOff {
-								channel: (*channel).max(i as u8),
+								channel: report.clamp_channel((*channel).max(track_channel), time),
+								note: *note,
+							},
+							MIDITrackInner::Message(MIDIMessage::ControlChange {
+								channel,
+								controller_number:
+									controller @ (BANK_SELECT_CONTROLLER
+									| VOLUME_CONTROLLER
+									| PAN_CONTROLLER
+									| EXPRESSION_CONTROLLER
+									| SUSTAIN_CONTROLLER),
+								value,
+							}) => MidiEvent::ControlChange {
+								channel: report.clamp_channel((*channel).max(track_channel), time),
+								controller: *controller,
+								value: *value,
+							},
+							MIDITrackInner::Message(MIDIMessage::ProgramChange {
+								channel,
+								program_number,
+							}) => MidiEvent::ProgramChange {
+								channel: report.clamp_channel((*channel).max(track_channel), time),
+								program: *program_number,
+							},
+							MIDITrackInner::Message(MIDIMessage::PitchWheelChange {
+								channel,
+								value,
+							}) => MidiEvent::PitchBend {
+								channel: report.clamp_channel((*channel).max(track_channel), time),
+								value: (*value).min(16383) as i16 - 8192,
+							},
+							MIDITrackInner::Message(MIDIMessage::ChannelPressure {
+								channel,
+								pressure,
+							}) => MidiEvent::ChannelPressure {
+								channel: report.clamp_channel((*channel).max(track_channel), time),
+								pressure: *pressure,
+							},
+							MIDITrackInner::Message(MIDIMessage::PolyphonicKeyPressure {
+								channel,
+								note,
+								pressure,
+							}) => MidiEvent::PolyphonicKeyPressure {
+								channel: report.clamp_channel((*channel).max(track_channel), time),
 								note: *note,
+								pressure: *pressure,
 							},
 							MIDITrackInner::Meta(meta) if meta.meta_type == 0x51 => {
-								let microseconds_per_beat = u32::from_be_bytes([
-									0,
-									meta.bytes[0],
-									meta.bytes[1],
-									meta.bytes[2],
-								]);
+								let microseconds_per_beat =
+									tempo_microseconds(meta.bytes.borrow())?;
 								let tempo = 60_000_000.0 / microseconds_per_beat as f64;
 								MidiEvent::SetTempo { tempo }
 							}
-							_ => return None,
+							MIDITrackInner::Meta(meta) if meta.meta_type == 0x58 => {
+								let (numerator, denominator) = time_signature(meta.bytes.borrow())?;
+								MidiEvent::TimeSignature {
+									numerator,
+									denominator,
+								}
+							}
+							MIDITrackInner::Meta(meta) if meta.meta_type == 0x59 => {
+								let (sharps_flats, minor) = key_signature(meta.bytes.borrow())?;
+								MidiEvent::KeySignature {
+									sharps_flats,
+									minor,
+								}
+							}
+							MIDITrackInner::Meta(meta) if meta.meta_type == 0x05 => {
+								MidiEvent::Lyric {
+									text: String::from_utf8_lossy(meta.bytes.borrow()).into_owned(),
+								}
+							}
+							MIDITrackInner::Meta(meta) if meta.meta_type == 0x06 => {
+								MidiEvent::Marker {
+									name: String::from_utf8_lossy(meta.bytes.borrow()).into_owned(),
+								}
+							}
+							MIDITrackInner::Meta(meta) if matches!(meta.meta_type, 0x03 | 0x04) => {
+								let source_track = &mut source_tracks[i];
+								let name = if meta.meta_type == 0x03 {
+									&mut source_track.name
+								} else {
+									&mut source_track.instrument_name
+								};
+								name.get_or_insert_with(|| {
+									String::from_utf8_lossy(meta.bytes.borrow()).into_owned()
+								});
+								return None;
+							}
+							// Marks where the track ends, which nothing needs to be kept for
+							MIDITrackInner::Meta(meta) if meta.meta_type == 0x2F => return None,
+							MIDITrackInner::Meta(meta) => {
+								report
+									.unsupported_meta
+									.entry(meta.meta_type)
+									.or_insert(time);
+								return None;
+							}
+							MIDITrackInner::Message(message) => {
+								*report
+									.ignored_events
+									.entry(IgnoredEventKind::from_message(message))
+									.or_default() += 1;
+								return None;
+							}
 						};
-						Some(MidiTrackAccumulateEvent { time, inner })
+						Some(MidiTrackAccumulateEvent {
+							time,
+							inner,
+							source_track: i as u16,
+						})
 					})
 					.collect::<Vec<_>>()
 			})
 			.sorted_by_key(|event| event.time)
 			.collect::<Vec<_>>();
+		if let Some(ticks_per_second) = smpte_ticks_per_second {
+			smpte_to_beat_ticks(&mut events, ticks_per_second);
+		}
 
+		let track = Self {
+			events: events.into(),
+			ticks_per_beat,
+			source_tracks: source_tracks.into(),
+		};
+		Ok((track, report))
+	}
+
+	/// The tick of the last event, where tracks loop or stop
+	pub fn duration_ticks(&self) -> u64 {
+		self.events.last().map_or(0, |event| event.time)
+	}
+
+	/// Every marker with the tick it's at, in order
+	pub fn markers(&self) -> Vec<(u64, String)> {
+		self.events
+			.iter()
+			.filter_map(|event| match &event.inner {
+				MidiEvent::Marker { name } => Some((event.time, name.clone())),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Every lyric from `start_tick` up to but not including `end_tick`, with its tick, in order
+	pub fn lyrics_between(
+		&self,
+		start_tick: u64,
+		end_tick: u64,
+	) -> impl Iterator<Item = (u64, &str)> + '_ {
+		let first = self.events.partition_point(|event| event.time < start_tick);
+		self.events[first..]
+			.iter()
+			.take_while(move |event| event.time < end_tick)
+			.filter_map(|event| match &event.inner {
+				MidiEvent::Lyric { text } => Some((event.time, text.as_str())),
+				_ => None,
+			})
+	}
+
+	/// Each of [`Self::source_tracks`]' names, in the order they're in the file
+	pub fn track_names(&self) -> Vec<Option<String>> {
+		self.source_tracks
+			.iter()
+			.map(|track| track.name.clone())
+			.collect()
+	}
+
+	/// The index of the first source track called `name`, to match against [`MidiTrackAccumulateEvent::source_track`]
+	pub fn source_track_named(&self, name: &str) -> Option<u16> {
+		self.source_tracks
+			.iter()
+			.position(|track| track.name.as_deref() == Some(name))
+			.map(|index| index as u16)
+	}
+
+	/// The file's first key signature, wherever it is, or `None` if it hasn't got one
+	pub fn initial_key(&self) -> Option<Key> {
+		self.keys().next().map(|(_, key)| key)
+	}
+
+	/// The last key signature at or before `tick`, or `None` if there isn't one yet
+	pub fn key_at(&self, tick: u64) -> Option<Key> {
+		self.keys()
+			.take_while(|&(time, _)| time <= tick)
+			.last()
+			.map(|(_, key)| key)
+	}
+
+	/// Every key signature with the tick it's at, in order
+	fn keys(&self) -> impl Iterator<Item = (u64, Key)> + '_ {
+		self.events.iter().filter_map(|event| match event.inner {
+			MidiEvent::KeySignature {
+				sharps_flats,
+				minor,
+			} => Some((event.time, Key::from_sharps_flats(sharps_flats, minor)?)),
+			_ => None,
+		})
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Self {
+		Self::from_bytes_with_report(bytes).0
+	}
+
+	pub fn from_bytes_with_report(bytes: &[u8]) -> (Self, LoadReport) {
+		Self::try_from_bytes_with_report(bytes, &ParseLimits::unlimited())
+			.unwrap_or_else(|error| panic!("Failed to parse MIDI file: {error}"))
+	}
+
+	/// Parses a MIDI file within the default [`ParseLimits`], for files that can't be trusted
+	///
+	/// Input the parser would panic on, like variable-length numbers over four bytes,
+	/// is rejected as [`MidiLoadError::Malformed`] before it's parsed.
+	pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, MidiLoadError> {
+		Self::try_from_bytes_with_report(bytes, &ParseLimits::default()).map(|(track, _)| track)
+	}
+
+	/// Parses a MIDI file, failing before anything is allocated for it if it's over `limits`.
+	/// See [`Self::try_from_bytes`] for how malformed files are caught.
+	pub fn try_from_bytes_with_report(
+		bytes: &[u8],
+		limits: &ParseLimits,
+	) -> Result<(Self, LoadReport), MidiLoadError> {
+		limits.check_chunks(bytes)?;
+		let file = parse_midi_file::<String, Vec<u8>>(bytes)
+			.map_err(|_| MidiLoadError::Malformed)?
+			.1;
+		Self::try_from_midi_file(file, limits)
+	}
+}
+
+/// Retimes events from SMPTE ticks, which count seconds, to [`SMPTE_TICKS_PER_BEAT`] at the file's tempo.
+/// Tracks follow the file's tempo changes as they play, so the events still land at the same time,
+/// and beats and bars line up with the tempo the file was written at.
+fn smpte_to_beat_ticks(events: &mut [MidiTrackAccumulateEvent], ticks_per_second: f64) {
+	// Tracks play at 120 BPM until the file sets a tempo
+	let mut beats_per_second = 2.0;
+	let (mut seconds, mut beat) = (0.0, 0.0);
+	for event in events {
+		let event_seconds = event.time as f64 / ticks_per_second;
+		beat += (event_seconds - seconds) * beats_per_second;
+		seconds = event_seconds;
+		if let MidiEvent::SetTempo { tempo } = event.inner {
+			beats_per_second = tempo / 60.0;
+		}
+		event.time = (beat * SMPTE_TICKS_PER_BEAT as f64).round() as u64;
+	}
+}
+
+/// A set tempo meta event's microseconds per beat, if it's long enough and not zero
+fn tempo_microseconds(bytes: &[u8]) -> Option<u32> {
+	let &[a, b, c, ..] = bytes else {
+		return None;
+	};
+	Some(u32::from_be_bytes([0, a, b, c])).filter(|&microseconds| microseconds > 0)
+}
+
+/// A time signature meta event's numerator and denominator, if it's long enough and makes sense
+fn time_signature(bytes: &[u8]) -> Option<(u8, u8)> {
+	let &[numerator, denominator_power, ..] = bytes else {
+		return None;
+	};
+	let denominator = 1_u8.checked_shl(denominator_power as u32)?;
+	(numerator > 0).then_some((numerator, denominator))
+}
+
+/// Whether a channel message has a data byte with the high bit set, which the parser passes through
+/// rather than rejecting, e.g. a note number of 192
+fn has_bad_data_byte<Buffer: Borrow<[u8]>>(message: &MIDIMessage<Buffer>) -> bool {
+	let data = match message {
+		MIDIMessage::NoteOn(note) | MIDIMessage::NoteOff(note) => [note.note, note.velocity],
+		MIDIMessage::PolyphonicKeyPressure { note, pressure, .. } => [*note, *pressure],
+		MIDIMessage::ControlChange {
+			controller_number,
+			value,
+			..
+		} => [*controller_number, *value],
+		MIDIMessage::ProgramChange { program_number, .. } => [*program_number, 0],
+		MIDIMessage::ChannelPressure { pressure, .. } => [*pressure, 0],
+		_ => return false,
+	};
+	data.iter().any(|byte| byte & 0x80 != 0)
+}
+
+/// A key signature meta event's sharps or flats and whether it's minor, if it's long enough and makes sense
+fn key_signature(bytes: &[u8]) -> Option<(i8, bool)> {
+	let &[sharps_flats, minor, ..] = bytes else {
+		return None;
+	};
+	let sharps_flats = sharps_flats as i8;
+	((-7..=7).contains(&sharps_flats) && minor <= 1).then_some((sharps_flats, minor == 1))
+}
+
+/// Caps on the size of a MIDI file, so loading untrusted files can't run out of memory
+///
+/// Parsing takes time and memory in proportion to the file's size, so these bound both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+	/// Defaults to 16 MiB
+	pub max_file_bytes: usize,
+	/// The largest a single chunk may declare itself to be. Defaults to 16 MiB.
+	pub max_chunk_bytes: usize,
+	/// Defaults to 256
+	pub max_tracks: usize,
+	/// Across every track. Defaults to 1,000,000.
+	pub max_events: usize,
+}
+
+impl Default for ParseLimits {
+	fn default() -> Self {
 		Self {
-			events,
-			ticks_per_beat: match file
-				.header()
-				.expect("MIDI file must have a header chunk")
-				.division
-			{
-				MIDIFileDivision::TicksPerQuarterNote {
-					ticks_per_quarter_note,
-				} => ticks_per_quarter_note,
-				_ => panic!("Invalid MIDI file division"),
-			},
+			max_file_bytes: 16 * 1024 * 1024,
+			max_chunk_bytes: 16 * 1024 * 1024,
+			max_tracks: 256,
+			max_events: 1_000_000,
 		}
 	}
+}
 
-	pub fn from_bytes(bytes: &[u8]) -> Self {
-		Self::from_midi_file(
-			parse_midi_file::<String, Vec<u8>>(bytes)
-				.expect("Failed to parse MIDI file")
-				.1,
-		)
+impl ParseLimits {
+	pub fn unlimited() -> Self {
+		Self {
+			max_file_bytes: usize::MAX,
+			max_chunk_bytes: usize::MAX,
+			max_tracks: usize::MAX,
+			max_events: usize::MAX,
+		}
+	}
+
+	/// Walks the chunk headers, checking what they declare before anything is parsed,
+	/// and each track's events for anything the parser would panic on
+	fn check_chunks(&self, bytes: &[u8]) -> Result<(), MidiLoadError> {
+		if bytes.len() > self.max_file_bytes {
+			return Err(MidiLoadError::FileTooLarge);
+		}
+		if !bytes.starts_with(b"MThd") {
+			return Err(MidiLoadError::MissingHeader);
+		}
+		let mut tracks = 0;
+		let mut rest = bytes;
+		while let [a, b, c, d, length @ ..] = rest {
+			let Some(&[l0, l1, l2, l3]) = length.first_chunk() else {
+				break;
+			};
+			let length = u32::from_be_bytes([l0, l1, l2, l3]) as usize;
+			if length > self.max_chunk_bytes {
+				return Err(MidiLoadError::ChunkTooLarge);
+			}
+			let body = rest.get(8..8 + length).ok_or(MidiLoadError::Truncated)?;
+			if [*a, *b, *c, *d] == *b"MTrk" {
+				tracks += 1;
+				if tracks > self.max_tracks {
+					return Err(MidiLoadError::TooManyTracks);
+				}
+				check_track_events(body)?;
+			}
+			rest = &rest[8 + length..];
+		}
+		Ok(())
 	}
 }
 
-#[derive(Debug, Clone)]
+/// The parser shifts each byte of a variable-length number into a `u32` without checking
+/// how many there are, which overflows and panics past four bytes
+const MAX_VARIABLE_LENGTH_BYTES: usize = 4;
+
+/// Walks a track's events the way the parser does, rejecting delta times and lengths over
+/// [`MAX_VARIABLE_LENGTH_BYTES`]. Anything else the parser would reject is left for it to.
+fn check_track_events(mut body: &[u8]) -> Result<(), MidiLoadError> {
+	let mut running_status = None;
+	loop {
+		let Some((_, rest)) = variable_length_number(body)? else {
+			return Ok(());
+		};
+		body = rest;
+		// A meta event, unless it's cut short, in which case the parser takes the 0xFF as a reset
+		if let [0xFF, _, rest @ ..] = body
+			&& let Some((length, rest)) = variable_length_number(rest)?
+			&& let Some(rest) = rest.get(length as usize..)
+		{
+			body = rest;
+			running_status = None;
+		} else {
+			let status = match body {
+				[status @ 0x7F..=0xFF, rest @ ..] => {
+					body = rest;
+					running_status = Some(*status);
+					*status
+				}
+				_ => match running_status {
+					Some(status) => status,
+					None => return Ok(()),
+				},
+			};
+			let data_bytes = match status {
+				0x80..=0xBF | 0xE0..=0xEF | 0xF2 => 2,
+				0xC0..=0xDF | 0xF3 => 1,
+				0xF0 => {
+					let Some(end) = body.iter().position(|&byte| byte == 0xF7) else {
+						return Ok(());
+					};
+					running_status = None;
+					end + 1
+				}
+				_ => 0,
+			};
+			let Some(rest) = body.get(data_bytes..) else {
+				return Ok(());
+			};
+			body = rest;
+		}
+		if body.is_empty() {
+			return Ok(());
+		}
+	}
+}
+
+/// The number at the start of `bytes` and what follows it, or `None` if it runs off the end
+fn variable_length_number(bytes: &[u8]) -> Result<Option<(u32, &[u8])>, MidiLoadError> {
+	let Some(end) = bytes.iter().position(|byte| byte & 0x80 == 0) else {
+		return Ok(None);
+	};
+	if end >= MAX_VARIABLE_LENGTH_BYTES {
+		return Err(MidiLoadError::Malformed);
+	}
+	let number = bytes[..=end]
+		.iter()
+		.fold(0, |number, byte| number << 7 | (byte & 0x7F) as u32);
+	Ok(Some((number, &bytes[end + 1..])))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MidiLoadError {
+	/// Not a MIDI file, cut short, or rejected by the parser, e.g. for an overlong delta time
+	Malformed,
+	/// Doesn't start with a header chunk
+	MissingHeader,
+	/// A chunk is longer than what's left of the file
+	Truncated,
+	/// Zero ticks per quarter note or per frame, or a SMPTE frame rate other than 24, 25, 29.97 or 30
+	UnsupportedDivision,
+	/// A channel message with a data byte over 127
+	BadMessage,
+	FileTooLarge,
+	ChunkTooLarge,
+	TooManyTracks,
+	TooManyEvents,
+}
+
+impl std::fmt::Display for MidiLoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Malformed => write!(f, "not a valid MIDI file"),
+			Self::MissingHeader => write!(f, "missing header chunk"),
+			Self::Truncated => write!(f, "a chunk runs past the end of the file"),
+			Self::UnsupportedDivision => write!(f, "unsupported time division"),
+			Self::BadMessage => write!(f, "a message has a data byte over 127"),
+			Self::FileTooLarge => write!(f, "file is over the size limit"),
+			Self::ChunkTooLarge => write!(f, "a chunk is over the size limit"),
+			Self::TooManyTracks => write!(f, "more tracks than the limit"),
+			Self::TooManyEvents => write!(f, "more events than the limit"),
+		}
+	}
+}
+
+impl std::error::Error for MidiLoadError {}
+
+/// Everything that was dropped or adjusted while loading a [`MidiTrack`]
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+	/// How many messages of each unsupported kind were skipped
+	pub ignored_events: BTreeMap<IgnoredEventKind, usize>,
+	/// Unsupported meta event types, with the tick they first occurred at
+	pub unsupported_meta: BTreeMap<u8, u64>,
+	pub clamped_values: Vec<ClampedValue>,
+}
+
+impl LoadReport {
+	pub fn is_empty(&self) -> bool {
+		self.ignored_events.is_empty()
+			&& self.unsupported_meta.is_empty()
+			&& self.clamped_values.is_empty()
+	}
+
+	/// Logs a one-line summary of the report, if there's anything in it
+	pub fn warn_summary(&self) {
+		if self.is_empty() {
+			return;
+		}
+		let ignored = self
+			.ignored_events
+			.iter()
+			.map(|(kind, count)| format!("{count}x {kind:?}"))
+			.join(", ");
+		let metas = self
+			.unsupported_meta
+			.iter()
+			.map(|(meta_type, tick)| format!("0x{meta_type:02X} (first at tick {tick})"))
+			.join(", ");
+		warn!(
+			"MIDI file contains unsupported content: ignored events [{ignored}], unsupported metas [{metas}], {} clamped values",
+			self.clamped_values.len()
+		);
+	}
+
+	fn clamp_channel(&mut self, channel: u8, tick: u64) -> u8 {
+		if channel > 15 {
+			self.clamped_values.push(ClampedValue {
+				tick,
+				kind: ClampedValueKind::Channel,
+				original: channel,
+				clamped: 15,
+			});
+			15
+		} else {
+			channel
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum IgnoredEventKind {
+	PolyphonicKeyPressure,
+	ControlChange,
+	ProgramChange,
+	ChannelPressure,
+	PitchWheel,
+	SysEx,
+	System,
+}
+
+impl IgnoredEventKind {
+	fn from_message<Buffer: Borrow<[u8]>>(message: &MIDIMessage<Buffer>) -> Self {
+		match message {
+			MIDIMessage::PolyphonicKeyPressure { .. } => Self::PolyphonicKeyPressure,
+			MIDIMessage::ControlChange { .. } => Self::ControlChange,
+			MIDIMessage::ProgramChange { .. } => Self::ProgramChange,
+			MIDIMessage::ChannelPressure { .. } => Self::ChannelPressure,
+			MIDIMessage::PitchWheelChange { .. } => Self::PitchWheel,
+			MIDIMessage::SysExMessage(_) => Self::SysEx,
+			_ => Self::System,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClampedValue {
+	pub tick: u64,
+	pub kind: ClampedValueKind,
+	pub original: u8,
+	pub clamped: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClampedValueKind {
+	Channel,
+}
+
+/// The controller number of bank select, which MIDI files are read for and channels respond to.
+/// SoundFont banks are a single number, so the fine bank select after it is ignored.
+pub const BANK_SELECT_CONTROLLER: u8 = 0;
+
+/// The controller number of channel volume, which MIDI files are read for and channels respond to
+pub const VOLUME_CONTROLLER: u8 = 7;
+
+/// The controller number of pan, which MIDI files are read for and channels respond to
+pub const PAN_CONTROLLER: u8 = 10;
+
+/// The controller number of expression, which MIDI files are read for and channels respond to
+pub const EXPRESSION_CONTROLLER: u8 = 11;
+
+/// The controller number of the sustain pedal, which MIDI files are read for and channels respond to.
+/// 64 and up holds notes after they're released, below 64 lets them go.
+pub const SUSTAIN_CONTROLLER: u8 = 64;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum MidiEvent {
-	NoteOn { channel: u8, note: u8, velocity: u8 },
-	NoteOff { channel: u8, note: u8 },
-	SetTempo { tempo: f64 },
+	NoteOn {
+		channel: u8,
+		note: u8,
+		velocity: u8,
+	},
+	NoteOff {
+		channel: u8,
+		note: u8,
+	},
+	SetTempo {
+		tempo: f64,
+	},
+	/// Only [`BANK_SELECT_CONTROLLER`], [`VOLUME_CONTROLLER`], [`PAN_CONTROLLER`], [`EXPRESSION_CONTROLLER`]
+	/// and [`SUSTAIN_CONTROLLER`] do anything, other controllers are ignored
+	ControlChange {
+		channel: u8,
+		controller: u8,
+		value: u8,
+	},
+	/// Switches the channel to another preset in its bank, from the next note it plays
+	ProgramChange {
+		channel: u8,
+		program: u8,
+	},
+	/// Bends every note on the channel, from -8192 for fully down to 8191 for fully up,
+	/// over the channel's bend range, see [`crate::MidiAudioTrack::set_channel_bend_range`]
+	PitchBend {
+		channel: u8,
+		value: i16,
+	},
+	/// Aftertouch on every note on the channel, from 0 to 127, which scales its volume
+	/// as deep as the channel's aftertouch depth, see [`crate::MidiAudioTrack::set_channel_aftertouch_depth`]
+	ChannelPressure {
+		channel: u8,
+		pressure: u8,
+	},
+	/// Like [`Self::ChannelPressure`], for one key only
+	PolyphonicKeyPressure {
+		channel: u8,
+		note: u8,
+		pressure: u8,
+	},
+	/// A new meter, e.g. 6/8 is a numerator of 6 and a denominator of 8
+	TimeSignature {
+		numerator: u8,
+		denominator: u8,
+	},
+	/// A new key, with sharps counted up from C major or A minor and flats counted down, see [`Key::from_sharps_flats`].
+	/// Doesn't change how anything plays.
+	KeySignature {
+		sharps_flats: i8,
+		minor: bool,
+	},
+	/// A named point in the song, e.g. `chorus`, which [`crate::MidiQueueTiming::Marker`] waits for
+	Marker {
+		name: String,
+	},
+	/// A syllable or word to sing, as written in the file, with any invalid UTF-8 replaced
+	Lyric {
+		text: String,
+	},
+}
+
+impl Hash for MidiEvent {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			MidiEvent::NoteOn {
+				channel,
+				note,
+				velocity,
+			} => (channel, note, velocity).hash(state),
+			MidiEvent::NoteOff { channel, note } => (channel, note).hash(state),
+			MidiEvent::SetTempo { tempo } => tempo.to_bits().hash(state),
+			MidiEvent::ControlChange {
+				channel,
+				controller,
+				value,
+			} => (channel, controller, value).hash(state),
+			MidiEvent::ProgramChange { channel, program } => (channel, program).hash(state),
+			MidiEvent::PitchBend { channel, value } => (channel, value).hash(state),
+			MidiEvent::ChannelPressure { channel, pressure } => (channel, pressure).hash(state),
+			MidiEvent::PolyphonicKeyPressure {
+				channel,
+				note,
+				pressure,
+			} => (channel, note, pressure).hash(state),
+			MidiEvent::TimeSignature {
+				numerator,
+				denominator,
+			} => (numerator, denominator).hash(state),
+			MidiEvent::KeySignature {
+				sharps_flats,
+				minor,
+			} => (sharps_flats, minor).hash(state),
+			MidiEvent::Marker { name } => name.hash(state),
+			MidiEvent::Lyric { text } => text.hash(state),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A format 0 file at 480 ticks per beat with one track of `events`
+	fn file(events: &[u8]) -> Vec<u8> {
+		let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xE0MTrk".to_vec();
+		bytes.extend((events.len() as u32).to_be_bytes());
+		bytes.extend(events);
+		bytes
+	}
+
+	#[test]
+	fn loads_a_valid_file() {
+		let track =
+			MidiTrack::try_from_bytes(&file(b"\0\x90\x40\x40\x83\x60\x80\x40\0\0\xFF\x2F\0"))
+				.unwrap();
+		assert_eq!(track.ticks_per_beat, 480);
+		assert_eq!(track.events.len(), 2);
+		assert_eq!(track.events[1].time, 480);
+	}
+
+	#[test]
+	fn missing_header() {
+		assert_eq!(
+			MidiTrack::try_from_bytes(b"RIFF\0\0\0\0").err(),
+			Some(MidiLoadError::MissingHeader)
+		);
+		assert_eq!(
+			MidiTrack::try_from_bytes(b"").err(),
+			Some(MidiLoadError::MissingHeader)
+		);
+	}
+
+	#[test]
+	fn truncated_track() {
+		let bytes = file(b"\0\x90\x40\x40\0\xFF\x2F\0");
+		assert_eq!(
+			MidiTrack::try_from_bytes(&bytes[..bytes.len() - 3]).err(),
+			Some(MidiLoadError::Truncated)
+		);
+	}
+
+	#[test]
+	fn unsupported_division() {
+		let mut bytes = file(b"\0\xFF\x2F\0");
+		bytes[12..14].copy_from_slice(&[0, 0]);
+		assert_eq!(
+			MidiTrack::try_from_bytes(&bytes).err(),
+			Some(MidiLoadError::UnsupportedDivision)
+		);
+		// SMPTE at 23 frames per second
+		bytes[12..14].copy_from_slice(&[-23_i8 as u8, 40]);
+		assert_eq!(
+			MidiTrack::try_from_bytes(&bytes).err(),
+			Some(MidiLoadError::UnsupportedDivision)
+		);
+	}
+
+	#[test]
+	fn bad_short_message() {
+		assert_eq!(
+			MidiTrack::try_from_bytes(&file(b"\0\x90\xC0\x40\0\xFF\x2F\0")).err(),
+			Some(MidiLoadError::BadMessage)
+		);
+		assert_eq!(
+			MidiTrack::try_from_bytes(&file(b"\0\xB0\x07\xFF\0\xFF\x2F\0")).err(),
+			Some(MidiLoadError::BadMessage)
+		);
+	}
+
+	#[test]
+	fn skips_sysex() {
+		let track =
+			MidiTrack::try_from_bytes(&file(b"\0\xF0\x03\x01\x02\xF7\0\x90\x40\x40\0\xFF\x2F\0"))
+				.unwrap();
+		assert_eq!(track.events.len(), 1);
+	}
+
+	#[test]
+	fn malformed_events() {
+		// Cut off partway through a note on
+		assert_eq!(
+			MidiTrack::try_from_bytes(&file(b"\0\x90\x40")).err(),
+			Some(MidiLoadError::Malformed)
+		);
+		// A data byte with no running status to go with it
+		assert_eq!(
+			MidiTrack::try_from_bytes(&file(b"\0\x40\x40\x40")).err(),
+			Some(MidiLoadError::Malformed)
+		);
+	}
+
+	/// A note on, a note off and the end of the track, then a second track with just its end
+	fn two_tracks() -> Vec<u8> {
+		let mut bytes = file(b"\0\x90\x40\x40\0\x80\x40\0\0\xFF\x2F\0");
+		bytes[11] = 2;
+		bytes.extend(b"MTrk\0\0\0\x04\0\xFF\x2F\0");
+		bytes
+	}
+
+	fn parse_within(limits: ParseLimits) -> Option<MidiLoadError> {
+		MidiTrack::try_from_bytes_with_report(&two_tracks(), &limits).err()
+	}
+
+	#[test]
+	fn file_size_limit() {
+		let size = two_tracks().len();
+		let at = |max_file_bytes| {
+			parse_within(ParseLimits {
+				max_file_bytes,
+				..ParseLimits::default()
+			})
+		};
+		assert_eq!(at(size), None);
+		assert_eq!(at(size - 1), Some(MidiLoadError::FileTooLarge));
+	}
+
+	#[test]
+	fn chunk_size_limit() {
+		let at = |max_chunk_bytes| {
+			parse_within(ParseLimits {
+				max_chunk_bytes,
+				..ParseLimits::default()
+			})
+		};
+		// The first track is the largest chunk
+		assert_eq!(at(12), None);
+		assert_eq!(at(11), Some(MidiLoadError::ChunkTooLarge));
+	}
+
+	#[test]
+	fn track_limit() {
+		let at = |max_tracks| {
+			parse_within(ParseLimits {
+				max_tracks,
+				..ParseLimits::default()
+			})
+		};
+		assert_eq!(at(2), None);
+		assert_eq!(at(1), Some(MidiLoadError::TooManyTracks));
+	}
+
+	#[test]
+	fn event_limit() {
+		let at = |max_events| {
+			parse_within(ParseLimits {
+				max_events,
+				..ParseLimits::default()
+			})
+		};
+		// Counting both ends of tracks
+		assert_eq!(at(4), None);
+		assert_eq!(at(3), Some(MidiLoadError::TooManyEvents));
+	}
+
+	#[test]
+	fn overlong_variable_length_numbers() {
+		// Four bytes is as long as they get
+		assert!(
+			MidiTrack::try_from_bytes(&file(b"\x81\x80\x80\0\x90\x40\x40\0\xFF\x2F\0")).is_ok()
+		);
+		for events in [
+			// Six bytes, which would overflow the parser's shift
+			&b"\x81\x80\x80\x80\x80\0\x90\x40\x40\0\xFF\x2F\0"[..],
+			// A five byte delta time
+			b"\x81\x80\x80\x80\0\x90\x40\x40\0\xFF\x2F\0",
+			// A five byte meta event length
+			b"\0\xFF\x01\x81\x80\x80\x80\0\0\xFF\x2F\0",
+			// A five byte delta time after a note in running status
+			b"\0\x90\x40\x40\0\x40\0\x81\x80\x80\x80\0\xFF\x2F\0",
+			// After a SysEx message
+			b"\0\xF0\x01\xF7\xFF\xFF\xFF\xFF\x7F\xFF\x2F\0",
+		] {
+			assert_eq!(
+				MidiTrack::try_from_bytes(&file(events)).err(),
+				Some(MidiLoadError::Malformed),
+				"{events:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_the_crafted_fuzz_seeds() {
+		for (bytes, error) in [
+			(
+				&include_bytes!("../fuzz/corpus/midi_track/overlong_delta.mid")[..],
+				MidiLoadError::Malformed,
+			),
+			(
+				include_bytes!("../fuzz/corpus/midi_track/overlong_meta_length.mid"),
+				MidiLoadError::Malformed,
+			),
+			(
+				include_bytes!("../fuzz/corpus/midi_track/huge_chunk.mid"),
+				MidiLoadError::ChunkTooLarge,
+			),
+			(
+				include_bytes!("../fuzz/corpus/midi_track/truncated_track.mid"),
+				MidiLoadError::Truncated,
+			),
+		] {
+			assert_eq!(MidiTrack::try_from_bytes(bytes).err(), Some(error));
+		}
+	}
+
+	#[test]
+	fn reports_skipped_content() {
+		// A SysEx message, then a sequencer-specific meta event and a modulation wheel change a beat in
+		let (track, report) = MidiTrack::try_from_bytes_with_report(
+			&file(b"\0\xF0\x03\x01\x02\xF7\0\x90\x40\x40\x83\x60\xFF\x7F\x02\x00\x01\0\xB0\x01\x40\0\xF0\x01\xF7\0\xFF\x2F\0"),
+			&ParseLimits::default(),
+		)
+		.unwrap();
+		assert_eq!(track.events.len(), 1);
+		assert_eq!(report.ignored_events[&IgnoredEventKind::SysEx], 2);
+		assert_eq!(report.ignored_events[&IgnoredEventKind::ControlChange], 1);
+		assert_eq!(report.unsupported_meta, BTreeMap::from([(0x7F, 480)]));
+		assert!(report.clamped_values.is_empty());
+		assert!(!report.is_empty());
+	}
 }
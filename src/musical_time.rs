@@ -0,0 +1,113 @@
+/// The rate music is currently moving at, as seen by a track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicalTime {
+	/// Including any playback rate changes
+	pub beats_per_second: f64,
+	pub beats_per_bar: f64,
+	pub samples_per_second: f64,
+}
+
+impl MusicalTime {
+	pub fn samples_per_beat(&self) -> f64 {
+		self.samples_per_second / self.beats_per_second
+	}
+}
+
+/// Something that caches beat lengths in samples, e.g. a tempo-synced effect,
+/// and needs to rescale them when the tempo or meter changes
+///
+/// Listeners are notified inside the render tick, before the changed frame is rendered.
+pub trait MusicalTimeListener: Send + Sync {
+	fn musical_time_changed(&mut self, old: &MusicalTime, new: &MusicalTime);
+}
+
+/// A repeating period measured in beats and counted in samples,
+/// which keeps its phase when the tempo changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatPeriod {
+	pub period_beats: f64,
+	samples_per_period: f64,
+	position_samples: f64,
+}
+
+impl BeatPeriod {
+	pub fn new(period_beats: f64, time: &MusicalTime) -> Self {
+		Self {
+			period_beats,
+			samples_per_period: period_beats * time.samples_per_beat(),
+			position_samples: 0.0,
+		}
+	}
+
+	/// Advances one sample, returning true when a new period starts
+	pub fn tick(&mut self) -> bool {
+		self.position_samples += 1.0;
+		if self.position_samples >= self.samples_per_period {
+			self.position_samples -= self.samples_per_period;
+			true
+		} else {
+			false
+		}
+	}
+
+	/// How far through the current period we are, in 0..1
+	pub fn phase(&self) -> f64 {
+		self.position_samples / self.samples_per_period
+	}
+
+	pub fn samples_per_period(&self) -> f64 {
+		self.samples_per_period
+	}
+}
+
+impl MusicalTimeListener for BeatPeriod {
+	fn musical_time_changed(&mut self, _old: &MusicalTime, new: &MusicalTime) {
+		let phase = self.phase();
+		self.samples_per_period = self.period_beats * new.samples_per_beat();
+		self.position_samples = phase * self.samples_per_period;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn at_tempo(beats_per_minute: f64) -> MusicalTime {
+		MusicalTime {
+			beats_per_second: beats_per_minute / 60.0,
+			beats_per_bar: 4.0,
+			samples_per_second: 44100.0,
+		}
+	}
+
+	#[test]
+	fn periods_count_beats_in_samples() {
+		let mut period = BeatPeriod::new(0.5, &at_tempo(120.0));
+		assert_eq!(period.samples_per_period(), 11025.0);
+		let starts = (0..44100).filter(|_| period.tick()).count();
+		assert_eq!(starts, 4);
+	}
+
+	#[test]
+	fn tempo_changes_keep_the_phase() {
+		let old = at_tempo(120.0);
+		let mut period = BeatPeriod::new(1.0, &old);
+		for _ in 0..5512 {
+			period.tick();
+		}
+		let phase = period.phase();
+		assert!((phase - 0.25).abs() < 0.001);
+
+		let new = at_tempo(60.0);
+		period.musical_time_changed(&old, &new);
+		assert_eq!(period.samples_per_period(), 44100.0);
+		assert_eq!(period.phase(), phase);
+
+		// The rest of the period takes as long as it should at the new tempo
+		let remaining = (1..).find(|_| period.tick()).unwrap();
+		assert!(
+			(remaining as f64 - 0.75 * 44100.0).abs() <= 1.0,
+			"{remaining}"
+		);
+	}
+}
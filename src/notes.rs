@@ -1,6 +1,18 @@
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use bevy::reflect::Reflect;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Chord, Key, Scale};
+
+/// Ordered from C up to B, like the notes of an octave
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum NoteLetter {
 	C,
 	D,
@@ -11,10 +23,165 @@ pub enum NoteLetter {
 	B,
 }
 
+impl NoteLetter {
+	/// Semitones above C
+	fn semitone(self) -> i16 {
+		match self {
+			Self::C => 0,
+			Self::D => 2,
+			Self::E => 4,
+			Self::F => 5,
+			Self::G => 7,
+			Self::A => 9,
+			Self::B => 11,
+		}
+	}
+
+	fn next(self) -> Self {
+		match self {
+			Self::C => Self::D,
+			Self::D => Self::E,
+			Self::E => Self::F,
+			Self::F => Self::G,
+			Self::G => Self::A,
+			Self::A => Self::B,
+			Self::B => Self::C,
+		}
+	}
+}
+
+/// How a note's letter is raised or lowered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Accidental {
+	#[default]
+	Natural,
+	Sharp,
+	/// The constants are all spelled with sharps, see [`Note::enharmonic_flat`]
+	Flat,
+}
+
+impl Accidental {
+	/// Semitones above the letter
+	fn semitones(self) -> i16 {
+		match self {
+			Self::Natural => 0,
+			Self::Sharp => 1,
+			Self::Flat => -1,
+		}
+	}
+
+	fn symbol(self) -> &'static str {
+		match self {
+			Self::Natural => "",
+			Self::Sharp => "#",
+			Self::Flat => "b",
+		}
+	}
+}
+
+/// The distance between two notes, up to an octave
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interval {
+	Unison,
+	MinorSecond,
+	MajorSecond,
+	MinorThird,
+	MajorThird,
+	PerfectFourth,
+	Tritone,
+	PerfectFifth,
+	MinorSixth,
+	MajorSixth,
+	MinorSeventh,
+	MajorSeventh,
+	Octave,
+}
+
+impl Interval {
+	const ALL: [Interval; 13] = [
+		Self::Unison,
+		Self::MinorSecond,
+		Self::MajorSecond,
+		Self::MinorThird,
+		Self::MajorThird,
+		Self::PerfectFourth,
+		Self::Tritone,
+		Self::PerfectFifth,
+		Self::MinorSixth,
+		Self::MajorSixth,
+		Self::MinorSeventh,
+		Self::MajorSeventh,
+		Self::Octave,
+	];
+
+	pub fn semitones(self) -> i8 {
+		self as i8
+	}
+
+	/// `None` if it's more than an octave
+	pub fn from_semitones(semitones: u8) -> Option<Self> {
+		Self::ALL.get(semitones as usize).copied()
+	}
+
+	/// The interval between two notes in either direction, `None` if they're more than an octave apart
+	pub fn between(a: &Note, b: &Note) -> Option<Self> {
+		u8::try_from(a.interval_abs_semitones(b))
+			.ok()
+			.and_then(Self::from_semitones)
+	}
+}
+
+/// Ways of writing a note's name, see [`Note::format`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NoteNameStyle {
+	/// Like [`Display`], e.g. `F#3`, with middle C as C4
+	#[default]
+	Scientific,
+	/// French fixed do, e.g. `Fa♯2`, with middle C as Do3
+	Solfege,
+	/// LilyPond's absolute pitches in Dutch, e.g. `fis`, with middle C as `c'`.
+	/// Each `'` is an octave above C3, and each `,` an octave below.
+	Lilypond,
+}
+
+/// The pitch notes are tuned to, in equal temperament. [`Note`]'s frequencies are for
+/// the default, with A4 at 440 Hz.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tuning {
+	/// The frequency of A4, in Hz
+	pub a4: f32,
+}
+
+impl Default for Tuning {
+	fn default() -> Self {
+		Self::new(440.0)
+	}
+}
+
+impl Tuning {
+	pub fn new(a4_hz: f32) -> Self {
+		Self { a4: a4_hz }
+	}
+
+	/// In Hz
+	pub fn frequency(&self, note: Note) -> f32 {
+		self.a4 * 2_f32.powf((note.midi_number() as f32 - 69.0) / 12.0)
+	}
+
+	/// How much higher every note is than with A4 at 440 Hz
+	pub fn ratio(&self) -> f32 {
+		self.a4 / 440.0
+	}
+}
+
+/// Notes are equal when they're the same pitch, however they're spelled
+#[derive(Debug, Clone, Copy, Reflect)]
+#[reflect(Debug, PartialEq, Hash)]
 pub struct Note {
 	pub note_letter: NoteLetter,
-	pub sharp: bool,
+	pub accidental: Accidental,
 	pub octave: i8,
 
 	/// In Hz
@@ -27,780 +194,1191 @@ impl Display for Note {
 			f,
 			"{:?}{}{}",
 			self.note_letter,
-			if self.sharp { "#" } else { "" },
+			self.accidental.symbol(),
 			self.octave
 		)
 	}
 }
 
+impl PartialEq for Note {
+	fn eq(&self, other: &Self) -> bool {
+		self.midi_number() == other.midi_number()
+	}
+}
+
+impl Eq for Note {}
+
+/// Ordered by pitch, so both spellings of a note are equal
+impl PartialOrd for Note {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Note {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.midi_number().cmp(&other.midi_number())
+	}
+}
+
+/// Hashes the pitch like [`PartialEq`] compares it, so both spellings of a note are the same key
+impl Hash for Note {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.midi_number().hash(state);
+	}
+}
+
+/// Parses names like `C4`, `F#3`, `as5`, `Bb5` or `C-1`: a letter, an optional `#` or `s` for sharp
+/// or `b` or `♭` for flat, and an octave. The letter and sharp are case-insensitive.
+/// Flats keep their spelling, and are equal to the sharp with the same pitch, e.g. `Bb5` and [`Note::AS5`].
+impl FromStr for Note {
+	type Err = NoteParseError;
+
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		let mut chars = name.chars();
+		let letter = chars.next().ok_or(NoteParseError::Empty)?;
+		let note_letter = match letter.to_ascii_uppercase() {
+			'C' => NoteLetter::C,
+			'D' => NoteLetter::D,
+			'E' => NoteLetter::E,
+			'F' => NoteLetter::F,
+			'G' => NoteLetter::G,
+			'A' => NoteLetter::A,
+			'B' => NoteLetter::B,
+			_ => return Err(NoteParseError::InvalidLetter(letter)),
+		};
+		let rest = chars.as_str();
+		let (accidental, octave) = if let Some(octave) = rest.strip_prefix(['#', 's', 'S']) {
+			(Accidental::Sharp, octave)
+		} else if let Some(octave) = rest.strip_prefix(['b', '♭']) {
+			(Accidental::Flat, octave)
+		} else {
+			(Accidental::Natural, rest)
+		};
+		let octave = octave
+			.parse::<i8>()
+			.map_err(|_| NoteParseError::InvalidOctave(octave.to_string()))?;
+		let number = (octave as i16 + 1) * 12 + note_letter.semitone() + accidental.semitones();
+		if !(0..=127).contains(&number) {
+			return Err(NoteParseError::OutOfRange);
+		}
+		let note = Self::from_midi_number(number as u8);
+		if accidental == Accidental::Flat {
+			return Ok(Self {
+				note_letter,
+				accidental,
+				octave,
+				..note
+			});
+		}
+		Ok(note)
+	}
+}
+
+/// Serializes as its name, e.g. `"F#3"`, so the frequency can't disagree with the pitch
+#[cfg(feature = "serde")]
+impl Serialize for Note {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Note {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		String::deserialize(deserializer)?
+			.parse()
+			.map_err(serde::de::Error::custom)
+	}
+}
+
+/// The notes between two bounds, spelled with sharps, see [`Note::range`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteRange(RangeInclusive<u8>);
+
+impl NoteRange {
+	/// Only the notes without sharps, the white keys of a piano
+	pub fn naturals(self) -> impl DoubleEndedIterator<Item = Note> {
+		self.filter(|note| note.accidental == Accidental::Natural)
+	}
+}
+
+impl Iterator for NoteRange {
+	type Item = Note;
+
+	fn next(&mut self) -> Option<Note> {
+		self.0.next().map(Note::from_midi_number)
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.0.size_hint()
+	}
+}
+
+impl DoubleEndedIterator for NoteRange {
+	fn next_back(&mut self) -> Option<Note> {
+		self.0.next_back().map(Note::from_midi_number)
+	}
+}
+
+impl ExactSizeIterator for NoteRange {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoteParseError {
+	Empty,
+	/// Not one of A to G
+	InvalidLetter(char),
+	InvalidOctave(String),
+	/// Outside C-1 to G9
+	OutOfRange,
+}
+
+impl std::fmt::Display for NoteParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Empty => write!(f, "empty note name"),
+			Self::InvalidLetter(letter) => write!(f, "{letter:?} isn't a note letter"),
+			Self::InvalidOctave(octave) => write!(f, "{octave:?} isn't an octave"),
+			Self::OutOfRange => write!(f, "outside the range of midi notes"),
+		}
+	}
+}
+
+impl std::error::Error for NoteParseError {}
+
 impl Note {
 	pub const CN1: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 8.175,
 	};
 	pub const CSN1: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: -1,
 		frequency: 8.661,
 	};
+	pub const DBN1: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CSN1
+	};
 	pub const DN1: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 9.176,
 	};
 	pub const DSN1: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: -1,
 		frequency: 9.722,
 	};
+	pub const EBN1: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DSN1
+	};
 	pub const EN1: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 10.30,
 	};
 	pub const FN1: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 10.91,
 	};
 	pub const FSN1: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: -1,
 		frequency: 11.56,
 	};
+	pub const GBN1: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FSN1
+	};
 	pub const GN1: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 12.25,
 	};
 	pub const GSN1: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: -1,
 		frequency: 12.98,
 	};
+	pub const ABN1: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GSN1
+	};
 	pub const AN1: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 13.75,
 	};
 	pub const ASN1: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: -1,
 		frequency: 14.57,
 	};
+	pub const BBN1: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::ASN1
+	};
 	pub const BN1: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: -1,
 		frequency: 15.43,
 	};
 	pub const C0: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 16.35,
 	};
 	pub const CS0: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 0,
 		frequency: 17.32,
 	};
+	pub const DB0: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS0
+	};
 	pub const D0: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 18.35,
 	};
 	pub const DS0: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 0,
 		frequency: 19.45,
 	};
+	pub const EB0: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS0
+	};
 	pub const E0: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 20.60,
 	};
 	pub const F0: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 21.83,
 	};
 	pub const FS0: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 0,
 		frequency: 23.12,
 	};
+	pub const GB0: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS0
+	};
 	pub const G0: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 24.50,
 	};
 	pub const GS0: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 0,
 		frequency: 25.96,
 	};
+	pub const AB0: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS0
+	};
 	pub const A0: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 27.50,
 	};
 	pub const AS0: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 0,
 		frequency: 29.14,
 	};
+	pub const BB0: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS0
+	};
 	pub const B0: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 0,
 		frequency: 30.87,
 	};
 	pub const C1: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 32.70,
 	};
 	pub const CS1: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 1,
 		frequency: 34.65,
 	};
+	pub const DB1: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS1
+	};
 	pub const D1: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 36.71,
 	};
 	pub const DS1: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 1,
 		frequency: 38.89,
 	};
+	pub const EB1: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS1
+	};
 	pub const E1: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 41.20,
 	};
 	pub const F1: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 43.65,
 	};
 	pub const FS1: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 1,
 		frequency: 46.25,
 	};
+	pub const GB1: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS1
+	};
 	pub const G1: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 49.00,
 	};
 	pub const GS1: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 1,
 		frequency: 51.91,
 	};
+	pub const AB1: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS1
+	};
 	pub const A1: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 55.00,
 	};
 	pub const AS1: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 1,
 		frequency: 58.27,
 	};
+	pub const BB1: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS1
+	};
 	pub const B1: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 1,
 		frequency: 61.74,
 	};
 	pub const C2: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 65.41,
 	};
 	pub const CS2: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 2,
 		frequency: 69.30,
 	};
+	pub const DB2: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS2
+	};
 	pub const D2: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 73.42,
 	};
 	pub const DS2: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 2,
 		frequency: 77.78,
 	};
+	pub const EB2: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS2
+	};
 	pub const E2: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 82.41,
 	};
 	pub const F2: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 87.31,
 	};
 	pub const FS2: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 2,
 		frequency: 92.50,
 	};
+	pub const GB2: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS2
+	};
 	pub const G2: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 98.00,
 	};
 	pub const GS2: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 2,
 		frequency: 103.83,
 	};
+	pub const AB2: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS2
+	};
 	pub const A2: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 110.00,
 	};
 	pub const AS2: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 2,
 		frequency: 116.54,
 	};
+	pub const BB2: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS2
+	};
 	pub const B2: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 2,
 		frequency: 123.47,
 	};
 	pub const C3: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 130.81,
 	};
 	pub const CS3: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 3,
 		frequency: 138.59,
 	};
+	pub const DB3: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS3
+	};
 	pub const D3: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 146.83,
 	};
 	pub const DS3: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 3,
 		frequency: 155.56,
 	};
+	pub const EB3: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS3
+	};
 	pub const E3: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 164.81,
 	};
 	pub const F3: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 174.61,
 	};
 	pub const FS3: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 3,
 		frequency: 185.00,
 	};
+	pub const GB3: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS3
+	};
 	pub const G3: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 196.00,
 	};
 	pub const GS3: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 3,
 		frequency: 207.65,
 	};
+	pub const AB3: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS3
+	};
 	pub const A3: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 220.00,
 	};
 	pub const AS3: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 3,
 		frequency: 233.08,
 	};
+	pub const BB3: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS3
+	};
 	pub const B3: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 3,
 		frequency: 246.94,
 	};
 	pub const C4: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 261.63,
 	};
 	pub const CS4: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 4,
 		frequency: 277.18,
 	};
+	pub const DB4: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS4
+	};
 	pub const D4: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 293.66,
 	};
 	pub const DS4: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 4,
 		frequency: 311.13,
 	};
+	pub const EB4: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS4
+	};
 	pub const E4: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 329.63,
 	};
 	pub const F4: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 349.23,
 	};
 	pub const FS4: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 4,
 		frequency: 369.99,
 	};
+	pub const GB4: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS4
+	};
 	pub const G4: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 392.00,
 	};
 	pub const GS4: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 4,
 		frequency: 415.30,
 	};
+	pub const AB4: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS4
+	};
 	pub const A4: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 440.00,
 	};
 	pub const AS4: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 4,
 		frequency: 466.16,
 	};
+	pub const BB4: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS4
+	};
 	pub const B4: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 4,
 		frequency: 493.88,
 	};
 	pub const C5: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 523.25,
 	};
 	pub const CS5: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 5,
 		frequency: 554.37,
 	};
+	pub const DB5: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS5
+	};
 	pub const D5: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 587.33,
 	};
 	pub const DS5: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 5,
 		frequency: 622.25,
 	};
+	pub const EB5: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS5
+	};
 	pub const E5: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 659.25,
 	};
 	pub const F5: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 698.46,
 	};
 	pub const FS5: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 5,
 		frequency: 739.99,
 	};
+	pub const GB5: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS5
+	};
 	pub const G5: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 783.99,
 	};
 	pub const GS5: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 5,
 		frequency: 830.61,
 	};
+	pub const AB5: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS5
+	};
 	pub const A5: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 880.00,
 	};
 	pub const AS5: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 5,
 		frequency: 932.33,
 	};
+	pub const BB5: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS5
+	};
 	pub const B5: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 5,
 		frequency: 987.77,
 	};
 	pub const C6: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1046.50,
 	};
 	pub const CS6: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 6,
 		frequency: 1108.73,
 	};
+	pub const DB6: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS6
+	};
 	pub const D6: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1174.66,
 	};
 	pub const DS6: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 6,
 		frequency: 1244.51,
 	};
+	pub const EB6: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS6
+	};
 	pub const E6: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1318.51,
 	};
 	pub const F6: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1396.91,
 	};
 	pub const FS6: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 6,
 		frequency: 1479.98,
 	};
+	pub const GB6: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS6
+	};
 	pub const G6: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1567.98,
 	};
 	pub const GS6: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 6,
 		frequency: 1661.22,
 	};
+	pub const AB6: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS6
+	};
 	pub const A6: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1760.00,
 	};
 	pub const AS6: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 6,
 		frequency: 1864.66,
 	};
+	pub const BB6: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS6
+	};
 	pub const B6: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 6,
 		frequency: 1975.53,
 	};
 	pub const C7: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 2093.00,
 	};
 	pub const CS7: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 7,
 		frequency: 2217.46,
 	};
+	pub const DB7: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS7
+	};
 	pub const D7: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 2349.83,
 	};
 	pub const DS7: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 7,
 		frequency: 2489.02,
 	};
+	pub const EB7: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS7
+	};
 	pub const E7: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 2637.02,
 	};
 	pub const F7: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 2793.83,
 	};
 	pub const FS7: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 7,
 		frequency: 2959.96,
 	};
+	pub const GB7: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS7
+	};
 	pub const G7: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 3135.96,
 	};
 	pub const GS7: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 7,
 		frequency: 3322.44,
 	};
+	pub const AB7: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS7
+	};
 	pub const A7: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 3520.00,
 	};
 	pub const AS7: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 7,
 		frequency: 3729.31,
 	};
+	pub const BB7: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS7
+	};
 	pub const B7: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 7,
 		frequency: 3951.07,
 	};
 	pub const C8: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 4186.01,
 	};
 	pub const CS8: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 8,
 		frequency: 4434.92,
 	};
+	pub const DB8: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS8
+	};
 	pub const D8: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 4698.63,
 	};
 	pub const DS8: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 8,
 		frequency: 4978.03,
 	};
+	pub const EB8: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS8
+	};
 	pub const E8: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 5274.04,
 	};
 	pub const F8: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 5587.65,
 	};
 	pub const FS8: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 8,
 		frequency: 5919.91,
 	};
+	pub const GB8: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS8
+	};
 	pub const G8: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 6271.93,
 	};
 	pub const GS8: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 8,
 		frequency: 6644.88,
 	};
+	pub const AB8: Note = Note {
+		note_letter: NoteLetter::A,
+		accidental: Accidental::Flat,
+		..Note::GS8
+	};
 	pub const A8: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 7040.00,
 	};
 	pub const AS8: Note = Note {
 		note_letter: NoteLetter::A,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 8,
 		frequency: 7458.62,
 	};
+	pub const BB8: Note = Note {
+		note_letter: NoteLetter::B,
+		accidental: Accidental::Flat,
+		..Note::AS8
+	};
 	pub const B8: Note = Note {
 		note_letter: NoteLetter::B,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 8,
 		frequency: 7902.13,
 	};
 	pub const C9: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 9,
 		frequency: 8372.02,
 	};
 	pub const CS9: Note = Note {
 		note_letter: NoteLetter::C,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 9,
 		frequency: 8869.84,
 	};
+	pub const DB9: Note = Note {
+		note_letter: NoteLetter::D,
+		accidental: Accidental::Flat,
+		..Note::CS9
+	};
 	pub const D9: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 9,
 		frequency: 9397.27,
 	};
 	pub const DS9: Note = Note {
 		note_letter: NoteLetter::D,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 9,
-		frequency: 10548.1,
+		frequency: 9956.06,
+	};
+	pub const EB9: Note = Note {
+		note_letter: NoteLetter::E,
+		accidental: Accidental::Flat,
+		..Note::DS9
 	};
 	pub const E9: Note = Note {
 		note_letter: NoteLetter::E,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 9,
-		frequency: 11175.3,
+		frequency: 10548.1,
 	};
 	pub const F9: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 9,
-		frequency: 11839.8,
+		frequency: 11175.3,
 	};
 	pub const FS9: Note = Note {
 		note_letter: NoteLetter::F,
-		sharp: true,
+		accidental: Accidental::Sharp,
 		octave: 9,
-		frequency: 12543.8,
+		frequency: 11839.8,
+	};
+	pub const GB9: Note = Note {
+		note_letter: NoteLetter::G,
+		accidental: Accidental::Flat,
+		..Note::FS9
 	};
 	pub const G9: Note = Note {
 		note_letter: NoteLetter::G,
-		sharp: false,
+		accidental: Accidental::Natural,
 		octave: 9,
-		frequency: 13289.7,
+		frequency: 12543.9,
 	};
 
 	pub const NOTES: [Note; 128] = [
@@ -934,13 +1512,679 @@ impl Note {
 		Note::G9,
 	];
 
-	/// Relative to C-1 (the lowest midi note)
+	/// See [`Note`]'s [`FromStr`] implementation
+	pub fn parse(name: &str) -> Result<Self, NoteParseError> {
+		name.parse()
+	}
+
+	/// The midi note number, from 0 for C-1 to 127 for G9, counting 12 semitones an octave with sharps and flats,
+	/// so A4 is 69. The inverse of [`Self::from_midi_number`].
+	pub fn midi_number(&self) -> u8 {
+		let semitone = self.note_letter.semitone() + self.accidental.semitones();
+		((self.octave as i16 + 1) * 12 + semitone).clamp(0, 127) as u8
+	}
+
+	/// The note with a midi note number, clamped to G9
+	pub fn from_midi_number(number: u8) -> Self {
+		Self::NOTES[number.min(127) as usize]
+	}
+
+	/// The note's name written in `style`, keeping its sharp or flat spelling
+	pub fn format(&self, style: NoteNameStyle) -> String {
+		match style {
+			NoteNameStyle::Scientific => self.to_string(),
+			NoteNameStyle::Solfege => {
+				let syllable = match self.note_letter {
+					NoteLetter::C => "Do",
+					NoteLetter::D => "Ré",
+					NoteLetter::E => "Mi",
+					NoteLetter::F => "Fa",
+					NoteLetter::G => "Sol",
+					NoteLetter::A => "La",
+					NoteLetter::B => "Si",
+				};
+				format!(
+					"{syllable}{}{}",
+					match self.accidental {
+						Accidental::Natural => "",
+						Accidental::Sharp => "♯",
+						Accidental::Flat => "♭",
+					},
+					self.octave - 1
+				)
+			}
+			NoteNameStyle::Lilypond => {
+				let letter = match self.note_letter {
+					NoteLetter::C => "c",
+					NoteLetter::D => "d",
+					NoteLetter::E => "e",
+					NoteLetter::F => "f",
+					NoteLetter::G => "g",
+					NoteLetter::A => "a",
+					NoteLetter::B => "b",
+				};
+				let name = match self.accidental {
+					Accidental::Natural => letter.to_string(),
+					Accidental::Sharp => format!("{letter}is"),
+					// Contracted, like the Dutch names
+					Accidental::Flat => match self.note_letter {
+						NoteLetter::E => "es".to_string(),
+						NoteLetter::A => "as".to_string(),
+						_ => format!("{letter}es"),
+					},
+				};
+				let octaves = self.octave as i32 - 3;
+				let mark = if octaves < 0 { "," } else { "'" };
+				name + &mark.repeat(octaves.unsigned_abs() as usize)
+			}
+		}
+	}
+
+	/// The same pitch spelled as a flat, e.g. Bb3 for A#3. Notes that aren't sharp are unchanged.
+	pub fn enharmonic_flat(&self) -> Self {
+		let note = self.enharmonic_sharp();
+		if note.accidental != Accidental::Sharp {
+			return if self.accidental == Accidental::Sharp {
+				note
+			} else {
+				*self
+			};
+		}
+		Self {
+			note_letter: note.note_letter.next(),
+			accidental: Accidental::Flat,
+			..note
+		}
+	}
+
+	/// The same pitch as it's spelled in [`Note::NOTES`], with sharps
+	pub fn enharmonic_sharp(&self) -> Self {
+		Self::from_midi_number(self.midi_number())
+	}
+
+	/// Like [`Display`], but with sharps spelled as flats
+	pub fn to_string_flat(&self) -> String {
+		self.enharmonic_flat().to_string()
+	}
+
+	/// The note's frequency `cents` sharp, or flat if negative
+	pub fn detuned_frequency(&self, cents: f32) -> f32 {
+		self.frequency * 2_f32.powf(cents / 1200.0)
+	}
+
+	/// The note `semitones` higher, or lower if negative.
+	/// `None` if that's outside the range of midi notes.
+	pub fn transpose(self, semitones: i8) -> Option<Self> {
+		let number = self.midi_number() as i16 + semitones as i16;
+		(0..=127)
+			.contains(&number)
+			.then(|| Self::from_midi_number(number as u8))
+	}
+
+	/// The note `interval` higher, `None` if that's above G9
+	pub fn add_interval(self, interval: Interval) -> Option<Self> {
+		self.transpose(interval.semitones())
+	}
+
+	/// The note `interval` lower, `None` if that's below C-1
+	pub fn sub_interval(self, interval: Interval) -> Option<Self> {
+		self.transpose(-interval.semitones())
+	}
+
+	pub fn octave_up(self) -> Option<Self> {
+		self.transpose(12)
+	}
+
+	pub fn octave_down(self) -> Option<Self> {
+		self.transpose(-12)
+	}
+
+	/// How many semitones `other` is above this note, negative if it's below
+	pub fn interval_semitones(&self, other: &Note) -> i32 {
+		other.midi_number() as i32 - self.midi_number() as i32
+	}
+
+	/// How many semitones apart the notes are, in either direction
+	pub fn interval_abs_semitones(&self, other: &Note) -> i32 {
+		self.interval_semitones(other).abs()
+	}
+
+	/// The seven notes of the major scale starting on this note, spelled with sharps.
+	/// Notes above G9 are `None`.
+	pub fn major_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::Major)
+	}
+
+	/// The seven notes of the natural minor (Aeolian) scale starting on this note.
+	/// Notes above G9 are `None`.
+	pub fn natural_minor_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::NaturalMinor)
+	}
+
+	/// Natural minor with a raised seventh
+	pub fn harmonic_minor_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::HarmonicMinor)
+	}
+
+	/// Natural minor with a raised sixth and seventh, as it's played going up
+	pub fn melodic_minor_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::MelodicMinor)
+	}
+
+	/// Natural minor with a raised sixth
+	pub fn dorian_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::Dorian)
+	}
+
+	/// Natural minor with a lowered second
+	pub fn phrygian_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::Phrygian)
+	}
+
+	/// Major with a raised fourth
+	pub fn lydian_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::Lydian)
+	}
+
+	/// Major with a lowered seventh
+	pub fn mixolydian_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::Mixolydian)
+	}
+
+	/// Natural minor with a lowered second and fifth
+	pub fn locrian_scale(&self) -> [Option<Self>; 7] {
+		self.scale(Scale::Locrian)
+	}
+
+	/// The first seven degrees of `scale` from this note
+	fn scale(&self, scale: Scale) -> [Option<Self>; 7] {
+		std::array::from_fn(|degree| scale.degree(*self, degree))
+	}
+
+	/// Every note from `low` up to and including `high`, e.g. for the keys of a piano roll.
+	/// Empty if `high` is below `low`, like a reversed [`std::ops::RangeInclusive`].
+	pub fn range(low: Note, high: Note) -> NoteRange {
+		NoteRange(low.midi_number()..=high.midi_number())
+	}
+
+	/// Moves the note `degrees` steps up `key`'s scale, or down if negative, spelled for the key,
+	/// e.g. D4 up two steps in D major is F#4, and F4 up a step in F major is Bb4.
+	/// Notes outside the key are first moved down to the key's note below them,
+	/// so C#4 up a step in C major is D4, and C#4 up no steps is C4.
+	/// Notes the key would spell with a double sharp or flat are spelled with a sharp.
+	/// `None` if the result is outside the range of midi notes.
+	pub fn transpose_diatonic(&self, key: &Key, degrees: i8) -> Option<Self> {
+		let semitones = key.scale().semitones();
+		let tonic_letter = key.tonic.note_letter;
+		let tonic = (tonic_letter.semitone() + key.tonic.accidental.semitones()).rem_euclid(12);
+		let number = self.midi_number() as i16;
+		let above_tonic = (number - tonic).rem_euclid(12);
+		let degree = semitones
+			.iter()
+			.rposition(|&semitones| semitones as i16 <= above_tonic)
+			.unwrap_or(0);
+		let octave = (number - above_tonic - tonic).div_euclid(12);
+
+		let degree = degree as i16 + degrees as i16;
+		let (octave, degree) = (octave + degree.div_euclid(7), degree.rem_euclid(7) as usize);
+		let number = tonic + octave * 12 + semitones[degree] as i16;
+		if !(0..=127).contains(&number) {
+			return None;
+		}
+		let note = Self::from_midi_number(number as u8);
+
+		let note_letter = (0..degree).fold(tonic_letter, |letter, _| letter.next());
+		let accidental = match (number - note_letter.semitone()).rem_euclid(12) {
+			0 => Accidental::Natural,
+			1 => Accidental::Sharp,
+			11 => Accidental::Flat,
+			_ => return Some(note),
+		};
+		Some(Self {
+			note_letter,
+			accidental,
+			octave: ((number - note_letter.semitone() - accidental.semitones()).div_euclid(12) - 1)
+				as i8,
+			..note
+		})
+	}
+
+	/// The major triad with this note as its root, spelled with sharps.
+	/// `None` if any of it is above G9. See [`Chord`] for more chords.
+	pub fn major_chord(&self) -> Option<[Self; 3]> {
+		self.chord(Chord::Major)
+	}
+
+	pub fn minor_chord(&self) -> Option<[Self; 3]> {
+		self.chord(Chord::Minor)
+	}
+
+	pub fn diminished_chord(&self) -> Option<[Self; 3]> {
+		self.chord(Chord::Diminished)
+	}
+
+	/// The major triad with a minor seventh on top
+	pub fn dominant_seventh(&self) -> Option<[Self; 4]> {
+		self.chord(Chord::DominantSeventh)
+	}
+
+	/// Like [`Chord::notes`], but `None` unless every note fits
+	fn chord<const N: usize>(&self, chord: Chord) -> Option<[Self; N]> {
+		chord.notes(*self).try_into().ok()
+	}
+
+	#[deprecated(note = "use `Note::midi_number`")]
+	pub fn to_midi_number(&self) -> u8 {
+		self.midi_number()
+	}
+
+	#[deprecated(note = "use `Note::midi_number`")]
 	pub fn position(&self) -> u8 {
-		(self.octave * 7 + self.note_letter as i8) as u8
+		self.midi_number()
 	}
 
-	/// Relative to C-1 (the lowest midi note)
+	#[deprecated(note = "use `Note::from_midi_number`")]
 	pub fn from_position(position: u8) -> Self {
-		Self::NOTES[position as usize]
+		Self::from_midi_number(position)
+	}
+
+	/// The note closest in pitch to `frequency` in Hz, clamped to the range of midi notes
+	pub fn from_frequency(frequency: f32) -> Self {
+		Self::from_frequency_with_cents(frequency).0
+	}
+
+	/// Like [`Self::from_frequency`], with how many cents sharp `frequency` is of the note
+	/// (negative if flat). Anything that isn't a positive number is C-1, 0 cents off.
+	pub fn from_frequency_with_cents(frequency: f32) -> (Self, f32) {
+		Self::try_from_frequency(frequency).unwrap_or((Self::CN1, 0.0))
+	}
+
+	/// Like [`Self::from_frequency_with_cents`], but `None` if `frequency` isn't a positive number
+	pub fn try_from_frequency(frequency: f32) -> Option<(Self, f32)> {
+		// Infinity is still above G9, so it clamps like any other high frequency
+		if frequency.is_nan() || frequency <= 0.0 {
+			return None;
+		}
+		// Equal temperament, with A4 (midi note 69) at 440 Hz
+		let semitones = 69.0 + 12.0 * (frequency / 440.0).log2();
+		let position = semitones.round().clamp(0.0, 127.0);
+		Some((
+			Self::NOTES[position as usize],
+			(semitones - position) * 100.0,
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn from_frequency_finds_exact_notes() {
+		for note in Note::NOTES {
+			let (found, cents) = Note::try_from_frequency(note.frequency).unwrap();
+			assert_eq!(found, note);
+			// The constants are rounded, which is up to about half a cent at the bottom
+			assert!(cents.abs() < 1.0, "{note} is {cents} cents off");
+		}
+	}
+
+	#[test]
+	fn from_frequency_measures_cents() {
+		let (note, cents) = Note::from_frequency_with_cents(445.0);
+		assert_eq!(note, Note::A4);
+		assert!((cents - 19.56).abs() < 0.01);
+
+		let (note, cents) = Note::from_frequency_with_cents(435.0);
+		assert_eq!(note, Note::A4);
+		assert!((cents + 19.79).abs() < 0.01);
+
+		// Past the halfway point it's the next note, flat
+		let (note, cents) = Note::from_frequency_with_cents(455.0);
+		assert_eq!(note, Note::AS4);
+		assert!(cents < 0.0);
+	}
+
+	#[test]
+	fn from_frequency_clamps_to_the_extremes() {
+		assert_eq!(Note::from_frequency(1.0), Note::CN1);
+		assert_eq!(Note::from_frequency(100_000.0), Note::G9);
+		assert_eq!(Note::from_frequency(f32::INFINITY), Note::G9);
+		assert_eq!(Note::from_frequency(f32::MIN_POSITIVE), Note::CN1);
+	}
+
+	#[test]
+	fn flat_spellings_are_the_same_pitch() {
+		let flat = Note::AS3.enharmonic_flat();
+		assert_eq!(flat.note_letter, NoteLetter::B);
+		assert_eq!(flat.accidental, Accidental::Flat);
+		assert_eq!(flat.octave, 3);
+		assert_eq!(flat.frequency, Note::AS3.frequency);
+		assert_eq!(flat, Note::AS3);
+		assert_eq!(flat.to_string(), "Bb3");
+		assert_eq!(Note::AS3.to_string_flat(), "Bb3");
+		assert_eq!(flat.enharmonic_sharp().to_string(), "A#3");
+
+		assert_eq!(Note::CS4.to_string_flat(), "Db4");
+		assert_eq!(Note::C4.enharmonic_flat(), Note::C4);
+		assert_eq!(Note::C4.to_string_flat(), "C4");
+	}
+
+	#[test]
+	fn flat_names_parse_with_their_spelling() {
+		let note: Note = "Bb5".parse().unwrap();
+		assert_eq!(note, Note::AS5);
+		assert_eq!(note.to_string(), "Bb5");
+		assert_eq!(note.frequency, Note::AS5.frequency);
+		assert_eq!("Cb4".parse::<Note>().unwrap(), Note::B3);
+		assert_eq!("Cb4".parse::<Note>().unwrap().to_string(), "Cb4");
+	}
+
+	#[test]
+	fn try_from_frequency_rejects_non_positive_numbers() {
+		assert_eq!(Note::try_from_frequency(0.0), None);
+		assert_eq!(Note::try_from_frequency(-440.0), None);
+		assert_eq!(Note::try_from_frequency(f32::NEG_INFINITY), None);
+		assert_eq!(Note::try_from_frequency(f32::NAN), None);
+		assert_eq!(Note::from_frequency(f32::NAN), Note::CN1);
+	}
+
+	#[test]
+	fn midi_numbers() {
+		assert_eq!(Note::CN1.midi_number(), 0);
+		assert_eq!(Note::C4.midi_number(), 60);
+		assert_eq!(Note::A4.midi_number(), 69);
+		assert_eq!(Note::G9.midi_number(), 127);
+		assert_eq!(Note::from_midi_number(0), Note::CN1);
+		assert_eq!(Note::from_midi_number(60), Note::C4);
+		assert_eq!(Note::from_midi_number(69), Note::A4);
+		assert_eq!(Note::from_midi_number(127), Note::G9);
+		assert_eq!(Note::from_midi_number(200), Note::G9);
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn deprecated_midi_number_names_still_work() {
+		for note in Note::NOTES {
+			assert_eq!(note.to_midi_number(), note.midi_number());
+			assert_eq!(note.position(), note.midi_number());
+			assert_eq!(Note::from_position(note.midi_number()), note);
+		}
+	}
+
+	#[test]
+	fn midi_numbers_round_trip() {
+		for (number, note) in Note::NOTES.into_iter().enumerate() {
+			assert_eq!(note.midi_number() as usize, number);
+			assert_eq!(
+				Note::from_midi_number(number as u8).to_string(),
+				note.to_string()
+			);
+		}
+	}
+
+	#[test]
+	fn interval_semitones() {
+		assert_eq!(Note::C4.interval_semitones(&Note::C4), 0);
+		assert_eq!(Note::C4.interval_semitones(&Note::C5), 12);
+		assert_eq!(Note::C5.interval_semitones(&Note::C4), -12);
+		assert_eq!(Note::C4.interval_semitones(&Note::FS4), 6);
+		assert_eq!(Note::B3.interval_semitones(&Note::D4), 3);
+		assert_eq!(Note::A4.interval_semitones(&Note::E2), -29);
+		assert_eq!(Note::A4.interval_abs_semitones(&Note::E2), 29);
+		assert_eq!(Note::CN1.interval_abs_semitones(&Note::G9), 127);
+	}
+
+	#[test]
+	fn names_round_trip() {
+		for note in Note::NOTES {
+			let parsed: Note = note.to_string().parse().unwrap();
+			assert_eq!(parsed.to_string(), note.to_string());
+			assert_eq!(parsed.frequency, note.frequency);
+		}
+	}
+
+	#[test]
+	fn parses_names_case_insensitively() {
+		assert_eq!(Note::parse("C4"), Ok(Note::C4));
+		assert_eq!(Note::parse("c4"), Ok(Note::C4));
+		assert_eq!(Note::parse("F#3"), Ok(Note::FS3));
+		assert_eq!(Note::parse("a#5"), Ok(Note::AS5));
+		assert_eq!(Note::parse("As5"), Ok(Note::AS5));
+		assert_eq!(Note::parse("aS5"), Ok(Note::AS5));
+		assert_eq!(Note::parse("C-1"), Ok(Note::CN1));
+		assert_eq!(Note::parse("C#-1"), Ok(Note::CSN1));
+	}
+
+	#[test]
+	fn rejects_bad_names() {
+		assert_eq!(Note::parse(""), Err(NoteParseError::Empty));
+		assert_eq!(Note::parse("H4"), Err(NoteParseError::InvalidLetter('H')));
+		assert_eq!(
+			Note::parse("C"),
+			Err(NoteParseError::InvalidOctave(String::new()))
+		);
+		assert_eq!(
+			Note::parse("C#x"),
+			Err(NoteParseError::InvalidOctave("x".to_string()))
+		);
+		assert_eq!(
+			Note::parse("C##4"),
+			Err(NoteParseError::InvalidOctave("#4".to_string()))
+		);
+		assert_eq!(Note::parse("G#9"), Err(NoteParseError::OutOfRange));
+		assert_eq!(Note::parse("Cb-1"), Err(NoteParseError::OutOfRange));
+	}
+
+	#[test]
+	fn parses_every_note() {
+		for note in Note::NOTES {
+			assert_eq!(note.to_string().parse::<Note>(), Ok(note));
+		}
+	}
+
+	#[test]
+	fn parses_flats_as_the_same_pitch() {
+		assert_eq!(Note::parse("Bb5"), Ok(Note::AS5));
+		assert_eq!(Note::parse("B♭5"), Ok(Note::AS5));
+		assert_eq!(Note::parse("Db4"), Ok(Note::CS4));
+		assert_eq!(Note::parse("db4"), Ok(Note::CS4));
+		assert_eq!(Note::parse("Fb4"), Ok(Note::E4));
+		assert_eq!(
+			Note::parse("Bb5").map(|note| note.to_string()),
+			Ok("Bb5".to_string())
+		);
+	}
+
+	#[test]
+	fn equal_notes_hash_the_same() {
+		use std::collections::{HashMap, HashSet};
+
+		let c4 = Note {
+			note_letter: NoteLetter::C,
+			accidental: Accidental::Natural,
+			octave: 4,
+			frequency: 261.63,
+		};
+		assert_eq!(c4, Note::C4);
+		let mut velocities = HashMap::new();
+		velocities.insert(Note::C4, 0.5);
+		assert_eq!(velocities.get(&c4), Some(&0.5));
+
+		let notes = HashSet::from([Note::AS4, Note::BB4, Note::AS4.enharmonic_flat()]);
+		assert_eq!(notes.len(), 1);
+		assert_ne!(Note::C4, Note::C5);
+	}
+
+	#[test]
+	fn intervals() {
+		assert_eq!(Note::C4.interval_semitones(&Note::G4), 7);
+		assert_eq!(Note::A4.add_interval(Interval::Octave), Some(Note::A5));
+		assert_eq!(Note::B3.add_interval(Interval::MinorSecond), Some(Note::C4));
+		assert_eq!(Note::AS4.add_interval(Interval::MajorThird), Some(Note::D5));
+		assert_eq!(
+			Note::C4.sub_interval(Interval::PerfectFifth),
+			Some(Note::F3)
+		);
+		assert_eq!(Note::G9.add_interval(Interval::MinorSecond), None);
+		assert_eq!(Note::CN1.sub_interval(Interval::MinorSecond), None);
+	}
+
+	#[test]
+	fn intervals_between_notes() {
+		assert_eq!(
+			Interval::between(&Note::C4, &Note::G4),
+			Some(Interval::PerfectFifth)
+		);
+		assert_eq!(
+			Interval::between(&Note::G4, &Note::C4),
+			Some(Interval::PerfectFifth)
+		);
+		assert_eq!(
+			Interval::between(&Note::C4, &Note::C5),
+			Some(Interval::Octave)
+		);
+		assert_eq!(Interval::between(&Note::C4, &Note::CS5), None);
+		assert_eq!(Interval::from_semitones(6), Some(Interval::Tritone));
+		assert_eq!(Interval::MajorSixth.semitones(), 9);
+	}
+
+	#[test]
+	fn notes_sort_by_pitch() {
+		assert!(Note::CN1 < Note::C4 && Note::C4 < Note::G9);
+		assert!(Note::B3 < Note::C4);
+		assert_eq!(Note::AS4.cmp(&Note::BB4), Ordering::Equal);
+
+		let mut notes = vec![Note::G4, Note::C4, Note::E2, Note::AS4, Note::CN1, Note::G9];
+		notes.sort();
+		assert_eq!(
+			notes,
+			vec![Note::CN1, Note::E2, Note::C4, Note::G4, Note::AS4, Note::G9]
+		);
+		assert_eq!(notes.iter().min(), Some(&Note::CN1));
+		assert!(NoteLetter::C < NoteLetter::D && NoteLetter::A < NoteLetter::B);
+	}
+
+	#[test]
+	fn chords() {
+		assert_eq!(Note::C4.major_chord(), Some([Note::C4, Note::E4, Note::G4]));
+		assert_eq!(Note::A3.minor_chord(), Some([Note::A3, Note::C4, Note::E4]));
+		assert_eq!(
+			Note::B3.diminished_chord(),
+			Some([Note::B3, Note::D4, Note::F4])
+		);
+		assert_eq!(
+			Note::G3.dominant_seventh(),
+			Some([Note::G3, Note::B3, Note::D4, Note::F4])
+		);
+		assert_eq!(Note::E9.major_chord(), None);
+		assert_eq!(Note::C9.dominant_seventh(), None);
+	}
+
+	#[test]
+	fn scales() {
+		assert_eq!(
+			Note::C4.major_scale(),
+			[
+				Note::C4,
+				Note::D4,
+				Note::E4,
+				Note::F4,
+				Note::G4,
+				Note::A4,
+				Note::B4
+			]
+			.map(Some)
+		);
+		assert_eq!(
+			Note::A3.natural_minor_scale(),
+			[
+				Note::A3,
+				Note::B3,
+				Note::C4,
+				Note::D4,
+				Note::E4,
+				Note::F4,
+				Note::G4
+			]
+			.map(Some)
+		);
+		assert_eq!(Note::A3.harmonic_minor_scale()[6], Some(Note::GS4));
+		assert_eq!(Note::D4.dorian_scale()[5], Some(Note::B4));
+		assert_eq!(Note::F4.lydian_scale()[3], Some(Note::B4));
+		// Crosses into the next octave partway up
+		assert_eq!(
+			Note::G4.major_scale(),
+			[
+				Note::G4,
+				Note::A4,
+				Note::B4,
+				Note::C5,
+				Note::D5,
+				Note::E5,
+				Note::FS5
+			]
+			.map(Some)
+		);
+		assert_eq!(
+			Note::D9.major_scale(),
+			[
+				Some(Note::D9),
+				Some(Note::E9),
+				Some(Note::FS9),
+				Some(Note::G9),
+				None,
+				None,
+				None
+			]
+		);
+	}
+
+	#[test]
+	fn name_styles() {
+		assert_eq!(Note::FS3.format(NoteNameStyle::Scientific), "F#3");
+		assert_eq!(Note::C4.format(NoteNameStyle::Solfege), "Do3");
+		assert_eq!(Note::FS3.format(NoteNameStyle::Solfege), "Fa♯2");
+		assert_eq!(Note::BB4.format(NoteNameStyle::Solfege), "Si♭3");
+		assert_eq!(Note::G4.format(NoteNameStyle::Solfege), "Sol3");
+		assert_eq!(Note::CN1.format(NoteNameStyle::Solfege), "Do-2");
+	}
+
+	#[test]
+	fn lilypond_names() {
+		assert_eq!(Note::C3.format(NoteNameStyle::Lilypond), "c");
+		assert_eq!(Note::C4.format(NoteNameStyle::Lilypond), "c'");
+		assert_eq!(Note::CS4.format(NoteNameStyle::Lilypond), "cis'");
+		assert_eq!(Note::A5.format(NoteNameStyle::Lilypond), "a''");
+		assert_eq!(Note::B2.format(NoteNameStyle::Lilypond), "b,");
+		assert_eq!(Note::CN1.format(NoteNameStyle::Lilypond), "c,,,,");
+		assert_eq!(Note::EB4.format(NoteNameStyle::Lilypond), "es'");
+		assert_eq!(Note::AB4.format(NoteNameStyle::Lilypond), "as'");
+		assert_eq!(Note::BB4.format(NoteNameStyle::Lilypond), "bes'");
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serializes_as_its_name() {
+		assert_eq!(serde_json::to_string(&Note::FS3).unwrap(), r#""F#3""#);
+		assert_eq!(serde_json::from_str::<Note>(r#""F#3""#).unwrap(), Note::FS3);
+		for note in [Note::CN1, Note::BB4, Note::G9] {
+			let json = serde_json::to_string(&note).unwrap();
+			let parsed: Note = serde_json::from_str(&json).unwrap();
+			assert_eq!(parsed.to_string(), note.to_string());
+			assert_eq!(parsed.frequency, note.frequency);
+		}
+		assert!(serde_json::from_str::<Note>(r#""H4""#).is_err());
+	}
+
+	#[test]
+	fn tuning() {
+		assert_eq!(Tuning::default().frequency(Note::A4), 440.0);
+		assert_eq!(Tuning::default().ratio(), 1.0);
+		let tuning = Tuning::new(432.0);
+		assert_eq!(tuning.frequency(Note::A4), 432.0);
+		assert!((tuning.frequency(Note::A5) - 864.0).abs() < 0.01);
+		assert!((tuning.frequency(Note::C4) - Note::C4.frequency * 432.0 / 440.0).abs() < 0.01);
+		assert_eq!(tuning.ratio(), 432.0 / 440.0);
 	}
 }
@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashSet};
+
+use crate::setup::{MidiFileAsset, SoundFontAsset};
+use crate::source::MidiAudio;
+
+/// Tracks the music a level needs, so a loading screen can wait until it's all ready to play
+///
+/// SoundFonts and MIDI files are ready once they've loaded. Each [`MidiAudio`] is ready once it exists
+/// and has rendered its first `prefill` of audio ahead, so it doesn't start with an underrun.
+/// [`MusicReady`] is sent once everything is ready, and again if more is added and becomes ready.
+#[derive(Resource, Debug, Clone)]
+pub struct MusicPreload {
+	soundfonts: Vec<Handle<SoundFontAsset>>,
+	midi_files: Vec<Handle<MidiFileAsset>>,
+	audios: Vec<Handle<MidiAudio>>,
+	prefill: Duration,
+	prefilled: HashSet<AssetId<MidiAudio>>,
+	ready_count: usize,
+	announced: bool,
+}
+
+impl Default for MusicPreload {
+	fn default() -> Self {
+		Self {
+			soundfonts: vec![],
+			midi_files: vec![],
+			audios: vec![],
+			prefill: Duration::from_millis(100),
+			prefilled: HashSet::new(),
+			ready_count: 0,
+			announced: false,
+		}
+	}
+}
+
+impl MusicPreload {
+	/// How much audio each [`MidiAudio`] renders ahead before it counts as ready. Defaults to 100 ms.
+	pub fn with_prefill(mut self, prefill: Duration) -> Self {
+		self.prefill = prefill;
+		self
+	}
+
+	pub fn add_soundfont(&mut self, handle: Handle<SoundFontAsset>) {
+		self.soundfonts.push(handle);
+		self.announced = false;
+	}
+
+	pub fn add_midi_file(&mut self, handle: Handle<MidiFileAsset>) {
+		self.midi_files.push(handle);
+		self.announced = false;
+	}
+
+	pub fn add_audio(&mut self, handle: Handle<MidiAudio>) {
+		self.audios.push(handle);
+		self.announced = false;
+	}
+
+	/// Forgets everything, e.g. when leaving the level
+	pub fn clear(&mut self) {
+		*self = Self {
+			prefill: self.prefill,
+			..default()
+		};
+	}
+
+	/// From 0.0 to 1.0, counting each asset equally. 1.0 if nothing's been added.
+	pub fn progress(&self) -> f32 {
+		let total = self.soundfonts.len() + self.midi_files.len() + self.audios.len();
+		if total == 0 {
+			return 1.0;
+		}
+		self.ready_count as f32 / total as f32
+	}
+
+	pub fn is_ready(&self) -> bool {
+		self.progress() >= 1.0
+	}
+}
+
+/// Sent once everything in [`MusicPreload`] is ready to play
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicReady;
+
+pub(crate) fn preload_music(
+	mut preload: ResMut<MusicPreload>,
+	soundfonts: Res<Assets<SoundFontAsset>>,
+	midi_files: Res<Assets<MidiFileAsset>>,
+	mut audios: ResMut<Assets<MidiAudio>>,
+	mut ready: EventWriter<MusicReady>,
+) {
+	if preload.announced {
+		return;
+	}
+	let preload = &mut *preload;
+	for handle in &preload.audios {
+		if !preload.prefilled.contains(&handle.id())
+			&& let Some(audio) = audios.get_mut(handle)
+		{
+			audio.prefill(preload.prefill);
+			preload.prefilled.insert(handle.id());
+		}
+	}
+	let soundfonts_ready = preload
+		.soundfonts
+		.iter()
+		.filter(|handle| soundfonts.contains(*handle))
+		.count();
+	let midi_files_ready = preload
+		.midi_files
+		.iter()
+		.filter(|handle| midi_files.contains(*handle))
+		.count();
+	let audios_ready = preload
+		.audios
+		.iter()
+		.filter(|handle| preload.prefilled.contains(&handle.id()))
+		.count();
+	preload.ready_count = soundfonts_ready + midi_files_ready + audios_ready;
+	if preload.is_ready() {
+		preload.announced = true;
+		ready.send(MusicReady);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use itertools::Itertools;
+
+	use super::*;
+	use crate::SoundFont;
+	use crate::midi::MidiTrack;
+
+	type Load = Box<dyn FnOnce(&mut World)>;
+
+	/// Reserves a handle for an asset that only turns up when its load runs, like a slow parse
+	fn slow<A: Asset>(app: &mut App, asset: impl FnOnce() -> A + 'static) -> (Handle<A>, Load) {
+		let handle = app.world().resource::<Assets<A>>().reserve_handle();
+		let id = handle.id();
+		let load: Load = Box::new(move |world: &mut World| {
+			world.resource_mut::<Assets<A>>().insert(id, asset());
+		});
+		(handle, load)
+	}
+
+	#[test]
+	fn slow_loads_count_up_and_announce_once() {
+		let mut app = App::new();
+		app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+			.init_asset::<SoundFontAsset>()
+			.init_asset::<MidiFileAsset>()
+			.init_asset::<MidiAudio>()
+			.init_resource::<MusicPreload>()
+			.add_event::<MusicReady>()
+			.add_systems(PostUpdate, preload_music);
+		let (soundfont, load_soundfont) = slow(&mut app, || {
+			let bytes = include_bytes!("../assets/hl4mgm.sf2");
+			SoundFontAsset(Arc::new(SoundFont::new(&mut &bytes[..]).unwrap()))
+		});
+		let (octave, load_octave) = slow(&mut app, || {
+			let (track, load_report) =
+				MidiTrack::from_bytes_with_report(include_bytes!("../assets/octave.mid"));
+			MidiFileAsset { track, load_report }
+		});
+		let (fray, load_fray) = slow(&mut app, || {
+			let (track, load_report) =
+				MidiTrack::from_bytes_with_report(include_bytes!("../assets/fray.mid"));
+			MidiFileAsset { track, load_report }
+		});
+		let (audio, load_audio) = slow(&mut app, || {
+			MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2"))
+		});
+		let mut preload = app.world_mut().resource_mut::<MusicPreload>();
+		preload.add_soundfont(soundfont);
+		preload.add_midi_file(octave);
+		preload.add_midi_file(fray);
+		preload.add_audio(audio);
+
+		let mut loads = vec![load_soundfont, load_octave, load_fray, load_audio].into_iter();
+		let mut cursor = app.world().resource::<Events<MusicReady>>().get_cursor();
+		let mut progress = vec![];
+		let mut announcements = 0;
+		for update in 0..20 {
+			// Each load takes three frames
+			if update % 3 == 2
+				&& let Some(load) = loads.next()
+			{
+				load(app.world_mut());
+			}
+			app.update();
+			progress.push(app.world().resource::<MusicPreload>().progress());
+			announcements += cursor
+				.read(app.world().resource::<Events<MusicReady>>())
+				.count();
+		}
+		assert!(
+			progress.windows(2).all(|pair| pair[0] <= pair[1]),
+			"{progress:?}"
+		);
+		assert_eq!(progress[0], 0.0);
+		assert_eq!(*progress.last().unwrap(), 1.0);
+		assert_eq!(
+			progress.iter().copied().dedup().count(),
+			5,
+			"one step for each asset: {progress:?}"
+		);
+		assert_eq!(announcements, 1);
+	}
+}
@@ -0,0 +1,58 @@
+//! The supported public surface, for `use soundyrust::prelude::*;`
+//!
+//! The audio buffer's internals aren't part of it:
+//!
+//! ```compile_fail
+//! use soundyrust::MidiBufferMessage;
+//! ```
+//!
+//! and enums that will grow need a wildcard arm:
+//!
+//! ```compile_fail
+//! use soundyrust::prelude::*;
+//!
+//! fn describe(timing: MidiQueueTiming) -> &'static str {
+//!     match timing {
+//!         MidiQueueTiming::Immediate => "now",
+//!         MidiQueueTiming::Beat => "beat",
+//!         MidiQueueTiming::Bar => "bar",
+//!         MidiQueueTiming::Loop => "loop",
+//!         MidiQueueTiming::AfterBeats(_) => "later",
+//!         MidiQueueTiming::Marker(_) => "marker",
+//!     }
+//! }
+//! ```
+
+pub use crate::{
+	Automation, AutomationParameter, BeatGrid, ChannelPlaybackOptions, ControlBatch,
+	DispatchedEvent, DispatchedEventKind, EventBatch, HitGrade, HitJudge, Instrument,
+	InstrumentContext, Judgement, LayerAlternation, MidiAudio, MidiAudioSettings, MidiAudioTrack,
+	MidiAudioTrackHandle, MidiEvent, MidiQueueEvent, MidiQueueEventType, MidiQueueLooping,
+	MidiQueueTiming, MidiTrack, MidiTrackAccumulateEvent, MusicPreload, MusicReady, MusicRuntime,
+	MusicSetup, MusicalTime, MusicalTimeListener, NoTracksError, Note, QueueClock, Score,
+	SmoothedParam, SongHandle, SoundFont, SoundyPlugin, SourceTrack, UpcomingNote, VoiceSource,
+	Waveform,
+};
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn covers_playing_and_queueing() {
+		let mut audio = MidiAudio::with_builtin_synth(Waveform::Sine);
+		let handle: MidiAudioTrackHandle = audio.add_track(MidiAudioTrack::from_bytes(
+			include_bytes!("../assets/octave.mid"),
+			1.0,
+		));
+		audio.queue(
+			handle,
+			MidiQueueEvent {
+				event: MidiQueueEventType::Stop,
+				timing: MidiQueueTiming::Bar,
+				looping: MidiQueueLooping::Once,
+			},
+		);
+		assert!(audio.start_playing_note(Note::A4).is_ok());
+	}
+}
@@ -0,0 +1,26 @@
+/// Small seeded xorshift generator, so randomized playback is reproducible across machines
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+	pub(crate) fn new(seed: u64) -> Self {
+		// Zero is a fixed point of xorshift
+		Self(seed.max(1))
+	}
+
+	pub(crate) fn next_u64(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	pub(crate) fn below(&mut self, bound: usize) -> usize {
+		(self.next_u64() % bound as u64) as usize
+	}
+
+	/// Uniform in -1..1
+	pub(crate) fn signed_unit(&mut self) -> f32 {
+		(self.next_u64() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+	}
+}
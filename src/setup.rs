@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use rustysynth::{SoundFont, SoundFontError};
+
+use crate::midi::{LoadReport, MidiLoadError, MidiTrack, ParseLimits};
+use crate::source::{
+	MidiAudio, MidiAudioTrack, MidiAudioTrackHandle, MidiQueueEvent, MidiQueueEventType,
+	MidiQueueLooping, MidiQueueTiming,
+};
+
+/// A SoundFont loaded through the asset server, from `.sf2` files
+#[derive(Asset, TypePath, Clone)]
+pub struct SoundFontAsset(pub Arc<SoundFont>);
+
+/// A MIDI file loaded through the asset server, from `.mid` and `.midi` files, within the default [`ParseLimits`]
+#[derive(Asset, TypePath, Clone)]
+pub struct MidiFileAsset {
+	pub track: MidiTrack,
+	pub load_report: LoadReport,
+}
+
+#[derive(Debug)]
+pub enum SoundyLoadError {
+	Io(std::io::Error),
+	SoundFont(SoundFontError),
+	Midi(MidiLoadError),
+}
+
+impl std::fmt::Display for SoundyLoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "Couldn't read asset: {error}"),
+			Self::SoundFont(error) => write!(f, "Couldn't parse SoundFont: {error}"),
+			Self::Midi(error) => write!(f, "Couldn't parse MIDI file: {error}"),
+		}
+	}
+}
+
+impl std::error::Error for SoundyLoadError {}
+
+impl From<std::io::Error> for SoundyLoadError {
+	fn from(error: std::io::Error) -> Self {
+		Self::Io(error)
+	}
+}
+
+#[derive(Default)]
+pub struct SoundFontLoader;
+
+impl AssetLoader for SoundFontLoader {
+	type Asset = SoundFontAsset;
+	type Settings = ();
+	type Error = SoundyLoadError;
+
+	async fn load(
+		&self,
+		reader: &mut dyn Reader,
+		_settings: &(),
+		_load_context: &mut LoadContext<'_>,
+	) -> Result<Self::Asset, Self::Error> {
+		let mut bytes = vec![];
+		reader.read_to_end(&mut bytes).await?;
+		let soundfont =
+			SoundFont::new(&mut bytes.as_slice()).map_err(SoundyLoadError::SoundFont)?;
+		Ok(SoundFontAsset(Arc::new(soundfont)))
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["sf2"]
+	}
+}
+
+#[derive(Default)]
+pub struct MidiFileLoader;
+
+impl AssetLoader for MidiFileLoader {
+	type Asset = MidiFileAsset;
+	type Settings = ();
+	type Error = SoundyLoadError;
+
+	async fn load(
+		&self,
+		reader: &mut dyn Reader,
+		_settings: &(),
+		_load_context: &mut LoadContext<'_>,
+	) -> Result<Self::Asset, Self::Error> {
+		let mut bytes = vec![];
+		reader.read_to_end(&mut bytes).await?;
+		let (track, load_report) =
+			MidiTrack::try_from_bytes_with_report(&bytes, &ParseLimits::default())
+				.map_err(SoundyLoadError::Midi)?;
+		Ok(MidiFileAsset { track, load_report })
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["mid", "midi"]
+	}
+}
+
+/// Declares a [`MidiAudio`] to build once its assets load, so music can be set up from a scene file.
+/// The built asset is played on the same entity, and its handles are written to a [`MusicRuntime`].
+#[derive(Component, Reflect, Debug, Clone, Default)]
+#[reflect(Component, Default)]
+pub struct MusicSetup {
+	/// Asset path of the SoundFont
+	pub soundfont: String,
+	pub tracks: Vec<MusicTrackSetup>,
+}
+
+#[derive(Reflect, Debug, Clone)]
+#[reflect(Default)]
+pub struct MusicTrackSetup {
+	/// Asset path of the MIDI file
+	pub midi: String,
+	/// Overrides the file's time signatures, see [`MidiAudioTrack::new`]
+	pub time_signature: Option<f64>,
+	pub channel_patches: Vec<MusicChannelPatch>,
+	pub looping: bool,
+	/// Beats at the end of the file that lead into the next pass, see [`MidiAudioTrack::with_pickup`]
+	pub pickup_beats: f64,
+	pub volume: f32,
+	/// Whether the track starts playing straight away, rather than waiting for a queued play
+	pub playing: bool,
+	pub queue: Vec<MusicQueueSetup>,
+}
+
+impl Default for MusicTrackSetup {
+	fn default() -> Self {
+		Self {
+			midi: String::new(),
+			time_signature: None,
+			channel_patches: vec![],
+			looping: true,
+			pickup_beats: 0.0,
+			volume: 1.0,
+			playing: true,
+			queue: vec![],
+		}
+	}
+}
+
+#[derive(Reflect, Debug, Clone, Copy, Default)]
+pub struct MusicChannelPatch {
+	pub channel: u8,
+	pub bank: u8,
+	pub patch: u8,
+}
+
+/// A play or stop queued on a track when it's created
+#[derive(Reflect, Debug, Clone)]
+pub struct MusicQueueSetup {
+	/// Plays the track when true, stops it when false
+	pub play: bool,
+	pub timing: MidiQueueTiming,
+	pub looping: MidiQueueLooping,
+}
+
+/// What was built from a [`MusicSetup`], with tracks in the same order
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct MusicRuntime {
+	pub audio: Handle<MidiAudio>,
+	pub tracks: Vec<MidiAudioTrackHandle>,
+}
+
+#[derive(Component)]
+pub(crate) struct PendingMusic {
+	soundfont: Handle<SoundFontAsset>,
+	tracks: Vec<Handle<MidiFileAsset>>,
+}
+
+pub(crate) fn load_music_setups(
+	setups: Query<(Entity, &MusicSetup), Added<MusicSetup>>,
+	asset_server: Res<AssetServer>,
+	mut commands: Commands,
+) {
+	for (entity, setup) in &setups {
+		commands.entity(entity).insert(PendingMusic {
+			soundfont: asset_server.load(&setup.soundfont),
+			tracks: setup
+				.tracks
+				.iter()
+				.map(|track| asset_server.load(&track.midi))
+				.collect(),
+		});
+	}
+}
+
+pub(crate) fn build_music_setups(
+	setups: Query<(Entity, &MusicSetup, &PendingMusic)>,
+	soundfonts: Res<Assets<SoundFontAsset>>,
+	midi_files: Res<Assets<MidiFileAsset>>,
+	mut audios: ResMut<Assets<MidiAudio>>,
+	mut commands: Commands,
+) {
+	for (entity, setup, pending) in &setups {
+		let Some(soundfont) = soundfonts.get(&pending.soundfont) else {
+			continue;
+		};
+		let Some(midi_files) = pending
+			.tracks
+			.iter()
+			.map(|handle| midi_files.get(handle))
+			.collect::<Option<Vec<_>>>()
+		else {
+			continue;
+		};
+
+		let mut audio = MidiAudio::new(soundfont.0.clone());
+		let tracks = setup
+			.tracks
+			.iter()
+			.zip(midi_files)
+			.map(|(track_setup, midi_file)| audio.add_track(build_track(track_setup, midi_file)))
+			.collect();
+		let audio = audios.add(audio);
+
+		commands
+			.entity(entity)
+			.remove::<PendingMusic>()
+			.insert((AudioPlayer(audio.clone()), MusicRuntime { audio, tracks }));
+	}
+}
+
+fn build_track(setup: &MusicTrackSetup, midi_file: &MidiFileAsset) -> MidiAudioTrack {
+	let mut track = MidiAudioTrack::new(midi_file.track.clone(), setup.time_signature)
+		.with_load_report(midi_file.load_report.clone())
+		.with_name(&setup.midi)
+		.with_looping(setup.looping)
+		.with_pickup(setup.pickup_beats)
+		.with_volume(setup.volume);
+	for patch in &setup.channel_patches {
+		track = track.with_channel_patch(patch.channel, patch.bank, patch.patch);
+	}
+	if !setup.playing {
+		track = track.stopped();
+	}
+	for queued in &setup.queue {
+		track = track.with_queue(MidiQueueEvent {
+			event: if queued.play {
+				MidiQueueEventType::Play
+			} else {
+				MidiQueueEventType::Stop
+			},
+			timing: queued.timing.clone(),
+			looping: queued.looping,
+		});
+	}
+	track
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use bevy::audio::AudioPlugin;
+	use bevy::scene::ScenePlugin;
+
+	use super::*;
+	use crate::SoundyPlugin;
+
+	/// Loads the example's scene and runs the app until its music is built
+	fn load_scene() -> (App, MusicRuntime) {
+		let mut app = App::new();
+		app.add_plugins((
+			MinimalPlugins,
+			AssetPlugin::default(),
+			AudioPlugin::default(),
+			ScenePlugin,
+			SoundyPlugin,
+		));
+		let scene = app.world().resource::<AssetServer>().load("music.scn.ron");
+		app.world_mut().spawn(DynamicSceneRoot(scene));
+
+		let start = Instant::now();
+		loop {
+			app.update();
+			let mut runtimes = app.world_mut().query::<&MusicRuntime>();
+			if let Some(runtime) = runtimes.iter(app.world()).next().cloned() {
+				return (app, runtime);
+			}
+			assert!(
+				start.elapsed() < Duration::from_secs(30),
+				"the scene's music was never built"
+			);
+			std::thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	#[test]
+	fn scenes_set_up_music() {
+		let (app, runtime) = load_scene();
+		let audio = app
+			.world()
+			.resource::<Assets<MidiAudio>>()
+			.get(&runtime.audio)
+			.unwrap();
+		let [backing, lead] = runtime.tracks[..] else {
+			panic!("{:?}", runtime.tracks);
+		};
+
+		let backing_info = audio.track_info(&backing).unwrap();
+		assert_eq!(backing_info.name.as_deref(), Some("fray backing.mid"));
+		assert!(backing_info.is_playing);
+		assert_eq!(backing_info.volume, 1.0);
+
+		let lead_info = audio.track_info(&lead).unwrap();
+		assert_eq!(lead_info.name.as_deref(), Some("fray lead.mid"));
+		assert!(!lead_info.is_playing);
+		assert_eq!(lead_info.volume, 0.8);
+		assert_eq!(
+			audio.explain_note(&lead, 0, 60, 100).unwrap().patch_number,
+			80
+		);
+		assert_eq!(
+			audio.queued_events(&lead).unwrap(),
+			[MidiQueueEvent {
+				event: MidiQueueEventType::Play,
+				timing: MidiQueueTiming::Bar,
+				looping: MidiQueueLooping::Once,
+			}]
+		);
+	}
+}
@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// 10 ms at 44.1 kHz
+const DEFAULT_TIME_CONSTANT_FRAMES: f32 = 441.0;
+
+/// Close enough to the target to stop moving
+const SETTLE_THRESHOLD: f32 = 1e-5;
+
+/// A parameter whose target can be set from any thread, and that glides towards it
+/// a frame at a time as audio is rendered, so changes don't click
+///
+/// Each frame moves a fixed fraction of the remaining distance, getting about 63% of the way
+/// to a new target after one time constant. Ticking never allocates or locks.
+///
+/// Any number of threads can set the target, but only one thread may [`Self::tick`] it, normally the audio thread.
+#[derive(Debug)]
+pub struct SmoothedParam {
+	target: AtomicU32,
+	value: AtomicU32,
+	coefficient: f32,
+}
+
+impl SmoothedParam {
+	pub fn new(value: f32) -> Self {
+		Self {
+			target: AtomicU32::new(value.to_bits()),
+			value: AtomicU32::new(value.to_bits()),
+			coefficient: coefficient(DEFAULT_TIME_CONSTANT_FRAMES),
+		}
+	}
+
+	/// Sets how many frames it takes to get most of the way to a new target. Defaults to 10 ms' worth.
+	/// Zero frames jumps straight to the target.
+	pub fn with_time_constant(mut self, frames: f32) -> Self {
+		self.coefficient = coefficient(frames);
+		self
+	}
+
+	pub fn target(&self) -> f32 {
+		f32::from_bits(self.target.load(Ordering::Relaxed))
+	}
+
+	pub fn set_target(&self, target: f32) {
+		self.target.store(target.to_bits(), Ordering::Relaxed);
+	}
+
+	/// The smoothed value as of the last rendered frame
+	pub fn value(&self) -> f32 {
+		f32::from_bits(self.value.load(Ordering::Relaxed))
+	}
+
+	/// Jumps straight to `value` without smoothing
+	pub fn set_immediate(&self, value: f32) {
+		self.set_target(value);
+		self.value.store(value.to_bits(), Ordering::Relaxed);
+	}
+
+	pub fn is_settled(&self) -> bool {
+		self.value() == self.target()
+	}
+
+	/// Moves one frame towards the target, returning the new value.
+	/// This loads the value and then stores the next one, without a compare-and-swap, so two threads ticking
+	/// at once can lose a step. Call it from exactly one thread.
+	pub fn tick(&self) -> f32 {
+		let target = self.target();
+		let value = self.value();
+		if value == target {
+			return value;
+		}
+		let mut next = value + (target - value) * self.coefficient;
+		if (target - next).abs() <= SETTLE_THRESHOLD {
+			next = target;
+		}
+		self.value.store(next.to_bits(), Ordering::Relaxed);
+		next
+	}
+}
+
+impl Clone for SmoothedParam {
+	fn clone(&self) -> Self {
+		Self {
+			target: AtomicU32::new(self.target.load(Ordering::Relaxed)),
+			value: AtomicU32::new(self.value.load(Ordering::Relaxed)),
+			coefficient: self.coefficient,
+		}
+	}
+}
+
+impl Default for SmoothedParam {
+	fn default() -> Self {
+		Self::new(0.0)
+	}
+}
+
+fn coefficient(time_constant_frames: f32) -> f32 {
+	if time_constant_frames <= 0.0 {
+		1.0
+	} else {
+		1.0 - (-1.0 / time_constant_frames).exp()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::thread;
+
+	use super::*;
+
+	#[test]
+	fn ramps_exponentially_without_overshooting() {
+		let param = SmoothedParam::new(0.0).with_time_constant(100.0);
+		param.set_target(1.0);
+		let ramp = (0..500).map(|_| param.tick()).collect::<Vec<_>>();
+		assert!(
+			ramp.windows(2)
+				.all(|pair| pair[0] < pair[1] || pair[1] == 1.0)
+		);
+		assert!(ramp.iter().all(|&value| value <= 1.0));
+		// About 63% of the way after one time constant, and 95% after three
+		assert!((ramp[99] - 0.632).abs() < 0.01, "{}", ramp[99]);
+		assert!((ramp[299] - 0.950).abs() < 0.01, "{}", ramp[299]);
+	}
+
+	#[test]
+	fn settles_exactly_on_the_target() {
+		let param = SmoothedParam::new(0.0);
+		param.set_target(0.5);
+		for _ in 0..10_000 {
+			param.tick();
+		}
+		assert!(param.is_settled());
+		assert_eq!(param.value(), 0.5);
+	}
+
+	#[test]
+	fn new_targets_continue_from_the_current_value() {
+		let param = SmoothedParam::new(0.0).with_time_constant(100.0);
+		param.set_target(1.0);
+		for _ in 0..50 {
+			param.tick();
+		}
+		let midway = param.value();
+		param.set_target(0.0);
+		let next = param.tick();
+		// Turns around smoothly rather than jumping
+		assert!(next < midway && midway - next < 0.01, "{midway} -> {next}");
+		for _ in 0..2000 {
+			param.tick();
+		}
+		assert_eq!(param.value(), 0.0);
+	}
+
+	#[test]
+	fn targets_can_be_set_while_another_thread_ticks() {
+		let param = Arc::new(SmoothedParam::new(0.0).with_time_constant(10.0));
+		let setters = (0..4)
+			.map(|setter| {
+				let param = param.clone();
+				thread::spawn(move || {
+					for step in 0..10_000 {
+						param.set_target(((setter * 10_000 + step) % 101) as f32 / 100.0);
+					}
+				})
+			})
+			.collect::<Vec<_>>();
+		let ticker = {
+			let param = param.clone();
+			thread::spawn(move || {
+				for _ in 0..100_000 {
+					let value = param.tick();
+					assert!((0.0..=1.0).contains(&value), "{value}");
+				}
+			})
+		};
+		setters
+			.into_iter()
+			.for_each(|setter| setter.join().unwrap());
+		ticker.join().unwrap();
+		param.set_target(0.25);
+		for _ in 0..1000 {
+			param.tick();
+		}
+		assert_eq!(param.value(), 0.25);
+	}
+}
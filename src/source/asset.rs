@@ -0,0 +1,3750 @@
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use bevy::utils::HashSet;
+use bevy::utils::hashbrown::HashMap;
+use bevy::{audio::Source, prelude::*, utils::Duration};
+use rustysynth::{SoundFont, SoundFontError};
+
+use crate::batch::ControlBatch;
+use crate::beat_grid::BeatGrid;
+use crate::calibration::CalibrationPulse;
+use crate::midi::{LoadReport, MidiEvent, MidiTrack};
+use crate::smoothed::SmoothedParam;
+use crate::synth::Waveform;
+use crate::{Key, Note, Tuning, gm};
+
+use super::bank::{MissingDrumBankPolicy, PERCUSSION_BANK, RegionMatch, SoundFontBank};
+use super::playback_clock::PlaybackClock;
+use super::queue::{MidiQueueEvent, MidiQueueEventType, MidiQueueTiming, QueueClock};
+use super::renderer::{Channel, MidiAudioTrack, UpcomingNote, VoiceOrigin};
+use super::settings::{MidiAudioSettings, SettingsError, StallPolicy};
+use super::voice::Voice;
+
+/// Frames rendered at a time while skipping ahead after a stall, to keep the scratch buffer small
+const SKIP_CHUNK_FRAMES: usize = 4096;
+
+#[derive(Asset, TypePath)]
+pub struct MidiAudio {
+	tracks: HashMap<MidiAudioTrackHandle, MidiAudioTrack>,
+	next_track_handle: usize,
+	songs: HashMap<SongHandle, Vec<MidiAudioTrackHandle>>,
+	next_song_handle: usize,
+	soundfont: SoundFontBank,
+	settings: MidiAudioSettings,
+	/// Set once a decoder exists, after which the output format can't change
+	decoder_created: AtomicBool,
+	current_audio_channel: u16,
+	buffer: Arc<Mutex<VecDeque<i16>>>,
+	buffer_events: Vec<(Instant, MidiBufferMessage)>,
+	buffer_event_now: Instant,
+	/// Voices that aren't tied to any track and are dropped once they finish
+	one_shots: Vec<Voice>,
+	missing_drum_bank_policy: MissingDrumBankPolicy,
+	warned_missing_drum_bank: bool,
+	logical_clock: bool,
+	/// Frames rendered so far
+	frame: u64,
+	dispatched_events: Vec<DispatchedEvent>,
+	master_gain: GainRamp,
+	volume: SmoothedParam,
+	strict_policy: StrictPolicy,
+	strict_violations: Vec<StrictViolation>,
+	live_input_window: Duration,
+	/// With how many cents each note on is detuned by
+	pending_live_events: Vec<(MidiEvent, f32)>,
+	/// The frame the oldest pending live event arrived on
+	pending_live_since: u64,
+	stalled_frames: u64,
+	/// Ticks that found the buffer already played out while there was music to render
+	underruns: u64,
+	/// Observed every tick, see [`Self::estimated_beat_at`]
+	playback_clock: PlaybackClock,
+	/// Set once the master gain falls silent, for [`HandoffHandle`]s handing off from this asset
+	handoff_fades: Vec<Arc<AtomicBool>>,
+}
+
+impl MidiAudio {
+	/// Until a track is added or a one-shot is played, the asset renders nothing and plays silence,
+	/// so it's cheap to create one up front for live playing
+	pub fn new(soundfont: Arc<SoundFont>) -> Self {
+		Self::from_bank(SoundFontBank::new(soundfont))
+	}
+
+	/// An asset with no SoundFont, playing every note with a simple built-in oscillator
+	pub fn with_builtin_synth(waveform: Waveform) -> Self {
+		Self::from_bank(SoundFontBank::builtin(waveform))
+	}
+
+	fn from_bank(soundfont: SoundFontBank) -> Self {
+		let settings = MidiAudioSettings::default();
+		Self {
+			tracks: HashMap::new(),
+			next_track_handle: 0,
+			songs: HashMap::new(),
+			next_song_handle: 0,
+			soundfont,
+			settings,
+			decoder_created: AtomicBool::new(false),
+			current_audio_channel: 0,
+			buffer: Arc::new(Mutex::new(VecDeque::new())),
+			buffer_events: vec![],
+			buffer_event_now: Instant::now(),
+			one_shots: vec![],
+			missing_drum_bank_policy: MissingDrumBankPolicy::default(),
+			warned_missing_drum_bank: false,
+			logical_clock: false,
+			frame: 0,
+			dispatched_events: vec![],
+			master_gain: GainRamp::default(),
+			volume: SmoothedParam::new(1.0),
+			strict_policy: StrictPolicy::default(),
+			strict_violations: vec![],
+			live_input_window: Duration::ZERO,
+			pending_live_events: vec![],
+			pending_live_since: 0,
+			stalled_frames: 0,
+			underruns: 0,
+			playback_clock: PlaybackClock::new(settings.sample_rate as f64),
+			handoff_fades: vec![],
+		}
+	}
+
+	pub fn add_track(&mut self, mut midi_track: MidiAudioTrack) -> MidiAudioTrackHandle {
+		self.apply_missing_drum_bank_policy(&mut midi_track);
+		midi_track.strict = self.strict_policy != StrictPolicy::Lenient;
+		midi_track.set_samples_per_second(self.samples_per_second());
+		let handle = MidiAudioTrackHandle(self.next_track_handle);
+		self.next_track_handle += 1;
+		self.tracks.insert(handle, midi_track);
+		handle
+	}
+
+	/// Adds a group of tracks that can be unloaded together, e.g. the stems of one piece of music
+	pub fn load_song(&mut self, tracks: Vec<MidiAudioTrack>) -> SongHandle {
+		let handle = SongHandle(self.next_song_handle);
+		self.next_song_handle += 1;
+		let tracks = tracks
+			.into_iter()
+			.map(|track| self.add_track(track))
+			.collect();
+		self.songs.insert(handle, tracks);
+		handle
+	}
+
+	/// The song's tracks, in the order they were loaded
+	pub fn song_tracks(&self, song: &SongHandle) -> Option<&[MidiAudioTrackHandle]> {
+		self.songs.get(song).map(Vec::as_slice)
+	}
+
+	/// Stops the song's tracks and removes them once their last notes have rung out.
+	/// Their handles stop working straight away.
+	pub fn unload_song(&mut self, song: SongHandle) {
+		self.retire_song(song, None);
+	}
+
+	/// Loads `tracks` as a new song and crossfades to it from `old` over `transition`,
+	/// with `old` playing on as it fades out and then being unloaded
+	pub fn switch_song(
+		&mut self,
+		old: SongHandle,
+		tracks: Vec<MidiAudioTrack>,
+		transition: Duration,
+	) -> SongHandle {
+		let frames = transition.as_secs_f64() * self.samples_per_second();
+		self.retire_song(old, Some(frames));
+		let new = self.load_song(tracks);
+		for handle in &self.songs[&new] {
+			if let Some(track) = self.tracks.get_mut(handle) {
+				track.fade = GainRamp {
+					gain: 0.0,
+					..default()
+				};
+				track.fade.fade_to(1.0, frames, 0);
+			}
+		}
+		new
+	}
+
+	/// Moves the song's tracks to handles nobody else has, to be removed once they're silent.
+	/// With `fade_frames` they keep playing while they fade out, otherwise they stop and ring out.
+	fn retire_song(&mut self, song: SongHandle, fade_frames: Option<f64>) {
+		let Some(handles) = self.songs.remove(&song) else {
+			return;
+		};
+		for handle in handles {
+			let Some(mut track) = self.tracks.remove(&handle) else {
+				continue;
+			};
+			match fade_frames {
+				Some(frames) => track.fade.fade_to(0.0, frames, 0),
+				None => {
+					track.is_playing = false;
+					// Otherwise looping samples would hold on forever
+					track.release_all_notes();
+				}
+			}
+			track.retiring = true;
+			let retired = MidiAudioTrackHandle(self.next_track_handle);
+			self.next_track_handle += 1;
+			self.tracks.insert(retired, track);
+		}
+	}
+
+	pub fn with_track(mut self, midi_track: MidiAudioTrack) -> Self {
+		self.add_track(midi_track);
+		self
+	}
+
+	/// Removes a track straight away, cutting off any notes it's playing.
+	/// Other tracks' handles keep working, and handles are never reused.
+	pub fn remove_track(&mut self, handle: MidiAudioTrackHandle) -> Option<MidiAudioTrack> {
+		for tracks in self.songs.values_mut() {
+			tracks.retain(|track| *track != handle);
+		}
+		self.tracks.remove(&handle)
+	}
+
+	/// Removes every track, including unloaded songs still ringing out
+	pub fn clear_tracks(&mut self) {
+		self.tracks.clear();
+		self.songs.clear();
+	}
+
+	pub fn soundfont_bank(&self) -> &SoundFontBank {
+		&self.soundfont
+	}
+
+	/// Plays notes with the built-in synth when their preset can't be found in the SoundFont
+	pub fn with_synth_fallback(mut self, waveform: Waveform) -> Self {
+		self.soundfont.set_fallback(Some(waveform));
+		self
+	}
+
+	/// What to do with percussion channels when the SoundFont has no percussion bank,
+	/// applied to tracks as they're added
+	pub fn with_missing_drum_bank_policy(mut self, policy: MissingDrumBankPolicy) -> Self {
+		self.missing_drum_bank_policy = policy;
+		self
+	}
+
+	pub fn set_missing_drum_bank_policy(&mut self, policy: MissingDrumBankPolicy) {
+		self.missing_drum_bank_policy = policy;
+	}
+
+	/// Whether conditions that are normally silently ignored get recorded, see [`Self::strict_violations`]
+	pub fn with_strict_policy(mut self, policy: StrictPolicy) -> Self {
+		self.set_strict_policy(policy);
+		self
+	}
+
+	pub fn set_strict_policy(&mut self, policy: StrictPolicy) {
+		self.strict_policy = policy;
+		for track in self.tracks.values_mut() {
+			track.strict = policy != StrictPolicy::Lenient;
+		}
+	}
+
+	/// Everything recorded under a strict policy so far
+	pub fn strict_violations(&self) -> &[StrictViolation] {
+		&self.strict_violations
+	}
+
+	pub fn clear_strict_violations(&mut self) {
+		self.strict_violations.clear();
+	}
+
+	fn record_violation(&mut self, violation: StrictViolation) {
+		match self.strict_policy {
+			StrictPolicy::Lenient => return,
+			StrictPolicy::Collect => {}
+			StrictPolicy::PanicInDebug => {
+				if cfg!(debug_assertions) {
+					panic!("Strict violation: {violation}");
+				}
+			}
+		}
+		self.strict_violations.push(violation);
+	}
+
+	fn apply_missing_drum_bank_policy(&mut self, track: &mut MidiAudioTrack) {
+		if self.soundfont.has_percussion() {
+			return;
+		}
+		let drum_channels = track
+			.channels
+			.values_mut()
+			.filter(|channel| channel.bank_number == PERCUSSION_BANK)
+			.collect::<Vec<_>>();
+		if drum_channels.is_empty() {
+			return;
+		}
+
+		if !self.warned_missing_drum_bank {
+			self.warned_missing_drum_bank = true;
+			warn!(
+				"SoundFont has no percussion bank, percussion channels will be {}",
+				match self.missing_drum_bank_policy {
+					MissingDrumBankPolicy::RemapToMelodic => {
+						format!("played with {}", PresetName { bank: 0, patch: 0 })
+					}
+					MissingDrumBankPolicy::Mute => "muted".to_owned(),
+				}
+			);
+		}
+
+		for channel in drum_channels {
+			match self.missing_drum_bank_policy {
+				MissingDrumBankPolicy::RemapToMelodic => {
+					channel.bank_number = 0;
+					channel.patch_number = 0;
+				}
+				MissingDrumBankPolicy::Mute => channel.muted = true,
+			}
+		}
+	}
+
+	pub fn settings(&self) -> &MidiAudioSettings {
+		&self.settings
+	}
+
+	/// Changes how the asset renders. The buffer size can change at any time,
+	/// but the sample rate and channel count are fixed once a decoder has been created.
+	pub fn set_settings(&mut self, settings: MidiAudioSettings) -> Result<(), SettingsError> {
+		settings.validate()?;
+		if self.decoder_created.load(Ordering::Relaxed)
+			&& (settings.sample_rate != self.settings.sample_rate
+				|| settings.channels != self.settings.channels)
+		{
+			return Err(SettingsError::DecoderCreated);
+		}
+		if settings.sample_rate != self.settings.sample_rate {
+			self.playback_clock.reset(settings.sample_rate as f64);
+		}
+		self.settings = settings;
+		for track in self.tracks.values_mut() {
+			track.set_samples_per_second(self.settings.sample_rate as f64);
+		}
+		Ok(())
+	}
+
+	fn samples_per_second(&self) -> f64 {
+		self.settings.sample_rate as f64
+	}
+
+	pub fn from_bytes(soundfont_bytes: &[u8]) -> Self {
+		Self::try_from_bytes(soundfont_bytes)
+			.unwrap_or_else(|error| panic!("Failed to parse SoundFont: {error}"))
+	}
+
+	pub fn try_from_bytes(soundfont_bytes: &[u8]) -> Result<Self, SoundFontLoadError> {
+		let soundfont = SoundFont::new(&mut Cursor::new(soundfont_bytes))?;
+		Ok(Self::new(Arc::new(soundfont)))
+	}
+
+	pub fn tick(&mut self, delta: Duration) {
+		self.playback_clock
+			.observe(self.frame - self.buffered_frames() as u64, Instant::now());
+		if self.logical_clock {
+			return;
+		}
+		self.buffer_event_now += delta;
+
+		if self.buffer.lock().unwrap().is_empty() && self.frame > 0 && !self.is_idle() {
+			self.underruns += 1;
+		}
+		let ticks = (delta.as_secs_f64() * self.samples_per_second()) as usize;
+		let stalled_ticks = ticks.saturating_sub(self.settings.max_backlog_frames);
+		let ticks = if stalled_ticks > 0 {
+			self.stalled_frames += stalled_ticks as u64;
+			if self.settings.stall_policy == StallPolicy::CatchUp {
+				// Anything still buffered was rendered before the stall and would only play late
+				self.buffer.lock().unwrap().clear();
+				self.skip(stalled_ticks);
+			}
+			self.settings.max_backlog_frames
+		} else {
+			ticks
+		};
+		let buffered = self.buffer.lock().unwrap().len();
+		let max_ticks = self
+			.settings
+			.buffer_frames
+			.saturating_sub(buffered / self.settings.channels as usize);
+		self.render(ticks.min(max_ticks));
+	}
+
+	/// Renders until `duration` of audio is buffered ahead, so playback doesn't start with an underrun.
+	/// Does nothing with a logical clock.
+	pub fn prefill(&mut self, duration: Duration) {
+		if self.logical_clock {
+			return;
+		}
+		let frames = ((duration.as_secs_f64() * self.samples_per_second()) as usize)
+			.min(self.settings.buffer_frames);
+		let buffered = self.buffer.lock().unwrap().len() / self.settings.channels as usize;
+		self.render(frames.saturating_sub(buffered));
+	}
+
+	/// How many frames of music stalls have delayed or skipped, see [`MidiAudioSettings::stall_policy`]
+	pub fn stalled_frames(&self) -> u64 {
+		self.stalled_frames
+	}
+
+	/// How many times the decoder ran out of rendered audio and played silence in the middle of the music
+	pub fn underruns(&self) -> u64 {
+		self.underruns
+	}
+
+	/// Frames rendered but not yet played
+	pub fn buffered_frames(&self) -> usize {
+		self.buffer.lock().unwrap().len() / self.settings.channels as usize
+	}
+
+	/// Advances playback by `ticks` frames without hearing them
+	fn skip(&mut self, ticks: usize) {
+		if self.is_idle() {
+			self.skip_idle(ticks);
+			return;
+		}
+		let mut buffer =
+			VecDeque::with_capacity(SKIP_CHUNK_FRAMES * self.settings.channels as usize);
+		for chunk in (0..ticks).step_by(SKIP_CHUNK_FRAMES) {
+			self.tick_n_times(SKIP_CHUNK_FRAMES.min(ticks - chunk), &mut buffer);
+			buffer.clear();
+		}
+		self.finish_handoffs();
+	}
+
+	/// Drives playback from the game's own fixed timestep instead of from frame time,
+	/// so every machine dispatches the same events given the same inputs
+	///
+	/// While enabled, [`Self::tick`] does nothing and playback only advances through [`Self::advance_logical`].
+	pub fn set_logical_clock(&mut self, logical_clock: bool) {
+		self.logical_clock = logical_clock;
+	}
+
+	pub fn with_logical_clock(mut self) -> Self {
+		self.logical_clock = true;
+		self
+	}
+
+	/// Renders exactly `frames` frames, returning every event dispatched along the way
+	pub fn advance_logical(&mut self, frames: u32) -> EventBatch {
+		let start_frame = self.frame;
+		self.tracks
+			.values_mut()
+			.for_each(|track| track.record_events = true);
+		self.render(frames as usize);
+		self.tracks
+			.values_mut()
+			.for_each(|track| track.record_events = false);
+		EventBatch {
+			start_frame,
+			frames,
+			events: std::mem::take(&mut self.dispatched_events),
+		}
+	}
+
+	fn render(&mut self, ticks: usize) {
+		if self.is_idle() {
+			self.skip_idle(ticks);
+			return;
+		}
+		for track in self.tracks.values_mut() {
+			track.level = 0.0;
+		}
+		let mut buffer = VecDeque::with_capacity(ticks * self.settings.channels as usize);
+		self.tick_n_times(ticks, &mut buffer);
+		let samples_per_second = self.samples_per_second();
+
+		let buffer = buffer
+			.into_iter()
+			.enumerate()
+			.filter_map(|(i, message)| match message {
+				MidiBufferMessage::Audio(sample) => Some(sample),
+				#[allow(unreachable_patterns)]
+				_ => {
+					self.buffer_events.push((
+						self.buffer_event_now
+							+ Duration::from_secs_f64(i as f64 / samples_per_second),
+						message,
+					));
+					None
+				}
+			});
+		self.buffer.lock().unwrap().extend(buffer);
+
+		self.buffer_events
+			.retain(|(time, _)| *time > self.buffer_event_now);
+		self.finish_handoffs();
+	}
+
+	/// Lets [`HandoffHandle`]s know this asset has faded out, leaving only its buffer to play
+	fn finish_handoffs(&mut self) {
+		if self.master_gain.is_silent() {
+			for faded in self.handoff_fades.drain(..) {
+				faded.store(true, Ordering::Relaxed);
+			}
+		}
+	}
+
+	/// Nothing could make a sound, so there's no need to render anything.
+	/// The decoder plays silence while its buffer is empty.
+	fn is_idle(&self) -> bool {
+		self.tracks.is_empty() && self.one_shots.is_empty() && self.pending_live_events.is_empty()
+	}
+
+	/// Advances the clock as if `ticks` frames of silence were rendered
+	fn skip_idle(&mut self, ticks: usize) {
+		self.frame += ticks as u64;
+		for _ in 0..ticks {
+			self.master_gain.tick();
+			self.volume.tick();
+		}
+		self.finish_handoffs();
+	}
+
+	fn tick_n_times(&mut self, ticks: usize, buffer: &mut VecDeque<MidiBufferMessage>) {
+		for _ in 0..ticks * self.settings.channels as usize {
+			self.tick_once(buffer);
+		}
+	}
+
+	fn tick_once(&mut self, buffer: &mut VecDeque<MidiBufferMessage>) {
+		if self.current_audio_channel == 0 {
+			self.frame += 1;
+			self.flush_live_events();
+			for track in self.tracks.values_mut() {
+				track.timings.clear();
+				if track.is_playing {
+					track.tick_timing();
+				}
+			}
+			let shared_timings = if self
+				.tracks
+				.values()
+				.any(|track| track.queue_clock == QueueClock::Shared)
+			{
+				self.tracks
+					.values()
+					.flat_map(|track| track.timings.iter().cloned())
+					.collect()
+			} else {
+				HashSet::new()
+			};
+
+			let referenced_timings = self
+				.tracks
+				.values()
+				.filter_map(|track| match track.queue_clock {
+					QueueClock::Track(handle) => Some(handle),
+					_ => None,
+				})
+				.filter_map(|handle| Some((handle, self.tracks.get(&handle)?.timings.clone())))
+				.collect::<HashMap<_, _>>();
+
+			for track in self.tracks.values_mut() {
+				match track.queue_clock {
+					QueueClock::Shared => track.process_queue(&shared_timings),
+					QueueClock::Track(handle) => match referenced_timings.get(&handle) {
+						Some(timings) => track.process_queue(timings),
+						None => track.process_queue(&HashSet::new()),
+					},
+					QueueClock::Free => {
+						let mut timings = std::mem::take(&mut track.free_timings);
+						track.tick_free_clock(&mut timings);
+						track.process_queue(&timings);
+						track.free_timings = timings;
+					}
+					_ => {
+						let mut timings = std::mem::take(&mut track.timings);
+						if track.is_waiting_to_start() {
+							// Its bars and beats count on while it waits, but it's already at the start of its loop
+							track.tick_free_clock(&mut timings);
+							if !track.queue.is_empty() {
+								timings.insert(MidiQueueTiming::Loop);
+							}
+						}
+						track.process_queue(&timings);
+						track.timings = timings;
+					}
+				}
+				track.update_quiet();
+			}
+
+			for track in self.tracks.values_mut().filter(|track| track.is_playing) {
+				track.apply_scheduled_unmutes();
+				track.apply_automation();
+				track.tick_midi(&self.soundfont);
+				track.notify_musical_time();
+			}
+
+			for (handle, track) in self.tracks.iter_mut() {
+				if track.record_events {
+					self.dispatched_events
+						.extend(track.dispatched.drain(..).map(|kind| DispatchedEvent {
+							frame: self.frame,
+							track: *handle,
+							kind,
+						}));
+				}
+			}
+
+			let violations = self
+				.tracks
+				.iter_mut()
+				.flat_map(|(handle, track)| {
+					track
+						.violations
+						.drain(..)
+						.map(|(beat, kind)| StrictViolation {
+							track: *handle,
+							beat: Some(beat),
+							kind,
+						})
+				})
+				.collect::<Vec<_>>();
+			for violation in violations {
+				self.record_violation(violation);
+			}
+		}
+
+		let any_solo = self.tracks.values().any(|track| track.solo);
+		let mut sample = 0;
+		for track in self.tracks.values_mut().filter(|track| !track.quiet) {
+			let track_sample = track.sample(self.current_audio_channel);
+			track.level = track
+				.level
+				.max(track_sample.unsigned_abs() as f32 / i16::MAX as f32);
+			if !track.muted && (track.solo || !any_solo) {
+				sample += track_sample;
+			}
+		}
+		let one_shot_sample = self
+			.one_shots
+			.iter()
+			.map(|voice| voice.sample(self.current_audio_channel))
+			.sum::<i32>();
+		let sample = ((sample + one_shot_sample) as f32
+			* self.master_gain.gain
+			* self.volume.value()) as i32;
+		let sample = sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+
+		if self.current_audio_channel == 0 {
+			self.tracks
+				.values_mut()
+				.filter(|track| !track.quiet)
+				.flat_map(|track| track.channels.values_mut())
+				.for_each(Channel::tick_voices);
+			self.tracks
+				.values_mut()
+				.filter(|track| !track.quiet)
+				.for_each(|track| {
+					track.tick_seam_tails();
+					track.fade.tick();
+					track.volume.tick();
+				});
+			self.tracks
+				.retain(|_, track| !track.retiring || !(track.quiet || track.fade.is_silent()));
+			self.master_gain.tick();
+			self.volume.tick();
+			self.one_shots.iter_mut().for_each(|voice| voice.tick());
+			self.tracks
+				.values_mut()
+				.filter(|track| track.is_playing)
+				.filter_map(|track| track.calibration.as_mut())
+				.for_each(|(_, frame)| *frame += 1);
+			self.one_shots.retain(|voice| !voice.is_finished());
+		}
+		self.current_audio_channel = (self.current_audio_channel + 1) % self.settings.channels;
+
+		buffer.push_back(MidiBufferMessage::Audio(sample));
+	}
+
+	pub fn queue(&mut self, handle: MidiAudioTrackHandle, event: MidiQueueEvent) {
+		if let Some(track) = self.tracks.get_mut(&handle) {
+			track.queue.push(event)
+		} else {
+			self.record_violation(StrictViolation {
+				track: handle,
+				beat: None,
+				kind: StrictViolationKind::UnknownTrack,
+			});
+		}
+	}
+
+	/// Adds a track that plays a short sine pulse every `pulse_interval`, bypassing the SoundFont,
+	/// starting on the next rendered frame
+	pub fn calibration_track(
+		&mut self,
+		frequency: f32,
+		pulse_interval: Duration,
+	) -> MidiAudioTrackHandle {
+		let mut track = MidiAudioTrack::new(
+			MidiTrack {
+				events: Arc::new([]),
+				ticks_per_beat: 480,
+				source_tracks: Arc::new([]),
+			},
+			1.0,
+		);
+		track.calibration = Some((
+			CalibrationPulse::new(frequency, pulse_interval, self.samples_per_second() as f32),
+			0,
+		));
+		self.add_track(track)
+	}
+
+	pub fn calibration_pulse(&self, handle: &MidiAudioTrackHandle) -> Option<CalibrationPulse> {
+		self.tracks.get(handle)?.calibration.map(|(pulse, _)| pulse)
+	}
+
+	pub fn master_gain(&self) -> f32 {
+		self.master_gain.gain
+	}
+
+	pub fn set_master_gain(&mut self, gain: f32) {
+		self.master_gain = GainRamp { gain, ..default() };
+	}
+
+	/// The master volume as currently heard, which glides to whatever [`Self::set_volume`] last set
+	pub fn volume(&self) -> f32 {
+		self.volume.value()
+	}
+
+	/// Smoothly changes the master volume, separately from the master gain's fades
+	pub fn set_volume(&mut self, volume: f32) {
+		self.volume.set_target(volume);
+	}
+
+	/// The track's volume as currently heard
+	pub fn track_volume(&self, handle: &MidiAudioTrackHandle) -> Option<f32> {
+		self.tracks.get(handle).map(|track| track.volume.value())
+	}
+
+	/// Smoothly changes the track's volume
+	pub fn set_track_volume(&mut self, handle: &MidiAudioTrackHandle, volume: f32) {
+		if let Some(track) = self.tracks.get(handle) {
+			track.volume.set_target(volume);
+		}
+	}
+
+	pub fn track_gain(&self, handle: &MidiAudioTrackHandle) -> Option<f32> {
+		self.tracks.get(handle).map(|track| track.gain())
+	}
+
+	/// Changes the track's gain straight away, see [`MidiAudioTrack::with_gain`].
+	/// Use [`Self::set_track_volume`] to change its loudness smoothly.
+	pub fn set_track_gain(&mut self, handle: &MidiAudioTrackHandle, gain: f32) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_gain(gain);
+		}
+	}
+
+	/// Ramps the master gain to `gain` over `duration`, after waiting `delay`
+	pub fn fade_master_gain(&mut self, gain: f32, duration: Duration, delay: Duration) {
+		self.master_gain.fade_to(
+			gain,
+			duration.as_secs_f64() * self.samples_per_second(),
+			(delay.as_secs_f64() * self.samples_per_second()) as u64,
+		);
+	}
+
+	/// Whether this asset has faded out completely and played everything it rendered,
+	/// so it can be despawned without an audible cut
+	pub fn is_faded_out(&self) -> bool {
+		self.master_gain.is_silent() && self.buffer.lock().unwrap().is_empty()
+	}
+
+	/// Takes over from `other`'s `from` track, starting this asset's `to` track at the beat that's
+	/// currently audible in `other`, then crossfading between the two assets on the next `timing` boundary
+	///
+	/// The returned handle reports once `other` has faded out and played everything it rendered,
+	/// so it can be despawned without an audible cut. Returns `None` if either track doesn't exist.
+	pub fn handoff_from(
+		&mut self,
+		other: &mut MidiAudio,
+		from: &MidiAudioTrackHandle,
+		to: &MidiAudioTrackHandle,
+		timing: MidiQueueTiming,
+		crossfade: Duration,
+	) -> Option<HandoffHandle> {
+		let beat = other.audible_beat(from)?;
+		let from_track = other.tracks.get(from)?;
+		let beats_per_second = from_track.beats_per_second * from_track.tempo_multiplier;
+		let boundary = from_track.next_boundary_beat(beat, &timing);
+		let until_boundary = ((boundary - beat) / beats_per_second).max(0.0);
+		// Each asset renders ahead of what's heard by whatever it has buffered
+		let latency = self.buffered_frames() as f64 / self.samples_per_second();
+		let other_latency = other.buffered_frames() as f64 / other.samples_per_second();
+
+		let to_track = self.tracks.get_mut(to)?;
+		to_track.seek_to_beat((beat + latency * beats_per_second).max(0.0));
+		// Seeking skips the file's tempo changes, so carry on at the tempo that's playing
+		to_track.set_tempo(beats_per_second * 60.0 / to_track.tempo_multiplier);
+		to_track.is_playing = true;
+
+		self.set_master_gain(0.0);
+		self.fade_master_gain(
+			1.0,
+			crossfade,
+			Duration::from_secs_f64((until_boundary - latency).max(0.0)),
+		);
+		other.fade_master_gain(
+			0.0,
+			crossfade,
+			Duration::from_secs_f64((until_boundary - other_latency).max(0.0)),
+		);
+
+		let faded = Arc::new(AtomicBool::new(false));
+		other.handoff_fades.push(faded.clone());
+		Some(HandoffHandle {
+			faded,
+			buffer: other.buffer.clone(),
+		})
+	}
+
+	/// Renders the track from the start until it first loops, and compares the `window` of audio
+	/// either side of the seam. Returns `None` if the track doesn't loop within a few passes' worth of time.
+	pub fn analyze_loop_seam(
+		&self,
+		handle: &MidiAudioTrackHandle,
+		window: Duration,
+	) -> Option<SeamReport> {
+		let track = self.tracks.get(handle)?;
+		let loop_seconds = track.loop_beats() / (track.beats_per_second * track.tempo_multiplier);
+		let window_frames = ((window.as_secs_f64() * self.samples_per_second()) as usize).max(1);
+		let max_frames = (loop_seconds * 4.0 * self.samples_per_second()) as usize + window_frames;
+
+		let mut scratch = MidiAudio::from_bank(self.soundfont.clone());
+		scratch.settings = self.settings;
+		let scratch_handle = scratch.add_track(track.duplicate());
+		let mut buffer = VecDeque::new();
+		let mut render_frame = |scratch: &mut MidiAudio| {
+			for _ in 0..scratch.settings.channels {
+				scratch.tick_once(&mut buffer);
+			}
+			let frame = buffer
+				.drain(..)
+				.map(|MidiBufferMessage::Audio(sample)| sample as f32 / i16::MAX as f32)
+				.sum::<f32>();
+			frame / scratch.settings.channels as f32
+		};
+
+		let mut tail = VecDeque::with_capacity(window_frames);
+		let mut head = Vec::with_capacity(window_frames);
+		let mut last_beat = 0.0;
+		for _ in 0..max_frames {
+			let frame = render_frame(&mut scratch);
+			let scratch_track = &scratch.tracks[&scratch_handle];
+			if scratch_track.beat < last_beat {
+				// Tracks that don't loop go back to the start too, but stop there
+				if !scratch_track.is_playing {
+					return None;
+				}
+				head.push(frame);
+				break;
+			}
+			last_beat = scratch_track.beat;
+			if tail.len() == window_frames {
+				tail.pop_front();
+			}
+			tail.push_back(frame);
+		}
+		if head.is_empty() {
+			return None;
+		}
+		while head.len() < window_frames {
+			head.push(render_frame(&mut scratch));
+		}
+
+		let level = |frames: &mut dyn Iterator<Item = &f32>| {
+			let (sum, count) = frames.fold((0.0, 0), |(sum, count), frame| {
+				(sum + frame * frame, count + 1)
+			});
+			(sum / count.max(1) as f32).sqrt()
+		};
+		Some(SeamReport {
+			discontinuity: (head[0] - tail.back().copied().unwrap_or_default()).abs(),
+			tail_level: level(&mut tail.iter()),
+			head_level: level(&mut head.iter()),
+		})
+	}
+
+	/// Records commands with `record` and applies them all to the track before any more audio is rendered
+	pub fn batch(&mut self, handle: &MidiAudioTrackHandle, record: impl FnOnce(&mut ControlBatch)) {
+		let mut batch = ControlBatch::new();
+		record(&mut batch);
+		self.apply_batch(handle, batch);
+	}
+
+	pub fn apply_batch(&mut self, handle: &MidiAudioTrackHandle, batch: ControlBatch) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			for command in batch.into_commands() {
+				track.apply_command(command);
+			}
+		} else {
+			self.record_violation(StrictViolation {
+				track: *handle,
+				beat: None,
+				kind: StrictViolationKind::UnknownTrack,
+			});
+		}
+	}
+
+	pub fn start_playing_note(&mut self, note: Note) -> Result<(), NoTracksError> {
+		self.start_playing_note_detuned(note, 0.0)
+	}
+
+	/// Like [`Self::start_playing_note`], but `cents` sharp (or flat if negative), for slides and vibrato
+	pub fn start_playing_note_detuned(
+		&mut self,
+		note: Note,
+		cents: f32,
+	) -> Result<(), NoTracksError> {
+		self.play_detuned_live_event(
+			MidiEvent::NoteOn {
+				channel: 0,
+				note: note.midi_number(),
+				velocity: 127,
+			},
+			cents,
+		)
+	}
+
+	pub fn stop_playing_note(&mut self, note: Note) -> Result<(), NoTracksError> {
+		self.play_live_event(MidiEvent::NoteOff {
+			channel: 0,
+			note: note.midi_number(),
+		})
+	}
+
+	/// Holds live note ons for up to `window` so notes pressed together (like a mashed chord)
+	/// start on the same frame. Zero plays them as soon as they arrive.
+	pub fn with_live_input_window(mut self, window: Duration) -> Self {
+		self.set_live_input_window(window);
+		self
+	}
+
+	pub fn set_live_input_window(&mut self, window: Duration) {
+		self.live_input_window = window;
+	}
+
+	/// Plays an event from a controller on the live track, like [`Self::start_playing_note`] on any channel.
+	/// MPE controllers send each note on its own member channel, see [`MidiAudioTrack::with_mpe`].
+	pub fn play_live_event(&mut self, event: MidiEvent) -> Result<(), NoTracksError> {
+		self.play_detuned_live_event(event, 0.0)
+	}
+
+	fn play_detuned_live_event(
+		&mut self,
+		event: MidiEvent,
+		detune_cents: f32,
+	) -> Result<(), NoTracksError> {
+		if self.live_track().is_none() {
+			return Err(NoTracksError);
+		}
+		let is_note_on = matches!(event, MidiEvent::NoteOn { .. });
+		if self.live_input_window.is_zero() || (!is_note_on && self.pending_live_events.is_empty())
+		{
+			self.dispatch_live_event(event, detune_cents);
+		} else {
+			if self.pending_live_events.is_empty() {
+				self.pending_live_since = self.frame;
+			}
+			// Note offs wait with the note ons before them, so quick taps still sound
+			self.pending_live_events.push((event, detune_cents));
+		}
+		Ok(())
+	}
+
+	fn flush_live_events(&mut self) {
+		if self.pending_live_events.is_empty() {
+			return;
+		}
+		let window_frames =
+			(self.live_input_window.as_secs_f64() * self.samples_per_second()) as u64;
+		if self.frame - self.pending_live_since < window_frames {
+			return;
+		}
+		for (event, detune_cents) in std::mem::take(&mut self.pending_live_events) {
+			self.dispatch_live_event(event, detune_cents);
+		}
+	}
+
+	fn dispatch_live_event(&mut self, event: MidiEvent, detune_cents: f32) {
+		let Some(track) = self
+			.live_track()
+			.and_then(|handle| self.tracks.get_mut(&handle))
+		else {
+			return;
+		};
+		match event {
+			MidiEvent::NoteOn {
+				channel,
+				note,
+				velocity,
+			} if detune_cents != 0.0 => track.start_detuned_live_note(
+				channel,
+				note,
+				velocity,
+				detune_cents,
+				&self.soundfont,
+			),
+			event => track.interpret_event(event, VoiceOrigin::Live, &self.soundfont),
+		}
+	}
+
+	/// Live notes play on the first track that's still loaded
+	fn live_track(&self) -> Option<MidiAudioTrackHandle> {
+		self.tracks
+			.iter()
+			.filter(|(_, track)| !track.retiring)
+			.map(|(handle, _)| *handle)
+			.min_by_key(|handle| handle.0)
+	}
+
+	/// Every track in the order they were added
+	pub fn track_handles(&self) -> Vec<MidiAudioTrackHandle> {
+		let mut handles = self.tracks.keys().copied().collect::<Vec<_>>();
+		handles.sort_by_key(|handle| handle.0);
+		handles
+	}
+
+	/// A snapshot of where the track is and how it's playing, for debugging tools
+	pub fn track_info(&self, handle: &MidiAudioTrackHandle) -> Option<TrackInfo> {
+		let track = self.tracks.get(handle)?;
+		Some(TrackInfo {
+			name: track.name().map(str::to_owned),
+			beat: track.beat,
+			bar: track.meter.bar_of(track.beat).max(0.0) as u32,
+			beats_per_second: track.beats_per_second * track.tempo_multiplier,
+			is_playing: track.is_playing,
+			volume: track.volume.value(),
+			muted: track.muted,
+			solo: track.solo,
+			active_voices: track.active_voices(),
+			level: track.level,
+		})
+	}
+
+	/// Notes held or releasing on the track, for keeping an eye on load. 0 if the track isn't loaded.
+	pub fn active_note_count(&self, handle: &MidiAudioTrackHandle) -> usize {
+		self.tracks
+			.get(handle)
+			.map_or(0, |track| track.active_notes())
+	}
+
+	/// Like [`Self::active_note_count`], summed over every track
+	pub fn total_active_note_count(&self) -> usize {
+		self.tracks.values().map(|track| track.active_notes()).sum()
+	}
+
+	/// The events waiting on the track's queue, in the order they were queued
+	pub fn queued_events(&self, handle: &MidiAudioTrackHandle) -> Option<&[MidiQueueEvent]> {
+		self.tracks.get(handle).map(|track| track.queue.as_slice())
+	}
+
+	/// Leaves the track out of the mix while it keeps playing
+	pub fn set_track_muted(&mut self, handle: &MidiAudioTrackHandle, muted: bool) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.muted = muted;
+		}
+	}
+
+	/// While any track is soloed, only soloed tracks are heard
+	pub fn set_track_solo(&mut self, handle: &MidiAudioTrackHandle, solo: bool) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.solo = solo;
+		}
+	}
+
+	/// Cuts off every note on one track, including live notes, leaving it playing.
+	/// See [`Self::stop_all_notes_all_tracks`] to silence everything.
+	pub fn stop_all_notes(&mut self, handle: &MidiAudioTrackHandle) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.stop_all_notes();
+		}
+	}
+
+	/// Cuts off every note on every track and every one-shot, leaving playback running
+	pub fn stop_all_notes_all_tracks(&mut self) {
+		for track in self.tracks.values_mut() {
+			track.stop_all_notes();
+		}
+		self.one_shots.clear();
+	}
+
+	/// Jumps the track to the start of `bar`, counting from 0, cutting off whatever it was playing
+	pub fn seek_to_bar(&mut self, handle: &MidiAudioTrackHandle, bar: u32) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.seek_to_beat(track.bar_start_beat(bar));
+		}
+	}
+
+	/// Stops the track where it is, freezing its notes, so [`Self::resume`] carries on from the same point
+	pub fn pause(&mut self, handle: &MidiAudioTrackHandle) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.pause();
+		}
+	}
+
+	pub fn resume(&mut self, handle: &MidiAudioTrackHandle) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.is_playing = true;
+		}
+	}
+
+	/// Like [`Self::pause`], for every track.
+	/// Unloaded songs still ringing or fading out are left to finish.
+	pub fn pause_all(&mut self) {
+		self.tracks
+			.values_mut()
+			.filter(|track| !track.retiring)
+			.for_each(MidiAudioTrack::pause);
+	}
+
+	/// Like [`Self::resume`], for every track, including ones that were stopped rather than paused.
+	/// Unloaded songs still ringing or fading out are left to finish.
+	pub fn resume_all(&mut self) {
+		for track in self.tracks.values_mut().filter(|track| !track.retiring) {
+			track.is_playing = true;
+		}
+	}
+
+	/// Stops the track and sends it back to its start, cutting off its notes. Play it again to start over.
+	pub fn restart(&mut self, handle: &MidiAudioTrackHandle) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.restart();
+		}
+	}
+
+	pub fn is_playing(&self, handle: &MidiAudioTrackHandle) -> bool {
+		self.tracks
+			.get(handle)
+			.is_some_and(|track| track.is_playing)
+	}
+
+	pub fn beats_per_second(&self, handle: &MidiAudioTrackHandle) -> Option<f64> {
+		self.tracks.get(handle).map(|track| track.beats_per_second)
+	}
+
+	pub fn beats_per_bar(&self, handle: &MidiAudioTrackHandle) -> Option<f64> {
+		self.tracks
+			.get(handle)
+			.map(|track| track.meter.beats_per_bar)
+	}
+
+	/// See [`MidiAudioTrack::current_beat`]. Use [`Self::audible_beat`] to line things up with what's heard.
+	pub fn current_beat(&self, handle: &MidiAudioTrackHandle) -> Option<f64> {
+		self.tracks.get(handle).map(MidiAudioTrack::current_beat)
+	}
+
+	pub fn current_tick(&self, handle: &MidiAudioTrackHandle) -> Option<f64> {
+		self.tracks.get(handle).map(MidiAudioTrack::current_tick)
+	}
+
+	/// See [`MidiAudioTrack::progress`]
+	pub fn progress(&self, handle: &MidiAudioTrackHandle) -> Option<f64> {
+		self.tracks.get(handle).map(MidiAudioTrack::progress)
+	}
+
+	/// See [`MidiAudioTrack::current_lyric`]. It changes when the lyric is rendered, a little before it's heard.
+	pub fn current_lyric(&self, handle: &MidiAudioTrackHandle) -> Option<&str> {
+		self.tracks.get(handle)?.current_lyric()
+	}
+
+	/// See [`MidiAudioTrack::current_key`]
+	pub fn current_key(&self, handle: &MidiAudioTrackHandle) -> Option<Key> {
+		self.tracks.get(handle)?.current_key()
+	}
+
+	/// A grid in the track's meter with a gridline on every beat, which queue timings fire on.
+	/// Refine it with [`BeatGrid::with_subdivision`] and friends to snap notes to the same bars.
+	pub fn beat_grid(&self, handle: &MidiAudioTrackHandle) -> Option<BeatGrid> {
+		self.tracks.get(handle).map(MidiAudioTrack::beat_grid)
+	}
+
+	/// The beat currently coming out of the speakers, accounting for audio that's buffered but not yet played
+	///
+	/// This is exact to the sample, but jumps whenever the audio backend pulls a burst of frames.
+	/// For animations and other UI, use [`Self::estimated_beat_at`] instead.
+	pub fn audible_beat(&self, handle: &MidiAudioTrackHandle) -> Option<f64> {
+		let track = self.tracks.get(handle)?;
+		let buffered_seconds = self.buffer.lock().unwrap().len() as f64
+			/ self.settings.channels as f64
+			/ self.samples_per_second();
+		Some(track.beat - buffered_seconds * track.beats_per_second * track.tempo_multiplier)
+	}
+
+	/// A smooth estimate of the beat coming out of the speakers at `instant`, for driving animations and other UI
+	///
+	/// Unlike [`Self::audible_beat`], it moves steadily with the wall clock however the audio backend
+	/// pulls frames, and is usually within a few milliseconds of it. Falls back to [`Self::audible_beat`]
+	/// until the asset has ticked.
+	pub fn estimated_beat_at(
+		&self,
+		handle: &MidiAudioTrackHandle,
+		instant: Instant,
+	) -> Option<f64> {
+		let track = self.tracks.get(handle)?;
+		let Some(played_frames) = self.playback_clock.played_frames_at(instant) else {
+			return self.audible_beat(handle);
+		};
+		let unplayed_seconds = (self.frame as f64 - played_frames) / self.samples_per_second();
+		Some(track.beat - unplayed_seconds * track.beats_per_second * track.tempo_multiplier)
+	}
+
+	/// NoteOns from the file within `beats_ahead` beats after the current position, on `channel` if given
+	pub fn upcoming_notes(
+		&self,
+		handle: &MidiAudioTrackHandle,
+		channel: Option<u8>,
+		beats_ahead: f64,
+	) -> Vec<UpcomingNote> {
+		self.tracks
+			.get(handle)
+			.map(|track| track.notes_between(channel, track.beat, track.beat + beats_ahead))
+			.unwrap_or_default()
+	}
+
+	/// A rough loudness overview of the whole track, for drawing waveform-style thumbnails
+	///
+	/// This is an analytical estimate rather than a render: each bucket holds the peak of the summed
+	/// velocities of the notes sounding in it (scaled by channel volume and mute state), normalized so the
+	/// loudest bucket is 1.0. It doesn't account for the shape of the instruments' samples, but it's
+	/// deterministic and linear in the number of events.
+	pub fn render_overview(&self, handle: &MidiAudioTrackHandle, buckets: usize) -> Vec<f32> {
+		self.tracks
+			.get(handle)
+			.map(|track| track.overview(buckets))
+			.unwrap_or_default()
+	}
+
+	/// NoteOns in the track's pickup, on `channel` if given, see [`MidiAudioTrack::with_pickup`]
+	///
+	/// Beats are relative to the start of the pass the pickup leads into, so they're negative,
+	/// or zero for notes right on the seam.
+	pub fn pickup_notes(
+		&self,
+		handle: &MidiAudioTrackHandle,
+		channel: Option<u8>,
+	) -> Vec<UpcomingNote> {
+		self.tracks
+			.get(handle)
+			.map(|track| track.pickup_notes(channel))
+			.unwrap_or_default()
+	}
+
+	/// NoteOns from the file between two beats, on `channel` if given
+	///
+	/// Beats are relative to the start of the track's current pass, so negative beats are in the previous pass
+	/// and beats past the end of the track are in the next one.
+	pub fn notes_between(
+		&self,
+		handle: &MidiAudioTrackHandle,
+		channel: Option<u8>,
+		start_beat: f64,
+		end_beat: f64,
+	) -> Vec<UpcomingNote> {
+		self.tracks
+			.get(handle)
+			.map(|track| track.notes_between(channel, start_beat, end_beat))
+			.unwrap_or_default()
+	}
+
+	pub fn set_channel_muted(&mut self, handle: &MidiAudioTrackHandle, channel: u8, muted: bool) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_muted(channel, muted);
+		}
+	}
+
+	/// Returns whether the channel is muted now, see [`MidiAudioTrack::toggle_channel_mute`]
+	pub fn toggle_channel_mute(&mut self, handle: &MidiAudioTrackHandle, channel: u8) -> bool {
+		self.tracks
+			.get_mut(handle)
+			.is_some_and(|track| track.toggle_channel_mute(channel))
+	}
+
+	/// See [`MidiAudioTrack::solo_channel`]
+	pub fn solo_channel(&mut self, handle: &MidiAudioTrackHandle, channel: u8) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.solo_channel(channel);
+		}
+	}
+
+	pub fn clear_channel_solo(&mut self, handle: &MidiAudioTrackHandle) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.clear_solo();
+		}
+	}
+
+	/// Bends every note on `channel`, e.g. from an MPE controller's per-note pitch bend
+	pub fn set_channel_bend(&mut self, handle: &MidiAudioTrackHandle, channel: u8, semitones: f32) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_bend(channel, semitones);
+		}
+	}
+
+	/// Sets how far the file's pitch bends bend a channel, see [`MidiAudioTrack::set_channel_bend_range`]
+	pub fn set_channel_bend_range(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		semitones: f32,
+	) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_bend_range(channel, semitones);
+		}
+	}
+
+	/// Sets how much the file's aftertouch scales a channel, see [`MidiAudioTrack::set_channel_aftertouch_depth`]
+	pub fn set_channel_aftertouch_depth(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		depth: f32,
+	) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_aftertouch_depth(channel, depth);
+		}
+	}
+
+	pub fn set_channel_pressure(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		pressure: f32,
+	) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_pressure(channel, pressure);
+		}
+	}
+
+	/// Sets a channel's volume, e.g. to rebalance a file's parts from the game
+	pub fn set_channel_volume(&mut self, handle: &MidiAudioTrackHandle, channel: u8, volume: f32) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_volume(channel, volume);
+		}
+	}
+
+	/// Pans a channel, from -1.0 for fully left to 1.0 for fully right
+	pub fn set_channel_pan(&mut self, handle: &MidiAudioTrackHandle, channel: u8, pan: f32) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_pan(channel, pan);
+		}
+	}
+
+	/// Sets a channel's expression, e.g. to swell a crossfading instrument from the game
+	pub fn set_channel_expression(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		expression: f32,
+	) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_expression(channel, expression);
+		}
+	}
+
+	/// Plays a note on a member channel of an MPE track all of its own, returning the channel
+	/// so the game can bend it with [`Self::set_channel_bend`] and stop it with [`Self::stop_mpe_note`].
+	/// `None` if the track isn't set up for MPE.
+	pub fn start_mpe_note(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		note: Note,
+		velocity: u8,
+	) -> Option<u8> {
+		let track = self.tracks.get_mut(handle)?;
+		let channel = track.allocate_mpe_channel()?;
+		track.interpret_event(
+			MidiEvent::NoteOn {
+				channel,
+				note: note.midi_number(),
+				velocity,
+			},
+			VoiceOrigin::Live,
+			&self.soundfont,
+		);
+		Some(channel)
+	}
+
+	pub fn stop_mpe_note(&mut self, handle: &MidiAudioTrackHandle, channel: u8, note: Note) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.interpret_event(
+				MidiEvent::NoteOff {
+					channel,
+					note: note.midi_number(),
+				},
+				VoiceOrigin::Live,
+				&self.soundfont,
+			);
+		}
+	}
+
+	/// Retunes a track, including the notes it's playing, see [`MidiAudioTrack::set_tuning`]
+	pub fn set_track_tuning(&mut self, handle: &MidiAudioTrackHandle, tuning: Tuning) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_tuning(tuning);
+		}
+	}
+
+	/// Sets a track's tempo from the game, see [`MidiAudioTrack::set_tempo`]
+	pub fn set_track_tempo(&mut self, handle: &MidiAudioTrackHandle, beats_per_minute: f64) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_tempo(beats_per_minute);
+		}
+	}
+
+	/// Pins a track's tempo so the file's tempo changes are ignored, see [`MidiAudioTrack::with_tempo_locked`]
+	pub fn set_track_tempo_locked(&mut self, handle: &MidiAudioTrackHandle, locked: bool) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_tempo_locked(locked);
+		}
+	}
+
+	/// Shifts one channel's notes, e.g. to move the bass up an octave without touching the drums.
+	/// Adds to the track's transposition, and notes already playing keep their pitch.
+	pub fn set_channel_transpose(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		semitones: i8,
+	) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.set_channel_transpose(channel, semitones);
+		}
+	}
+
+	/// Unmutes `channel` once the track reaches `beat`, e.g. the next note after a missed one
+	pub fn schedule_channel_unmute(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		beat: f64,
+	) {
+		if let Some(track) = self.tracks.get_mut(handle) {
+			track.scheduled_unmutes.push((beat, channel));
+		}
+	}
+
+	/// Plays a note once on its own, outside of any track
+	pub fn play_one_shot(&mut self, bank_number: u8, patch_number: u8, note: Note, velocity: u8) {
+		let mut channel = Channel::new(bank_number, patch_number);
+		if let Ok(voice) = channel.create_voice(
+			note.midi_number(),
+			velocity,
+			&self.soundfont,
+			self.samples_per_second() as f32,
+		) {
+			self.one_shots.push(voice);
+		}
+	}
+
+	/// Adds a fresh copy of a track, see [`MidiAudioTrack::duplicate`]
+	pub fn duplicate_track(
+		&mut self,
+		handle: &MidiAudioTrackHandle,
+	) -> Option<MidiAudioTrackHandle> {
+		let track = self.tracks.get(handle)?.duplicate();
+		Some(self.add_track(track))
+	}
+
+	pub fn load_report(&self, handle: &MidiAudioTrackHandle) -> Option<&LoadReport> {
+		self.tracks
+			.get(handle)
+			.and_then(MidiAudioTrack::load_report)
+	}
+
+	/// What a channel would play `note` with right now, for debugging instruments that sound wrong.
+	/// `note` is the key before any transposition, as it appears in the file.
+	pub fn explain_note(
+		&self,
+		handle: &MidiAudioTrackHandle,
+		channel: u8,
+		note: u8,
+		velocity: u8,
+	) -> Option<NoteExplanation> {
+		let track = self.tracks.get(handle)?;
+		let note = track.transposed(channel, note);
+		let channel = track.channels.get(&channel)?;
+		let regions =
+			self.soundfont
+				.resolve(note, velocity, channel.bank_number, channel.patch_number);
+		let custom_instrument = channel.instrument.is_some();
+		Some(NoteExplanation {
+			bank_number: channel.bank_number,
+			patch_number: channel.patch_number,
+			preset_name: self
+				.soundfont
+				.preset_name(channel.bank_number, channel.patch_number)
+				.map(str::to_owned),
+			uses_fallback: !custom_instrument
+				&& regions.is_empty()
+				&& self.soundfont.has_fallback(),
+			custom_instrument,
+			regions,
+		})
+	}
+}
+
+/// How a channel would play a note, see [`MidiAudio::explain_note`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteExplanation {
+	pub bank_number: u8,
+	pub patch_number: u8,
+	pub preset_name: Option<String>,
+	/// The SoundFont regions that would play, empty if the preset has none for the note
+	pub regions: Vec<RegionMatch>,
+	/// Whether the built-in synth would play the note instead of the SoundFont
+	pub uses_fallback: bool,
+	/// Whether the channel plays a custom [`super::Instrument`], which the regions don't apply to
+	pub custom_instrument: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+#[non_exhaustive]
+pub struct NoTracksError;
+
+impl std::fmt::Display for NoTracksError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "MidiAudio has no tracks to play live notes on")
+	}
+}
+
+impl std::error::Error for NoTracksError {}
+
+/// Why [`MidiAudio::try_from_bytes`] couldn't load a SoundFont
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SoundFontLoadError {
+	/// Doesn't start with a RIFF chunk
+	NotASoundFont,
+	/// Ends partway through a chunk
+	Truncated,
+	/// Has samples that aren't 16-bit
+	UnsupportedSampleFormat,
+	/// Missing chunks, or presets, instruments or samples that refer to ones that don't exist
+	Malformed(SoundFontError),
+}
+
+impl From<SoundFontError> for SoundFontLoadError {
+	fn from(error: SoundFontError) -> Self {
+		match error {
+			SoundFontError::IoError(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+				Self::Truncated
+			}
+			SoundFontError::RiffChunkNotFound | SoundFontError::InvalidRiffChunkType { .. } => {
+				Self::NotASoundFont
+			}
+			SoundFontError::UnsupportedSampleFormat => Self::UnsupportedSampleFormat,
+			error => Self::Malformed(error),
+		}
+	}
+}
+
+impl std::fmt::Display for SoundFontLoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NotASoundFont => write!(f, "not a SoundFont"),
+			Self::Truncated => write!(f, "the file ends partway through a chunk"),
+			Self::UnsupportedSampleFormat => write!(f, "only 16-bit samples are supported"),
+			Self::Malformed(error) => write!(f, "malformed SoundFont: {error}"),
+		}
+	}
+}
+
+impl std::error::Error for SoundFontLoadError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Malformed(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct MidiAudioTrackHandle(usize);
+
+/// A group of tracks loaded together, see [`MidiAudio::load_song`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct SongHandle(usize);
+
+/// Reports when the asset a [`MidiAudio::handoff_from`] handed off from is done playing
+#[derive(Debug, Clone)]
+pub struct HandoffHandle {
+	faded: Arc<AtomicBool>,
+	buffer: Arc<Mutex<VecDeque<i16>>>,
+}
+
+impl HandoffHandle {
+	/// Whether the old asset has faded out completely and played everything it rendered,
+	/// so it can be despawned without an audible cut
+	pub fn is_finished(&self) -> bool {
+		self.faded.load(Ordering::Relaxed) && self.buffer.lock().unwrap().is_empty()
+	}
+}
+
+/// Plays a [`MidiAudio`]'s rendered audio, see [`Decodable::decoder`]
+pub struct MidiDecoder {
+	buffer: Arc<Mutex<VecDeque<i16>>>,
+	num_audio_channels: u16,
+	samples_per_second: u32,
+}
+
+impl MidiDecoder {
+	/// Takes the next frame as left and right samples from -1.0 to 1.0, silent if nothing's been rendered yet.
+	/// For audio backends that pull whole stereo frames instead of going through bevy_audio.
+	pub fn next_frame(&mut self) -> [f32; 2] {
+		let mut buffer = self.buffer.lock().unwrap();
+		let mut next = || buffer.pop_front().unwrap_or(0) as f32 / i16::MAX as f32;
+		let left = next();
+		let right = if self.num_audio_channels == 1 {
+			left
+		} else {
+			next()
+		};
+		[left, right]
+	}
+}
+
+impl Iterator for MidiDecoder {
+	type Item = i16;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.buffer.lock().unwrap().pop_front().or(Some(0))
+	}
+}
+
+impl Source for MidiDecoder {
+	fn current_frame_len(&self) -> Option<usize> {
+		if self.buffer.lock().unwrap().is_empty() {
+			Some(1)
+		} else {
+			None
+		}
+	}
+
+	fn channels(&self) -> u16 {
+		self.num_audio_channels
+	}
+
+	fn sample_rate(&self) -> u32 {
+		self.samples_per_second
+	}
+
+	fn total_duration(&self) -> Option<Duration> {
+		None
+	}
+}
+
+impl Decodable for MidiAudio {
+	type DecoderItem = <MidiDecoder as Iterator>::Item;
+
+	type Decoder = MidiDecoder;
+
+	fn decoder(&self) -> Self::Decoder {
+		self.decoder_created.store(true, Ordering::Relaxed);
+		MidiDecoder {
+			buffer: self.buffer.clone(),
+			num_audio_channels: self.settings.channels,
+			samples_per_second: self.settings.sample_rate,
+		}
+	}
+}
+
+/// A gain that ramps linearly to a target, a frame at a time
+pub(super) struct GainRamp {
+	pub(super) gain: f32,
+	target: f32,
+	/// Per frame
+	step: f32,
+	delay_frames: u64,
+}
+
+impl Default for GainRamp {
+	fn default() -> Self {
+		Self {
+			gain: 1.0,
+			target: 1.0,
+			step: 0.0,
+			delay_frames: 0,
+		}
+	}
+}
+
+impl GainRamp {
+	pub(super) fn fade_to(&mut self, gain: f32, frames: f64, delay_frames: u64) {
+		self.target = gain;
+		self.step = (gain - self.gain) / frames.max(1.0) as f32;
+		self.delay_frames = delay_frames;
+	}
+
+	pub(super) fn is_silent(&self) -> bool {
+		self.gain == 0.0 && self.target == 0.0
+	}
+
+	pub(super) fn tick(&mut self) {
+		if self.delay_frames > 0 {
+			self.delay_frames -= 1;
+		} else if self.gain != self.target {
+			self.gain += self.step;
+			if (self.step > 0.0 && self.gain >= self.target)
+				|| (self.step <= 0.0 && self.gain <= self.target)
+			{
+				self.gain = self.target;
+			}
+		}
+	}
+}
+
+/// How the audio either side of a loop seam compares, see [`MidiAudio::analyze_loop_seam`]
+///
+/// Levels are relative to full scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeamReport {
+	/// The jump between the last frame of the loop and the first frame of the next pass
+	pub discontinuity: f32,
+	pub tail_level: f32,
+	pub head_level: f32,
+}
+
+/// Where a track is and how it's playing, see [`MidiAudio::track_info`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackInfo {
+	pub name: Option<String>,
+	/// Within the current pass
+	pub beat: f64,
+	/// Within the current pass, counting from 0
+	pub bar: u32,
+	/// Including the tempo multiplier
+	pub beats_per_second: f64,
+	pub is_playing: bool,
+	pub volume: f32,
+	pub muted: bool,
+	pub solo: bool,
+	pub active_voices: usize,
+	/// The loudest sample of the last render relative to full scale, whether the track is muted or not
+	pub level: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StrictPolicy {
+	/// Silently ignore anything that can't be played
+	#[default]
+	Lenient,
+	/// Record violations, see [`MidiAudio::strict_violations`]
+	Collect,
+	/// Record violations, and panic on the first one in debug builds
+	PanicInDebug,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrictViolation {
+	pub track: MidiAudioTrackHandle,
+	/// Where the track was when it happened, if it happened during playback
+	pub beat: Option<f64>,
+	pub kind: StrictViolationKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StrictViolationKind {
+	/// The SoundFont has no preset for a note's bank and patch
+	MissingPreset { bank: u8, patch: u8 },
+	/// The preset exists but has no samples for a note
+	EmptySampleSet { bank: u8, patch: u8, note: u8 },
+	/// A note off with no voice playing to stop
+	UnmatchedNoteOff { channel: u8, note: u8 },
+	/// An event for a channel above 15
+	ChannelOutOfRange { channel: u8 },
+	/// An event for a channel the track doesn't have
+	UnknownChannel { channel: u8 },
+	/// A queue event or batch for a track that isn't in this asset
+	UnknownTrack,
+	/// A channel's custom instrument had nothing to play for a note
+	InstrumentDeclined { note: u8 },
+}
+
+impl StrictViolationKind {
+	pub(super) fn unknown_channel(channel: u8) -> Self {
+		if channel > 15 {
+			Self::ChannelOutOfRange { channel }
+		} else {
+			Self::UnknownChannel { channel }
+		}
+	}
+}
+
+/// Names presets and keys after their General MIDI instruments and drums, e.g. "bank 128 patch 0 key 70 'Maracas' has no region"
+impl std::fmt::Display for StrictViolationKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match *self {
+			Self::MissingPreset { bank, patch } => {
+				write!(f, "{} has no preset", PresetName { bank, patch })
+			}
+			Self::EmptySampleSet { bank, patch, note } if bank == PERCUSSION_BANK => {
+				write!(f, "{} key {note}", PresetName { bank, patch })?;
+				if let Some(drum) = gm::drum_name(note) {
+					write!(f, " '{drum}'")?;
+				}
+				write!(f, " has no region")
+			}
+			Self::EmptySampleSet { bank, patch, note } => write!(
+				f,
+				"{} has no region for note {note}",
+				PresetName { bank, patch }
+			),
+			Self::UnmatchedNoteOff { channel, note } => {
+				write!(f, "channel {channel} key {note}")?;
+				if channel == 9
+					&& let Some(drum) = gm::drum_name(note)
+				{
+					write!(f, " '{drum}'")?;
+				}
+				write!(f, " was let go without being played")
+			}
+			Self::ChannelOutOfRange { channel } => write!(f, "channel {channel} is above 15"),
+			Self::UnknownChannel { channel } => write!(f, "channel {channel} isn't on the track"),
+			Self::UnknownTrack => write!(f, "the track isn't in this asset"),
+			Self::InstrumentDeclined { note } => write!(
+				f,
+				"the channel's instrument had nothing to play for note {note}"
+			),
+		}
+	}
+}
+
+impl std::fmt::Display for StrictViolation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} on track {}", self.kind, self.track.0)?;
+		if let Some(beat) = self.beat {
+			write!(f, " at beat {beat:.2}")?;
+		}
+		Ok(())
+	}
+}
+
+/// "bank 0 patch 48 'String Ensemble 1'", leaving the name off drum kits since General MIDI only names their keys
+struct PresetName {
+	bank: u8,
+	patch: u8,
+}
+
+impl std::fmt::Display for PresetName {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "bank {} patch {}", self.bank, self.patch)?;
+		if self.bank != PERCUSSION_BANK {
+			write!(f, " '{}'", gm::instrument_name(self.patch))?;
+		}
+		Ok(())
+	}
+}
+
+/// Everything dispatched during one [`MidiAudio::advance_logical`] call
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct EventBatch {
+	pub start_frame: u64,
+	pub frames: u32,
+	pub events: Vec<DispatchedEvent>,
+}
+
+impl EventBatch {
+	/// A hash of the batch for comparing between machines built from the same source
+	pub fn checksum(&self) -> u64 {
+		let mut hasher = std::hash::DefaultHasher::new();
+		self.hash(&mut hasher);
+		hasher.finish()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct DispatchedEvent {
+	pub frame: u64,
+	pub track: MidiAudioTrackHandle,
+	pub kind: DispatchedEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum DispatchedEventKind {
+	Midi(MidiEvent),
+	Timing(MidiQueueTiming),
+	Queue(MidiQueueEventType),
+}
+
+pub(crate) enum MidiBufferMessage {
+	Audio(i16),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::musical_time::{MusicalTime, MusicalTimeListener};
+	use crate::{
+		Automation, AutomationParameter, ChannelPlaybackOptions, Drift, Instrument,
+		InstrumentContext, LayerAlternation, MidiQueueLooping, MpeConfig, SynthInstrument,
+		VoiceSource,
+	};
+
+	fn audio() -> MidiAudio {
+		MidiAudio::from_bytes(include_bytes!("../../assets/hl4mgm.sf2")).with_logical_clock()
+	}
+
+	fn fray() -> MidiAudioTrack {
+		MidiAudioTrack::from_bytes(include_bytes!("../../assets/fray.mid"), 1.0)
+	}
+
+	#[test]
+	fn soundfont_load_errors() {
+		let soundfont = include_bytes!("../../assets/hl4mgm.sf2");
+		assert!(matches!(
+			MidiAudio::try_from_bytes(b"not a soundfont"),
+			Err(SoundFontLoadError::NotASoundFont)
+		));
+		assert!(matches!(
+			MidiAudio::try_from_bytes(&soundfont[..soundfont.len() / 2]),
+			Err(SoundFontLoadError::Truncated)
+		));
+		assert!(matches!(
+			MidiAudio::try_from_bytes(b""),
+			Err(SoundFontLoadError::Truncated)
+		));
+	}
+
+	#[test]
+	fn unloaded_songs_ring_out_and_are_removed() {
+		let mut audio = audio();
+		let song = audio.load_song(vec![fray()]);
+		audio.add_track(fray());
+		audio.advance_logical(44100);
+		audio.unload_song(song);
+		assert_eq!(audio.tracks.len(), 2);
+		audio.advance_logical(44100 * 5);
+		assert_eq!(audio.tracks.len(), 1);
+	}
+
+	#[test]
+	fn resume_all_leaves_unloaded_songs_to_ring_out() {
+		let mut audio = audio();
+		let song = audio.load_song(vec![fray()]);
+		audio.add_track(fray());
+		audio.advance_logical(44100);
+		audio.unload_song(song);
+		audio.resume_all();
+		assert_eq!(audio.tracks.len(), 2);
+		audio.advance_logical(44100 * 5);
+		assert_eq!(audio.tracks.len(), 1);
+	}
+
+	#[test]
+	fn pause_all_lets_switched_songs_fade_out() {
+		let mut audio = audio();
+		let song = audio.load_song(vec![fray()]);
+		audio.advance_logical(44100);
+		audio.switch_song(song, vec![fray()], Duration::from_millis(100));
+		audio.pause_all();
+		let retiring = audio.tracks.values().find(|track| track.retiring).unwrap();
+		assert!(retiring.is_playing && !retiring.quiet);
+		audio.advance_logical(44100);
+		assert_eq!(audio.tracks.len(), 1);
+	}
+
+	#[test]
+	fn switching_songs_round_and_round_leaves_nothing_behind() {
+		let mut audio = audio();
+		let songs = |index| {
+			let lead =
+				MidiAudioTrack::from_bytes(include_bytes!("../../assets/fray lead.mid"), 1.0);
+			let backing =
+				MidiAudioTrack::from_bytes(include_bytes!("../../assets/fray backing.mid"), 1.0);
+			match index {
+				0 => vec![fray()],
+				1 => vec![lead, backing],
+				_ => vec![lead],
+			}
+		};
+		let mut song = audio.load_song(songs(0));
+		audio.advance_logical(44100);
+		let baseline = (
+			audio.tracks.len(),
+			audio.songs.len(),
+			audio.total_active_note_count(),
+		);
+		assert!(baseline.2 > 0);
+		for next in [1, 2, 0].repeat(3) {
+			song = audio.switch_song(song, songs(next), Duration::from_millis(100));
+			audio.advance_logical(44100);
+		}
+		assert_eq!(audio.song_tracks(&song).map(<[_]>::len), Some(1));
+		assert_eq!(
+			(
+				audio.tracks.len(),
+				audio.songs.len(),
+				audio.total_active_note_count()
+			),
+			baseline
+		);
+	}
+
+	/// A MIDI file with one track of `events` at 480 ticks per beat
+	fn file(events: &[u8]) -> Vec<u8> {
+		let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xE0MTrk".to_vec();
+		bytes.extend((events.len() as u32).to_be_bytes());
+		bytes.extend(events);
+		bytes
+	}
+
+	fn track(events: &[u8]) -> MidiAudioTrack {
+		MidiAudioTrack::from_bytes(&file(events), 1.0)
+	}
+
+	/// A track with no notes of its own, to play live notes on
+	fn silent_track() -> MidiAudioTrack {
+		track(b"\0\xFF\x2F\0")
+	}
+
+	/// How many times the left channel crosses zero going up over `frames` frames, about its frequency per second
+	fn upward_crossings(audio: &mut MidiAudio, frames: u32) -> usize {
+		let mut decoder = audio.decoder();
+		let mut crossings = 0;
+		let mut last = 0.0;
+		for _ in 0..frames / 441 {
+			audio.advance_logical(441);
+			for _ in 0..441 {
+				let [left, _] = decoder.next_frame();
+				if last < 0.0 && left >= 0.0 {
+					crossings += 1;
+				}
+				last = left;
+			}
+		}
+		crossings
+	}
+
+	#[test]
+	fn tuning_scales_pitch() {
+		let mut counts = vec![];
+		for a4 in [440.0, 432.0] {
+			let mut audio = audio();
+			// A sine wave crosses zero once a cycle, where the SoundFont's samples have overtones
+			audio.add_track(
+				silent_track()
+					.with_channel_instrument(0, SynthInstrument::new(Waveform::Sine))
+					.with_tuning(Tuning::new(a4)),
+			);
+			audio.start_playing_note(Note::A4).unwrap();
+			audio.advance_logical(4410);
+			counts.push(upward_crossings(&mut audio, 44100) as f32);
+		}
+		assert!((counts[0] - 440.0).abs() <= 1.0, "{counts:?}");
+		assert!((counts[1] - 432.0).abs() <= 1.0, "{counts:?}");
+	}
+
+	#[test]
+	fn full_pitch_bend_is_a_whole_tone_up() {
+		let mut audio = audio();
+		// Bent all the way up, then A4 held for four beats
+		let events = b"\0\xE0\x7F\x7F\0\x90\x45\x64\x8F\x00\x80\x45\0\0\xFF\x2F\0";
+		audio.add_track(
+			track(events).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		audio.advance_logical(4410);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::B4.frequency).abs() <= 1.0, "{crossings}");
+	}
+
+	#[test]
+	fn pitch_bend_range_is_per_channel() {
+		let mut audio = audio();
+		let events = b"\0\xE0\x7F\x7F\0\x90\x45\x64\x8F\x00\x80\x45\0\0\xFF\x2F\0";
+		audio.add_track(
+			track(events)
+				.with_channel_instrument(0, SynthInstrument::new(Waveform::Sine))
+				.with_channel_bend_range(0, 12.0),
+		);
+		audio.advance_logical(4410);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::A5.frequency).abs() <= 1.0, "{crossings}");
+	}
+
+	/// The loudest left channel sample over `frames` frames
+	fn peak(audio: &mut MidiAudio, frames: u32) -> f32 {
+		let mut decoder = audio.decoder();
+		audio.advance_logical(frames);
+		(0..frames)
+			.map(|_| decoder.next_frame()[0].abs())
+			.fold(0.0, f32::max)
+	}
+
+	#[test]
+	fn rising_aftertouch_gets_louder() {
+		let mut audio = audio();
+		// A4 held while the channel's pressure rises from nothing to full a beat at a time
+		let events = b"\0\xD0\x00\0\x90\x45\x64\x83\x60\xD0\x40\x83\x60\xD0\x7F\x83\x60\x80\x45\0\0\xFF\x2F\0";
+		audio.add_track(
+			track(events).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		let peaks = (0..3)
+			.map(|_| {
+				audio.advance_logical(4410);
+				peak(&mut audio, 22050 - 4410)
+			})
+			.collect::<Vec<_>>();
+		assert!(peaks[0] < 0.01, "{peaks:?}");
+		assert!(peaks[1] > 0.01 && peaks[1] < peaks[2], "{peaks:?}");
+	}
+
+	/// Both sides of the piano's stereo samples for a held C4, with the pan controller set to `pan`
+	fn piano_frames(pan: u8) -> Vec<[f32; 2]> {
+		let mut audio = audio();
+		let mut events = b"\0\xB0\x0A".to_vec();
+		events.push(pan);
+		events.extend(b"\0\x90\x3C\x64\x8F\x00\x80\x3C\0\0\xFF\x2F\0");
+		audio.add_track(track(&events));
+		let mut decoder = audio.decoder();
+		audio.advance_logical(22050);
+		(0..22050).map(|_| decoder.next_frame()).collect()
+	}
+
+	#[test]
+	fn hard_left_balances_stereo_samples() {
+		let centered = piano_frames(64);
+		let left = piano_frames(0);
+		assert!(centered.iter().any(|frame| frame[1] != 0.0));
+		assert!(left.iter().all(|frame| frame[1] == 0.0));
+		// The near side's sample plays as is rather than being boosted
+		assert!(
+			centered
+				.iter()
+				.zip(&left)
+				.all(|(centered, left)| centered[0] == left[0])
+		);
+	}
+
+	#[test]
+	fn key_pressure_only_affects_its_note() {
+		let mut audio = audio();
+		// A4 and E5 held, with no pressure on A4
+		let events =
+			b"\0\x90\x45\x64\0\x90\x4C\x64\0\xA0\x45\x00\x8F\x00\x80\x45\0\0\x80\x4C\0\0\xFF\x2F\0";
+		audio.add_track(
+			track(events).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		audio.advance_logical(4410);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::E5.frequency).abs() <= 1.0, "{crossings}");
+	}
+
+	/// The peak of a sine A4 held for a beat after `controllers`
+	fn peak_after(controllers: &[u8]) -> f32 {
+		let mut audio = audio();
+		let events = [controllers, b"\0\x90\x45\x64\x83\x60\x80\x45\0\0\xFF\x2F\0"].concat();
+		audio.add_track(
+			track(&events).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		peak(&mut audio, 11025)
+	}
+
+	#[test]
+	fn channels_default_to_volume_100() {
+		let default = peak_after(b"");
+		assert_eq!(peak_after(b"\0\xB0\x07\x64"), default);
+		let low = peak_after(b"\0\xB0\x07\x19");
+		assert!(
+			(low / default - 25.0 / 100.0).abs() < 0.01,
+			"{low} vs {default}"
+		);
+	}
+
+	/// The left channel of the next `frames` frames
+	fn left_frames(audio: &mut MidiAudio, frames: u32) -> Vec<f32> {
+		let mut decoder = audio.decoder();
+		audio.advance_logical(frames);
+		(0..frames).map(|_| decoder.next_frame()[0]).collect()
+	}
+
+	/// The largest jump between neighbouring frames
+	fn largest_step(frames: &[f32]) -> f32 {
+		frames
+			.windows(2)
+			.map(|pair| (pair[1] - pair[0]).abs())
+			.fold(0.0, f32::max)
+	}
+
+	#[test]
+	fn volume_jumps_glide_without_clicking() {
+		let mut audio = audio();
+		// A4 held for eight beats
+		let events = b"\0\x90\x45\x64\x9E\x00\x80\x45\0\0\xFF\x2F\0";
+		audio.add_track(
+			track(events).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		// Past the note's attack, and stopping mid-cycle where cutting the sine straight off would click
+		left_frames(&mut audio, 4410);
+		let mut frames = left_frames(&mut audio, 4000);
+		let steady = largest_step(&frames);
+		audio.set_volume(0.0);
+		frames.extend(left_frames(&mut audio, 4410));
+		let cut = largest_step(&frames[3999..]);
+		assert!(cut <= steady * 1.01, "{steady} -> {cut}");
+		assert!(peak(&mut audio, 4410) < 0.001);
+	}
+
+	#[test]
+	fn bars_follow_meter_changes() {
+		let mut audio = audio();
+		// Eight beats of 4/4, then twelve of 3/4 under a held note that runs to the end
+		let events = b"\0\xFF\x58\x04\x04\x02\x18\x08\x9E\x00\xFF\x58\x04\x03\x02\x18\x08\0\x90\x3C\x40\xAD\x00\x80\x3C\0\0\xFF\x2F\0";
+		audio.add_track(MidiAudioTrack::from_bytes(&file(events), None));
+		let batch = audio.advance_logical(44100 * 10 - 1);
+		let bar_beats = batch
+			.events
+			.iter()
+			.filter(|event| event.kind == DispatchedEventKind::Timing(MidiQueueTiming::Bar))
+			.map(|event| (event.frame as f64 / 22050.0).round())
+			.collect::<Vec<_>>();
+		assert_eq!(bar_beats, vec![4.0, 8.0, 11.0, 14.0, 17.0]);
+		let track = audio.tracks.values().next().unwrap();
+		assert_eq!(track.musical_time().beats_per_bar, 3.0);
+	}
+
+	#[test]
+	fn stops_on_a_marker() {
+		let mut audio = audio();
+		// "verse" at the start and "chorus" four beats in, under a note held for eight beats
+		let events = b"\0\xFF\x06\x05verse\0\x90\x3C\x40\x8F\x00\xFF\x06\x06chorus\x8F\x00\x80\x3C\0\0\xFF\x2F\0";
+		assert_eq!(
+			MidiTrack::from_bytes(&file(events)).markers(),
+			vec![(0, "verse".to_string()), (1920, "chorus".to_string())]
+		);
+		let handle = audio.add_track(track(events));
+		audio.queue(
+			handle,
+			MidiQueueEvent {
+				event: MidiQueueEventType::Stop,
+				timing: MidiQueueTiming::Marker("chorus".to_string()),
+				looping: MidiQueueLooping::Once,
+			},
+		);
+		let batch = audio.advance_logical(44100 * 3);
+		let stops = batch
+			.events
+			.iter()
+			.filter(|event| event.kind == DispatchedEventKind::Queue(MidiQueueEventType::Stop))
+			.map(|event| event.frame)
+			.collect::<Vec<_>>();
+		assert_eq!(stops, vec![4 * 22050]);
+		let track = &audio.tracks[&handle];
+		assert!(!track.is_playing);
+		assert!((track.current_beat() - 4.0).abs() < 0.001);
+	}
+
+	#[test]
+	fn set_tempo_on_the_next_bar() {
+		let mut audio = audio();
+		// A note held for eight beats
+		let handle = audio.add_track(track(b"\0\x90\x3C\x40\x9E\x00\x80\x3C\0\0\xFF\x2F\0"));
+		audio.queue(
+			handle,
+			MidiQueueEvent {
+				event: MidiQueueEventType::SetTempo(140.0),
+				timing: MidiQueueTiming::Bar,
+				looping: MidiQueueLooping::Once,
+			},
+		);
+		audio.advance_logical(44100);
+		assert_eq!(audio.tracks[&handle].musical_time().beats_per_second, 2.0);
+		let batch = audio.advance_logical(44100);
+		let tempo_changes = batch
+			.events
+			.iter()
+			.filter(|event| {
+				event.kind == DispatchedEventKind::Queue(MidiQueueEventType::SetTempo(140.0))
+			})
+			.map(|event| event.frame)
+			.collect::<Vec<_>>();
+		assert_eq!(tempo_changes, vec![4 * 22050]);
+		assert_eq!(
+			audio.tracks[&handle].musical_time().beats_per_second,
+			140.0 / 60.0
+		);
+	}
+
+	/// The frames `event` was dispatched at on `handle`
+	fn dispatched_frames(
+		batch: &EventBatch,
+		handle: MidiAudioTrackHandle,
+		event: MidiQueueEventType,
+	) -> Vec<u64> {
+		batch
+			.events
+			.iter()
+			.filter(|dispatched| {
+				dispatched.track == handle
+					&& dispatched.kind == DispatchedEventKind::Queue(event.clone())
+			})
+			.map(|dispatched| dispatched.frame)
+			.collect()
+	}
+
+	/// Beats add up a sample at a time, so boundaries can land a frame or two either side
+	fn assert_near_frames(frames: Vec<u64>, expected: &[u64]) {
+		assert_eq!(frames.len(), expected.len(), "{frames:?}");
+		for (frame, expected) in frames.iter().zip(expected) {
+			assert!(frame.abs_diff(*expected) <= 2, "{frames:?}");
+		}
+	}
+
+	fn stop_on_loop() -> MidiQueueEvent {
+		MidiQueueEvent {
+			event: MidiQueueEventType::Stop,
+			timing: MidiQueueTiming::Loop,
+			looping: MidiQueueLooping::Once,
+		}
+	}
+
+	#[test]
+	fn loop_timings_follow_their_own_track() {
+		let mut audio = audio();
+		// Four and eight beat loops
+		audio.add_track(track(b"\0\x90\x3C\x40\x8F\x00\x80\x3C\0\0\xFF\x2F\0"));
+		let long = audio.add_track(track(b"\0\x90\x3C\x40\x9E\x00\x80\x3C\0\0\xFF\x2F\0"));
+		// Past the start of the first pass, which is a Loop of its own
+		audio.advance_logical(1);
+		audio.queue(long, stop_on_loop());
+		let batch = audio.advance_logical(44100 * 5);
+		assert_near_frames(
+			dispatched_frames(&batch, long, MidiQueueEventType::Stop),
+			&[8 * 22050],
+		);
+	}
+
+	#[test]
+	fn shared_timing_follows_every_track() {
+		let mut audio = audio();
+		audio.add_track(track(b"\0\x90\x3C\x40\x8F\x00\x80\x3C\0\0\xFF\x2F\0"));
+		let long = audio
+			.add_track(track(b"\0\x90\x3C\x40\x9E\x00\x80\x3C\0\0\xFF\x2F\0").with_shared_timing());
+		// Past the start of the first pass, which is a Loop of its own
+		audio.advance_logical(1);
+		audio.queue(long, stop_on_loop());
+		let batch = audio.advance_logical(44100 * 5);
+		assert_near_frames(
+			dispatched_frames(&batch, long, MidiQueueEventType::Stop),
+			&[4 * 22050],
+		);
+	}
+
+	/// Whether the track's channel 0 is holding a live C4
+	fn live_c4_held(audio: &MidiAudio, handle: MidiAudioTrackHandle) -> bool {
+		audio.tracks[&handle].channels[&0]
+			.voices
+			.contains_key(&(VoiceOrigin::Live, 60))
+	}
+
+	/// C4 on channel 0 for a beat, then E4 to beat 4
+	const C4_THEN_E4: &[u8] =
+		b"\0\x90\x3C\x40\x83\x60\x80\x3C\0\0\x90\x40\x40\x9E\x00\x80\x40\0\0\xFF\x2F\0";
+
+	#[test]
+	fn live_notes_outlast_the_files_note_off() {
+		let mut audio = audio();
+		let handle = audio.add_track(track(C4_THEN_E4));
+		audio.advance_logical(100);
+		audio.start_playing_note(Note::C4).unwrap();
+		audio.advance_logical(22050);
+		assert!(live_c4_held(&audio, handle));
+
+		audio.stop_playing_note(Note::C4).unwrap();
+		assert!(!live_c4_held(&audio, handle));
+	}
+
+	#[test]
+	fn shared_voice_ownership_lets_the_file_stop_live_notes() {
+		let mut audio = audio();
+		let handle = audio.add_track(track(C4_THEN_E4).with_shared_voice_ownership());
+		audio.advance_logical(100);
+		audio.start_playing_note(Note::C4).unwrap();
+		audio.advance_logical(22050);
+		assert!(!live_c4_held(&audio, handle));
+	}
+
+	/// Peaks just after beats 1, 4, 7 and 9 of a held eight beat sine note
+	fn automated_peaks(automation: Automation) -> Vec<f32> {
+		let mut audio = audio();
+		audio.add_track(
+			track(b"\0\x90\x3C\x7F\x9E\x00\x80\x3C\0\0\xFF\x2F\0")
+				.with_channel_instrument(0, SynthInstrument::new(Waveform::Sine))
+				.with_automation(automation),
+		);
+		let mut frame = 0;
+		let mut peaks = vec![];
+		for beat in [1, 4, 7, 9] {
+			peak(&mut audio, beat * 22050 - frame);
+			peaks.push(peak(&mut audio, 441));
+			frame = beat * 22050 + 441;
+		}
+		peaks
+	}
+
+	#[test]
+	fn volume_automation_loops_with_the_track() {
+		// The sine's envelope fades too, so compare against it without automation
+		let plain = automated_peaks(Automation::new());
+		// Full volume for two beats, then down to a fifth by beat 6
+		let automated = automated_peaks(
+			Automation::new()
+				.with_breakpoint(0.0, AutomationParameter::TrackVolume, 1.0)
+				.with_breakpoint(2.0, AutomationParameter::TrackVolume, 1.0)
+				.with_breakpoint(6.0, AutomationParameter::TrackVolume, 0.2),
+		);
+		// Beat 9 is beat 1 again
+		for ((automated, plain), expected) in automated.iter().zip(&plain).zip([1.0, 0.6, 0.2, 1.0])
+		{
+			assert!(
+				(automated / plain - expected).abs() < 0.02,
+				"{automated:?} {plain:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn missed_notes_unmute_on_schedule() {
+		let mut audio = audio();
+		let handle = audio.add_track(fray());
+		audio.set_channel_muted(&handle, 0, true);
+		audio.schedule_channel_unmute(&handle, 0, 2.0);
+		let muted = |audio: &MidiAudio| audio.tracks[&handle].channels[&0].muted;
+		audio.advance_logical(22050);
+		assert!(muted(&audio));
+		audio.advance_logical(22050 + 10);
+		assert!(!muted(&audio));
+		assert!(audio.tracks[&handle].scheduled_unmutes.is_empty());
+	}
+
+	#[test]
+	fn duplicated_tracks_start_fresh() {
+		let mut audio = audio();
+		let handle = audio.add_track(fray());
+		audio.advance_logical(44100);
+		audio.queue(
+			handle,
+			MidiQueueEvent {
+				event: MidiQueueEventType::Stop,
+				timing: MidiQueueTiming::Bar,
+				looping: MidiQueueLooping::Once,
+			},
+		);
+		let copy = audio.duplicate_track(&handle).unwrap();
+		assert_ne!(copy, handle);
+		let (original, copy) = (&audio.tracks[&handle], &audio.tracks[&copy]);
+		assert!(original.beat > 0.0 && original.active_voices() > 0);
+		assert_eq!(copy.beat, 0.0);
+		assert_eq!(copy.active_voices(), 0);
+		assert!(copy.queue.is_empty());
+	}
+
+	/// hl4mgm.sf2 with its percussion presets moved to bank 1, like a font with no drum kits
+	fn melodic_only_soundfont() -> Vec<u8> {
+		let mut bytes = include_bytes!("../../assets/hl4mgm.sf2").to_vec();
+		let chunk = bytes
+			.windows(4)
+			.position(|window| window == b"phdr")
+			.unwrap();
+		let size = u32::from_le_bytes(bytes[chunk + 4..chunk + 8].try_into().unwrap()) as usize;
+		// Each preset header is a 20 byte name, then the preset and bank numbers
+		for header in bytes[chunk + 8..chunk + 8 + size].chunks_exact_mut(38) {
+			if header[22..24] == [128, 0] {
+				header[22..24].copy_from_slice(&[1, 0]);
+			}
+		}
+		bytes
+	}
+
+	/// A RIFF chunk, padded to an even length
+	fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+		let mut bytes = id.to_vec();
+		bytes.extend((data.len() as u32).to_le_bytes());
+		bytes.extend(data);
+		if data.len() % 2 == 1 {
+			bytes.push(0);
+		}
+		bytes
+	}
+
+	/// A SoundFont with one preset of two velocity layers overlapping from 40 to 80, each a looping A4 sine.
+	/// The soft layer is the left side of a stereo pair and the loud layer the right, so each can be heard on its own.
+	fn velocity_layered_soundfont() -> Vec<u8> {
+		let sine = (0..4410)
+			.map(|frame| ((frame as f32 / 100.0 * std::f32::consts::TAU).sin() * 16000.0) as i16)
+			.collect::<Vec<_>>();
+		let samples = [sine.as_slice(), &[0; 46], &sine, &[0; 46]]
+			.concat()
+			.into_iter()
+			.flat_map(i16::to_le_bytes)
+			.collect::<Vec<_>>();
+		let name = |name: &str| {
+			let mut bytes = name.as_bytes().to_vec();
+			bytes.resize(20, 0);
+			bytes
+		};
+		let u16s = |values: &[u16]| {
+			values
+				.iter()
+				.flat_map(|value| value.to_le_bytes())
+				.collect()
+		};
+		let u32s = |values: &[u32]| {
+			values
+				.iter()
+				.flat_map(|value| value.to_le_bytes())
+				.collect()
+		};
+		let generator =
+			|operator: u16, amount: [u8; 2]| [u16s(&[operator]), amount.to_vec()].concat();
+		let phdr = [
+			name("Swell"),
+			u16s(&[0, 0, 0]),
+			u32s(&[0, 0, 0]),
+			name("EOP"),
+			u16s(&[0, 0, 1]),
+			u32s(&[0, 0, 0]),
+		]
+		.concat();
+		// Every key, the layer's velocities, looping, then the sample
+		let zone = |velocities: [u8; 2], sample: u16| {
+			[
+				generator(43, [0, 127]),
+				generator(44, velocities),
+				generator(54, [1, 0]),
+				generator(53, sample.to_le_bytes()),
+			]
+			.concat()
+		};
+		// A stereo pair's header: name, start, end, loop start, loop end, sample rate, root key, correction, link, type
+		let sample_header = |sample: &str, start: u32, link: u16, sample_type: u16| {
+			[
+				name(sample),
+				u32s(&[start, start + 4410, start, start + 4400, 44100]),
+				vec![69, 0],
+				u16s(&[link, sample_type]),
+			]
+			.concat()
+		};
+		let pdta = [
+			b"pdta".to_vec(),
+			chunk(b"phdr", &phdr),
+			chunk(b"pbag", &u16s(&[0, 0, 1, 0])),
+			// The preset plays instrument 0
+			chunk(
+				b"pgen",
+				&[generator(41, [0, 0]), generator(0, [0, 0])].concat(),
+			),
+			chunk(
+				b"inst",
+				&[name("Layers"), u16s(&[0]), name("EOI"), u16s(&[2])].concat(),
+			),
+			chunk(b"ibag", &u16s(&[0, 0, 4, 0, 8, 0])),
+			chunk(
+				b"igen",
+				&[zone([0, 80], 0), zone([40, 127], 1), generator(0, [0, 0])].concat(),
+			),
+			chunk(
+				b"shdr",
+				&[
+					sample_header("Soft", 0, 1, 4),
+					sample_header("Loud", 4456, 0, 2),
+					sample_header("EOS", 0, 0, 0),
+				]
+				.concat(),
+			),
+		]
+		.concat();
+		let sfbk = [
+			b"sfbk".to_vec(),
+			chunk(
+				b"LIST",
+				&[b"INFO".to_vec(), chunk(b"ifil", &u16s(&[2, 1]))].concat(),
+			),
+			chunk(
+				b"LIST",
+				&[b"sdta".to_vec(), chunk(b"smpl", &samples)].concat(),
+			),
+			chunk(b"LIST", &pdta),
+		]
+		.concat();
+		chunk(b"RIFF", &sfbk)
+	}
+
+	/// Peaks of the left and right channels over the next `frames` frames
+	fn stereo_peaks(audio: &mut MidiAudio, frames: u32) -> [f32; 2] {
+		let mut decoder = audio.decoder();
+		audio.advance_logical(frames);
+		(0..frames)
+			.map(|_| decoder.next_frame())
+			.fold([0.0, 0.0], |[left, right], [l, r]| {
+				[left.max(l.abs()), right.max(r.abs())]
+			})
+	}
+
+	fn swell() -> (MidiAudio, MidiAudioTrackHandle) {
+		let mut audio = MidiAudio::from_bytes(&velocity_layered_soundfont()).with_logical_clock();
+		// A4 at full velocity, with the expression rising from pp to mf to ff a beat at a time
+		let events = b"\0\xB0\x0B\x14\0\x90\x45\x7F\x83\x60\xB0\x0B\x3C\x83\x60\xB0\x0B\x7F\x83\x60\x80\x45\0\0\xFF\x2F\0";
+		let handle = audio.add_track(track(events).with_channel_options(
+			0,
+			ChannelPlaybackOptions {
+				layer_alternation: LayerAlternation::VelocityCrossfade,
+			},
+		));
+		(audio, handle)
+	}
+
+	#[test]
+	fn velocity_crossfades_swell_from_the_soft_layer_to_the_loud() {
+		let (mut audio, _) = swell();
+		// Leaving out the frames either side of each beat, where the expression changes
+		let [pp, mf, ff] = [(); 3].map(|_| {
+			stereo_peaks(&mut audio, 441);
+			let peaks = stereo_peaks(&mut audio, 22050 - 882);
+			stereo_peaks(&mut audio, 441);
+			peaks
+		});
+		assert!(pp[0] > 0.01 && pp[1] == 0.0, "{pp:?}");
+		assert!(mf[0] > 0.01 && mf[1] > 0.01, "{mf:?}");
+		assert!(ff[0] == 0.0 && ff[1] > 0.01, "{ff:?}");
+	}
+
+	#[test]
+	fn velocity_crossfades_count_a_voice_per_layer() {
+		let (mut audio, handle) = swell();
+		audio.advance_logical(441);
+		assert_eq!(audio.active_note_count(&handle), 1);
+		assert_eq!(audio.track_info(&handle).unwrap().active_voices, 2);
+	}
+
+	/// A drum hit on channel 9 every beat
+	fn drum_track() -> MidiAudioTrack {
+		track(b"\0\x99\x24\x7F\x83\x60\x89\x24\0\0\x99\x24\x7F\x83\x60\x89\x24\0\0\xFF\x2F\0")
+	}
+
+	#[test]
+	fn missing_drum_banks_remap_to_melodic() {
+		let mut audio = MidiAudio::from_bytes(&melodic_only_soundfont()).with_logical_clock();
+		assert!(!audio.soundfont.has_percussion());
+		assert!(!audio.warned_missing_drum_bank);
+		let handle = audio.add_track(drum_track());
+		audio.add_track(drum_track());
+		assert!(audio.warned_missing_drum_bank);
+		let channel = &audio.tracks[&handle].channels[&9];
+		assert_eq!((channel.bank_number, channel.patch_number), (0, 0));
+		assert!(peak(&mut audio, 22050) > 0.0);
+	}
+
+	#[test]
+	fn missing_drum_banks_can_be_muted() {
+		let mut audio = MidiAudio::from_bytes(&melodic_only_soundfont())
+			.with_logical_clock()
+			.with_missing_drum_bank_policy(MissingDrumBankPolicy::Mute);
+		let handle = audio.add_track(drum_track());
+		assert!(audio.tracks[&handle].channels[&9].muted);
+		assert_eq!(peak(&mut audio, 22050), 0.0);
+	}
+
+	/// C4 on channel 0 for a beat
+	const C4: &[u8] = b"\0\x90\x3C\x64\x83\x60\x80\x3C\0\0\xFF\x2F\0";
+
+	#[test]
+	fn note_offs_fade_out_instead_of_stopping_dead() {
+		let mut audio = audio();
+		// C4 for a beat, then a volume change three beats later so it doesn't loop straight back
+		audio.add_track(track(
+			b"\0\x90\x3C\x64\x83\x60\x80\x3C\0\x8B\x20\xB0\x07\x64\0\xFF\x2F\0",
+		));
+		let frames = left_frames(&mut audio, 22050 + 441);
+		let (held, released) = frames.split_at(22050);
+		let peak = |frames: &[f32]| frames.iter().fold(0.0, |peak, frame| frame.abs().max(peak));
+		// Still ringing through the hundredth of a second after the note off
+		let before = peak(&held[held.len() - 441..]);
+		assert!(peak(&released[released.len() - 100..]) > before * 0.1);
+		assert!(largest_step(&frames[22040..22060]) < before, "{before}");
+	}
+
+	#[test]
+	fn explained_notes_list_the_presets_regions() {
+		let mut audio = audio();
+		let handle = audio.add_track(track(C4));
+		let explanation = audio.explain_note(&handle, 0, 60, 100).unwrap();
+		assert_eq!((explanation.bank_number, explanation.patch_number), (0, 0));
+		assert!(explanation.preset_name.is_some());
+		assert!(!explanation.regions.is_empty());
+		assert_eq!(
+			explanation.regions,
+			audio.soundfont_bank().resolve(60, 100, 0, 0)
+		);
+		assert!(!explanation.uses_fallback && !explanation.custom_instrument);
+		assert_eq!(
+			audio.explain_note(&MidiAudioTrackHandle(99), 0, 60, 100),
+			None
+		);
+	}
+
+	#[test]
+	fn explained_notes_follow_the_track_s_transpose() {
+		let mut audio = audio();
+		let handle = audio.add_track(track(C4).with_transpose(24));
+		let explanation = audio.explain_note(&handle, 0, 60, 100).unwrap();
+		let c6 = audio.soundfont_bank().resolve(84, 100, 0, 0);
+		assert_ne!(c6, audio.soundfont_bank().resolve(60, 100, 0, 0));
+		assert_eq!(explanation.regions, c6);
+	}
+
+	#[test]
+	fn explained_notes_add_the_channel_s_transpose_to_the_track_s() {
+		let mut audio = audio();
+		let handle = audio.add_track(track(C4).with_transpose(12).with_channel_transpose(0, 12));
+		let explanation = audio.explain_note(&handle, 0, 60, 100).unwrap();
+		assert_eq!(
+			explanation.regions,
+			audio.soundfont_bank().resolve(84, 100, 0, 0)
+		);
+	}
+
+	#[test]
+	fn explained_drums_without_a_drum_bank_use_the_melodic_preset() {
+		let mut audio = MidiAudio::from_bytes(&melodic_only_soundfont()).with_logical_clock();
+		let handle = audio.add_track(drum_track());
+		let explanation = audio.explain_note(&handle, 9, 36, 127).unwrap();
+		assert_eq!((explanation.bank_number, explanation.patch_number), (0, 0));
+		assert!(!explanation.regions.is_empty());
+		assert_eq!(
+			explanation.regions,
+			audio.soundfont_bank().resolve(36, 127, 0, 0)
+		);
+		assert!(!explanation.uses_fallback);
+	}
+
+	#[test]
+	fn explained_missing_presets_have_no_regions() {
+		let mut audio = audio();
+		assert!(!audio.soundfont_bank().has_preset(5, 0));
+		let handle = audio.add_track(track(C4).with_channel_patch(0, 5, 0));
+		let explanation = audio.explain_note(&handle, 0, 60, 100).unwrap();
+		assert_eq!(explanation.regions, vec![]);
+		assert!(!explanation.uses_fallback);
+		// Named after the General MIDI instrument it's meant to be
+		assert_eq!(
+			explanation.preset_name.as_deref(),
+			Some(crate::gm::instrument_name(0))
+		);
+
+		let mut audio = MidiAudio::from_bytes(include_bytes!("../../assets/hl4mgm.sf2"))
+			.with_synth_fallback(Waveform::Sine);
+		let handle = audio.add_track(track(C4).with_channel_patch(0, 5, 0));
+		let explanation = audio.explain_note(&handle, 0, 60, 100).unwrap();
+		assert!(explanation.regions.is_empty() && explanation.uses_fallback);
+	}
+
+	#[test]
+	fn drum_banks_are_left_alone_when_present() {
+		let mut audio = audio();
+		assert!(audio.soundfont.has_percussion());
+		let handle = audio.add_track(drum_track());
+		assert!(!audio.warned_missing_drum_bank);
+		assert_eq!(
+			audio.tracks[&handle].channels[&9].bank_number,
+			PERCUSSION_BANK
+		);
+		assert!(peak(&mut audio, 22050) > 0.0);
+	}
+
+	/// Fray with seeded drift, a live note and a tempo change, returning every batch's checksum
+	fn scripted_checksums(pull_frames: usize) -> Vec<u64> {
+		let mut audio = audio();
+		let drift = Drift {
+			pitch_cents: 10.0,
+			velocity: 10,
+			timing_ms: 20.0,
+			exclude_percussion: false,
+		};
+		let handle = audio.add_track(fray().with_drift(drift, 42));
+		let mut decoder = audio.decoder();
+		(0..20)
+			.map(|step| {
+				match step {
+					5 => audio.start_playing_note(Note::C4).unwrap(),
+					8 => audio.stop_playing_note(Note::C4).unwrap(),
+					10 => audio.queue(
+						handle,
+						MidiQueueEvent {
+							event: MidiQueueEventType::SetTempo(150.0),
+							timing: MidiQueueTiming::Beat,
+							looping: MidiQueueLooping::Once,
+						},
+					),
+					_ => {}
+				}
+				let batch = audio.advance_logical(2205);
+				// The audio side keeps its own pace, which mustn't change what happens
+				for _ in 0..pull_frames {
+					decoder.next_frame();
+				}
+				batch.checksum()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn logical_clocks_are_deterministic() {
+		let checksums = scripted_checksums(0);
+		assert_eq!(scripted_checksums(2205), checksums);
+		assert_eq!(scripted_checksums(100), checksums);
+		assert_eq!(scripted_checksums(5000), checksums);
+	}
+
+	#[test]
+	fn builtin_synth_plays_without_a_soundfont() {
+		for waveform in [
+			Waveform::Sine,
+			Waveform::Square,
+			Waveform::Triangle,
+			Waveform::Saw,
+		] {
+			let mut audio = MidiAudio::with_builtin_synth(waveform).with_logical_clock();
+			audio.add_track(silent_track());
+			audio.start_playing_note(Note::A4).unwrap();
+			let crossings = upward_crossings(&mut audio, 44100) as f32;
+			assert!(
+				(crossings - Note::A4.frequency).abs() <= 1.0,
+				"{waveform:?} {crossings}"
+			);
+		}
+	}
+
+	#[test]
+	fn held_looping_presets_keep_sounding() {
+		let mut audio = audio();
+		// Strings, which loop a short sample, holding C4 for sixteen beats
+		audio.add_track(track(
+			b"\0\xC0\x30\0\x90\x3C\x64\xBC\x00\x80\x3C\0\0\xFF\x2F\0",
+		));
+		let mut decoder = audio.decoder();
+		for second in 0..7 {
+			audio.advance_logical(44100);
+			let squares = (0..44100)
+				.map(|_| decoder.next_frame()[0].powi(2))
+				.sum::<f32>();
+			let rms = (squares / 44100.0).sqrt();
+			assert!(rms > 0.01, "{rms} in second {second}");
+		}
+	}
+
+	#[test]
+	fn missing_presets_fall_back_to_the_synth() {
+		for (fallback, sounds) in [(None, false), (Some(Waveform::Sine), true)] {
+			let mut audio = audio();
+			if let Some(waveform) = fallback {
+				audio = audio.with_synth_fallback(waveform);
+			}
+			// No bank 42 in the SoundFont
+			audio.add_track(silent_track().with_channel_patch(0, 42, 0));
+			audio.start_playing_note(Note::A4).unwrap();
+			assert_eq!(peak(&mut audio, 4410) > 0.0, sounds, "{fallback:?}");
+		}
+	}
+
+	struct TempoChanges(Arc<Mutex<Vec<(f64, f64)>>>);
+
+	impl MusicalTimeListener for TempoChanges {
+		fn musical_time_changed(&mut self, old: &MusicalTime, new: &MusicalTime) {
+			self.0
+				.lock()
+				.unwrap()
+				.push((old.beats_per_second, new.beats_per_second));
+		}
+	}
+
+	#[test]
+	fn listeners_hear_tempo_changes() {
+		let mut audio = audio();
+		let changes = Arc::new(Mutex::new(vec![]));
+		let handle = audio.add_track(
+			track(b"\0\x90\x3C\x40\x9E\x00\x80\x3C\0\0\xFF\x2F\0")
+				.with_musical_time_listener(TempoChanges(changes.clone())),
+		);
+		audio.advance_logical(100);
+		assert_eq!(*changes.lock().unwrap(), vec![]);
+		audio.queue(
+			handle,
+			MidiQueueEvent {
+				event: MidiQueueEventType::SetTempo(60.0),
+				timing: MidiQueueTiming::Immediate,
+				looping: MidiQueueLooping::Once,
+			},
+		);
+		audio.advance_logical(100);
+		assert_eq!(*changes.lock().unwrap(), vec![(2.0, 1.0)]);
+	}
+
+	#[test]
+	fn batches_apply_before_the_next_frame() {
+		let mut audio = audio();
+		let handle = audio.add_track(fray());
+		audio.advance_logical(100);
+		audio.batch(&handle, |batch| {
+			batch.mute_channel(0).set_channel_patch(0, 0, 40).stop();
+		});
+		let track = &audio.tracks[&handle];
+		let channel = &track.channels[&0];
+		assert!(channel.muted);
+		assert_eq!(channel.patch_number, 40);
+		assert!(!track.is_playing);
+
+		// Stopped along with the mute, so nothing more is dispatched
+		let batch = audio.advance_logical(22050);
+		assert!(
+			!batch
+				.events
+				.iter()
+				.any(|event| matches!(event.kind, DispatchedEventKind::Midi(_)))
+		);
+	}
+
+	#[test]
+	fn calibration_tracks_pulse_on_exact_frames() {
+		let mut audio = MidiAudio::with_builtin_synth(Waveform::Sine).with_logical_clock();
+		audio.calibration_track(1000.0, Duration::from_millis(100));
+		let mut decoder = audio.decoder();
+		audio.advance_logical(44100);
+		let samples = (0..44100)
+			.map(|_| decoder.next_frame()[0])
+			.collect::<Vec<_>>();
+		// Each pulse fades in from silence, so its first few samples round to nothing
+		let onsets = (1..samples.len())
+			.filter(|&frame| samples[frame - 1] == 0.0 && samples[frame] != 0.0)
+			.collect::<Vec<_>>();
+		assert_eq!(onsets.len(), 10);
+		assert!(onsets[0] < 10, "{onsets:?}");
+		assert!(
+			onsets.windows(2).all(|pair| pair[1] - pair[0] == 4410),
+			"{onsets:?}"
+		);
+	}
+
+	/// A violin patch change, two empty bars, then a beat of C4
+	fn late_start() -> MidiAudioTrack {
+		track(b"\0\xC0\x28\x9E\x00\x90\x3C\x7F\x83\x60\x80\x3C\0\0\xFF\x2F\0")
+	}
+
+	#[test]
+	fn trimmed_tracks_start_on_their_first_note() {
+		let mut untrimmed = audio();
+		untrimmed.add_track(late_start());
+		assert_eq!(peak(&mut untrimmed, 2205), 0.0);
+
+		let mut audio = audio();
+		let handle = audio.add_track(late_start().with_trim_leading_silence(true));
+		assert!(peak(&mut audio, 2205) > 0.0);
+		assert_eq!(audio.tracks[&handle].channels[&0].patch_number, 40);
+
+		// The next pass starts from the top, so once the note's release has died away it's the intro
+		peak(&mut audio, 5 * 22050 - 2205);
+		let beat = audio.tracks[&handle].beat;
+		assert!((3.9..4.1).contains(&beat), "{beat}");
+		assert_eq!(peak(&mut audio, 22050), 0.0);
+	}
+
+	#[test]
+	fn overviews_match_a_full_render() {
+		// A loud beat, a rest, a half as loud beat and another rest
+		let events = b"\0\x90\x3C\x7F\x83\x60\x80\x3C\0\x83\x60\x90\x3C\x40\x83\x60\x80\x3C\0\x83\x60\xB0\x07\x64\0\xFF\x2F\0";
+		let mut audio = audio();
+		let handle = audio.add_track(
+			track(events).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		let overview = audio.render_overview(&handle, 4);
+
+		let rendered = (0..4).map(|_| peak(&mut audio, 22050)).collect::<Vec<_>>();
+		let loudest = rendered.iter().copied().fold(0.0, f32::max);
+		// The synth's notes are cut off when they stop, so only its slow decay can throw the estimate off
+		for (estimate, rendered) in overview.iter().zip(&rendered) {
+			assert!(
+				(estimate - rendered / loudest).abs() < 0.05,
+				"{overview:?} {rendered:?}"
+			);
+		}
+		assert!(audio.render_overview(&handle, 0).is_empty());
+	}
+
+	#[test]
+	fn overviews_are_deterministic_and_normalized() {
+		let mut audio = audio();
+		let handle = audio.add_track(fray());
+		let overview = audio.render_overview(&handle, 100);
+		assert_eq!(overview.len(), 100);
+		assert_eq!(overview.iter().copied().fold(0.0, f32::max), 1.0);
+		assert_eq!(audio.render_overview(&handle, 100), overview);
+	}
+
+	#[test]
+	fn handoffs_keep_the_beat() {
+		let mut old = audio();
+		let from = old.add_track(fray());
+		peak(&mut old, 33075);
+		let mut new = audio();
+		let to = new.add_track(fray().stopped());
+
+		let handoff = new
+			.handoff_from(
+				&mut old,
+				&from,
+				&to,
+				MidiQueueTiming::Bar,
+				Duration::from_millis(100),
+			)
+			.unwrap();
+		assert!(new.tracks[&to].is_playing);
+		assert_eq!(new.master_gain(), 0.0);
+		// Both assets rendered in step, as two decoders pulling at the same rate would
+		for _ in 0..30 {
+			let (old_beat, new_beat) = (old.audible_beat(&from), new.audible_beat(&to));
+			assert!(
+				(old_beat.unwrap() - new_beat.unwrap()).abs() < 1e-6,
+				"{old_beat:?} {new_beat:?}"
+			);
+			peak(&mut old, 4410);
+			peak(&mut new, 4410);
+		}
+		assert!(old.is_faded_out());
+		assert!(handoff.is_finished());
+		assert_eq!(new.master_gain(), 1.0);
+	}
+
+	#[test]
+	fn handoffs_finish_once_the_old_buffer_plays_out() {
+		let mut old = audio();
+		let from = old.add_track(fray());
+		let mut new = audio();
+		let to = new.add_track(fray().stopped());
+		let handoff = new
+			.handoff_from(&mut old, &from, &to, MidiQueueTiming::Beat, Duration::ZERO)
+			.unwrap();
+		assert!(!handoff.is_finished());
+		old.advance_logical(44100);
+		assert!(old.master_gain() == 0.0 && !handoff.is_finished());
+		let mut decoder = old.decoder();
+		for _ in 0..44100 {
+			decoder.next_frame();
+		}
+		assert!(handoff.is_finished());
+	}
+
+	#[test]
+	fn handoffs_account_for_the_new_asset_s_buffer() {
+		let mut old = audio();
+		let from = old.add_track(fray());
+		peak(&mut old, 33075);
+		let mut new = audio();
+		let to = new.add_track(fray().stopped());
+		// Rendered but never played, so everything the new asset renders from now on is heard this much later
+		new.advance_logical(4410);
+
+		new.handoff_from(
+			&mut old,
+			&from,
+			&to,
+			MidiQueueTiming::Bar,
+			Duration::from_millis(100),
+		);
+		for _ in 0..30 {
+			let (old_beat, new_beat) = (old.audible_beat(&from), new.audible_beat(&to));
+			assert!(
+				(old_beat.unwrap() - new_beat.unwrap()).abs() < 1e-6,
+				"{old_beat:?} {new_beat:?}"
+			);
+			peak(&mut old, 4410);
+			peak(&mut new, 4410);
+		}
+	}
+
+	fn strict() -> MidiAudio {
+		audio().with_strict_policy(StrictPolicy::Collect)
+	}
+
+	fn violation_kinds(audio: &MidiAudio) -> Vec<StrictViolationKind> {
+		audio
+			.strict_violations()
+			.iter()
+			.map(|violation| violation.kind)
+			.collect()
+	}
+
+	/// Never has anything to play
+	struct Declines;
+
+	impl Instrument for Declines {
+		fn create_voice(
+			&mut self,
+			_note: u8,
+			_velocity: u8,
+			_context: &InstrumentContext,
+		) -> Option<Box<dyn VoiceSource>> {
+			None
+		}
+
+		fn fresh(&self) -> Box<dyn Instrument> {
+			Box::new(Declines)
+		}
+	}
+
+	#[test]
+	fn strict_violations_say_where_they_happened() {
+		let mut audio = strict();
+		// Stops D4 on beat 1, which never started
+		let handle = audio.add_track(track(
+			b"\0\x90\x3C\x40\x83\x60\x80\x3E\0\x83\x60\x80\x3C\0\0\xFF\x2F\0",
+		));
+		audio.advance_logical(33075);
+		let [violation] = audio.strict_violations() else {
+			panic!("{:?}", audio.strict_violations());
+		};
+		assert_eq!(violation.track, handle);
+		assert_eq!(
+			violation.kind,
+			StrictViolationKind::UnmatchedNoteOff {
+				channel: 0,
+				note: 62
+			}
+		);
+		assert!((violation.beat.unwrap() - 1.0).abs() < 0.01);
+
+		audio.clear_strict_violations();
+		assert!(audio.strict_violations().is_empty());
+	}
+
+	#[test]
+	fn strict_violations_cover_notes_that_cant_play() {
+		let mut audio = strict();
+		audio.add_track(silent_track().with_channel_patch(0, 42, 0));
+		audio.start_playing_note(Note::C4).unwrap();
+		audio.advance_logical(1);
+
+		let mut drums = strict();
+		// The drum kit has nothing down at key 0
+		drums.add_track(silent_track().with_channel_patch(0, PERCUSSION_BANK, 0));
+		drums.start_playing_note(Note::CN1).unwrap();
+		drums.advance_logical(1);
+
+		let mut declined = strict();
+		declined.add_track(silent_track().with_channel_instrument(0, Declines));
+		declined.start_playing_note(Note::C4).unwrap();
+		declined.advance_logical(1);
+
+		assert_eq!(
+			[
+				violation_kinds(&audio),
+				violation_kinds(&drums),
+				violation_kinds(&declined)
+			],
+			[
+				vec![StrictViolationKind::MissingPreset { bank: 42, patch: 0 }],
+				vec![StrictViolationKind::EmptySampleSet {
+					bank: PERCUSSION_BANK,
+					patch: 0,
+					note: 0
+				}],
+				vec![StrictViolationKind::InstrumentDeclined { note: 60 }],
+			]
+		);
+	}
+
+	#[test]
+	fn strict_violations_name_general_midi_instruments_and_drums() {
+		let mut audio = strict();
+		audio.add_track(silent_track().with_channel_patch(0, 42, 48));
+		audio.start_playing_note(Note::C4).unwrap();
+		audio.advance_logical(1);
+		assert_eq!(
+			audio.strict_violations()[0].to_string(),
+			"bank 42 patch 48 'String Ensemble 1' has no preset on track 0 at beat 0.00"
+		);
+		let maracas = StrictViolationKind::EmptySampleSet {
+			bank: PERCUSSION_BANK,
+			patch: 0,
+			note: 70,
+		};
+		assert_eq!(
+			maracas.to_string(),
+			"bank 128 patch 0 key 70 'Maracas' has no region"
+		);
+		let let_go = StrictViolationKind::UnmatchedNoteOff {
+			channel: 9,
+			note: 70,
+		};
+		assert_eq!(
+			let_go.to_string(),
+			"channel 9 key 70 'Maracas' was let go without being played"
+		);
+	}
+
+	#[test]
+	fn strict_violations_cover_bad_targets() {
+		let mut audio = strict();
+		audio.add_track(silent_track());
+		audio
+			.play_live_event(MidiEvent::NoteOn {
+				channel: 20,
+				note: 60,
+				velocity: 100,
+			})
+			.unwrap();
+		// Recorded by the track, and collected as it renders
+		audio.advance_logical(1);
+		audio.queue(MidiAudioTrackHandle(99), stop_on_loop());
+		assert_eq!(
+			violation_kinds(&audio),
+			[
+				StrictViolationKind::ChannelOutOfRange { channel: 20 },
+				StrictViolationKind::UnknownTrack
+			]
+		);
+		assert_eq!(audio.strict_violations()[1].beat, None);
+	}
+
+	#[test]
+	fn lenient_assets_ignore_violations() {
+		let mut audio = audio();
+		audio.add_track(silent_track().with_channel_patch(0, 42, 0));
+		audio.start_playing_note(Note::C4).unwrap();
+		audio.advance_logical(1);
+		audio.queue(MidiAudioTrackHandle(99), stop_on_loop());
+		assert!(audio.strict_violations().is_empty());
+	}
+
+	#[test]
+	#[cfg(debug_assertions)]
+	#[should_panic(expected = "Strict violation")]
+	fn strict_violations_can_panic() {
+		let mut audio = audio().with_strict_policy(StrictPolicy::PanicInDebug);
+		audio.queue(MidiAudioTrackHandle(99), stop_on_loop());
+	}
+
+	/// A square wave held right up to the loop seam, which cuts it off mid-cycle
+	fn clicky_loop(seamless: bool) -> SeamReport {
+		let mut audio = audio();
+		let handle = audio.add_track(
+			track(b"\0\x90\x3C\x7F\x9E\x00\x80\x3C\0\0\xFF\x2F\0")
+				.with_channel_instrument(0, SynthInstrument::new(Waveform::Square))
+				.with_seamless_loop(seamless),
+		);
+		audio
+			.analyze_loop_seam(&handle, Duration::from_millis(10))
+			.unwrap()
+	}
+
+	#[test]
+	fn seamless_loops_smooth_the_seam() {
+		let clicky = clicky_loop(false);
+		let seamless = clicky_loop(true);
+		assert!(clicky.tail_level > 0.01, "{clicky:?}");
+		assert!(clicky.discontinuity > 0.01, "{clicky:?}");
+		assert!(seamless.discontinuity < 0.01, "{seamless:?}");
+		assert!(seamless.discontinuity < clicky.discontinuity / 4.0);
+	}
+
+	#[test]
+	fn seams_are_only_found_in_looping_tracks() {
+		let mut audio = audio();
+		let handle = audio.add_track(fray().with_looping(false));
+		assert_eq!(
+			audio.analyze_loop_seam(&handle, Duration::from_millis(10)),
+			None
+		);
+	}
+
+	/// The frames a C major chord's notes start on, pressed 5 ms apart
+	fn chord_onsets(window: Duration) -> Vec<u64> {
+		let mut audio = audio().with_live_input_window(window);
+		let handle = audio.add_track(silent_track());
+		let presses = [(0, Note::C4), (220, Note::E4), (441, Note::G4)];
+		let mut onsets = vec![];
+		let mut sounding = 0;
+		for frame in 0..2000 {
+			for (_, note) in presses.iter().filter(|(press, _)| *press == frame) {
+				audio.start_playing_note(*note).unwrap();
+			}
+			audio.advance_logical(1);
+			let now_sounding = audio.active_note_count(&handle);
+			onsets.extend((sounding..now_sounding).map(|_| frame));
+			sounding = now_sounding;
+		}
+		onsets
+	}
+
+	#[test]
+	fn live_input_windows_line_chords_up() {
+		let staggered = chord_onsets(Duration::ZERO);
+		assert_eq!(staggered, [0, 220, 441]);
+		let together = chord_onsets(Duration::from_millis(15));
+		assert_eq!(together.len(), 3);
+		assert!(together.iter().all(|&onset| onset == together[0]));
+		// Held back by no more than the window
+		assert!(together[0] <= 662, "{together:?}");
+	}
+
+	#[test]
+	fn live_input_windows_leave_the_file_alone() {
+		let mut audio = audio().with_live_input_window(Duration::from_millis(15));
+		let handle = audio.add_track(fray());
+		audio.advance_logical(1);
+		assert!(audio.active_note_count(&handle) > 0);
+	}
+
+	#[test]
+	fn zero_drift_is_bit_identical() {
+		let render = |track: MidiAudioTrack| {
+			let mut audio = audio();
+			audio.add_track(track);
+			let mut decoder = audio.decoder();
+			audio.advance_logical(44100 * 4);
+			(0..44100 * 4)
+				.map(|_| decoder.next_frame())
+				.collect::<Vec<_>>()
+		};
+		let drifting = Drift {
+			exclude_percussion: true,
+			..Drift::default()
+		};
+		let drifted = render(fray().with_drift(drifting, 42));
+		assert!(drifted.iter().any(|&[left, _]| left != 0.0));
+		assert_eq!(drifted, render(fray()));
+	}
+
+	#[test]
+	fn empty_assets_play_silence_until_a_track_is_added() {
+		let mut audio = MidiAudio::from_bytes(include_bytes!("../../assets/hl4mgm.sf2"));
+		let mut decoder = audio.decoder();
+		audio.tick(Duration::from_millis(100));
+		assert!(audio.is_idle());
+		assert_eq!(audio.buffered_frames(), 0);
+		assert_eq!(audio.frame, 4410);
+		assert!((0..4410 * 2).all(|_| decoder.next() == Some(0)));
+
+		audio.add_track(track(b"\0\x90\x3C\x64\x83\x60\x80\x3C\0\0\xFF\x2F\0"));
+		audio.tick(Duration::from_millis(100));
+		assert_eq!(audio.buffered_frames(), 4410);
+		assert!((0..4410 * 2).any(|_| decoder.next() != Some(0)));
+	}
+
+	fn play_on(timing: MidiQueueTiming) -> MidiQueueEvent {
+		MidiQueueEvent {
+			event: MidiQueueEventType::Play,
+			timing,
+			looping: MidiQueueLooping::Once,
+		}
+	}
+
+	/// Adds a four and an eight beat loop, then a stopped six beat loop that waits on `clock`,
+	/// given the other two's handles, and queues it to play on a loop.
+	/// Returns the frames it starts playing on.
+	fn play_on_loop_frames(
+		clock: fn(MidiAudioTrackHandle, MidiAudioTrackHandle) -> QueueClock,
+	) -> Vec<u64> {
+		let mut audio = audio();
+		let four = audio.add_track(track(b"\0\x90\x3C\x40\x8F\x00\x80\x3C\0\0\xFF\x2F\0"));
+		let eight = audio.add_track(track(b"\0\x90\x3C\x40\x9E\x00\x80\x3C\0\0\xFF\x2F\0"));
+		let six = audio.add_track(
+			track(b"\0\x90\x3C\x40\x96\x40\x80\x3C\0\0\xFF\x2F\0")
+				.stopped()
+				.with_queue_clock(clock(four, eight)),
+		);
+		audio.advance_logical(1000);
+		audio.queue(six, play_on(MidiQueueTiming::Loop));
+		let batch = audio.advance_logical(44100 * 5);
+		dispatched_frames(&batch, six, MidiQueueEventType::Play)
+	}
+
+	#[test]
+	fn own_clocks_play_straight_away_while_stopped() {
+		assert_eq!(play_on_loop_frames(|_, _| QueueClock::Own), vec![1001]);
+	}
+
+	#[test]
+	fn shared_clocks_play_on_any_tracks_loop() {
+		assert_near_frames(play_on_loop_frames(|_, _| QueueClock::Shared), &[4 * 22050]);
+	}
+
+	#[test]
+	fn track_clocks_play_on_that_tracks_loop() {
+		assert_near_frames(
+			play_on_loop_frames(|_, eight| QueueClock::Track(eight)),
+			&[8 * 22050],
+		);
+		assert_near_frames(
+			play_on_loop_frames(|four, _| QueueClock::Track(four)),
+			&[4 * 22050],
+		);
+	}
+
+	#[test]
+	fn free_clocks_play_on_their_own_loop_length() {
+		assert_near_frames(play_on_loop_frames(|_, _| QueueClock::Free), &[6 * 22050]);
+	}
+
+	#[test]
+	fn stopped_tracks_wake_up_on_their_next_bar_or_beat() {
+		let mut audio = audio();
+		let four_beats = b"\0\x90\x3C\x40\x8F\x00\x80\x3C\0\0\xFF\x2F\0";
+		let on_bar = audio.add_track(track(four_beats).stopped());
+		let on_beat = audio.add_track(track(four_beats).stopped());
+		audio.advance_logical(22050 + 100);
+		audio.queue(on_bar, play_on(MidiQueueTiming::Bar));
+		audio.queue(on_beat, play_on(MidiQueueTiming::Beat));
+		let batch = audio.advance_logical(44100 * 2);
+		assert_near_frames(
+			dispatched_frames(&batch, on_bar, MidiQueueEventType::Play),
+			&[4 * 22050],
+		);
+		assert_near_frames(
+			dispatched_frames(&batch, on_beat, MidiQueueEventType::Play),
+			&[2 * 22050],
+		);
+		assert!(audio.is_playing(&on_bar) && audio.is_playing(&on_beat));
+	}
+
+	#[test]
+	fn stopped_tracks_are_already_at_their_loop() {
+		let mut audio = audio();
+		let handle =
+			audio.add_track(track(b"\0\x90\x3C\x40\x8F\x00\x80\x3C\0\0\xFF\x2F\0").stopped());
+		audio.advance_logical(1000);
+		audio.queue(handle, play_on(MidiQueueTiming::Loop));
+		let batch = audio.advance_logical(1);
+		assert_eq!(
+			dispatched_frames(&batch, handle, MidiQueueEventType::Play),
+			vec![1001]
+		);
+	}
+
+	#[test]
+	fn output_settings_are_fixed_once_a_decoder_exists() {
+		let mut audio = audio();
+		let settings = MidiAudioSettings::default().with_sample_rate(48000);
+		audio.set_settings(settings).unwrap();
+		let _decoder = audio.decoder();
+		assert_eq!(
+			audio.set_settings(settings.with_sample_rate(44100)),
+			Err(SettingsError::DecoderCreated)
+		);
+		assert_eq!(
+			audio.set_settings(settings.with_channels(1)),
+			Err(SettingsError::DecoderCreated)
+		);
+		assert_eq!(
+			audio.set_settings(settings.with_buffer_frames(0)),
+			Err(SettingsError::EmptyBuffer)
+		);
+		audio
+			.set_settings(settings.with_buffer_frames(4800))
+			.unwrap();
+		assert_eq!(*audio.settings(), settings.with_buffer_frames(4800));
+	}
+
+	#[test]
+	fn other_sample_rates_render_at_that_rate() {
+		let mut audio = audio();
+		audio
+			.set_settings(MidiAudioSettings::default().with_sample_rate(22050))
+			.unwrap();
+		// A4 for four beats
+		let handle = audio.add_track(
+			track(b"\0\x90\x45\x64\x8F\x00\x80\x45\0\0\xFF\x2F\0")
+				.with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		assert_eq!(audio.decoder().sample_rate(), 22050);
+		// A second at 22.05 kHz
+		let crossings = upward_crossings(&mut audio, 22050);
+		assert!((439..=441).contains(&crossings), "{crossings}");
+		assert!((audio.current_beat(&handle).unwrap() - 2.0).abs() < 1e-3);
+	}
+
+	/// Plays two notes of eight beats each at 60 fps for a second, a tenth of a second ahead, then stalls the game
+	/// for three seconds, with the audio thread playing out everything buffered if `played_out`.
+	/// Returns the track's beat from just before the stall.
+	fn stall(
+		stall_policy: StallPolicy,
+		played_out: bool,
+	) -> (MidiAudio, MidiAudioTrackHandle, f64) {
+		let mut audio = MidiAudio::from_bytes(include_bytes!("../../assets/hl4mgm.sf2"));
+		audio
+			.set_settings(MidiAudioSettings::default().with_stall_policy(stall_policy))
+			.unwrap();
+		let events =
+			b"\0\x90\x3C\x64\x9E\x00\x80\x3C\0\0\x90\x3E\x64\x9E\x00\x80\x3E\0\0\xFF\x2F\0";
+		let handle = audio.add_track(track(events));
+		let mut decoder = audio.decoder();
+		audio.prefill(Duration::from_millis(100));
+		for _ in 0..60 {
+			audio.tick(Duration::from_secs_f64(1.0 / 60.0));
+			for _ in 0..735 {
+				decoder.next_frame();
+			}
+		}
+		let before = audio.current_beat(&handle).unwrap();
+		if played_out {
+			for _ in 0..audio.buffered_frames() {
+				decoder.next_frame();
+			}
+		}
+		audio.tick(Duration::from_secs(3));
+		(audio, handle, before)
+	}
+
+	#[test]
+	fn stalls_play_through_a_bounded_backlog() {
+		let (audio, handle, before) = stall(StallPolicy::PlayThrough, true);
+		let max_backlog_frames = audio.settings().max_backlog_frames;
+		assert!(audio.buffered_frames() <= max_backlog_frames);
+		assert_eq!(
+			audio.stalled_frames(),
+			3 * 44100 - max_backlog_frames as u64
+		);
+		// The music only gets as far as the backlog, half a second, and runs late by the rest
+		let beats = audio.current_beat(&handle).unwrap() - before;
+		assert!((beats - 1.0).abs() < 0.01, "{beats}");
+	}
+
+	#[test]
+	fn stalls_catch_up_to_now() {
+		let (mut audio, handle, before) = stall(StallPolicy::CatchUp, false);
+		// The tenth of a second buffered before the stall is dropped, leaving only the backlog rendered after it
+		let max_backlog_frames = audio.settings().max_backlog_frames;
+		assert_eq!(audio.buffered_frames(), max_backlog_frames);
+		assert_eq!(
+			audio.stalled_frames(),
+			3 * 44100 - max_backlog_frames as u64
+		);
+		let after = audio.current_beat(&handle).unwrap();
+		assert!((after - before - 6.0).abs() < 0.01, "{}", after - before);
+		// Carries on from there rather than from where the stall started
+		audio.tick(Duration::from_secs_f64(1.0 / 60.0));
+		let beats = audio.current_beat(&handle).unwrap() - after;
+		assert!((beats - 2.0 / 60.0).abs() < 0.001, "{beats}");
+		assert_eq!(audio.underruns(), 0);
+	}
+
+	/// Sines holding A4 and E5 on tracks of their own for eight beats, a tenth of a second in
+	fn two_sines() -> (MidiAudio, MidiAudioTrackHandle, MidiAudioTrackHandle) {
+		let mut audio = audio();
+		let sine = |note: u8| {
+			let events = [
+				b"\0\x90",
+				&[note][..],
+				b"\x64\x9E\x00\x80",
+				&[note],
+				b"\0\0\xFF\x2F\0",
+			];
+			track(&events.concat()).with_channel_instrument(0, SynthInstrument::new(Waveform::Sine))
+		};
+		let a4 = audio.add_track(sine(0x45));
+		let e5 = audio.add_track(sine(0x4C));
+		peak(&mut audio, 4410);
+		(audio, a4, e5)
+	}
+
+	#[test]
+	fn muted_tracks_keep_playing_unheard() {
+		let (mut audio, a4, e5) = two_sines();
+		audio.set_track_muted(&a4, true);
+		audio.set_track_muted(&e5, true);
+		assert_eq!(peak(&mut audio, 4410), 0.0);
+		let info = audio.track_info(&a4).unwrap();
+		assert!(
+			info.muted && info.is_playing && info.level > 0.0,
+			"{info:?}"
+		);
+		assert!((info.beat - 0.4).abs() < 1e-3, "{info:?}");
+		audio.set_track_muted(&e5, false);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::E5.frequency).abs() <= 1.0, "{crossings}");
+	}
+
+	#[test]
+	fn only_soloed_tracks_are_heard() {
+		let (mut audio, a4, e5) = two_sines();
+		audio.set_track_solo(&a4, true);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::A4.frequency).abs() <= 1.0, "{crossings}");
+		audio.set_track_solo(&a4, false);
+		audio.set_track_solo(&e5, true);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::E5.frequency).abs() <= 1.0, "{crossings}");
+		assert!(audio.track_info(&e5).unwrap().solo);
+		assert!(!audio.track_info(&a4).unwrap().solo);
+	}
+
+	#[test]
+	fn seeking_to_a_bar_cuts_off_the_track_s_notes() {
+		let (mut audio, a4, e5) = two_sines();
+		audio.seek_to_bar(&a4, 1);
+		let info = audio.track_info(&a4).unwrap();
+		assert_eq!((info.bar, info.beat), (1, 4.0));
+		assert_eq!(audio.active_note_count(&a4), 0);
+		// The other track carries on where it was
+		let info = audio.track_info(&e5).unwrap();
+		assert_eq!(info.bar, 0);
+		assert_eq!(audio.active_note_count(&e5), 1);
+		let crossings = upward_crossings(&mut audio, 44100) as f32;
+		assert!((crossings - Note::E5.frequency).abs() <= 1.0, "{crossings}");
+	}
+
+	#[test]
+	fn stopping_a_track_s_notes_leaves_the_others_playing() {
+		let (mut audio, a4, e5) = two_sines();
+		audio.play_one_shot(0, 0, Note::C4, 100);
+		audio.stop_all_notes(&a4);
+		assert_eq!(audio.active_note_count(&a4), 0);
+		assert_eq!(audio.active_note_count(&e5), 1);
+		assert!(audio.track_info(&a4).unwrap().is_playing);
+		audio.stop_all_notes_all_tracks();
+		assert_eq!(audio.total_active_note_count(), 0);
+		assert_eq!(peak(&mut audio, 441), 0.0);
+	}
+
+	#[test]
+	fn underruns_count_the_decoder_running_dry() {
+		let mut audio = MidiAudio::from_bytes(include_bytes!("../../assets/hl4mgm.sf2"));
+		// C4 for eight beats
+		audio.add_track(track(b"\0\x90\x3C\x64\x9E\x00\x80\x3C\0\0\xFF\x2F\0"));
+		let mut decoder = audio.decoder();
+		audio.prefill(Duration::from_millis(100));
+		for _ in 0..30 {
+			audio.tick(Duration::from_secs_f64(1.0 / 60.0));
+			for _ in 0..735 {
+				decoder.next_frame();
+			}
+		}
+		assert_eq!(audio.underruns(), 0);
+		// The game falls behind and the audio thread plays out everything buffered and more
+		for _ in 0..audio.buffered_frames() + 100 {
+			decoder.next_frame();
+		}
+		audio.tick(Duration::from_secs_f64(1.0 / 60.0));
+		assert_eq!(audio.underruns(), 1);
+	}
+
+	/// Sines on an MPE lower zone, with A4 and E5 held on member channels of their own
+	fn mpe_chord() -> (MidiAudio, MidiAudioTrackHandle, u8, u8) {
+		let mut audio = audio();
+		let handle = audio.add_track(
+			silent_track()
+				.with_mpe(MpeConfig::lower_zone(15))
+				.with_channel_instrument(0, SynthInstrument::new(Waveform::Sine)),
+		);
+		let a4 = audio.start_mpe_note(&handle, Note::A4, 100).unwrap();
+		let e5 = audio.start_mpe_note(&handle, Note::E5, 100).unwrap();
+		assert_ne!(a4, e5);
+		(audio, handle, a4, e5)
+	}
+
+	/// The pitch of `heard` over the next second, with `muted` silenced
+	fn frequency_alone(
+		audio: &mut MidiAudio,
+		handle: &MidiAudioTrackHandle,
+		heard: u8,
+		muted: u8,
+	) -> f32 {
+		audio.set_channel_muted(handle, heard, false);
+		audio.set_channel_muted(handle, muted, true);
+		upward_crossings(audio, 44100) as f32
+	}
+
+	#[test]
+	fn mpe_bends_only_their_own_note() {
+		let (mut audio, handle, a4, e5) = mpe_chord();
+		audio.set_channel_bend(&handle, a4, 2.0);
+		let bent = frequency_alone(&mut audio, &handle, a4, e5);
+		let held = frequency_alone(&mut audio, &handle, e5, a4);
+		assert!((bent - Note::B4.frequency).abs() <= 1.0, "{bent}");
+		assert!((held - Note::E5.frequency).abs() <= 1.0, "{held}");
+	}
+
+	#[test]
+	fn mpe_pressure_only_affects_its_note() {
+		let (mut audio, handle, a4, e5) = mpe_chord();
+		audio.set_channel_pressure(&handle, a4, 0.0);
+		audio.set_channel_muted(&handle, e5, true);
+		assert!(peak(&mut audio, 4410) < 0.001);
+		audio.set_channel_muted(&handle, e5, false);
+		audio.set_channel_muted(&handle, a4, true);
+		assert!(peak(&mut audio, 4410) > 0.01);
+	}
+
+	#[test]
+	fn mpe_master_bend_bends_every_note() {
+		let (mut audio, handle, a4, e5) = mpe_chord();
+		audio.set_channel_bend(&handle, a4, 2.0);
+		audio.set_channel_bend(&handle, 0, 2.0);
+		let both = frequency_alone(&mut audio, &handle, a4, e5);
+		let master = frequency_alone(&mut audio, &handle, e5, a4);
+		assert!((both - Note::CS5.frequency).abs() <= 1.0, "{both}");
+		assert!((master - Note::FS5.frequency).abs() <= 1.0, "{master}");
+		// Notes started after the bend are bent too
+		let late_note = audio.start_mpe_note(&handle, Note::A4, 100).unwrap();
+		audio.set_channel_muted(&handle, a4, true);
+		let late = frequency_alone(&mut audio, &handle, late_note, e5);
+		assert!((late - Note::B4.frequency).abs() <= 1.0, "{late}");
+	}
+
+	/// The frames `note` started and stopped at on channel 0
+	fn note_frames(batch: &EventBatch, note: u8) -> (Vec<u64>, Vec<u64>) {
+		let frames = |matches: fn(&MidiEvent, u8) -> bool| {
+			batch
+				.events
+				.iter()
+				.filter(|dispatched| {
+					matches!(&dispatched.kind, DispatchedEventKind::Midi(event) if matches(event, note))
+				})
+				.map(|dispatched| dispatched.frame)
+				.collect::<Vec<_>>()
+		};
+		(
+			frames(
+				|event, note| matches!(*event, MidiEvent::NoteOn { note: on, velocity, .. } if on == note && velocity > 0),
+			),
+			frames(
+				|event, note| matches!(*event, MidiEvent::NoteOff { note: off, .. } if off == note),
+			),
+		)
+	}
+
+	#[test]
+	fn pickups_play_once_a_pass_over_the_last_beat() {
+		let mut audio = audio();
+		// C4 on the downbeat, E4 for the whole last beat, and a G4 eighth note leading into the next pass
+		let events = b"\0\x90\x3C\x64\x83\x60\x80\x3C\0\x87\x40\x90\x40\x64\x81\x70\x90\x43\x64\x81\x70\x80\x40\0\0\x80\x43\0\0\xFF\x2F\0";
+		audio.add_track(track(events).with_pickup(0.5));
+		let batch = audio.advance_logical(280000);
+		// Starting half a beat early, so each pass is 88200 frames from 11025
+		let (pickups, _) = note_frames(&batch, 0x43);
+		assert_near_frames(pickups.clone(), &[0, 88200, 176400, 264600]);
+		let (downbeats, _) = note_frames(&batch, 0x3C);
+		assert_near_frames(downbeats, &[11025, 99225, 187425, 275625]);
+		// Each pass's pickup sounds while its last beat is still playing
+		let (last_beats, last_beat_ends) = note_frames(&batch, 0x40);
+		assert_near_frames(last_beats.clone(), &[77175, 165375, 253575]);
+		for (pickup, start) in pickups[1..].iter().zip(last_beats) {
+			let end = last_beat_ends.iter().find(|&&end| end > start).unwrap();
+			assert!(
+				(start..*end).contains(pickup),
+				"{pickup} outside {start}..{end}"
+			);
+		}
+	}
+}
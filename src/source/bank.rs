@@ -0,0 +1,364 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use bevy::utils::hashbrown::HashMap;
+use rustysynth::{InstrumentRegion, PresetRegion, SampleHeader, SoundFont};
+
+use crate::gm;
+use crate::synth::Waveform;
+
+use super::instrument::SynthInstrument;
+use super::voice::{SampleType, Voice};
+
+/// The General MIDI bank that percussion presets live in
+pub(super) const PERCUSSION_BANK: u8 = 128;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingDrumBankPolicy {
+	/// Play percussion channels with bank 0 patch 0, so they're at least audible
+	#[default]
+	RemapToMelodic,
+	Mute,
+}
+
+/// A region of a SoundFont preset that would play for a note, see [`SoundFontBank::resolve`]
+///
+/// Indices are into the SoundFont's own lists, and ranges are the instrument region's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMatch {
+	pub preset_region_index: usize,
+	pub instrument_index: usize,
+	pub instrument_region_index: usize,
+	pub sample_index: usize,
+	pub sample_name: String,
+	pub key_range: RangeInclusive<u8>,
+	pub vel_range: RangeInclusive<u8>,
+}
+
+#[derive(Clone)]
+pub struct SoundFontBank {
+	soundfont: Option<Arc<SoundFont>>,
+	preset_index: HashMap<(u8, u8), usize>,
+	/// Played instead when a preset can't be found
+	fallback: Option<Waveform>,
+}
+
+impl SoundFontBank {
+	pub fn new(soundfont: Arc<SoundFont>) -> Self {
+		let preset_index = soundfont
+			.get_presets()
+			.iter()
+			.enumerate()
+			.map(|(index, preset)| {
+				(
+					(
+						preset.get_bank_number() as u8,
+						preset.get_patch_number() as u8,
+					),
+					index,
+				)
+			})
+			.collect();
+		Self {
+			soundfont: Some(soundfont),
+			preset_index,
+			fallback: None,
+		}
+	}
+
+	/// A bank with no SoundFont, where every note is played by the built-in synth
+	pub fn builtin(waveform: Waveform) -> Self {
+		Self {
+			soundfont: None,
+			preset_index: HashMap::new(),
+			fallback: Some(waveform),
+		}
+	}
+
+	pub fn set_fallback(&mut self, fallback: Option<Waveform>) {
+		self.fallback = fallback;
+	}
+
+	pub fn soundfont(&self) -> Option<&Arc<SoundFont>> {
+		self.soundfont.as_ref()
+	}
+
+	pub(super) fn fallback_voice(
+		&self,
+		note: u8,
+		velocity: u8,
+		samples_per_second: f32,
+	) -> Option<Voice> {
+		Some(SynthInstrument::new(self.fallback?).voice(note, velocity, samples_per_second))
+	}
+
+	/// The SoundFont's name for a preset, or its General MIDI name if the SoundFont doesn't name it
+	pub fn preset_name(&self, bank_number: u8, patch_number: u8) -> Option<&str> {
+		let soundfont_name = self
+			.preset_index
+			.get(&(bank_number, patch_number))
+			.zip(self.soundfont.as_ref())
+			.map(|(&index, soundfont)| soundfont.get_presets()[index].get_name())
+			.filter(|name| !name.trim().is_empty());
+		soundfont_name
+			.or_else(|| (bank_number != PERCUSSION_BANK).then(|| gm::instrument_name(patch_number)))
+	}
+
+	/// Every region the SoundFont has for a note, for working out why an instrument sounds the way it does.
+	/// Empty if the preset is missing or has nothing for the note.
+	pub fn resolve(
+		&self,
+		note: u8,
+		velocity: u8,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Vec<RegionMatch> {
+		let Some((soundfont, &preset_index)) = self
+			.soundfont
+			.as_ref()
+			.zip(self.preset_index.get(&(bank_number, patch_number)))
+		else {
+			return vec![];
+		};
+		let (note, velocity) = (note as i32, velocity as i32);
+		let range = |start: i32, end: i32| start.clamp(0, 127) as u8..=end.clamp(0, 127) as u8;
+		let preset = &soundfont.get_presets()[preset_index];
+		let mut matches = vec![];
+		for (preset_region_index, preset_region) in preset.get_regions().iter().enumerate() {
+			if !preset_region.contains(note, velocity) {
+				continue;
+			}
+			let instrument_index = preset_region.get_instrument_id();
+			let instrument = &soundfont.get_instruments()[instrument_index];
+			for (instrument_region_index, region) in instrument.get_regions().iter().enumerate() {
+				if !region.contains(note, velocity) {
+					continue;
+				}
+				let sample_index = region.get_sample_id();
+				matches.push(RegionMatch {
+					preset_region_index,
+					instrument_index,
+					instrument_region_index,
+					sample_index,
+					sample_name: soundfont.get_sample_headers()[sample_index]
+						.get_name()
+						.to_owned(),
+					key_range: range(region.get_key_range_start(), region.get_key_range_end()),
+					vel_range: range(
+						region.get_velocity_range_start(),
+						region.get_velocity_range_end(),
+					),
+				});
+			}
+		}
+		matches
+	}
+
+	pub(super) fn has_fallback(&self) -> bool {
+		self.fallback.is_some()
+	}
+
+	pub fn has_preset(&self, bank_number: u8, patch_number: u8) -> bool {
+		self.preset_index.contains_key(&(bank_number, patch_number))
+	}
+
+	pub fn has_percussion(&self) -> bool {
+		self.preset_index
+			.keys()
+			.any(|&(bank_number, _)| bank_number == PERCUSSION_BANK)
+	}
+
+	pub fn get_sample_headers(
+		&self,
+		note: i32,
+		velocity: i32,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Option<Vec<&SampleHeader>> {
+		self.get_sample_layers(note, velocity, bank_number, patch_number)
+			.map(|layers| layers.into_iter().flatten().collect())
+	}
+
+	/// Like [`Self::get_sample_headers`], but grouped into layers, keeping linked stereo samples together
+	pub fn get_sample_layers(
+		&self,
+		note: i32,
+		velocity: i32,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Option<Vec<Vec<&SampleHeader>>> {
+		self.get_region_layers(note, velocity, bank_number, patch_number)
+			.map(|layers| layers.into_iter().map(samples_of).collect())
+	}
+
+	/// Like [`Self::get_sample_layers`], with the regions each sample is played from
+	pub(super) fn get_region_layers(
+		&self,
+		note: i32,
+		velocity: i32,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Option<Vec<Vec<SampleRegion<'_>>>> {
+		let soundfont = self.soundfont.as_ref()?;
+		let &preset_index = self.preset_index.get(&(bank_number, patch_number))?;
+		let preset = &soundfont.get_presets()[preset_index];
+		let preset_regions = preset
+			.get_regions()
+			.iter()
+			.filter(|region| region.contains(note, velocity));
+		let instrument_regions = preset_regions.flat_map(|preset_region| {
+			soundfont.get_instruments()[preset_region.get_instrument_id()]
+				.get_regions()
+				.iter()
+				.filter(move |region| region.contains(note, velocity))
+				.map(move |region| (preset_region, region))
+		});
+		let mut layers: Vec<Vec<IndexedSample>> = vec![];
+		for (preset_region, region) in instrument_regions {
+			let sample_id = region.get_sample_id();
+			let sample = &soundfont.get_sample_headers()[sample_id];
+			let linked_layer = layers
+				.iter_mut()
+				.find(|layer| is_linked(layer, sample_id, sample));
+			match linked_layer {
+				Some(layer) => layer.push((sample_id, (preset_region, region, sample))),
+				None => layers.push(vec![(sample_id, (preset_region, region, sample))]),
+			}
+		}
+		Some(
+			layers
+				.into_iter()
+				.map(|layer| layer.into_iter().map(|(_, sample)| sample).collect())
+				.collect(),
+		)
+	}
+
+	/// Every layer the preset has for a key at any velocity, with the velocities it plays at,
+	/// keeping linked stereo samples together
+	pub fn get_velocity_layers(
+		&self,
+		note: i32,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Option<Vec<(RangeInclusive<u8>, Vec<&SampleHeader>)>> {
+		self.get_velocity_region_layers(note, bank_number, patch_number)
+			.map(|layers| {
+				layers
+					.into_iter()
+					.map(|(velocities, layer)| (velocities, samples_of(layer)))
+					.collect()
+			})
+	}
+
+	/// Like [`Self::get_velocity_layers`], with the regions each sample is played from
+	pub(super) fn get_velocity_region_layers(
+		&self,
+		note: i32,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Option<Vec<(RangeInclusive<u8>, Vec<SampleRegion<'_>>)>> {
+		let soundfont = self.soundfont.as_ref()?;
+		let &preset_index = self.preset_index.get(&(bank_number, patch_number))?;
+		let preset = &soundfont.get_presets()[preset_index];
+		let mut layers: Vec<(RangeInclusive<u8>, Vec<IndexedSample>)> = vec![];
+		for preset_region in preset.get_regions().iter().filter(|region| {
+			region.get_key_range_start() <= note && note <= region.get_key_range_end()
+		}) {
+			let instrument = &soundfont.get_instruments()[preset_region.get_instrument_id()];
+			for region in instrument.get_regions().iter().filter(|region| {
+				region.get_key_range_start() <= note && note <= region.get_key_range_end()
+			}) {
+				let start = region
+					.get_velocity_range_start()
+					.max(preset_region.get_velocity_range_start());
+				let end = region
+					.get_velocity_range_end()
+					.min(preset_region.get_velocity_range_end());
+				if start > end {
+					continue;
+				}
+				let velocities = start.clamp(0, 127) as u8..=end.clamp(0, 127) as u8;
+				let sample_id = region.get_sample_id();
+				let sample = &soundfont.get_sample_headers()[sample_id];
+				let linked_layer = layers.iter_mut().find(|(other_velocities, layer)| {
+					*other_velocities == velocities && is_linked(layer, sample_id, sample)
+				});
+				match linked_layer {
+					Some((_, layer)) => layer.push((sample_id, (preset_region, region, sample))),
+					None => layers.push((
+						velocities,
+						vec![(sample_id, (preset_region, region, sample))],
+					)),
+				}
+			}
+		}
+		Some(
+			layers
+				.into_iter()
+				.map(|(velocities, layer)| {
+					(
+						velocities,
+						layer.into_iter().map(|(_, sample)| sample).collect(),
+					)
+				})
+				.collect(),
+		)
+	}
+}
+
+/// A sample with the preset and instrument regions that play it, for their loop, envelope and other generators
+pub(super) type SampleRegion<'a> = (&'a PresetRegion, &'a InstrumentRegion, &'a SampleHeader);
+
+/// A sample with its index in the SoundFont
+type IndexedSample<'a> = (usize, SampleRegion<'a>);
+
+fn samples_of(layer: Vec<SampleRegion<'_>>) -> Vec<&SampleHeader> {
+	layer.into_iter().map(|(_, _, sample)| sample).collect()
+}
+
+/// Whether a stereo sample is the other half of one already in `layer`
+fn is_linked(layer: &[IndexedSample], sample_id: usize, sample: &SampleHeader) -> bool {
+	layer.iter().any(|&(other_id, (_, _, other))| {
+		other.get_link() as usize == sample_id || sample.get_link() as usize == other_id
+	}) && sample.get_sample_type() != SampleType::Mono as i32
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::Waveform;
+
+	use super::*;
+
+	#[test]
+	fn unnamed_presets_use_general_midi_names() {
+		let bank = SoundFontBank::builtin(Waveform::Sine);
+		assert_eq!(bank.preset_name(0, 40), Some("Violin"));
+		assert_eq!(bank.preset_name(PERCUSSION_BANK, 0), None);
+	}
+
+	fn hl4mgm() -> SoundFontBank {
+		let mut bytes = &include_bytes!("../../assets/hl4mgm.sf2")[..];
+		SoundFontBank::new(Arc::new(SoundFont::new(&mut bytes).unwrap()))
+	}
+
+	#[test]
+	fn resolving_finds_the_regions_for_the_note() {
+		let regions = hl4mgm().resolve(60, 100, 0, 0);
+		assert!(!regions.is_empty());
+		for region in &regions {
+			assert!(region.key_range.contains(&60) && region.vel_range.contains(&100));
+			assert!(!region.sample_name.is_empty());
+		}
+	}
+
+	#[test]
+	fn resolving_missing_presets_finds_nothing() {
+		let bank = hl4mgm();
+		assert!(!bank.has_preset(5, 0));
+		assert_eq!(bank.resolve(60, 100, 5, 0), vec![]);
+		assert_eq!(
+			SoundFontBank::builtin(Waveform::Sine).resolve(60, 100, 0, 0),
+			vec![]
+		);
+	}
+}
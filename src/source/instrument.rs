@@ -0,0 +1,385 @@
+use bevy::utils::hashbrown::HashMap;
+use rustysynth::{InstrumentRegion, LoopMode, PresetRegion};
+
+use crate::Note;
+use crate::rng::Rng;
+use crate::synth::{Oscillator, Waveform};
+
+use super::asset::StrictViolationKind;
+use super::bank::{SampleRegion, SoundFontBank};
+use super::renderer::LayerAlternation;
+use super::sample_math;
+use super::voice::{
+	CrossfadeVoice, SoundFontVoice, Voice, VoiceSample, VoiceSource, VolumeEnvelope,
+};
+
+/// What a channel asks its [`Instrument`] to play a note with
+pub struct InstrumentContext<'a> {
+	pub bank: &'a SoundFontBank,
+	pub bank_number: u8,
+	pub patch_number: u8,
+	/// The output sample rate voices are ticked at
+	pub samples_per_second: f32,
+}
+
+/// Creates a voice for each note a channel plays.
+/// Channels play their SoundFont preset unless given another, see [`super::MidiAudioTrack::with_channel_instrument`].
+pub trait Instrument: Send {
+	/// `None` if there's nothing to play for this note
+	fn create_voice(
+		&mut self,
+		note: u8,
+		velocity: u8,
+		context: &InstrumentContext,
+	) -> Option<Box<dyn VoiceSource>>;
+
+	/// A new instrument set up like this one, but without anything it's kept from notes it's played,
+	/// for [`super::MidiAudioTrack::duplicate`]
+	fn fresh(&self) -> Box<dyn Instrument>;
+}
+
+/// Plays the channel's bank and patch from the SoundFont,
+/// or the bank's built-in synth fallback if the SoundFont can't play a note
+#[derive(Debug, Clone)]
+pub struct SoundFontInstrument {
+	layer_alternation: LayerAlternation,
+	/// How many times each key has been hit, for round-robin layers
+	alternation_counters: HashMap<u8, usize>,
+	rng: Rng,
+}
+
+impl SoundFontInstrument {
+	pub fn new(layer_alternation: LayerAlternation) -> Self {
+		let seed = match layer_alternation {
+			LayerAlternation::Random { seed } => seed,
+			_ => 0,
+		};
+		Self {
+			layer_alternation,
+			alternation_counters: HashMap::new(),
+			rng: Rng::new(seed),
+		}
+	}
+
+	/// Like [`Instrument::create_voice`], but with why nothing could be played
+	pub(super) fn try_create_voice(
+		&mut self,
+		note: u8,
+		velocity: u8,
+		context: &InstrumentContext,
+	) -> Result<Voice, StrictViolationKind> {
+		let soundfont = context.bank;
+		let key = note;
+		let note = note as i32;
+		let velocity_value = velocity as i32;
+		let volume = velocity_value as f32 / 127.0;
+
+		if self.layer_alternation == LayerAlternation::VelocityCrossfade {
+			let Some(layers) = soundfont.get_velocity_region_layers(
+				note,
+				context.bank_number,
+				context.patch_number,
+			) else {
+				return soundfont
+					.fallback_voice(key, velocity, context.samples_per_second)
+					.ok_or(StrictViolationKind::MissingPreset {
+						bank: context.bank_number,
+						patch: context.patch_number,
+					});
+			};
+			let (sample_velocities, samples): (Vec<_>, Vec<_>) = layers
+				.into_iter()
+				.flat_map(|(velocities, layer)| {
+					layer.into_iter().map(move |sample| {
+						(
+							velocities.clone(),
+							voice_sample(note, sample, volume, context),
+						)
+					})
+				})
+				.unzip();
+			let Some(soundfont_data) = soundfont.soundfont().filter(|_| !samples.is_empty()) else {
+				return soundfont
+					.fallback_voice(key, velocity, context.samples_per_second)
+					.ok_or(StrictViolationKind::EmptySampleSet {
+						bank: context.bank_number,
+						patch: context.patch_number,
+						note: key,
+					});
+			};
+			return Ok(Box::new(CrossfadeVoice::new(
+				SoundFontVoice {
+					soundfont: soundfont_data.clone(),
+					samples,
+				},
+				sample_velocities,
+				velocity,
+			)));
+		}
+
+		let Some(mut layers) = soundfont.get_region_layers(
+			note,
+			velocity_value,
+			context.bank_number,
+			context.patch_number,
+		) else {
+			return soundfont
+				.fallback_voice(key, velocity, context.samples_per_second)
+				.ok_or(StrictViolationKind::MissingPreset {
+					bank: context.bank_number,
+					patch: context.patch_number,
+				});
+		};
+		let sample_headers = match self.layer_alternation {
+			LayerAlternation::Layered | LayerAlternation::VelocityCrossfade => {
+				layers.into_iter().flatten().collect()
+			}
+			_ if layers.is_empty() => vec![],
+			LayerAlternation::RoundRobin => {
+				let counter = self.alternation_counters.entry(key).or_default();
+				let layer = layers.swap_remove(*counter % layers.len());
+				*counter += 1;
+				layer
+			}
+			LayerAlternation::Random { .. } => {
+				let index = self.rng.below(layers.len());
+				layers.swap_remove(index)
+			}
+		};
+		let samples = sample_headers
+			.into_iter()
+			.map(|sample| voice_sample(note, sample, volume, context))
+			.collect::<Vec<_>>();
+		let Some(soundfont_data) = soundfont.soundfont().filter(|_| !samples.is_empty()) else {
+			return soundfont
+				.fallback_voice(key, velocity, context.samples_per_second)
+				.ok_or(StrictViolationKind::EmptySampleSet {
+					bank: context.bank_number,
+					patch: context.patch_number,
+					note: key,
+				});
+		};
+		Ok(Box::new(SoundFontVoice {
+			soundfont: soundfont_data.clone(),
+			samples,
+		}))
+	}
+}
+
+/// Plays `sample` at the pitch of `note`, looping and with the volume envelope its region says
+fn voice_sample(
+	note: i32,
+	(preset_region, region, sample): SampleRegion,
+	volume: f32,
+	context: &InstrumentContext,
+) -> VoiceSample {
+	let loop_mode = region.get_sample_modes();
+	let (start_loop, end_loop) = (region.get_sample_start_loop(), region.get_sample_end_loop());
+	// Samples with a broken loop play once instead of reading past it
+	let looping = loop_mode != LoopMode::NoLoop
+		&& sample.get_start() <= start_loop
+		&& start_loop < end_loop
+		&& end_loop <= sample.get_end();
+	VoiceSample {
+		speed: sample_math::speed(
+			2_f32.powf(
+				(note as f32 - sample.get_original_pitch() as f32
+					+ sample.get_pitch_correction() as f32 / 100.0)
+					/ 12.0,
+			) * sample.get_sample_rate() as f32
+				/ context.samples_per_second,
+		),
+		current_sample: sample_math::position(sample.get_start() as f64),
+		end_sample: sample_math::position(sample.get_end() as f64),
+		start_loop: sample_math::position(start_loop as f64),
+		end_loop: sample_math::position(end_loop as f64),
+		loop_mode,
+		looping,
+		sample_type: sample.get_sample_type().try_into().unwrap(),
+		volume: sample_math::gain(volume),
+		envelope: volume_envelope(note, preset_region, region, context.samples_per_second),
+	}
+}
+
+/// The instrument region's volume envelope, offset by the preset region's.
+/// Their times are in timecents, so adding them multiplies the times in seconds.
+fn volume_envelope(
+	note: i32,
+	preset_region: &PresetRegion,
+	region: &InstrumentRegion,
+	samples_per_second: f32,
+) -> VolumeEnvelope {
+	// Scales a time by how far the note is from middle C, with the timecents per key it's given
+	let key_scaling =
+		|timecents_per_key: i32| 2_f32.powf((timecents_per_key * (60 - note)) as f32 / 1200.0);
+	let hold = region.get_hold_volume_envelope()
+		* preset_region.get_hold_volume_envelope()
+		* key_scaling(
+			region.get_key_number_to_volume_envelope_hold()
+				+ preset_region.get_key_number_to_volume_envelope_hold(),
+		);
+	let decay = region.get_decay_volume_envelope()
+		* preset_region.get_decay_volume_envelope()
+		* key_scaling(
+			region.get_key_number_to_volume_envelope_decay()
+				+ preset_region.get_key_number_to_volume_envelope_decay(),
+		);
+	// Given as how many decibels below full volume it sustains at
+	let sustain_decibels =
+		region.get_sustain_volume_envelope() + preset_region.get_sustain_volume_envelope();
+	VolumeEnvelope::new(
+		region.get_attack_volume_envelope() * preset_region.get_attack_volume_envelope(),
+		hold,
+		decay,
+		10_f32.powf(-sustain_decibels / 20.0),
+		// Like rustysynth, at least 10 ms so releases don't click
+		(region.get_release_volume_envelope() * preset_region.get_release_volume_envelope())
+			.max(0.01),
+		samples_per_second,
+	)
+}
+
+impl Default for SoundFontInstrument {
+	fn default() -> Self {
+		Self::new(LayerAlternation::default())
+	}
+}
+
+impl Instrument for SoundFontInstrument {
+	fn create_voice(
+		&mut self,
+		note: u8,
+		velocity: u8,
+		context: &InstrumentContext,
+	) -> Option<Box<dyn VoiceSource>> {
+		self.try_create_voice(note, velocity, context).ok()
+	}
+
+	fn fresh(&self) -> Box<dyn Instrument> {
+		Box::new(Self::new(self.layer_alternation))
+	}
+}
+
+/// Plays every note with the built-in synth, ignoring the SoundFont
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SynthInstrument {
+	pub waveform: Waveform,
+}
+
+impl SynthInstrument {
+	pub fn new(waveform: Waveform) -> Self {
+		Self { waveform }
+	}
+
+	pub(super) fn voice(&self, note: u8, velocity: u8, samples_per_second: f32) -> Voice {
+		let frequency = Note::from_midi_number(note).frequency;
+		Box::new(Oscillator::new(
+			self.waveform,
+			frequency,
+			velocity as f32 / 127.0,
+			samples_per_second,
+		))
+	}
+}
+
+impl Instrument for SynthInstrument {
+	fn create_voice(
+		&mut self,
+		note: u8,
+		velocity: u8,
+		context: &InstrumentContext,
+	) -> Option<Box<dyn VoiceSource>> {
+		Some(self.voice(note, velocity, context.samples_per_second))
+	}
+
+	fn fresh(&self) -> Box<dyn Instrument> {
+		Box::new(*self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use rustysynth::SoundFont;
+
+	use super::*;
+
+	fn bank() -> SoundFontBank {
+		let mut bytes = &include_bytes!("../../assets/hl4mgm.sf2")[..];
+		SoundFontBank::new(Arc::new(SoundFont::new(&mut bytes).unwrap()))
+	}
+
+	/// The first melodic patch and key with two layers of different samples at full velocity
+	fn two_layer_key(bank: &SoundFontBank) -> (u8, u8) {
+		(0..128)
+			.flat_map(|patch| (0..128).map(move |key| (patch, key)))
+			.find(|&(patch, key)| {
+				bank.get_region_layers(key as i32, 127, 0, patch)
+					.is_some_and(|layers| {
+						layers.len() == 2
+							&& layers[0][0].2.get_start() != layers[1][0].2.get_start()
+					})
+			})
+			.expect("the SoundFont has a layered key")
+	}
+
+	/// The first few hundred frames of the voice, to tell which samples it plays
+	fn fingerprint(voice: &mut Voice) -> Vec<i32> {
+		(0..256)
+			.map(|_| {
+				voice.tick();
+				voice.sample(0) + voice.sample(1)
+			})
+			.collect()
+	}
+
+	fn hits(layer_alternation: LayerAlternation, count: usize) -> Vec<Voice> {
+		let bank = bank();
+		let (patch, key) = two_layer_key(&bank);
+		let context = InstrumentContext {
+			bank: &bank,
+			bank_number: 0,
+			patch_number: patch,
+			samples_per_second: 44100.0,
+		};
+		let mut instrument = SoundFontInstrument::new(layer_alternation);
+		(0..count)
+			.map(|_| instrument.create_voice(key, 127, &context).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn round_robin_alternates_layers() {
+		let prints = hits(LayerAlternation::RoundRobin, 4)
+			.iter_mut()
+			.map(fingerprint)
+			.collect::<Vec<_>>();
+		assert_ne!(prints[0], prints[1]);
+		assert_eq!(prints[0], prints[2]);
+		assert_eq!(prints[1], prints[3]);
+	}
+
+	#[test]
+	fn random_alternation_is_seeded() {
+		let prints = |seed| {
+			hits(LayerAlternation::Random { seed }, 8)
+				.iter_mut()
+				.map(fingerprint)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(prints(7), prints(7));
+	}
+
+	#[test]
+	fn layered_by_default_sums_both_layers() {
+		let mut round_robin = hits(LayerAlternation::RoundRobin, 2);
+		let mut layered = hits(LayerAlternation::default(), 1);
+		let sum = fingerprint(&mut round_robin[0])
+			.into_iter()
+			.zip(fingerprint(&mut round_robin[1]))
+			.map(|(first, second)| first + second)
+			.collect::<Vec<_>>();
+		assert_eq!(fingerprint(&mut layered[0]), sum);
+	}
+}
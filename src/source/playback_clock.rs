@@ -0,0 +1,112 @@
+use std::time::Instant;
+
+/// How much of the gap between an observation and the estimate is closed at once
+const PHASE_CORRECTION: f64 = 0.02;
+
+/// How many frames per second each frame of an observation's error adds to the estimated playback rate
+const RATE_CORRECTION: f64 = 0.0005;
+
+/// How far the estimated rate can drift from the nominal sample rate
+const MAX_RATE_DEVIATION: f64 = 0.01;
+
+/// An error this large means playback jumped, e.g. after an underrun or a stall, so the estimate starts over
+const RESYNC_SECONDS: f64 = 0.25;
+
+/// A smooth estimate of how many frames have been played, for clocks that shouldn't jitter
+/// when the audio backend pulls frames in bursts
+///
+/// Each observation of the frames played so far pulls the estimate a little towards it,
+/// and corrects the rate for the audio device's clock running slightly fast or slow.
+/// [`super::MidiAudio`] observes its decoder every tick. Audio backends that pull frames themselves
+/// can keep their own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackClock {
+	samples_per_second: f64,
+	/// Estimated frames per second of wall clock time
+	rate: f64,
+	/// The estimate as of the last observation
+	anchor: Option<(f64, Instant)>,
+}
+
+impl PlaybackClock {
+	pub fn new(samples_per_second: f64) -> Self {
+		Self {
+			samples_per_second,
+			rate: samples_per_second,
+			anchor: None,
+		}
+	}
+
+	/// Records that `played_frames` had been played by `now`
+	pub fn observe(&mut self, played_frames: u64, now: Instant) {
+		let played_frames = played_frames as f64;
+		let Some(estimate) = self.played_frames_at(now) else {
+			self.anchor = Some((played_frames, now));
+			return;
+		};
+		let error = played_frames - estimate;
+		if error.abs() > RESYNC_SECONDS * self.samples_per_second {
+			self.rate = self.samples_per_second;
+			self.anchor = Some((played_frames, now));
+			return;
+		}
+		let max_deviation = MAX_RATE_DEVIATION * self.samples_per_second;
+		self.rate = (self.rate + error * RATE_CORRECTION).clamp(
+			self.samples_per_second - max_deviation,
+			self.samples_per_second + max_deviation,
+		);
+		self.anchor = Some((estimate + error * PHASE_CORRECTION, now));
+	}
+
+	/// The estimated frames played by `instant`, extrapolated from the last observation.
+	/// `None` until something's been observed.
+	pub fn played_frames_at(&self, instant: Instant) -> Option<f64> {
+		let (frames, anchor) = self.anchor?;
+		let seconds = if instant >= anchor {
+			(instant - anchor).as_secs_f64()
+		} else {
+			-(anchor - instant).as_secs_f64()
+		};
+		Some(frames + seconds * self.rate)
+	}
+
+	/// Forgets every observation, e.g. when the sample rate changes
+	pub fn reset(&mut self, samples_per_second: f64) {
+		*self = Self::new(samples_per_second);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	#[test]
+	fn bursty_pulls_give_a_steady_estimate() {
+		let mut clock = PlaybackClock::new(44100.0);
+		let start = Instant::now();
+		// The device pulls 2048 frames at a time, ahead of playing them, so the frames pulled
+		// run a staircase that's on average half a burst ahead of the smooth line the estimate should follow
+		let pulled = |seconds: f64| ((seconds * 44100.0 / 2048.0).floor() + 1.0) * 2048.0;
+		let smooth = |seconds: f64| seconds * 44100.0 + 1024.0;
+		let mut worst_error: f64 = 0.0;
+		let mut worst_observation: f64 = 0.0;
+		for frame in 0..600 {
+			// 60 fps, with each game frame a couple of milliseconds early or late
+			let seconds = frame as f64 / 60.0 + [0.0, 0.002, -0.001, 0.0015][frame % 4];
+			let now = start + Duration::from_secs_f64(seconds);
+			clock.observe(pulled(seconds) as u64, now);
+			if seconds > 3.0 {
+				let between = now + Duration::from_millis(8);
+				let estimate = clock.played_frames_at(between).unwrap();
+				worst_error = worst_error.max((estimate - smooth(seconds + 0.008)).abs());
+				worst_observation =
+					worst_observation.max((pulled(seconds) - smooth(seconds)).abs());
+			}
+		}
+		// Within 3 ms, where the raw observations are off by up to half a burst
+		assert!(worst_error < 0.003 * 44100.0, "{worst_error}");
+		assert!(worst_observation > 1000.0, "{worst_observation}");
+	}
+}
@@ -0,0 +1,129 @@
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::asset::MidiAudioTrackHandle;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MidiQueueEvent {
+	pub event: MidiQueueEventType,
+	pub timing: MidiQueueTiming,
+	pub looping: MidiQueueLooping,
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum MidiQueueTiming {
+	Loop,
+	Bar,
+	Beat,
+	/// When the track reaches the file's marker with this name, see [`crate::MidiTrack::markers`]
+	Marker(String),
+	/// On the next frame, whichever clock the track's queue follows and whether it's playing or not.
+	/// Looping, it fires every frame.
+	Immediate,
+	/// This many beats after it's queued, counted at the track's tempo whichever clock its queue follows
+	/// and whether it's playing or not. Looping, it fires again every this many beats.
+	AfterBeats(f64),
+}
+
+impl Eq for MidiQueueTiming {}
+
+impl Hash for MidiQueueTiming {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			MidiQueueTiming::Marker(name) => name.hash(state),
+			MidiQueueTiming::AfterBeats(beats) => beats.to_bits().hash(state),
+			MidiQueueTiming::Loop
+			| MidiQueueTiming::Bar
+			| MidiQueueTiming::Beat
+			| MidiQueueTiming::Immediate => {}
+		}
+	}
+}
+
+/// Whose beats, bars and loops a track's queued events wait for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Reflect)]
+#[non_exhaustive]
+pub enum QueueClock {
+	/// The track's own, which only move while it's playing.
+	/// While it's stopped at its start, its loop fires straight away and its beats and bars are counted on at its tempo,
+	/// see [`super::MidiAudioTrack::stopped`].
+	#[default]
+	Own,
+	/// Every playing track's
+	Shared,
+	/// Another track's, which only move while that track is playing. Nothing fires if it's removed.
+	Track(MidiAudioTrackHandle),
+	/// Counted at the track's tempo from when it was added, whether it's playing or not
+	Free,
+}
+
+/// Reflected as a single opaque value, since reflection can't see through the `Box` of a nested event
+#[derive(Debug, Clone, PartialEq, Reflect)]
+#[reflect(opaque, Debug, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum MidiQueueEventType {
+	Play,
+	Stop,
+	Queue(Box<MidiQueueEvent>),
+	/// Sets the track's tempo in beats per minute, see [`super::MidiAudioTrack::set_tempo`]
+	SetTempo(f64),
+	/// Sets the track's gain, see [`super::MidiAudioTrack::set_gain`]
+	SetGain(f32),
+	/// Sets the track's transposition in semitones, see [`super::MidiAudioTrack::set_transpose`]
+	Transpose(i8),
+}
+
+impl Eq for MidiQueueEventType {}
+
+impl Hash for MidiQueueEventType {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			MidiQueueEventType::Queue(event) => event.hash(state),
+			MidiQueueEventType::SetTempo(beats_per_minute) => {
+				beats_per_minute.to_bits().hash(state)
+			}
+			MidiQueueEventType::SetGain(gain) => gain.to_bits().hash(state),
+			MidiQueueEventType::Transpose(semitones) => semitones.hash(state),
+			MidiQueueEventType::Play | MidiQueueEventType::Stop => {}
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MidiQueueLooping {
+	Loop,
+	Once,
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn queue_events_round_trip() {
+		let event = MidiQueueEvent {
+			event: MidiQueueEventType::Queue(Box::new(MidiQueueEvent {
+				event: MidiQueueEventType::SetTempo(140.0),
+				timing: MidiQueueTiming::Marker("chorus".to_string()),
+				looping: MidiQueueLooping::Once,
+			})),
+			timing: MidiQueueTiming::AfterBeats(2.5),
+			looping: MidiQueueLooping::Loop,
+		};
+		let json = serde_json::to_string(&event).unwrap();
+		assert_eq!(
+			serde_json::from_str::<MidiQueueEvent>(&json).unwrap(),
+			event
+		);
+	}
+}
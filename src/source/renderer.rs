@@ -0,0 +1,2554 @@
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+use bevy::utils::Duration;
+use bevy::utils::HashSet;
+use bevy::utils::hashbrown::HashMap;
+
+use crate::automation::{Automation, AutomationParameter};
+use crate::batch::ControlCommand;
+use crate::beat_grid::BeatGrid;
+use crate::calibration::CalibrationPulse;
+use crate::midi::{
+	BANK_SELECT_CONTROLLER, EXPRESSION_CONTROLLER, LoadReport, MidiEvent, MidiTrack,
+	PAN_CONTROLLER, SUSTAIN_CONTROLLER, VOLUME_CONTROLLER,
+};
+use crate::musical_time::{MusicalTime, MusicalTimeListener};
+use crate::rng::Rng;
+use crate::smoothed::SmoothedParam;
+use crate::{Key, Tuning};
+
+use super::asset::{DispatchedEventKind, GainRamp, StrictViolationKind};
+use super::bank::{PERCUSSION_BANK, SoundFontBank};
+use super::instrument::{Instrument, InstrumentContext, SoundFontInstrument};
+use super::queue::{
+	MidiQueueEvent, MidiQueueEventType, MidiQueueLooping, MidiQueueTiming, QueueClock,
+};
+use super::voice::{Voice, VoiceSource};
+
+pub struct MidiAudioTrack {
+	midi_track: MidiTrack,
+	name: Option<String>,
+	/// Track => Channel => Note => Voice
+	pub(super) channels: HashMap<u8, Channel>,
+	ticks_per_sample: f64,
+	samples_per_second: f64,
+	pub(super) beats_per_second: f64,
+	tick: f64,
+	pub(super) beat: f64,
+	event_index: usize,
+	/// The meter playing now, which time signatures in the file change unless it's fixed
+	pub(super) meter: Meter,
+	/// Beats per bar given when the track was made, overriding the file's time signatures
+	fixed_beats_per_bar: Option<f64>,
+	pub(super) queue: Vec<MidiQueueEvent>,
+	/// Beats left until each of [`Self::queue`]'s [`MidiQueueTiming::AfterBeats`] events fires, in the same order
+	queue_countdowns: Vec<f64>,
+	pub(super) is_playing: bool,
+	/// Stopped with nothing ringing, so it's left out of the mix until it plays again
+	pub(super) quiet: bool,
+	/// Part of an unloaded song, removed once it's quiet or faded out
+	pub(super) retiring: bool,
+	pub(super) fade: GainRamp,
+	/// Left out of the mix, while still playing on
+	pub(super) muted: bool,
+	/// While any track is soloed, only soloed tracks are mixed
+	pub(super) solo: bool,
+	/// The only channel heard, on top of channel mutes
+	solo_channel: Option<u8>,
+	/// The loudest sample of the last render, relative to full scale
+	pub(super) level: f32,
+	/// Timings hit during the current tick
+	pub(super) timings: HashSet<MidiQueueTiming>,
+	pub(super) queue_clock: QueueClock,
+	/// Beats counted by [`QueueClock::Free`]
+	free_beat: f64,
+	/// Timings [`QueueClock::Free`] hit during the current tick
+	pub(super) free_timings: HashSet<MidiQueueTiming>,
+	shared_voice_ownership: bool,
+	load_report: Option<LoadReport>,
+	automation: Automation,
+	pub(super) volume: SmoothedParam,
+	/// Multiplies the volume, for balancing tracks against each other without touching their automation
+	gain: f32,
+	pub(super) tempo_multiplier: f64,
+	/// Ignores the file's tempo changes, see [`Self::with_tempo_locked`]
+	tempo_locked: bool,
+	transpose: i8,
+	tuning: Tuning,
+	/// (beat, channel) pairs
+	pub(super) scheduled_unmutes: Vec<(f64, u8)>,
+	pub(super) record_events: bool,
+	/// Events dispatched this frame, only filled while recording
+	pub(super) dispatched: Vec<DispatchedEventKind>,
+	musical_time_listeners: Vec<Box<dyn MusicalTimeListener>>,
+	musical_time: MusicalTime,
+	/// The pulse and how many frames of it have played
+	pub(super) calibration: Option<(CalibrationPulse, u64)>,
+	/// Tick to jump to on the first tick, skipping silence before the first note
+	start_tick: Option<u64>,
+	/// The last stretch of the file that leads into the next pass, see [`Self::with_pickup`]
+	pickup_ticks: u64,
+	/// Whether the pickup still needs to play before the first pass
+	pickup_pending: bool,
+	/// Playing the pickup before the first pass
+	in_pickup: bool,
+	/// (channel, note) pairs the pickup started right on the seam, which the next pass's downbeat doesn't start again
+	seam_notes: Vec<(u8, u8)>,
+	/// (channel, note) pairs left out of the last pass's pickup, whose NoteOffs are left out too
+	skipped_pickup_notes: Vec<(u8, u8)>,
+	pub(super) strict: bool,
+	/// Recorded while strict, drained into the asset every frame
+	pub(super) violations: Vec<(f64, StrictViolationKind)>,
+	seam_crossfade: Option<Duration>,
+	/// Voices stopped just before the loop seam, fading out over the start of the next pass
+	seam_tails: Vec<SeamTail>,
+	looping: bool,
+	/// The beats looped between after the first time through, see [`Self::set_loop_range`]
+	loop_range: Option<(f64, f64)>,
+	drift: Option<Drift>,
+	drift_seed: u64,
+	drift_rng: Rng,
+	/// Note ons held back by drift
+	drifted_notes: Vec<DriftedNote>,
+	mpe: Option<MpeConfig>,
+	/// Where to start looking for a free member channel, so released notes can ring out
+	next_mpe_channel: u8,
+}
+
+impl MidiAudioTrack {
+	/// `time_signature` is the fraction of a whole note in a bar, e.g. 0.75 for 3/4.
+	/// Pass `None` to follow the file's time signature events instead, in 4/4 until the first one.
+	pub fn new(midi_track: MidiTrack, time_signature: impl Into<Option<f64>>) -> Self {
+		let samples_per_second = 44100.0;
+		let beats_per_second = 120.0 / 60.0;
+		let ticks_per_beat = midi_track.ticks_per_beat as f64;
+		let ticks_per_sample = (ticks_per_beat * beats_per_second) / samples_per_second;
+
+		let fixed_beats_per_bar = time_signature
+			.into()
+			.map(|time_signature| time_signature * 4.0);
+		let meter = meters(&midi_track, fixed_beats_per_bar)
+			.take_while(|meter| meter.beat <= 0.0)
+			.last()
+			.unwrap_or_default();
+
+		let channels = (0..16)
+			.map(|i| (i, Channel::new(if i == 9 { PERCUSSION_BANK } else { 0 }, 0)))
+			.collect();
+
+		Self {
+			midi_track,
+			name: None,
+			channels,
+			ticks_per_sample,
+			samples_per_second,
+			beats_per_second,
+			tick: 0.0,
+			beat: 0.0,
+			event_index: 0,
+			meter,
+			fixed_beats_per_bar,
+			queue: vec![],
+			queue_countdowns: vec![],
+			is_playing: true,
+			quiet: false,
+			retiring: false,
+			fade: GainRamp::default(),
+			muted: false,
+			solo: false,
+			solo_channel: None,
+			level: 0.0,
+			timings: HashSet::new(),
+			queue_clock: QueueClock::Own,
+			free_beat: 0.0,
+			free_timings: HashSet::new(),
+			shared_voice_ownership: false,
+			load_report: None,
+			automation: Automation::default(),
+			volume: SmoothedParam::new(1.0),
+			gain: 1.0,
+			tempo_multiplier: 1.0,
+			tempo_locked: false,
+			transpose: 0,
+			tuning: Tuning::default(),
+			scheduled_unmutes: vec![],
+			record_events: false,
+			dispatched: vec![],
+			musical_time_listeners: vec![],
+			musical_time: MusicalTime {
+				beats_per_second,
+				beats_per_bar: meter.beats_per_bar,
+				samples_per_second,
+			},
+			calibration: None,
+			start_tick: None,
+			pickup_ticks: 0,
+			pickup_pending: false,
+			in_pickup: false,
+			seam_notes: vec![],
+			skipped_pickup_notes: vec![],
+			strict: false,
+			violations: vec![],
+			seam_crossfade: None,
+			seam_tails: vec![],
+			looping: true,
+			loop_range: None,
+			drift: None,
+			drift_seed: 0,
+			drift_rng: Rng::new(0),
+			drifted_notes: vec![],
+			mpe: None,
+			next_mpe_channel: 0,
+		}
+	}
+
+	pub fn from_bytes(track_bytes: &[u8], time_signature: impl Into<Option<f64>>) -> Self {
+		let (midi_track, load_report) = MidiTrack::from_bytes_with_report(track_bytes);
+		Self {
+			load_report: Some(load_report),
+			..Self::new(midi_track, time_signature)
+		}
+	}
+
+	pub(crate) fn with_load_report(mut self, load_report: LoadReport) -> Self {
+		self.load_report = Some(load_report);
+		self
+	}
+
+	/// Whether the track starts over when it reaches the end, or stops. Defaults to looping.
+	pub fn with_looping(mut self, looping: bool) -> Self {
+		self.looping = looping;
+		self
+	}
+
+	/// Plays from the start, then loops from `start_beat` to `end_beat`, see [`Self::set_loop_range`]
+	pub fn with_loop_range(mut self, start_beat: f64, end_beat: f64) -> Self {
+		self.set_loop_range(start_beat, end_beat);
+		self
+	}
+
+	/// Once the track reaches `end_beat`, it jumps back to `start_beat` instead of playing on,
+	/// e.g. to play an intro once and then loop the bars after it.
+	/// Only while looping, and clamped to the file. An empty range loops the whole file.
+	pub fn set_loop_range(&mut self, start_beat: f64, end_beat: f64) {
+		let end_beat = end_beat.min(self.loop_beats());
+		let start_beat = start_beat.max(0.0);
+		self.loop_range = (start_beat < end_beat).then_some((start_beat, end_beat));
+	}
+
+	/// Loops the whole file again
+	pub fn clear_loop_range(&mut self) {
+		self.loop_range = None;
+	}
+
+	/// The start and end beats set by [`Self::set_loop_range`]
+	pub fn loop_range(&self) -> Option<(f64, f64)> {
+		self.loop_range
+	}
+
+	pub fn with_volume(mut self, volume: f32) -> Self {
+		self.volume = SmoothedParam::new(volume);
+		self
+	}
+
+	/// Scales the track on top of its volume, e.g. above 1.0 to boost a quiet patch.
+	/// The mix is still clipped to full scale.
+	pub fn with_gain(mut self, gain: f32) -> Self {
+		self.gain = gain;
+		self
+	}
+
+	pub fn set_gain(&mut self, gain: f32) {
+		self.gain = gain;
+	}
+
+	pub fn gain(&self) -> f32 {
+		self.gain
+	}
+
+	/// A name to show for the track in debugging tools
+	pub fn with_name(mut self, name: impl Into<String>) -> Self {
+		self.name = Some(name.into());
+		self
+	}
+
+	pub fn name(&self) -> Option<&str> {
+		self.name.as_deref()
+	}
+
+	/// What was skipped while loading the MIDI file, if this track was loaded from bytes
+	pub fn load_report(&self) -> Option<&LoadReport> {
+		self.load_report.as_ref()
+	}
+
+	pub fn with_channel_patch(
+		mut self,
+		channel_number: u8,
+		bank_number: u8,
+		patch_number: u8,
+	) -> Self {
+		let channel = self
+			.channels
+			.entry(channel_number)
+			.or_insert_with(|| Channel::new(bank_number, patch_number));
+		channel.bank_number = bank_number;
+		channel.patch_number = patch_number;
+		self
+	}
+
+	/// Plays a channel with `instrument` instead of its SoundFont preset
+	pub fn with_channel_instrument(
+		mut self,
+		channel_number: u8,
+		instrument: impl Instrument + 'static,
+	) -> Self {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.instrument = Some(Mutex::new(Box::new(instrument)));
+		}
+		self
+	}
+
+	/// A fresh copy of this track with the same MIDI data, channel setup and settings,
+	/// but starting from the beginning with no voices or queued events
+	pub fn duplicate(&self) -> Self {
+		let mut track = Self {
+			name: self.name.clone(),
+			channels: self
+				.channels
+				.iter()
+				.map(|(&number, channel)| (number, channel.duplicate()))
+				.collect(),
+			meter: self.meter_at(0.0),
+			solo_channel: self.solo_channel,
+			fixed_beats_per_bar: self.fixed_beats_per_bar,
+			queue_clock: self.queue_clock,
+			shared_voice_ownership: self.shared_voice_ownership,
+			load_report: self.load_report.clone(),
+			automation: self.automation.clone(),
+			volume: self.volume.clone(),
+			gain: self.gain,
+			tempo_multiplier: self.tempo_multiplier,
+			tempo_locked: self.tempo_locked,
+			transpose: self.transpose,
+			tuning: self.tuning,
+			seam_crossfade: self.seam_crossfade,
+			pickup_ticks: self.pickup_ticks,
+			pickup_pending: self.pickup_ticks > 0,
+			looping: self.looping,
+			loop_range: self.loop_range,
+			drift: self.drift,
+			drift_seed: self.drift_seed,
+			drift_rng: Rng::new(self.drift_seed),
+			mpe: self.mpe.clone(),
+			musical_time: self.musical_time,
+			..Self::new(
+				self.midi_track.clone(),
+				self.fixed_beats_per_bar
+					.map(|beats_per_bar| beats_per_bar / 4.0),
+			)
+		};
+		// The file won't set the tempo of a locked copy, so it keeps the one it was locked at
+		if self.tempo_locked {
+			track.set_tempo(self.beats_per_second * 60.0);
+		}
+		track
+	}
+
+	pub fn with_channel_options(
+		mut self,
+		channel_number: u8,
+		options: ChannelPlaybackOptions,
+	) -> Self {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.set_options(options);
+		}
+		self
+	}
+
+	pub fn set_channel_options(&mut self, channel_number: u8, options: ChannelPlaybackOptions) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.set_options(options);
+		}
+	}
+
+	pub(super) fn apply_command(&mut self, command: ControlCommand) {
+		match command {
+			ControlCommand::SetChannelMuted { channel, muted } => {
+				self.set_channel_muted(channel, muted)
+			}
+			ControlCommand::SetChannelPatch {
+				channel,
+				bank_number,
+				patch_number,
+			} => {
+				if let Some(channel) = self.channels.get_mut(&channel) {
+					channel.bank_number = bank_number;
+					channel.patch_number = patch_number;
+				}
+			}
+			ControlCommand::SetChannelOptions { channel, options } => {
+				self.set_channel_options(channel, options)
+			}
+			ControlCommand::Queue(event) => self.queue.push(event),
+			ControlCommand::Play => self.is_playing = true,
+			ControlCommand::Stop => self.is_playing = false,
+		}
+	}
+
+	/// Shifts every note from the file by `semitones`
+	pub fn with_transpose(mut self, semitones: i8) -> Self {
+		self.set_transpose(semitones);
+		self
+	}
+
+	/// Notes already playing keep their pitch, only ones started afterwards are shifted
+	pub fn set_transpose(&mut self, semitones: i8) {
+		self.transpose = semitones;
+	}
+
+	/// Shifts a channel's notes from the file by `semitones`, on top of the track's transposition
+	pub fn with_channel_transpose(mut self, channel_number: u8, semitones: i8) -> Self {
+		self.set_channel_transpose(channel_number, semitones);
+		self
+	}
+
+	pub fn set_channel_transpose(&mut self, channel_number: u8, semitones: i8) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.transpose = semitones;
+		}
+	}
+
+	/// Tunes every note on the track to `tuning` instead of A4 at 440 Hz
+	pub fn with_tuning(mut self, tuning: Tuning) -> Self {
+		self.set_tuning(tuning);
+		self
+	}
+
+	/// Retunes notes already playing as well as later ones
+	pub fn set_tuning(&mut self, tuning: Tuning) {
+		if tuning == self.tuning {
+			return;
+		}
+		let ratio = tuning.ratio() / self.tuning.ratio();
+		for voice in self.channels.values_mut().flat_map(Channel::all_voices_mut) {
+			voice.detune(ratio);
+		}
+		self.tuning = tuning;
+	}
+
+	pub fn tuning(&self) -> Tuning {
+		self.tuning
+	}
+
+	/// Bends every note on a channel by `semitones`, including notes started later
+	pub fn set_channel_bend(&mut self, channel_number: u8, semitones: f32) {
+		let Some(channel) = self.channels.get_mut(&channel_number) else {
+			return;
+		};
+		let change = semitones - channel.bend;
+		channel.set_bend(semitones);
+		// An MPE master channel's bend bends every note, on top of each member's own
+		if change != 0.0
+			&& let Some(mpe) = &self.mpe
+			&& mpe.master_channel == channel_number
+		{
+			let ratio = 2_f32.powf(change / 12.0);
+			for member in mpe.member_channels.clone() {
+				if let Some(channel) = self.channels.get_mut(&member) {
+					channel
+						.all_voices_mut()
+						.for_each(|voice| voice.detune(ratio));
+				}
+			}
+		}
+	}
+
+	/// How far `channel_number`'s notes are bent, including an MPE master channel's bend for its members
+	fn total_bend(&self, channel_number: u8) -> f32 {
+		let bend = |number| {
+			self.channels
+				.get(&number)
+				.map_or(0.0, |channel| channel.bend)
+		};
+		match &self.mpe {
+			Some(mpe) if mpe.member_channels.contains(&channel_number) => {
+				bend(channel_number) + bend(mpe.master_channel)
+			}
+			_ => bend(channel_number),
+		}
+	}
+
+	/// How many semitones the file's pitch bends on a channel bend its notes at most. Defaults to 2.
+	/// Only affects later bends, not one already applied.
+	pub fn with_channel_bend_range(mut self, channel_number: u8, semitones: f32) -> Self {
+		self.set_channel_bend_range(channel_number, semitones);
+		self
+	}
+
+	pub fn set_channel_bend_range(&mut self, channel_number: u8, semitones: f32) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.bend_range = semitones;
+		}
+	}
+
+	/// How much the file's aftertouch on a channel scales its loudness, from 0.0 for not at all
+	/// to 1.0 for silent with no pressure. Defaults to 1.0.
+	pub fn with_channel_aftertouch_depth(mut self, channel_number: u8, depth: f32) -> Self {
+		self.set_channel_aftertouch_depth(channel_number, depth);
+		self
+	}
+
+	/// Only affects later aftertouch, not what's already applied
+	pub fn set_channel_aftertouch_depth(&mut self, channel_number: u8, depth: f32) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.aftertouch_depth = depth.clamp(0.0, 1.0);
+		}
+	}
+
+	/// Scales a channel's loudness by how hard its keys are pressed, from 0.0 to 1.0
+	pub fn set_channel_pressure(&mut self, channel_number: u8, pressure: f32) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.set_pressure(pressure);
+		}
+	}
+
+	/// Sets a channel's volume like the volume controller, from 0.0 to 1.0,
+	/// until the file or automation sets it again
+	pub fn set_channel_volume(&mut self, channel_number: u8, volume: f32) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.set_volume(volume);
+		}
+	}
+
+	/// Pans a channel like the pan controller, from -1.0 for fully left to 1.0 for fully right,
+	/// until the file or automation sets it again
+	pub fn set_channel_pan(&mut self, channel_number: u8, pan: f32) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.set_pan(pan);
+		}
+	}
+
+	/// See [`Self::set_channel_pan`]
+	pub fn with_channel_pan(mut self, channel_number: u8, pan: f32) -> Self {
+		self.set_channel_pan(channel_number, pan);
+		self
+	}
+
+	/// Scales a channel's loudness like the expression controller, from 0.0 to 1.0,
+	/// until the file sets it again
+	pub fn set_channel_expression(&mut self, channel_number: u8, expression: f32) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.set_expression(expression);
+		}
+	}
+
+	/// Plays notes on the member channels of `mpe` with the master channel's instrument,
+	/// so each note can have its own bend and pressure. Bending the master channel bends every note.
+	pub fn with_mpe(mut self, mpe: MpeConfig) -> Self {
+		self.next_mpe_channel = *mpe.member_channels.start();
+		self.mpe = Some(mpe);
+		self
+	}
+
+	/// A member channel to play a new note on, preferring ones with nothing playing.
+	/// If every member is busy, the least recently picked one is cut off and reused.
+	pub(super) fn allocate_mpe_channel(&mut self) -> Option<u8> {
+		let members = self.mpe.as_ref()?.member_channels.clone();
+		let rotation = members
+			.clone()
+			.filter(|&channel| channel >= self.next_mpe_channel)
+			.chain(
+				members
+					.clone()
+					.filter(|&channel| channel < self.next_mpe_channel),
+			)
+			.collect::<Vec<_>>();
+		let channel = *rotation
+			.iter()
+			.find(|channel| {
+				self.channels
+					.get(*channel)
+					.is_some_and(|channel| channel.all_voices().next().is_none())
+			})
+			.or(rotation.first())?;
+		if let Some(channel) = self.channels.get_mut(&channel) {
+			channel.stop_voices();
+			channel.set_bend(0.0);
+			channel.set_pressure(1.0);
+		}
+		self.next_mpe_channel = if channel >= *members.end() {
+			*members.start()
+		} else {
+			channel + 1
+		};
+		Some(channel)
+	}
+
+	pub fn set_channel_muted(&mut self, channel_number: u8, muted: bool) {
+		if let Some(channel) = self.channels.get_mut(&channel_number) {
+			channel.muted = muted;
+		}
+	}
+
+	pub fn mute_channel(&mut self, channel_number: u8) {
+		self.set_channel_muted(channel_number, true);
+	}
+
+	pub fn unmute_channel(&mut self, channel_number: u8) {
+		self.set_channel_muted(channel_number, false);
+	}
+
+	/// Returns whether the channel is muted now
+	pub fn toggle_channel_mute(&mut self, channel_number: u8) -> bool {
+		let Some(channel) = self.channels.get_mut(&channel_number) else {
+			return false;
+		};
+		channel.muted = !channel.muted;
+		channel.muted
+	}
+
+	/// Leaves every other channel out of the mix until [`Self::clear_solo`], without touching their mutes
+	pub fn solo_channel(&mut self, channel_number: u8) {
+		self.solo_channel = Some(channel_number);
+	}
+
+	pub fn clear_solo(&mut self) {
+		self.solo_channel = None;
+	}
+
+	/// Whether the channel isn't muted or left out by a solo
+	fn is_channel_heard(&self, channel_number: u8, channel: &Channel) -> bool {
+		!channel.muted && self.solo_channel.is_none_or(|solo| solo == channel_number)
+	}
+
+	pub(super) fn apply_scheduled_unmutes(&mut self) {
+		if self.scheduled_unmutes.is_empty() {
+			return;
+		}
+		let beat = self.beat;
+		let channels = &mut self.channels;
+		self.scheduled_unmutes.retain(|&(unmute_beat, channel)| {
+			if beat >= unmute_beat {
+				if let Some(channel) = channels.get_mut(&channel) {
+					channel.muted = false;
+				}
+				false
+			} else {
+				true
+			}
+		});
+	}
+
+	pub(super) fn loop_beats(&self) -> f64 {
+		self.midi_track.duration_ticks() as f64 / self.midi_track.ticks_per_beat as f64
+	}
+
+	/// The beat the track has rendered up to, counting from the start of the file
+	pub fn current_beat(&self) -> f64 {
+		self.beat
+	}
+
+	/// Like [`Self::current_beat`], in ticks of the midi file
+	pub fn current_tick(&self) -> f64 {
+		self.tick
+	}
+
+	/// How far through the file the track is, from 0.0 to 1.0. 0.0 for a file with no events.
+	pub fn progress(&self) -> f64 {
+		let duration = self.midi_track.duration_ticks();
+		if duration == 0 {
+			return 0.0;
+		}
+		(self.tick / duration as f64).clamp(0.0, 1.0)
+	}
+
+	pub(super) fn set_samples_per_second(&mut self, samples_per_second: f64) {
+		self.samples_per_second = samples_per_second;
+		self.ticks_per_sample = (self.midi_track.ticks_per_beat as f64 * self.beats_per_second)
+			/ self.samples_per_second;
+	}
+
+	fn beats_per_sample(&self) -> f64 {
+		self.beats_per_second * self.tempo_multiplier / self.samples_per_second
+	}
+
+	/// The grid [`MidiQueueTiming::Beat`] and [`MidiQueueTiming::Bar`] fire on
+	pub(super) fn beat_grid(&self) -> BeatGrid {
+		BeatGrid::new(self.meter.beats_per_bar)
+	}
+
+	/// The first beat at or after `beat` where `timing` would fire
+	pub(super) fn next_boundary_beat(&self, beat: f64, timing: &MidiQueueTiming) -> f64 {
+		// The grid's bars count from the start of the meter
+		let offset = self.meter.beat;
+		match timing {
+			MidiQueueTiming::Beat => self.beat_grid().gridline_at_or_after(beat - offset) + offset,
+			MidiQueueTiming::Bar => self.beat_grid().bar_at_or_after(beat - offset) + offset,
+			MidiQueueTiming::Loop => self
+				.loop_range
+				.map_or(self.loop_beats(), |(_, end_beat)| end_beat)
+				.max(beat),
+			MidiQueueTiming::Immediate => beat,
+			MidiQueueTiming::AfterBeats(beats) => beat + beats.max(0.0),
+			// Or the next pass's, or straight away if the file hasn't got it
+			MidiQueueTiming::Marker(name) => {
+				let ticks_per_beat = self.midi_track.ticks_per_beat as f64;
+				let markers = self.midi_track.markers();
+				let mut ticks = markers
+					.iter()
+					.filter(|(_, marker)| marker == name)
+					.map(|&(tick, _)| tick as f64);
+				ticks
+					.clone()
+					.find(|&tick| tick >= beat * ticks_per_beat)
+					.map(|tick| tick / ticks_per_beat)
+					.or_else(|| Some(ticks.next()? / ticks_per_beat + self.loop_beats()))
+					.unwrap_or(beat)
+			}
+		}
+	}
+
+	/// The track's first meter, then every time signature change in the file in order
+	fn meters(&self) -> impl Iterator<Item = Meter> + '_ {
+		meters(&self.midi_track, self.fixed_beats_per_bar)
+	}
+
+	/// The meter playing at `beat`
+	fn meter_at(&self, beat: f64) -> Meter {
+		self.meters()
+			.take_while(|meter| meter.beat <= beat)
+			.last()
+			.unwrap_or_default()
+	}
+
+	/// The key the file's key signatures are in at the current tick, see [`MidiTrack::key_at`]
+	pub fn current_key(&self) -> Option<Key> {
+		self.midi_track.key_at(self.tick as u64)
+	}
+
+	/// The last lyric the track has played this pass, for showing along with it
+	pub fn current_lyric(&self) -> Option<&str> {
+		self.midi_track.events[..self.event_index]
+			.iter()
+			.rev()
+			.find_map(|event| match &event.inner {
+				MidiEvent::Lyric { text } => Some(text.as_str()),
+				_ => None,
+			})
+	}
+
+	/// Where `bar` starts, counting from 0 and following the file's time signatures
+	pub(super) fn bar_start_beat(&self, bar: u32) -> f64 {
+		let bar = bar as f64;
+		self.meters()
+			.take_while(|meter| meter.bar <= bar)
+			.last()
+			.unwrap_or_default()
+			.bar_start(bar)
+	}
+
+	/// Plays at `beats_per_minute` from now, like a tempo change in the file, until the file's next one.
+	/// The tempo multiplier still applies on top.
+	pub fn set_tempo(&mut self, beats_per_minute: f64) {
+		self.beats_per_second = beats_per_minute / 60.0;
+		self.ticks_per_sample = (self.midi_track.ticks_per_beat as f64 * self.beats_per_second)
+			/ self.samples_per_second;
+	}
+
+	/// Ignores the file's tempo changes, so the tempo only changes with [`Self::set_tempo`],
+	/// e.g. for the game's tempo to drive the music
+	pub fn with_tempo_locked(mut self, locked: bool) -> Self {
+		self.tempo_locked = locked;
+		self
+	}
+
+	pub fn set_tempo_locked(&mut self, locked: bool) {
+		self.tempo_locked = locked;
+	}
+
+	/// Jumps to `beat`, cutting off every note that's playing. Events before it aren't played.
+	/// Seeking past the end goes to the end, and does nothing if the track's already there.
+	pub fn seek_to_beat(&mut self, beat: f64) {
+		let end = self.loop_beats();
+		if beat > end && self.beat == end {
+			return;
+		}
+		self.beat = beat.clamp(0.0, end);
+		self.tick = self.beat * self.midi_track.ticks_per_beat as f64;
+		self.event_index = self
+			.midi_track
+			.events
+			.partition_point(|event| (event.time as f64) < self.tick);
+		if self.event_index >= self.midi_track.events.len() {
+			self.event_index = 0;
+		}
+		self.meter = self.meter_at(self.beat);
+		self.pickup_pending = false;
+		self.in_pickup = false;
+		self.seam_notes.clear();
+		self.stop_all_notes();
+	}
+
+	/// Stops where it is, holding its notes as they are until it plays again rather than letting them ring out
+	pub(super) fn pause(&mut self) {
+		self.is_playing = false;
+		self.quiet = true;
+	}
+
+	/// Stops at the very start, cutting off every note, ready to play from the top
+	pub(super) fn restart(&mut self) {
+		self.is_playing = false;
+		self.seek_to_beat(0.0);
+		self.pickup_pending = self.pickup_ticks > 0;
+	}
+
+	/// Like [`Self::seek_to_beat`], to a tick of the midi file
+	pub fn seek_to_tick(&mut self, tick: u64) {
+		self.seek_to_beat(tick as f64 / self.midi_track.ticks_per_beat as f64);
+	}
+
+	/// Voices playing or fading out, including ones finished but not yet released
+	pub(super) fn active_voices(&self) -> usize {
+		self.channels
+			.values()
+			.flat_map(Channel::all_voices)
+			.map(|voice| voice.voice_count())
+			.sum::<usize>()
+			+ self.seam_tails.len()
+	}
+
+	/// Notes held or releasing across every channel, however many voices each is layered from
+	pub(super) fn active_notes(&self) -> usize {
+		self.channels
+			.values()
+			.map(|channel| channel.voices.len() + channel.releasing.len())
+			.sum()
+	}
+
+	/// Lets go of every held note, so they play out their releases
+	pub(super) fn release_all_notes(&mut self) {
+		for channel in self.channels.values_mut() {
+			channel.release_all();
+		}
+		self.drifted_notes.clear();
+	}
+
+	/// Cuts off every voice, including drifted notes that haven't started yet
+	pub(super) fn stop_all_notes(&mut self) {
+		for channel in self.channels.values_mut() {
+			channel.stop_voices();
+		}
+		self.seam_tails.clear();
+		self.drifted_notes.clear();
+	}
+
+	pub(super) fn overview(&self, buckets: usize) -> Vec<f32> {
+		let events = &self.midi_track.events;
+		let length = events.last().map_or(0, |event| event.time);
+		let mut peaks = vec![0.0_f32; buckets];
+		if buckets == 0 || length == 0 {
+			return peaks;
+		}
+		let bucket_of = |time: u64| {
+			((time as u128 * buckets as u128 / length as u128) as usize).min(buckets - 1)
+		};
+
+		let mut sounding = HashMap::<(u8, u8), f32>::new();
+		let mut level = 0.0_f32;
+		let mut bucket = 0;
+		for event in events.iter() {
+			let event_bucket = bucket_of(event.time);
+			// Whatever was sounding carries through the buckets in between
+			for peak in &mut peaks[bucket..event_bucket] {
+				*peak = peak.max(level);
+			}
+			bucket = event_bucket;
+
+			match event.inner {
+				MidiEvent::NoteOn {
+					channel,
+					note,
+					velocity,
+				} if velocity > 0 => {
+					let gain = self
+						.channels
+						.get(&channel)
+						.filter(|channel_state| self.is_channel_heard(channel, channel_state))
+						.map_or(0.0, |channel| channel.volume);
+					sounding.insert((channel, note), velocity as f32 / 127.0 * gain);
+				}
+				MidiEvent::NoteOn { channel, note, .. } | MidiEvent::NoteOff { channel, note } => {
+					sounding.remove(&(channel, note));
+				}
+				_ => {}
+			}
+			level = sounding.values().sum();
+			peaks[bucket] = peaks[bucket].max(level);
+		}
+
+		let loudest = peaks.iter().copied().fold(0.0, f32::max);
+		if loudest > 0.0 {
+			peaks.iter_mut().for_each(|peak| *peak /= loudest);
+		}
+		peaks
+	}
+
+	pub(super) fn notes_between(
+		&self,
+		channel: Option<u8>,
+		start_beat: f64,
+		end_beat: f64,
+	) -> Vec<UpcomingNote> {
+		let ticks_per_beat = self.midi_track.ticks_per_beat as f64;
+		let events = &self.midi_track.events;
+		let loop_beats = events
+			.last()
+			.map_or(0.0, |event| event.time as f64 / ticks_per_beat);
+		if loop_beats <= 0.0 || end_beat <= start_beat {
+			return vec![];
+		}
+
+		let first_pass = (start_beat / loop_beats).floor() as i64;
+		let last_pass = (end_beat / loop_beats).floor() as i64;
+		(first_pass..=last_pass)
+			.flat_map(|pass| {
+				events.iter().filter_map(move |event| match event.inner {
+					MidiEvent::NoteOn {
+						channel: event_channel,
+						note,
+						velocity,
+					} if velocity > 0 && channel.is_none_or(|channel| channel == event_channel) => {
+						Some(UpcomingNote {
+							beat: event.time as f64 / ticks_per_beat + pass as f64 * loop_beats,
+							channel: event_channel,
+							note,
+							velocity,
+						})
+					}
+					_ => None,
+				})
+			})
+			.filter(|note| note.beat >= start_beat && note.beat < end_beat)
+			.collect()
+	}
+
+	/// Registers something to be told about tempo, meter and playback rate changes on this track
+	pub fn with_musical_time_listener(
+		mut self,
+		listener: impl MusicalTimeListener + 'static,
+	) -> Self {
+		self.add_musical_time_listener(listener);
+		self
+	}
+
+	pub fn add_musical_time_listener(&mut self, listener: impl MusicalTimeListener + 'static) {
+		self.musical_time_listeners.push(Box::new(listener));
+	}
+
+	pub fn musical_time(&self) -> MusicalTime {
+		MusicalTime {
+			beats_per_second: self.beats_per_second * self.tempo_multiplier,
+			beats_per_bar: self.meter.beats_per_bar,
+			samples_per_second: self.samples_per_second,
+		}
+	}
+
+	pub(super) fn notify_musical_time(&mut self) {
+		let musical_time = self.musical_time();
+		if musical_time != self.musical_time {
+			for listener in &mut self.musical_time_listeners {
+				listener.musical_time_changed(&self.musical_time, &musical_time);
+			}
+			self.musical_time = musical_time;
+		}
+	}
+
+	pub fn with_automation(mut self, automation: Automation) -> Self {
+		self.automation = automation;
+		self
+	}
+
+	pub fn automation(&self) -> &Automation {
+		&self.automation
+	}
+
+	pub fn automation_mut(&mut self) -> &mut Automation {
+		&mut self.automation
+	}
+
+	pub(super) fn apply_automation(&mut self) {
+		if self.automation.is_empty() {
+			return;
+		}
+		for parameter in self.automation.parameters() {
+			let Some(value) = self.automation.value_at(parameter, self.beat) else {
+				continue;
+			};
+			match parameter {
+				AutomationParameter::TrackVolume => self.volume.set_target(value),
+				AutomationParameter::ChannelVolume(channel) => {
+					if let Some(channel) = self.channels.get_mut(&channel) {
+						channel.volume = value;
+					}
+				}
+				AutomationParameter::ChannelPan(channel) => {
+					if let Some(channel) = self.channels.get_mut(&channel) {
+						channel.set_pan(value);
+					}
+				}
+				AutomationParameter::TempoMultiplier => self.tempo_multiplier = value as f64,
+				AutomationParameter::Transpose => {
+					self.transpose = value.round().clamp(i8::MIN as f32, i8::MAX as f32) as i8
+				}
+			}
+		}
+	}
+
+	pub(super) fn sample(&self, current_audio_channel: u16) -> i32 {
+		let sample = self
+			.channels
+			.iter()
+			.filter(|&(&number, channel)| self.is_channel_heard(number, channel))
+			.map(|(_, channel)| {
+				channel.voices_sample(current_audio_channel) * channel.volume * channel.dynamics()
+			})
+			.sum::<f32>();
+		let tails = self
+			.seam_tails
+			.iter()
+			.filter_map(|tail| {
+				let channel = self
+					.channels
+					.get(&tail.channel)
+					.filter(|channel| self.is_channel_heard(tail.channel, channel))?;
+				let fade = tail.remaining as f32 / tail.frames as f32;
+				Some(
+					tail.voice.sample(current_audio_channel) as f32
+						* fade * channel.volume
+						* channel.pan_gain(tail.voice.as_ref(), current_audio_channel),
+				)
+			})
+			.sum::<f32>();
+		let sample = sample + tails;
+		let calibration = self
+			.calibration
+			.map_or(0, |(pulse, frame)| pulse.sample(frame));
+		((sample + calibration as f32) * self.volume.value() * self.gain * self.fade.gain) as i32
+	}
+
+	pub fn with_queue(mut self, event: MidiQueueEvent) -> Self {
+		self.queue.push(event);
+		self
+	}
+
+	/// Adds the track without playing it, e.g. to start it later with a queued play.
+	///
+	/// While stopped at its start, before it first plays or once it's finished without looping,
+	/// the track is already at the start of its loop, so a play queued on [`MidiQueueTiming::Loop`] fires straight away.
+	/// Its beats and bars keep being counted at its tempo while it waits, so a play queued on either starts it on the next one.
+	/// A track stopped partway through has no timings of its own until it plays again,
+	/// so use [`Self::with_queue_clock`] to start it on another clock.
+	pub fn stopped(mut self) -> Self {
+		self.is_playing = false;
+		self
+	}
+
+	/// Lets this track's queue respond to the beats, bars and loops of every playing track,
+	/// instead of only its own
+	pub fn with_shared_timing(self) -> Self {
+		self.with_queue_clock(QueueClock::Shared)
+	}
+
+	/// Sets whose beats, bars and loops this track's queued events wait for. Defaults to [`QueueClock::Own`].
+	pub fn with_queue_clock(mut self, queue_clock: QueueClock) -> Self {
+		self.queue_clock = queue_clock;
+		self
+	}
+
+	/// Lets NoteOffs release any voice on their key, whether it was started by the file or live
+	pub fn with_shared_voice_ownership(mut self) -> Self {
+		self.shared_voice_ownership = true;
+		self
+	}
+
+	/// Stopped at the start of the track, see [`Self::stopped`]
+	pub(super) fn is_waiting_to_start(&self) -> bool {
+		!self.is_playing && self.beat == 0.0
+	}
+
+	/// Advances [`QueueClock::Free`], recording the timings it hits in `timings`
+	pub(super) fn tick_free_clock(&mut self, timings: &mut HashSet<MidiQueueTiming>) {
+		timings.clear();
+		let last_beat = self.free_beat;
+		self.free_beat += self.beats_per_sample();
+		insert_crossed_timings(&self.beat_grid(), last_beat, self.free_beat, timings);
+		let loop_beats = self.loop_beats();
+		if loop_beats > 0.0
+			&& (last_beat / loop_beats).floor() != (self.free_beat / loop_beats).floor()
+		{
+			timings.insert(MidiQueueTiming::Loop);
+		}
+	}
+
+	/// Once quiet, a stopped track stays quiet until it plays again or starts a note
+	pub(super) fn update_quiet(&mut self) {
+		self.quiet = !self.is_playing
+			&& (self.quiet
+				|| self.seam_tails.is_empty()
+					&& self
+						.channels
+						.values()
+						.flat_map(Channel::all_voices)
+						.all(|voice| voice.is_finished()));
+	}
+
+	pub(super) fn process_queue(&mut self, timings: &HashSet<MidiQueueTiming>) {
+		if self.record_events {
+			self.dispatched
+				.extend(timings.iter().cloned().map(DispatchedEventKind::Timing));
+		}
+		// Events are only ever added to the end of the queue, so the ones without a countdown are new
+		let new_countdowns = self.queue[self.queue_countdowns.len()..]
+			.iter()
+			.map(|event| match event.timing {
+				MidiQueueTiming::AfterBeats(beats) => beats,
+				_ => 0.0,
+			})
+			.collect::<Vec<_>>();
+		self.queue_countdowns.extend(new_countdowns);
+
+		let beats_per_sample = self.beats_per_sample();
+		let queue = std::mem::take(&mut self.queue);
+		let countdowns = std::mem::take(&mut self.queue_countdowns);
+		let mut new_queue = vec![];
+		for (event, mut countdown) in queue.into_iter().zip(countdowns) {
+			let fires = match event.timing {
+				MidiQueueTiming::Immediate => true,
+				MidiQueueTiming::AfterBeats(beats) => {
+					countdown -= beats_per_sample;
+					// Counting on from the overshoot, so looping events don't drift
+					let fires = countdown <= 0.0;
+					if fires {
+						countdown += beats;
+					}
+					fires
+				}
+				_ => timings.contains(&event.timing),
+			};
+			if fires {
+				if self.record_events {
+					self.dispatched
+						.push(DispatchedEventKind::Queue(event.event.clone()));
+				}
+				match &event.event {
+					MidiQueueEventType::Play => self.is_playing = true,
+					MidiQueueEventType::Stop => self.is_playing = false,
+					MidiQueueEventType::SetTempo(beats_per_minute) => {
+						self.set_tempo(*beats_per_minute)
+					}
+					MidiQueueEventType::SetGain(gain) => self.set_gain(*gain),
+					MidiQueueEventType::Transpose(semitones) => self.set_transpose(*semitones),
+					MidiQueueEventType::Queue(new_event) => {
+						new_queue.push(new_event.as_ref().clone())
+					}
+				}
+				if event.looping == MidiQueueLooping::Once {
+					continue;
+				}
+			}
+			self.queue.push(event);
+			self.queue_countdowns.push(countdown);
+		}
+		self.queue.append(&mut new_queue);
+	}
+
+	pub fn tick_timing(&mut self) {
+		self.tick += self.ticks_per_sample * self.tempo_multiplier;
+
+		// The pickup before the first pass isn't the start of a pass
+		let pass_start =
+			(self.beat == 0.0 || self.beat == self.loop_start_beat()) && !self.pickup_pending;
+		if pass_start {
+			self.timings.insert(MidiQueueTiming::Loop);
+		}
+
+		// Markers among the events played this tick, and the ones on the first tick of a pass,
+		// which were played as the track looped
+		let tick = self.tick as u64;
+		let first_event = if pass_start {
+			self.first_event_at(self.beat)
+		} else {
+			self.event_index
+		};
+		for event in self
+			.midi_track
+			.events
+			.iter()
+			.skip(first_event)
+			.take_while(|event| event.time <= tick)
+		{
+			if let MidiEvent::Marker { name } = &event.inner {
+				self.timings.insert(MidiQueueTiming::Marker(name.clone()));
+			}
+		}
+
+		let last_beat = self.beat;
+		self.beat += self.beats_per_sample();
+		let offset = self.meter.beat;
+		insert_crossed_timings(
+			&self.beat_grid(),
+			last_beat - offset,
+			self.beat - offset,
+			&mut self.timings,
+		);
+	}
+
+	/// Lets notes that stop just before the loop seam ring on and fade out over the start of the next pass,
+	/// instead of being cut off right at the seam. Uses a 10 ms crossfade.
+	pub fn with_seamless_loop(self, seamless: bool) -> Self {
+		self.with_seam_crossfade(seamless.then_some(Duration::from_millis(10)))
+	}
+
+	/// Like [`Self::with_seamless_loop`] with a custom crossfade length, clamped to 5–50 ms
+	pub fn with_seam_crossfade(mut self, crossfade: Option<Duration>) -> Self {
+		self.seam_crossfade = crossfade
+			.map(|crossfade| crossfade.clamp(Duration::from_millis(5), Duration::from_millis(50)));
+		self
+	}
+
+	fn seam_crossfade_frames(&self) -> Option<u32> {
+		let crossfade = self.seam_crossfade?;
+		let loop_end = self.midi_track.events.last()?.time as f64;
+		let frames = (crossfade.as_secs_f64() * self.samples_per_second) as u32;
+		let window_ticks = frames as f64 * self.ticks_per_sample * self.tempo_multiplier;
+		(loop_end - self.tick <= window_ticks).then_some(frames.max(1))
+	}
+
+	pub(super) fn tick_seam_tails(&mut self) {
+		for tail in &mut self.seam_tails {
+			tail.voice.tick();
+			tail.remaining -= 1;
+		}
+		self.seam_tails
+			.retain(|tail| tail.remaining > 0 && !tail.voice.is_finished());
+	}
+
+	/// Starts playback at the first note instead of the start of the file.
+	/// Events before it (tempo changes etc.) are still applied, and loops still wrap to the very start.
+	pub fn with_trim_leading_silence(mut self, trim: bool) -> Self {
+		self.start_tick = trim
+			.then(|| {
+				self.midi_track
+					.events
+					.iter()
+					.find(
+						|event| matches!(event.inner, MidiEvent::NoteOn { velocity, .. } if velocity > 0),
+					)
+					.map(|event| event.time)
+			})
+			.flatten();
+		self
+	}
+
+	/// Marks the last `beats` of the file as a pickup into the next pass, e.g. a drum fill leading into the loop.
+	/// The pickup also plays before the first pass, so the track starts `beats` early, and doesn't play
+	/// at the end of the last pass when the track doesn't loop.
+	///
+	/// A note the pickup starts right on the seam stands in for the same note on the next pass's downbeat,
+	/// so it isn't started twice.
+	pub fn with_pickup(mut self, beats: f64) -> Self {
+		self.pickup_ticks = (beats.max(0.0) * self.midi_track.ticks_per_beat as f64) as u64;
+		self.pickup_pending = self.pickup_ticks > 0;
+		self
+	}
+
+	/// How many beats at the end of the file lead into the next pass, see [`Self::with_pickup`]
+	pub fn pickup_beats(&self) -> f64 {
+		self.pickup_ticks as f64 / self.midi_track.ticks_per_beat as f64
+	}
+
+	fn loop_end_tick(&self) -> u64 {
+		self.midi_track.duration_ticks()
+	}
+
+	fn pickup_start_tick(&self) -> u64 {
+		self.loop_end_tick().saturating_sub(self.pickup_ticks)
+	}
+
+	/// NoteOns in the pickup, at beats relative to the start of the pass they lead into
+	pub(super) fn pickup_notes(&self, channel: Option<u8>) -> Vec<UpcomingNote> {
+		if self.pickup_ticks == 0 {
+			return vec![];
+		}
+		let ticks_per_beat = self.midi_track.ticks_per_beat as f64;
+		let loop_end = self.loop_end_tick();
+		let pickup_start = self.pickup_start_tick();
+		self.midi_track
+			.events
+			.iter()
+			.filter(|event| event.time >= pickup_start)
+			.filter_map(|event| match event.inner {
+				MidiEvent::NoteOn {
+					channel: event_channel,
+					note,
+					velocity,
+				} if velocity > 0 && channel.is_none_or(|channel| channel == event_channel) => {
+					Some(UpcomingNote {
+						beat: (event.time as f64 - loop_end as f64) / ticks_per_beat,
+						channel: event_channel,
+						note,
+						velocity,
+					})
+				}
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Jumps to the pickup, applying the events before it other than notes
+	fn start_pickup(&mut self, soundfont: &SoundFontBank) {
+		self.pickup_pending = false;
+		let pickup_start = self.pickup_start_tick();
+		while let Some(event) = self
+			.midi_track
+			.events
+			.get(self.event_index)
+			.filter(|event| event.time < pickup_start)
+		{
+			if !matches!(
+				event.inner,
+				MidiEvent::NoteOn { .. } | MidiEvent::NoteOff { .. }
+			) {
+				self.interpret_event(event.inner.clone(), VoiceOrigin::File, soundfont);
+			}
+			self.event_index += 1;
+		}
+		// Keeps the time already ticked this frame
+		self.tick += pickup_start as f64;
+		self.beat = self.tick / self.midi_track.ticks_per_beat as f64;
+		self.in_pickup = true;
+	}
+
+	/// Whether to leave out an event at `time` because of the pickup: notes on the downbeat
+	/// the pickup already started on the seam, and the pickup on the last pass of a track that doesn't loop
+	fn skipped_by_pickup(&mut self, time: u64, event: &MidiEvent) -> bool {
+		if self.pickup_ticks == 0 {
+			return false;
+		}
+		let loop_end = self.loop_end_tick();
+		if time > 0 && time < loop_end {
+			self.seam_notes.clear();
+		}
+		match *event {
+			MidiEvent::NoteOn {
+				channel,
+				note,
+				velocity,
+			} if velocity > 0 => {
+				let key = (channel, note);
+				if time == 0
+					&& let Some(index) = self.seam_notes.iter().position(|&seam| seam == key)
+				{
+					self.seam_notes.swap_remove(index);
+					return true;
+				}
+				if !self.looping && !self.in_pickup && time >= self.pickup_start_tick() {
+					self.skipped_pickup_notes.push(key);
+					return true;
+				}
+				if time == loop_end {
+					self.seam_notes.push(key);
+				}
+				false
+			}
+			MidiEvent::NoteOn { channel, note, .. } | MidiEvent::NoteOff { channel, note } => {
+				let Some(index) = self
+					.skipped_pickup_notes
+					.iter()
+					.position(|&skipped| skipped == (channel, note))
+				else {
+					return false;
+				};
+				self.skipped_pickup_notes.swap_remove(index);
+				true
+			}
+			MidiEvent::SetTempo { .. }
+			| MidiEvent::ControlChange { .. }
+			| MidiEvent::ProgramChange { .. }
+			| MidiEvent::PitchBend { .. }
+			| MidiEvent::ChannelPressure { .. }
+			| MidiEvent::PolyphonicKeyPressure { .. }
+			| MidiEvent::TimeSignature { .. }
+			| MidiEvent::KeySignature { .. }
+			| MidiEvent::Marker { .. }
+			| MidiEvent::Lyric { .. } => false,
+		}
+	}
+
+	pub fn tick_midi(&mut self, soundfont: &SoundFontBank) {
+		if self.pickup_pending {
+			self.start_pickup(soundfont);
+		}
+		if let Some(start_tick) = self.start_tick.take() {
+			while let Some(event) = self
+				.midi_track
+				.events
+				.get(self.event_index)
+				.filter(|event| event.time < start_tick)
+			{
+				// Note offs here only ever match the skipped note ons
+				if !matches!(
+					event.inner,
+					MidiEvent::NoteOn { .. } | MidiEvent::NoteOff { .. }
+				) {
+					self.interpret_event(event.inner.clone(), VoiceOrigin::File, soundfont);
+				}
+				self.event_index += 1;
+			}
+			self.tick = self.tick.max(start_tick as f64);
+			self.beat = self.tick / self.midi_track.ticks_per_beat as f64;
+		}
+
+		if self.looping
+			&& let Some((start_beat, end_beat)) = self.loop_range
+			&& self.tick >= end_beat * self.midi_track.ticks_per_beat as f64
+		{
+			self.loop_back(start_beat, end_beat);
+		}
+
+		self.tick_drifted_notes(soundfont);
+
+		while let Some(event) = self
+			.midi_track
+			.events
+			.get(self.event_index)
+			.filter(|event| event.time <= self.tick as u64)
+		{
+			let (time, event) = (event.time, event.inner.clone());
+			if !self.skipped_by_pickup(time, &event) {
+				if self.record_events {
+					self.dispatched
+						.push(DispatchedEventKind::Midi(event.clone()));
+				}
+				self.dispatch_file_event(event, soundfont);
+			}
+			self.event_index += 1;
+
+			if self.event_index >= self.midi_track.events.len() {
+				let finished = !self.in_pickup && !self.looping;
+				if finished {
+					self.is_playing = false;
+					self.pickup_pending = self.pickup_ticks > 0;
+				}
+				self.in_pickup = false;
+				for (beat, _) in &mut self.scheduled_unmutes {
+					*beat -= self.beat;
+				}
+				self.event_index = 0;
+				self.tick = 0.0;
+				self.beat = 0.0;
+				self.meter = self.meter_at(0.0);
+				// Waits at the start to be played again, rather than playing the first events now
+				if finished {
+					break;
+				}
+			}
+		}
+	}
+
+	/// Jumps from the end of the loop range back to its start, cutting off every note that's playing
+	fn loop_back(&mut self, start_beat: f64, end_beat: f64) {
+		for (beat, _) in &mut self.scheduled_unmutes {
+			*beat -= end_beat - start_beat;
+		}
+		self.beat = start_beat;
+		self.tick = start_beat * self.midi_track.ticks_per_beat as f64;
+		self.event_index = self.first_event_at(start_beat);
+		self.meter = self.meter_at(start_beat);
+		self.seam_notes.clear();
+		self.stop_all_notes();
+	}
+
+	/// Where passes start, the start of the loop range or of the file
+	fn loop_start_beat(&self) -> f64 {
+		self.loop_range.map_or(0.0, |(start_beat, _)| start_beat)
+	}
+
+	/// The index of the first event at or after `beat`
+	fn first_event_at(&self, beat: f64) -> usize {
+		let tick = beat * self.midi_track.ticks_per_beat as f64;
+		self.midi_track
+			.events
+			.partition_point(|event| (event.time as f64) < tick)
+	}
+
+	pub fn interpret_event(
+		&mut self,
+		event: MidiEvent,
+		origin: VoiceOrigin,
+		soundfont: &SoundFontBank,
+	) {
+		match event {
+			MidiEvent::NoteOn {
+				channel,
+				note,
+				velocity,
+			} => {
+				let voice_note = match origin {
+					VoiceOrigin::File => self.transposed(channel, note),
+					VoiceOrigin::Live => note,
+				};
+				self.start_note(channel, note, voice_note, velocity, origin, 0.0, soundfont);
+			}
+			MidiEvent::NoteOff { channel, note } => {
+				if origin == VoiceOrigin::File {
+					// A note that drifted past its own note off never starts
+					let pending = self.drifted_notes.len();
+					self.drifted_notes
+						.retain(|drifted| drifted.channel != channel || drifted.note != note);
+					if self.drifted_notes.len() != pending {
+						return;
+					}
+				}
+				let Some(channel_state) = self.channels.get_mut(&channel) else {
+					self.record_violation(StrictViolationKind::unknown_channel(channel));
+					return;
+				};
+				if channel_state.sustain {
+					let keys = if self.shared_voice_ownership {
+						vec![(VoiceOrigin::File, note), (VoiceOrigin::Live, note)]
+					} else {
+						vec![(origin, note)]
+					};
+					let held = keys
+						.into_iter()
+						.filter(|key| channel_state.voices.contains_key(key))
+						.collect::<Vec<_>>();
+					let unmatched = held.is_empty();
+					for key in held {
+						if !channel_state.sustained.contains(&key) {
+							channel_state.sustained.push(key);
+						}
+					}
+					if unmatched {
+						self.record_violation(StrictViolationKind::UnmatchedNoteOff {
+							channel,
+							note,
+						});
+					}
+					return;
+				}
+				let (file, live) = if self.shared_voice_ownership {
+					(
+						channel_state.voices.remove(&(VoiceOrigin::File, note)),
+						channel_state.voices.remove(&(VoiceOrigin::Live, note)),
+					)
+				} else {
+					match origin {
+						VoiceOrigin::File => (channel_state.voices.remove(&(origin, note)), None),
+						VoiceOrigin::Live => (None, channel_state.voices.remove(&(origin, note))),
+					}
+				};
+				if file.is_none() && live.is_none() {
+					self.record_violation(StrictViolationKind::UnmatchedNoteOff { channel, note });
+				}
+				let seam_frames = self.seam_crossfade_frames();
+				let Some(channel_state) = self.channels.get_mut(&channel) else {
+					return;
+				};
+				if let Some(voice) = live {
+					channel_state.release_voice(note, voice);
+				}
+				if let Some(mut voice) = file {
+					match seam_frames {
+						Some(frames) => {
+							voice.release();
+							self.seam_tails.push(SeamTail {
+								channel,
+								voice,
+								remaining: frames,
+								frames,
+							});
+						}
+						None => channel_state.release_voice(note, voice),
+					}
+				}
+			}
+			MidiEvent::ControlChange {
+				channel,
+				controller,
+				value,
+			} => {
+				let Some(channel_state) = self.channels.get_mut(&channel) else {
+					self.record_violation(StrictViolationKind::unknown_channel(channel));
+					return;
+				};
+				match controller {
+					BANK_SELECT_CONTROLLER => channel_state.select_bank(value),
+					VOLUME_CONTROLLER => channel_state.set_volume(value as f32 / 127.0),
+					// 64 is centered, so 0 is a little further left than 127 is right
+					PAN_CONTROLLER => channel_state.set_pan((value as f32 - 64.0) / 63.0),
+					EXPRESSION_CONTROLLER => channel_state.set_expression(value as f32 / 127.0),
+					SUSTAIN_CONTROLLER => channel_state.set_sustain(value >= 64),
+					_ => {}
+				}
+			}
+			MidiEvent::ProgramChange { channel, program } => {
+				let Some(channel_state) = self.channels.get_mut(&channel) else {
+					self.record_violation(StrictViolationKind::unknown_channel(channel));
+					return;
+				};
+				channel_state.patch_number = program;
+				// Files written for other synths select banks the SoundFont hasn't got,
+				// which play the program from the General MIDI bank instead, like rustysynth does
+				let general_midi_bank = if channel_state.bank_number >= PERCUSSION_BANK {
+					PERCUSSION_BANK
+				} else {
+					0
+				};
+				if !soundfont.has_preset(channel_state.bank_number, program)
+					&& soundfont.has_preset(general_midi_bank, program)
+				{
+					channel_state.bank_number = general_midi_bank;
+				}
+			}
+			MidiEvent::PitchBend { channel, value } => {
+				let Some(channel_state) = self.channels.get(&channel) else {
+					self.record_violation(StrictViolationKind::unknown_channel(channel));
+					return;
+				};
+				let semitones = value as f32 / 8192.0 * channel_state.bend_range;
+				self.set_channel_bend(channel, semitones);
+			}
+			MidiEvent::ChannelPressure { channel, pressure } => {
+				let Some(channel_state) = self.channels.get_mut(&channel) else {
+					self.record_violation(StrictViolationKind::unknown_channel(channel));
+					return;
+				};
+				channel_state.set_pressure(channel_state.aftertouch(pressure));
+			}
+			MidiEvent::PolyphonicKeyPressure {
+				channel,
+				note,
+				pressure,
+			} => {
+				let Some(channel_state) = self.channels.get_mut(&channel) else {
+					self.record_violation(StrictViolationKind::unknown_channel(channel));
+					return;
+				};
+				let pressure = channel_state.aftertouch(pressure);
+				channel_state.key_pressure.insert(note, pressure);
+			}
+			MidiEvent::SetTempo {
+				tempo: beats_per_minute,
+			} => {
+				if !self.tempo_locked {
+					self.set_tempo(beats_per_minute);
+				}
+			}
+			MidiEvent::TimeSignature {
+				numerator,
+				denominator,
+			} => {
+				if self.fixed_beats_per_bar.is_some() {
+					return;
+				}
+				// File events are interpreted while the event index is still on them,
+				// so bars count from the event rather than from however far past it this tick reached
+				let beat = match origin {
+					VoiceOrigin::File => self
+						.midi_track
+						.events
+						.get(self.event_index)
+						.map_or(self.beat, |event| {
+							event.time as f64 / self.midi_track.ticks_per_beat as f64
+						}),
+					VoiceOrigin::Live => self.beat,
+				};
+				let beats_per_bar = numerator as f64 * 4.0 / denominator as f64;
+				self.meter = self.meter.changed_to(beat, beats_per_bar);
+			}
+			MidiEvent::KeySignature { .. } | MidiEvent::Marker { .. } | MidiEvent::Lyric { .. } => {
+			}
+		}
+	}
+
+	/// Plays a live note `detune_cents` away from its key, on top of the channel's bend
+	pub(super) fn start_detuned_live_note(
+		&mut self,
+		channel: u8,
+		note: u8,
+		velocity: u8,
+		detune_cents: f32,
+		soundfont: &SoundFontBank,
+	) {
+		self.start_note(
+			channel,
+			note,
+			note,
+			velocity,
+			VoiceOrigin::Live,
+			detune_cents,
+			soundfont,
+		);
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	fn start_note(
+		&mut self,
+		channel: u8,
+		note: u8,
+		voice_note: u8,
+		velocity: u8,
+		origin: VoiceOrigin,
+		detune_cents: f32,
+		soundfont: &SoundFontBank,
+	) {
+		match self.create_voice(channel, voice_note, velocity, soundfont) {
+			Ok(mut voice) => {
+				let detune_cents = detune_cents + self.total_bend(channel) * 100.0;
+				let mut ratio = self.tuning.ratio();
+				if detune_cents != 0.0 {
+					ratio *= 2_f32.powf(detune_cents / 1200.0);
+				}
+				if ratio != 1.0 {
+					voice.detune(ratio);
+				}
+				let dynamics = self
+					.channels
+					.get(&channel)
+					.map_or(1.0, |channel| channel.dynamics());
+				if dynamics != 1.0 {
+					voice.set_dynamics(dynamics);
+				}
+				if let Some(channel) = self.channels.get_mut(&channel) {
+					// A new note on the key isn't held by the pedal until it's released too
+					channel.sustained.retain(|&key| key != (origin, note));
+					// Hitting a key again lets its last note ring out underneath
+					if let Some(previous) = channel.voices.insert((origin, note), voice) {
+						channel.release_voice(note, previous);
+					}
+					// Key pressure only lasts as long as the note it was applied to
+					channel.key_pressure.remove(&note);
+					self.quiet = false;
+				}
+			}
+			Err(kind) => self.record_violation(kind),
+		}
+	}
+
+	/// Plays an event from the MIDI file, applying drift to note ons
+	fn dispatch_file_event(&mut self, event: MidiEvent, soundfont: &SoundFontBank) {
+		let MidiEvent::NoteOn {
+			channel,
+			note,
+			velocity,
+		} = event
+		else {
+			self.interpret_event(event, VoiceOrigin::File, soundfont);
+			return;
+		};
+		let Some(drift) = self
+			.drift
+			.filter(|drift| !(drift.is_zero() || (drift.exclude_percussion && channel == 9)))
+		else {
+			self.interpret_event(event, VoiceOrigin::File, soundfont);
+			return;
+		};
+
+		let detune_cents = drift.pitch_cents * self.drift_rng.signed_unit();
+		let velocity_offset = (drift.velocity as f32 * self.drift_rng.signed_unit()).round();
+		let delay_seconds = drift.timing_ms / 1000.0 * self.drift_rng.signed_unit().abs();
+		let drifted = DriftedNote {
+			frames: (delay_seconds as f64 * self.samples_per_second) as u32,
+			channel,
+			note,
+			velocity: (velocity as f32 + velocity_offset).clamp(1.0, 127.0) as u8,
+			detune_cents,
+		};
+		if drifted.frames == 0 {
+			self.start_drifted_note(drifted, soundfont);
+		} else {
+			self.drifted_notes.push(drifted);
+		}
+	}
+
+	fn start_drifted_note(&mut self, drifted: DriftedNote, soundfont: &SoundFontBank) {
+		let voice_note = self.transposed(drifted.channel, drifted.note);
+		self.start_note(
+			drifted.channel,
+			drifted.note,
+			voice_note,
+			drifted.velocity,
+			VoiceOrigin::File,
+			drifted.detune_cents,
+			soundfont,
+		);
+	}
+
+	fn tick_drifted_notes(&mut self, soundfont: &SoundFontBank) {
+		if self.drifted_notes.is_empty() {
+			return;
+		}
+		for drifted in &mut self.drifted_notes {
+			drifted.frames = drifted.frames.saturating_sub(1);
+		}
+		let (due, waiting) = std::mem::take(&mut self.drifted_notes)
+			.into_iter()
+			.partition::<Vec<_>, _>(|drifted| drifted.frames == 0);
+		self.drifted_notes = waiting;
+		for drifted in due {
+			self.start_drifted_note(drifted, soundfont);
+		}
+	}
+
+	/// Randomly offsets the pitch, velocity and timing of every note on slightly,
+	/// differently on every loop pass, to make looping music feel less static
+	pub fn with_drift(mut self, drift: Drift, seed: u64) -> Self {
+		self.drift = Some(drift);
+		self.drift_seed = seed;
+		self.drift_rng = Rng::new(seed);
+		self
+	}
+
+	/// The key a note from the file sounds at, after track and channel transposition
+	pub(super) fn transposed(&self, channel: u8, note: u8) -> u8 {
+		let channel_transpose = self
+			.channels
+			.get(&channel)
+			.map_or(0, |channel| channel.transpose);
+		(note as i16 + self.transpose as i16 + channel_transpose as i16).clamp(0, 127) as u8
+	}
+
+	fn create_voice(
+		&mut self,
+		channel_index: u8,
+		note: u8,
+		velocity: u8,
+		soundfont: &SoundFontBank,
+	) -> Result<Voice, StrictViolationKind> {
+		let channel_index = match &self.mpe {
+			Some(mpe) if mpe.member_channels.contains(&channel_index) => mpe.master_channel,
+			_ => channel_index,
+		};
+		self.channels
+			.get_mut(&channel_index)
+			.ok_or(StrictViolationKind::unknown_channel(channel_index))?
+			.create_voice(note, velocity, soundfont, self.samples_per_second as f32)
+	}
+
+	fn record_violation(&mut self, kind: StrictViolationKind) {
+		if self.strict {
+			self.violations.push((self.beat, kind));
+		}
+	}
+}
+
+/// Records the beat and bar gridlines crossed going from `last_beat` to `beat`
+fn insert_crossed_timings(
+	grid: &BeatGrid,
+	last_beat: f64,
+	beat: f64,
+	timings: &mut HashSet<MidiQueueTiming>,
+) {
+	if grid.next_gridline_after(last_beat) <= beat {
+		timings.insert(MidiQueueTiming::Beat);
+		if grid.next_bar_after(last_beat) <= beat {
+			timings.insert(MidiQueueTiming::Bar);
+		}
+	}
+}
+
+pub(super) struct Channel {
+	pub(super) bank_number: u8,
+	pub(super) patch_number: u8,
+	/// Keyed by the untransposed note, so NoteOffs find their voice
+	pub(super) voices: HashMap<(VoiceOrigin, u8), Voice>,
+	volume: f32,
+	/// -1.0 is fully left, 1.0 is fully right
+	pan: f32,
+	options: ChannelPlaybackOptions,
+	pub(super) muted: bool,
+	/// In semitones, applied to every voice on the channel
+	bend: f32,
+	/// The semitones a full pitch bend from the file bends by
+	bend_range: f32,
+	/// Multiplies the volume, see [`MidiAudioTrack::set_channel_pressure`]
+	pressure: f32,
+	/// Multiplies the volume of each key's voices, from the file's polyphonic key pressure
+	key_pressure: HashMap<u8, f32>,
+	/// See [`MidiAudioTrack::set_channel_aftertouch_depth`]
+	aftertouch_depth: f32,
+	/// Multiplies the volume, from the file's expression controller
+	expression: f32,
+	/// Semitones added to notes from the file, on top of the track's transposition
+	transpose: i8,
+	/// Whether the sustain pedal is down
+	sustain: bool,
+	/// Voices released while the sustain pedal was down, which stop when it lifts
+	sustained: Vec<(VoiceOrigin, u8)>,
+	/// Voices whose notes have stopped, playing out their release until they're finished,
+	/// with the key pressure they were released at.
+	/// Not keyed by note, since a key can be played again while its last note is still releasing.
+	releasing: Vec<(f32, Voice)>,
+	soundfont_instrument: SoundFontInstrument,
+	/// Plays instead of the SoundFont when set
+	pub(super) instrument: Option<Mutex<Box<dyn Instrument>>>,
+}
+
+impl Channel {
+	pub(super) fn new(bank_number: u8, patch_number: u8) -> Self {
+		Self {
+			bank_number,
+			patch_number,
+			voices: HashMap::new(),
+			// General MIDI's default, so files that set it to 127 can be louder than ones that don't
+			volume: 100.0 / 127.0,
+			pan: 0.0,
+			options: ChannelPlaybackOptions::default(),
+			muted: false,
+			bend: 0.0,
+			bend_range: 2.0,
+			pressure: 1.0,
+			key_pressure: HashMap::new(),
+			aftertouch_depth: 1.0,
+			expression: 1.0,
+			transpose: 0,
+			sustain: false,
+			sustained: vec![],
+			releasing: vec![],
+			soundfont_instrument: SoundFontInstrument::default(),
+			instrument: None,
+		}
+	}
+
+	pub(super) fn create_voice(
+		&mut self,
+		note: u8,
+		velocity: u8,
+		soundfont: &SoundFontBank,
+		samples_per_second: f32,
+	) -> Result<Voice, StrictViolationKind> {
+		let context = InstrumentContext {
+			bank: soundfont,
+			bank_number: self.bank_number,
+			patch_number: self.patch_number,
+			samples_per_second,
+		};
+		match &self.instrument {
+			Some(instrument) => instrument
+				.lock()
+				.unwrap()
+				.create_voice(note, velocity, &context)
+				.ok_or(StrictViolationKind::InstrumentDeclined { note }),
+			None => self
+				.soundfont_instrument
+				.try_create_voice(note, velocity, &context),
+		}
+	}
+
+	fn duplicate(&self) -> Self {
+		let mut channel = Self {
+			volume: self.volume,
+			pan: self.pan,
+			muted: self.muted,
+			transpose: self.transpose,
+			bend_range: self.bend_range,
+			aftertouch_depth: self.aftertouch_depth,
+			instrument: self
+				.instrument
+				.as_ref()
+				.map(|instrument| Mutex::new(instrument.lock().unwrap().fresh())),
+			..Self::new(self.bank_number, self.patch_number)
+		};
+		channel.set_options(self.options);
+		channel
+	}
+
+	/// Like the bank select controller. Percussion channels stay among the percussion banks.
+	fn select_bank(&mut self, value: u8) {
+		self.bank_number = if self.bank_number >= PERCUSSION_BANK {
+			PERCUSSION_BANK.saturating_add(value)
+		} else {
+			value
+		};
+	}
+
+	fn set_bend(&mut self, semitones: f32) {
+		if semitones == self.bend {
+			return;
+		}
+		let ratio = 2_f32.powf((semitones - self.bend) / 12.0);
+		for voice in self.all_voices_mut() {
+			voice.detune(ratio);
+		}
+		self.bend = semitones;
+	}
+
+	/// How hard the channel is being played, from its expression and pressure
+	fn dynamics(&self) -> f32 {
+		self.expression * self.pressure
+	}
+
+	/// What `pressure` from the file's aftertouch, from 0 to 127, multiplies the volume by
+	fn aftertouch(&self, pressure: u8) -> f32 {
+		1.0 - self.aftertouch_depth * (1.0 - pressure as f32 / 127.0)
+	}
+
+	fn set_pressure(&mut self, pressure: f32) {
+		self.pressure = pressure.clamp(0.0, 1.0);
+		self.update_dynamics();
+	}
+
+	fn set_volume(&mut self, volume: f32) {
+		self.volume = volume.clamp(0.0, 1.0);
+	}
+
+	fn set_sustain(&mut self, sustain: bool) {
+		self.sustain = sustain;
+		if !sustain {
+			for key in std::mem::take(&mut self.sustained) {
+				if let Some(voice) = self.voices.remove(&key) {
+					self.release_voice(key.1, voice);
+				}
+			}
+		}
+	}
+
+	/// Lets go of a voice on `note`, keeping it playing until it's finished if it has a release of its own
+	fn release_voice(&mut self, note: u8, mut voice: Voice) {
+		if voice.release() {
+			self.releasing.push((self.key_pressure(note), voice));
+		}
+	}
+
+	/// Lets go of every held voice, including ones the sustain pedal's holding
+	fn release_all(&mut self) {
+		self.sustained.clear();
+		for ((_, note), voice) in std::mem::take(&mut self.voices) {
+			self.release_voice(note, voice);
+		}
+	}
+
+	/// Every voice on the channel, whether its note is held or releasing
+	pub(super) fn all_voices(&self) -> impl Iterator<Item = &Voice> {
+		self.voices
+			.values()
+			.chain(self.releasing.iter().map(|(_, voice)| voice))
+	}
+
+	fn all_voices_mut(&mut self) -> impl Iterator<Item = &mut Voice> {
+		self.voices
+			.values_mut()
+			.chain(self.releasing.iter_mut().map(|(_, voice)| voice))
+	}
+
+	/// This frame's output of every voice on `audio_channel`, each panned and scaled by its key's pressure
+	fn voices_sample(&self, audio_channel: u16) -> f32 {
+		let held = self.voices.iter().map(|(&(_, note), voice)| {
+			voice.sample(audio_channel) as f32
+				* self.pan_gain(voice.as_ref(), audio_channel)
+				* self.key_pressure(note)
+		});
+		let releasing = self.releasing.iter().map(|(pressure, voice)| {
+			voice.sample(audio_channel) as f32
+				* self.pan_gain(voice.as_ref(), audio_channel)
+				* pressure
+		});
+		held.chain(releasing).sum()
+	}
+
+	fn key_pressure(&self, note: u8) -> f32 {
+		self.key_pressure.get(&note).copied().unwrap_or(1.0)
+	}
+
+	/// Advances every voice a frame, dropping released ones that have finished
+	pub(super) fn tick_voices(&mut self) {
+		self.all_voices_mut().for_each(|voice| voice.tick());
+		self.releasing.retain(|(_, voice)| !voice.is_finished());
+	}
+
+	/// Cuts off every voice
+	fn stop_voices(&mut self) {
+		self.voices.clear();
+		self.sustained.clear();
+		self.releasing.clear();
+		self.key_pressure.clear();
+	}
+
+	fn set_pan(&mut self, pan: f32) {
+		self.pan = pan.clamp(-1.0, 1.0);
+	}
+
+	fn set_expression(&mut self, expression: f32) {
+		self.expression = expression.clamp(0.0, 1.0);
+		self.update_dynamics();
+	}
+
+	fn update_dynamics(&mut self) {
+		let dynamics = self.dynamics();
+		for voice in self.all_voices_mut() {
+			voice.set_dynamics(dynamics);
+		}
+	}
+
+	fn set_options(&mut self, options: ChannelPlaybackOptions) {
+		self.soundfont_instrument = SoundFontInstrument::new(options.layer_alternation);
+		self.options = options;
+	}
+
+	/// How loud `voice` plays on `current_audio_channel` at the channel's pan, unchanged when centered.
+	/// Mono voices pan with constant power. Stereo pairs balance instead, turning down the far side
+	/// so hard left plays the left sample as is and silences the right one.
+	fn pan_gain(&self, voice: &dyn VoiceSource, current_audio_channel: u16) -> f32 {
+		if self.pan == 0.0 {
+			return 1.0;
+		}
+		if voice.is_stereo() {
+			let away = if current_audio_channel == 0 {
+				self.pan
+			} else {
+				-self.pan
+			};
+			return (1.0 - away).min(1.0);
+		}
+		let angle = (self.pan + 1.0) * std::f32::consts::FRAC_PI_4;
+		let gain = if current_audio_channel == 0 {
+			angle.cos()
+		} else {
+			angle.sin()
+		};
+		gain * std::f32::consts::SQRT_2
+	}
+}
+
+/// Where a voice's NoteOn came from, so NoteOffs only release voices they own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoiceOrigin {
+	File,
+	Live,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChannelPlaybackOptions {
+	pub layer_alternation: LayerAlternation,
+}
+
+/// What to do when a key matches several layers (e.g. velocity layers) at once
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LayerAlternation {
+	/// Play every matching layer together
+	#[default]
+	Layered,
+	/// Cycle through the matching layers on successive hits of the same key
+	RoundRobin,
+	/// Pick one of the matching layers at random on each hit
+	Random { seed: u64 },
+	/// Play every velocity layer of a key, crossfading between overlapping layers
+	/// as the channel's expression and pressure change. Each layer counts as a voice.
+	VelocityCrossfade,
+}
+
+/// Which channels of an MPE controller share one instrument, see [`MidiAudioTrack::with_mpe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpeConfig {
+	pub master_channel: u8,
+	/// Each note plays on its own member channel
+	pub member_channels: RangeInclusive<u8>,
+}
+
+impl MpeConfig {
+	/// Master channel 0, with members counting up from channel 1
+	pub fn lower_zone(members: u8) -> Self {
+		Self {
+			master_channel: 0,
+			member_channels: 1..=members.clamp(1, 15),
+		}
+	}
+
+	/// Master channel 15, with members counting down from channel 14
+	pub fn upper_zone(members: u8) -> Self {
+		Self {
+			master_channel: 15,
+			member_channels: 15 - members.clamp(1, 15)..=14,
+		}
+	}
+}
+
+/// Maximum random offsets applied to each note on, see [`MidiAudioTrack::with_drift`]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Drift {
+	/// Either way
+	pub pitch_cents: f32,
+	/// Either way
+	pub velocity: u8,
+	/// Notes are only ever delayed, by up to this much
+	pub timing_ms: f32,
+	/// Leaves channel 9 alone, where General MIDI puts drums
+	pub exclude_percussion: bool,
+}
+
+impl Drift {
+	fn is_zero(&self) -> bool {
+		self.pitch_cents == 0.0 && self.velocity == 0 && self.timing_ms == 0.0
+	}
+}
+
+struct DriftedNote {
+	/// Until the note starts
+	frames: u32,
+	channel: u8,
+	note: u8,
+	velocity: u8,
+	detune_cents: f32,
+}
+
+/// A meter and where it starts, so bars keep counting on from the meter before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Meter {
+	pub(super) beat: f64,
+	/// How many bars come before it
+	pub(super) bar: f64,
+	pub(super) beats_per_bar: f64,
+}
+
+impl Default for Meter {
+	fn default() -> Self {
+		Self {
+			beat: 0.0,
+			bar: 0.0,
+			beats_per_bar: 4.0,
+		}
+	}
+}
+
+impl Meter {
+	/// A new meter from `beat`, which starts a new bar if it doesn't land on one
+	fn changed_to(self, beat: f64, beats_per_bar: f64) -> Self {
+		let bars = ((beat - self.beat) / self.beats_per_bar - 1e-9)
+			.ceil()
+			.max(0.0);
+		Self {
+			beat,
+			bar: self.bar + bars,
+			beats_per_bar,
+		}
+	}
+
+	/// The bar `beat` falls in, counting from the start of the track
+	pub(super) fn bar_of(&self, beat: f64) -> f64 {
+		self.bar + ((beat - self.beat) / self.beats_per_bar).floor()
+	}
+
+	fn bar_start(&self, bar: f64) -> f64 {
+		self.beat + (bar - self.bar) * self.beats_per_bar
+	}
+}
+
+/// The first meter of the track, then every time signature change in the file in order.
+/// A fixed meter never changes.
+fn meters(
+	midi_track: &MidiTrack,
+	fixed_beats_per_bar: Option<f64>,
+) -> impl Iterator<Item = Meter> + '_ {
+	let ticks_per_beat = midi_track.ticks_per_beat as f64;
+	let first = Meter {
+		beats_per_bar: fixed_beats_per_bar.unwrap_or(4.0),
+		..Meter::default()
+	};
+	let changes = midi_track
+		.events
+		.iter()
+		.filter(move |_| fixed_beats_per_bar.is_none())
+		.filter_map(move |event| match event.inner {
+			MidiEvent::TimeSignature {
+				numerator,
+				denominator,
+			} => Some((
+				event.time as f64 / ticks_per_beat,
+				numerator as f64 * 4.0 / denominator as f64,
+			)),
+			_ => None,
+		});
+	std::iter::once(first).chain(changes.scan(first, |meter, (beat, beats_per_bar)| {
+		*meter = meter.changed_to(beat, beats_per_bar);
+		Some(*meter)
+	}))
+}
+
+struct SeamTail {
+	channel: u8,
+	voice: Voice,
+	remaining: u32,
+	frames: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpcomingNote {
+	pub beat: f64,
+	pub channel: u8,
+	pub note: u8,
+	pub velocity: u8,
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use bevy::audio::Decodable;
+
+	use super::*;
+	use crate::source::SynthInstrument;
+	use crate::{MidiAudio, Waveform};
+
+	struct MeterChanges(Arc<Mutex<Vec<(f64, f64)>>>);
+
+	impl MusicalTimeListener for MeterChanges {
+		fn musical_time_changed(&mut self, old: &MusicalTime, new: &MusicalTime) {
+			if old.beats_per_bar != new.beats_per_bar {
+				self.0
+					.lock()
+					.unwrap()
+					.push((old.beats_per_bar, new.beats_per_bar));
+			}
+		}
+	}
+
+	#[test]
+	fn duplicates_keep_the_meter() {
+		let track = MidiAudioTrack::from_bytes(include_bytes!("../../assets/fray.mid"), 0.75);
+		let changes = Arc::new(Mutex::new(vec![]));
+		let duplicate = track
+			.duplicate()
+			.with_musical_time_listener(MeterChanges(changes.clone()));
+		assert_eq!(duplicate.musical_time().beats_per_bar, 3.0);
+
+		let mut audio =
+			MidiAudio::from_bytes(include_bytes!("../../assets/hl4mgm.sf2")).with_logical_clock();
+		audio.add_track(duplicate);
+		audio.advance_logical(44100);
+		assert_eq!(*changes.lock().unwrap(), vec![]);
+	}
+
+	fn configured_fray() -> MidiAudioTrack {
+		MidiAudioTrack::from_bytes(include_bytes!("../../assets/fray.mid"), 0.75)
+			.with_name("fray")
+			.with_gain(0.5)
+			.with_transpose(3)
+			.with_channel_patch(1, 0, 40)
+	}
+
+	#[test]
+	fn duplicates_copy_the_configuration() {
+		let duplicate = configured_fray().duplicate();
+		assert_eq!(duplicate.name(), Some("fray"));
+		assert_eq!(duplicate.gain, 0.5);
+		assert_eq!(duplicate.transpose, 3);
+		assert_eq!(duplicate.channels[&1].patch_number, 40);
+	}
+
+	#[test]
+	fn duplicates_start_fresh_and_share_events() {
+		let mut track = configured_fray();
+		track.tick = 960.0;
+		track.beat = 2.0;
+		track.queue.push(MidiQueueEvent {
+			event: MidiQueueEventType::Stop,
+			timing: MidiQueueTiming::Bar,
+			looping: MidiQueueLooping::Once,
+		});
+		let duplicate = track.duplicate();
+		assert_eq!(duplicate.tick, 0.0);
+		assert_eq!(duplicate.beat, 0.0);
+		assert!(duplicate.queue.is_empty());
+		assert!(Arc::ptr_eq(
+			&track.midi_track.events,
+			&duplicate.midi_track.events
+		));
+	}
+
+	#[test]
+	fn channel_transpose_adds_to_the_tracks() {
+		let track = three_notes().with_transpose(5).with_channel_transpose(0, 7);
+		assert_eq!(track.transposed(0, 60), 72);
+		assert_eq!(track.transposed(1, 60), 65);
+
+		let track = three_notes()
+			.with_transpose(-5)
+			.with_channel_transpose(0, 7);
+		assert_eq!(track.transposed(0, 60), 62);
+	}
+
+	#[test]
+	fn transposed_notes_clamp_to_the_midi_range() {
+		let up = three_notes()
+			.with_transpose(100)
+			.with_channel_transpose(0, 100);
+		assert_eq!(up.transposed(0, 127), 127);
+		assert_eq!(up.transposed(0, 0), 127);
+		let down = three_notes()
+			.with_transpose(-100)
+			.with_channel_transpose(0, -100);
+		assert_eq!(down.transposed(0, 127), 0);
+		assert_eq!(down.transposed(0, 100), 0);
+		// Only the sum is clamped, so a shift out of range can be brought back
+		let back = three_notes()
+			.with_transpose(-100)
+			.with_channel_transpose(0, 100);
+		assert_eq!(back.transposed(0, 60), 60);
+	}
+
+	/// Plays its first note with the built-in synth and declines the rest
+	struct FirstNoteOnly {
+		played: bool,
+	}
+
+	impl Instrument for FirstNoteOnly {
+		fn create_voice(
+			&mut self,
+			note: u8,
+			velocity: u8,
+			context: &InstrumentContext,
+		) -> Option<Box<dyn VoiceSource>> {
+			if std::mem::replace(&mut self.played, true) {
+				return None;
+			}
+			Some(SynthInstrument::default().voice(note, velocity, context.samples_per_second))
+		}
+
+		fn fresh(&self) -> Box<dyn Instrument> {
+			Box::new(Self { played: false })
+		}
+	}
+
+	#[test]
+	fn duplicates_get_their_own_instruments() {
+		let bank = SoundFontBank::builtin(Waveform::Sine);
+		let mut track = three_notes().with_channel_instrument(0, FirstNoteOnly { played: false });
+		let play = |track: &mut MidiAudioTrack| {
+			track
+				.channels
+				.get_mut(&0)
+				.unwrap()
+				.create_voice(60, 100, &bank, 44100.0)
+				.is_ok()
+		};
+		assert!(play(&mut track));
+		let mut duplicate = track.duplicate();
+		assert!(play(&mut duplicate));
+		assert!(!play(&mut track));
+		assert!(!play(&mut duplicate));
+	}
+
+	/// C4, D4 and E4 a beat each, looping every three beats
+	fn three_notes() -> MidiAudioTrack {
+		let events = b"\0\x90\x3C\x64\x83\x60\x80\x3C\0\0\x90\x3E\x64\x83\x60\x80\x3E\0\0\x90\x40\x64\x83\x60\x80\x40\0\0\xFF\x2F\0";
+		let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xE0MTrk".to_vec();
+		bytes.extend((events.len() as u32).to_be_bytes());
+		bytes.extend(events);
+		MidiAudioTrack::from_bytes(&bytes, 1.0)
+	}
+
+	/// The left channel of [`three_notes`] played `passes` times with a sine, drifting with `drift` and its seed
+	fn render_three_notes(drift: Option<(Drift, u64)>, passes: u32) -> Vec<f32> {
+		let mut track = three_notes();
+		if let Some((drift, seed)) = drift {
+			track = track.with_drift(drift, seed);
+		}
+		let mut audio = MidiAudio::with_builtin_synth(Waveform::Sine).with_logical_clock();
+		audio.add_track(track);
+		let mut decoder = audio.decoder();
+		audio.advance_logical(22050 * 3 * passes);
+		(0..22050 * 3 * passes)
+			.map(|_| decoder.next_frame()[0])
+			.collect()
+	}
+
+	/// Average cycles per frame from the first upward zero crossing to the last
+	fn frequency(samples: &[f32]) -> f32 {
+		let crossings = samples
+			.windows(2)
+			.enumerate()
+			.filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+			.map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+			.collect::<Vec<_>>();
+		(crossings.len() - 1) as f32 / (crossings[crossings.len() - 1] - crossings[0])
+	}
+
+	/// How each note on heard over `passes` passes drifted from the same note played without drift,
+	/// as (frames late, cents detuned, loudness against the undrifted note)
+	fn drifted_note_ons(drift: Drift, seed: u64, passes: u32) -> Vec<(usize, f32, f32)> {
+		let plain = render_three_notes(None, passes);
+		let drifted = render_three_notes(Some((drift, seed)), passes);
+		let onset = |note: &[f32]| note.iter().position(|&sample| sample != 0.0).unwrap();
+		let peak = |note: &[f32]| note.iter().copied().map(f32::abs).fold(0.0, f32::max);
+		plain
+			.chunks(22050)
+			.zip(drifted.chunks(22050))
+			.map(|(plain, drifted)| {
+				let (plain_onset, drifted_onset) = (onset(plain), onset(drifted));
+				// Half a beat, which even the latest note on fills before the next note
+				let pitch = |note: &[f32], onset: usize| frequency(&note[onset..onset + 11025]);
+				let cents =
+					1200.0 * (pitch(drifted, drifted_onset) / pitch(plain, plain_onset)).log2();
+				(
+					drifted_onset - plain_onset,
+					cents,
+					peak(drifted) / peak(plain),
+				)
+			})
+			.collect()
+	}
+
+	fn drift() -> Drift {
+		Drift {
+			pitch_cents: 20.0,
+			velocity: 10,
+			timing_ms: 15.0,
+			exclude_percussion: false,
+		}
+	}
+
+	const SEED: u64 = 0x5EED_D21F7;
+
+	#[test]
+	fn drift_repeats_for_the_same_seed() {
+		let drifted = render_three_notes(Some((drift(), SEED)), 2);
+		assert_eq!(drifted, render_three_notes(Some((drift(), SEED)), 2));
+		assert_ne!(drifted, render_three_notes(Some((drift(), SEED + 1)), 2));
+	}
+
+	#[test]
+	fn drift_stays_within_its_bounds() {
+		let note_ons = drifted_note_ons(drift(), SEED, 2);
+		assert_eq!(note_ons.len(), 6);
+
+		for &(late, cents, loudness) in &note_ons {
+			// 15 ms
+			assert!(late <= 662, "{note_ons:?}");
+			assert!(cents.abs() <= 20.1, "{note_ons:?}");
+			// Velocity 100, give or take 10
+			assert!((0.895..=1.105).contains(&loudness), "{note_ons:?}");
+		}
+		// Velocities can land back on 100, but timing and pitch hardly ever drift by nothing
+		assert!(
+			note_ons
+				.iter()
+				.all(|&(late, cents, _)| late > 0 && cents.abs() > 0.1),
+			"{note_ons:?}"
+		);
+		assert!(
+			note_ons.iter().any(|&(_, _, loudness)| loudness != 1.0),
+			"{note_ons:?}"
+		);
+	}
+
+	#[test]
+	fn drift_rolls_again_every_pass() {
+		let note_ons = drifted_note_ons(drift(), SEED, 2);
+		for (first, second) in note_ons[..3].iter().zip(&note_ons[3..]) {
+			assert_ne!(first, second);
+		}
+	}
+}
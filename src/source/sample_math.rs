@@ -0,0 +1,159 @@
+//! Playback positions, speeds and gains for [`super::voice::VoiceSample`], in floating point by default
+//! or in fixed point with the `fixed-point` feature, for targets where float math is slow.
+//! Each voice's fixed-point output stays within one LSB of the floating-point one.
+
+#[cfg(feature = "fixed-point")]
+pub(super) use fixed::*;
+#[cfg(not(feature = "fixed-point"))]
+pub(super) use float::*;
+
+// Both are built for tests, which check they agree
+#[cfg(any(test, not(feature = "fixed-point")))]
+mod float {
+	/// A fractional index into a SoundFont's wave data
+	pub(in super::super) type Position = f64;
+	/// Wave data samples advanced per frame
+	pub(in super::super) type Speed = f32;
+	pub(in super::super) type Gain = f32;
+
+	pub(in super::super) fn position(position: f64) -> Position {
+		position
+	}
+
+	pub(in super::super) fn speed(speed: f32) -> Speed {
+		speed
+	}
+
+	pub(in super::super) fn gain(gain: f32) -> Gain {
+		gain
+	}
+
+	pub(in super::super) fn advance(position: &mut Position, speed: Speed) {
+		*position += speed as f64;
+	}
+
+	/// `gain` multiplied by `factor`, e.g. by an envelope
+	pub(in super::super) fn scale(gain: Gain, factor: f32) -> Gain {
+		gain * factor
+	}
+
+	pub(in super::super) fn detune(speed: Speed, ratio: f32) -> Speed {
+		speed * ratio
+	}
+
+	/// Linearly interpolates between the samples either side of `position`
+	pub(in super::super) fn interpolate(wave_data: &[i16], position: Position, gain: Gain) -> i32 {
+		let floor = wave_data[position.floor() as usize] as f32;
+		let ceil = wave_data[position.ceil() as usize] as f32;
+		let fraction = position.fract() as f32;
+		((ceil * fraction + floor * (1.0 - fraction)) * gain) as i32
+	}
+}
+
+#[cfg(any(test, feature = "fixed-point"))]
+mod fixed {
+	const POSITION_BITS: u32 = 32;
+	const GAIN_BITS: u32 = 16;
+
+	/// Q32.32
+	pub(in super::super) type Position = u64;
+	/// Q32.32
+	pub(in super::super) type Speed = u64;
+	/// Q16
+	pub(in super::super) type Gain = i64;
+
+	pub(in super::super) fn position(position: f64) -> Position {
+		(position.max(0.0) * (1_u64 << POSITION_BITS) as f64).round() as u64
+	}
+
+	pub(in super::super) fn speed(speed: f32) -> Speed {
+		position(speed as f64)
+	}
+
+	pub(in super::super) fn gain(gain: f32) -> Gain {
+		(gain as f64 * (1_i64 << GAIN_BITS) as f64).round() as i64
+	}
+
+	pub(in super::super) fn advance(position: &mut Position, speed: Speed) {
+		*position += speed;
+	}
+
+	/// `gain` multiplied by `factor`, e.g. by an envelope
+	pub(in super::super) fn scale(gain: Gain, factor: f32) -> Gain {
+		(gain as f64 * factor as f64).round() as i64
+	}
+
+	/// Multiplies in `f32` like the float path, so repeated bends round the same way
+	pub(in super::super) fn detune(speed: Speed, ratio: f32) -> Speed {
+		self::speed((speed as f64 / (1_u64 << POSITION_BITS) as f64) as f32 * ratio)
+	}
+
+	/// Linearly interpolates between the samples either side of `position`
+	pub(in super::super) fn interpolate(wave_data: &[i16], position: Position, gain: Gain) -> i32 {
+		let index = (position >> POSITION_BITS) as usize;
+		let fraction = (position as u32 >> (POSITION_BITS - GAIN_BITS)) as i64;
+		let floor = wave_data[index] as i64;
+		let ceil = if fraction == 0 {
+			floor
+		} else {
+			wave_data[index + 1] as i64
+		};
+		let value = (floor << GAIN_BITS) + (ceil - floor) * fraction;
+		// Dividing rounds towards zero, like the float path's cast
+		(value * gain / (1 << (2 * GAIN_BITS))) as i32
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rustysynth::SoundFont;
+
+	use super::{fixed, float};
+
+	/// Plays every sample in the SoundFont once through both, sharp and bending further halfway,
+	/// fading out like an envelope
+	#[test]
+	fn fixed_point_stays_within_one_lsb_of_floating_point() {
+		let soundfont =
+			SoundFont::new(&mut &include_bytes!("../../assets/hl4mgm.sf2")[..]).unwrap();
+		let wave_data = soundfont.get_wave_data();
+		let mut compared = 0;
+		for header in soundfont.get_sample_headers() {
+			let (start, end) = (header.get_start() as f64, header.get_end() as f64);
+			let speed = 1.1 * header.get_sample_rate() as f32 / 44100.0;
+			let (mut float_position, mut fixed_position) =
+				(float::position(start), fixed::position(start));
+			let (mut float_speed, mut fixed_speed) = (float::speed(speed), fixed::speed(speed));
+			let (float_gain, fixed_gain) = (float::gain(0.8), fixed::gain(0.8));
+			let halfway = (end - start) / speed as f64 / 2.0;
+			let mut frame = 0;
+			// Stopping before the last sample, which has nothing after it to interpolate towards
+			while float_position < end - 1.0 && fixed_position < fixed::position(end - 1.0) {
+				let envelope = (-frame as f32 / 44100.0).exp();
+				let expected = float::interpolate(
+					wave_data,
+					float_position,
+					float::scale(float_gain, envelope),
+				);
+				let actual = fixed::interpolate(
+					wave_data,
+					fixed_position,
+					fixed::scale(fixed_gain, envelope),
+				);
+				assert!(
+					(expected - actual).abs() <= 1,
+					"{expected} {actual} at {float_position}"
+				);
+				float::advance(&mut float_position, float_speed);
+				fixed::advance(&mut fixed_position, fixed_speed);
+				frame += 1;
+				if frame as f64 == halfway.floor() {
+					float_speed = float::detune(float_speed, 1.03);
+					fixed_speed = fixed::detune(fixed_speed, 1.03);
+				}
+				compared += 1;
+			}
+		}
+		assert!(compared > 100_000, "{compared}");
+	}
+}
@@ -0,0 +1,152 @@
+/// How a [`super::MidiAudio`] renders and hands audio to its decoder
+///
+/// The sample rate and channel count are fixed once a decoder has been created,
+/// see [`super::MidiAudio::set_settings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiAudioSettings {
+	/// Frames per second
+	pub sample_rate: u32,
+	/// 1 for mono or 2 for stereo
+	pub channels: u16,
+	/// How many frames can be rendered ahead of playback. More survives longer frame hitches,
+	/// but queued events and gain changes take longer to be heard.
+	pub buffer_frames: usize,
+	/// The most frames [`super::MidiAudio::tick`] renders at once. A longer tick means the game stalled,
+	/// and the rest is handled by `stall_policy`.
+	pub max_backlog_frames: usize,
+	pub stall_policy: StallPolicy,
+}
+
+/// What happens to the music a stall would have played, see [`MidiAudioSettings::max_backlog_frames`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StallPolicy {
+	/// Picks up where it left off, so nothing is missed but the music runs late by the length of the stall
+	#[default]
+	PlayThrough,
+	/// Silently skips ahead to where the music would be without the stall, so it stays in time with the game,
+	/// dropping whatever was buffered before it. Skipping still renders the missed audio, which takes a fraction of the stall.
+	CatchUp,
+}
+
+impl Default for MidiAudioSettings {
+	fn default() -> Self {
+		Self {
+			sample_rate: 44100,
+			channels: 2,
+			buffer_frames: 44100,
+			max_backlog_frames: 22050,
+			stall_policy: StallPolicy::default(),
+		}
+	}
+}
+
+impl MidiAudioSettings {
+	pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+		self.sample_rate = sample_rate;
+		self
+	}
+
+	pub fn with_channels(mut self, channels: u16) -> Self {
+		self.channels = channels;
+		self
+	}
+
+	pub fn with_buffer_frames(mut self, buffer_frames: usize) -> Self {
+		self.buffer_frames = buffer_frames;
+		self
+	}
+
+	pub fn with_max_backlog_frames(mut self, max_backlog_frames: usize) -> Self {
+		self.max_backlog_frames = max_backlog_frames;
+		self
+	}
+
+	pub fn with_stall_policy(mut self, stall_policy: StallPolicy) -> Self {
+		self.stall_policy = stall_policy;
+		self
+	}
+
+	pub fn validate(&self) -> Result<(), SettingsError> {
+		if !(8000..=192_000).contains(&self.sample_rate) {
+			return Err(SettingsError::InvalidSampleRate(self.sample_rate));
+		}
+		if !(1..=2).contains(&self.channels) {
+			return Err(SettingsError::InvalidChannels(self.channels));
+		}
+		if self.buffer_frames == 0 || self.max_backlog_frames == 0 {
+			return Err(SettingsError::EmptyBuffer);
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SettingsError {
+	/// Outside of 8 kHz to 192 kHz
+	InvalidSampleRate(u32),
+	/// Only mono and stereo are supported
+	InvalidChannels(u16),
+	/// The buffer or backlog can't hold a single frame
+	EmptyBuffer,
+	/// The sample rate or channel count changed after a decoder was created,
+	/// which would play back at the wrong speed
+	DecoderCreated,
+}
+
+impl std::fmt::Display for SettingsError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::InvalidSampleRate(sample_rate) => {
+				write!(f, "unsupported sample rate {sample_rate}")
+			}
+			Self::InvalidChannels(channels) => write!(f, "unsupported channel count {channels}"),
+			Self::EmptyBuffer => write!(f, "buffer must hold at least one frame"),
+			Self::DecoderCreated => write!(
+				f,
+				"sample rate and channels can't change once a decoder has been created"
+			),
+		}
+	}
+}
+
+impl std::error::Error for SettingsError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_are_valid() {
+		assert_eq!(MidiAudioSettings::default().validate(), Ok(()));
+	}
+
+	#[test]
+	fn validation_checks_the_output_format() {
+		let settings = MidiAudioSettings::default();
+		for sample_rate in [8000, 22050, 48000, 192_000] {
+			assert_eq!(settings.with_sample_rate(sample_rate).validate(), Ok(()));
+		}
+		for sample_rate in [0, 7999, 192_001] {
+			assert_eq!(
+				settings.with_sample_rate(sample_rate).validate(),
+				Err(SettingsError::InvalidSampleRate(sample_rate))
+			);
+		}
+		assert_eq!(settings.with_channels(1).validate(), Ok(()));
+		for channels in [0, 3] {
+			assert_eq!(
+				settings.with_channels(channels).validate(),
+				Err(SettingsError::InvalidChannels(channels))
+			);
+		}
+		assert_eq!(
+			settings.with_buffer_frames(0).validate(),
+			Err(SettingsError::EmptyBuffer)
+		);
+		assert_eq!(
+			settings.with_max_backlog_frames(0).validate(),
+			Err(SettingsError::EmptyBuffer)
+		);
+	}
+}
@@ -0,0 +1,538 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use itertools::Itertools;
+
+use num_enum::TryFromPrimitive;
+use rustysynth::{LoopMode, SoundFont};
+
+use crate::synth::Oscillator;
+
+use super::sample_math::{self, Gain, Position, Speed};
+
+/// Produces the audio for one playing note, a frame at a time
+pub trait VoiceSource: Send + Sync {
+	/// Advances to the next frame
+	fn tick(&mut self);
+
+	/// Finished voices are dropped
+	fn is_finished(&self) -> bool;
+
+	/// This frame's output on `audio_channel`, where 0 is left and 1 is right
+	fn sample(&self, audio_channel: u16) -> i32;
+
+	/// Scales the voice's pitch by `ratio`, for voices that can be retuned
+	fn detune(&mut self, _ratio: f32) {}
+
+	/// Lets go of the note, e.g. so samples that loop until then play on to their end.
+	/// Returns whether the voice fades out on its own once released and should keep playing until it's finished,
+	/// rather than being cut off straight away.
+	fn release(&mut self) -> bool {
+		false
+	}
+
+	/// Sets how hard the note is being played as a multiple of its velocity, from the channel's
+	/// expression and pressure, for voices that change timbre with dynamics
+	fn set_dynamics(&mut self, _dynamics: f32) {}
+
+	/// How many voices' worth of work this is, e.g. one for each layer it plays at once
+	fn voice_count(&self) -> usize {
+		1
+	}
+
+	/// Whether the voice plays stereo sample pairs, already split between left and right,
+	/// which pan by turning down the far side instead of moving between the speakers
+	fn is_stereo(&self) -> bool {
+		false
+	}
+}
+
+pub(super) type Voice = Box<dyn VoiceSource>;
+
+/// Plays samples straight out of a SoundFont's wave data
+pub(super) struct SoundFontVoice {
+	pub(super) soundfont: Arc<SoundFont>,
+	pub(super) samples: Vec<VoiceSample>,
+}
+
+impl VoiceSource for SoundFontVoice {
+	fn tick(&mut self) {
+		self.samples.iter_mut().for_each(VoiceSample::tick);
+	}
+
+	fn is_finished(&self) -> bool {
+		self.samples.iter().all(VoiceSample::is_finished)
+	}
+
+	fn sample(&self, audio_channel: u16) -> i32 {
+		let wave_data = self.soundfont.get_wave_data();
+		self.samples
+			.iter()
+			.filter(|sample| !sample.is_finished())
+			.filter(|sample| {
+				sample.sample_type == SampleType::Mono || {
+					if audio_channel == 0 {
+						sample.sample_type == SampleType::Left
+					} else {
+						sample.sample_type == SampleType::Right
+					}
+				}
+			})
+			.map(|sample| {
+				sample_math::interpolate(
+					wave_data,
+					sample.current_sample,
+					sample_math::scale(sample.volume, sample.envelope.gain),
+				)
+			})
+			.sum::<i32>()
+	}
+
+	fn is_stereo(&self) -> bool {
+		self.samples
+			.iter()
+			.any(|sample| sample.sample_type != SampleType::Mono)
+	}
+
+	fn detune(&mut self, ratio: f32) {
+		for sample in &mut self.samples {
+			sample.speed = sample_math::detune(sample.speed, ratio);
+		}
+	}
+
+	fn release(&mut self) -> bool {
+		for sample in &mut self.samples {
+			if sample.loop_mode == LoopMode::LoopUntilNoteOff {
+				sample.looping = false;
+			}
+			sample.envelope.release();
+		}
+		true
+	}
+}
+
+/// Plays every velocity layer of a key at once, crossfading between overlapping layers
+/// as the channel's dynamics change, see [`super::LayerAlternation::VelocityCrossfade`]
+pub(super) struct CrossfadeVoice {
+	voice: SoundFontVoice,
+	/// Each distinct velocity range, from softest to loudest
+	layers: Vec<RangeInclusive<u8>>,
+	/// Which of `layers` each of the voice's samples belongs to
+	sample_layers: Vec<usize>,
+	velocity: u8,
+}
+
+impl CrossfadeVoice {
+	/// `sample_velocities` holds the velocity range of each of the voice's samples
+	pub(super) fn new(
+		voice: SoundFontVoice,
+		sample_velocities: Vec<RangeInclusive<u8>>,
+		velocity: u8,
+	) -> Self {
+		let layers = sample_velocities
+			.iter()
+			.cloned()
+			.sorted_by_key(|velocities| (*velocities.start(), *velocities.end()))
+			.dedup()
+			.collect::<Vec<_>>();
+		let sample_layers = sample_velocities
+			.iter()
+			.map(|velocities| layers.iter().position(|layer| layer == velocities).unwrap())
+			.collect();
+		let mut voice = Self {
+			voice,
+			layers,
+			sample_layers,
+			velocity,
+		};
+		voice.set_dynamics(1.0);
+		voice
+	}
+
+	/// How loud each layer plays at `level`, from 0 to 127. Where two layers overlap,
+	/// they crossfade at constant power across the overlap, and elsewhere only one layer plays.
+	fn layer_gains(&self, level: f32) -> Vec<f32> {
+		let contains = |layer: &RangeInclusive<u8>| {
+			*layer.start() as f32 <= level && level <= *layer.end() as f32
+		};
+		let mut gains = vec![0.0; self.layers.len()];
+		let softest = self.layers.iter().position(contains);
+		let loudest = self.layers.iter().rposition(contains);
+		match softest.zip(loudest) {
+			Some((softest, loudest)) if softest != loudest => {
+				let fade_start = *self.layers[loudest].start() as f32;
+				let fade_end = *self.layers[softest].end() as f32;
+				let fade = if fade_end > fade_start {
+					((level - fade_start) / (fade_end - fade_start)).clamp(0.0, 1.0)
+				} else {
+					0.5
+				};
+				let angle = fade * std::f32::consts::FRAC_PI_2;
+				gains[softest] = angle.cos();
+				gains[loudest] = angle.sin();
+			}
+			Some((layer, _)) => gains[layer] = 1.0,
+			// Between layers, the nearest one plays
+			None => {
+				let distance = |layer: &RangeInclusive<u8>| {
+					(*layer.start() as f32 - level)
+						.max(level - *layer.end() as f32)
+						.max(0.0)
+				};
+				if let Some(nearest) = self
+					.layers
+					.iter()
+					.position_min_by(|a, b| distance(a).total_cmp(&distance(b)))
+				{
+					gains[nearest] = 1.0;
+				}
+			}
+		}
+		gains
+	}
+}
+
+impl VoiceSource for CrossfadeVoice {
+	fn tick(&mut self) {
+		self.voice.tick();
+	}
+
+	fn is_finished(&self) -> bool {
+		self.voice.is_finished()
+	}
+
+	fn sample(&self, audio_channel: u16) -> i32 {
+		self.voice.sample(audio_channel)
+	}
+
+	fn detune(&mut self, ratio: f32) {
+		self.voice.detune(ratio);
+	}
+
+	fn release(&mut self) -> bool {
+		self.voice.release()
+	}
+
+	fn set_dynamics(&mut self, dynamics: f32) {
+		let level = (self.velocity as f32 * dynamics).clamp(0.0, 127.0);
+		let gains = self.layer_gains(level);
+		let volume = self.velocity as f32 / 127.0;
+		for (sample, &layer) in self.voice.samples.iter_mut().zip(&self.sample_layers) {
+			sample.volume = sample_math::gain(volume * gains[layer]);
+		}
+	}
+
+	fn voice_count(&self) -> usize {
+		self.layers.len()
+	}
+
+	fn is_stereo(&self) -> bool {
+		self.voice.is_stereo()
+	}
+}
+
+impl VoiceSource for Oscillator {
+	fn tick(&mut self) {
+		Oscillator::tick(self);
+	}
+
+	fn is_finished(&self) -> bool {
+		Oscillator::is_finished(self)
+	}
+
+	fn sample(&self, _audio_channel: u16) -> i32 {
+		Oscillator::sample(self)
+	}
+
+	fn detune(&mut self, ratio: f32) {
+		Oscillator::detune(self, ratio);
+	}
+}
+
+pub(super) struct VoiceSample {
+	pub(super) speed: Speed,
+	pub(super) current_sample: Position,
+	pub(super) end_sample: Position,
+	pub(super) start_loop: Position,
+	pub(super) end_loop: Position,
+	pub(super) loop_mode: LoopMode,
+	/// Whether reaching `end_loop` jumps back to `start_loop`, until the note's released if it only loops until then
+	pub(super) looping: bool,
+	pub(super) sample_type: SampleType,
+	pub(super) volume: Gain,
+	pub(super) envelope: VolumeEnvelope,
+}
+
+impl VoiceSample {
+	fn tick(&mut self) {
+		sample_math::advance(&mut self.current_sample, self.speed);
+		if self.looping && self.current_sample >= self.end_loop {
+			self.current_sample -= self.end_loop - self.start_loop;
+		}
+		self.envelope.tick();
+	}
+
+	fn is_finished(&self) -> bool {
+		self.current_sample >= self.end_sample || self.envelope.is_silent()
+	}
+}
+
+/// Below this an envelope is considered silent, about -60 dB
+const SILENCE: f32 = 0.001;
+
+/// Scales how loud a sample plays over the course of its note, as its SoundFont region's volume envelope says
+#[derive(Debug, Clone, Copy)]
+pub(super) struct VolumeEnvelope {
+	pub(super) phase: EnvelopePhase,
+	/// From 0.0 to 1.0
+	pub(super) gain: f32,
+	/// Added to the gain each frame of the attack
+	attack_step: f32,
+	/// Frames left to hold at full volume between the attack and the decay
+	hold_frames: u32,
+	/// What the gain's multiplied by each frame of the decay
+	decay_factor: f32,
+	/// The gain held once the decay's over, until the note's released
+	sustain_level: f32,
+	/// What the gain's multiplied by each frame of the release
+	release_factor: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum EnvelopePhase {
+	Attack,
+	Hold,
+	Decay,
+	Sustain,
+	Release,
+}
+
+impl VolumeEnvelope {
+	/// Times are in seconds. The decay and release are exponential like rustysynth's,
+	/// falling by about 80 dB over their time.
+	pub(super) fn new(
+		attack: f32,
+		hold: f32,
+		decay: f32,
+		sustain_level: f32,
+		release: f32,
+		samples_per_second: f32,
+	) -> Self {
+		let falloff = |seconds: f32| (-9.226 / (seconds * samples_per_second).max(1.0)).exp();
+		Self {
+			phase: EnvelopePhase::Attack,
+			gain: 0.0,
+			attack_step: 1.0 / (attack * samples_per_second).max(1.0),
+			hold_frames: (hold * samples_per_second) as u32,
+			decay_factor: falloff(decay),
+			sustain_level: sustain_level.clamp(0.0, 1.0),
+			release_factor: falloff(release),
+		}
+	}
+
+	fn tick(&mut self) {
+		match self.phase {
+			EnvelopePhase::Attack => {
+				self.gain += self.attack_step;
+				if self.gain >= 1.0 {
+					self.gain = 1.0;
+					self.phase = EnvelopePhase::Hold;
+				}
+			}
+			EnvelopePhase::Hold => {
+				if self.hold_frames == 0 {
+					self.phase = EnvelopePhase::Decay;
+				} else {
+					self.hold_frames -= 1;
+				}
+			}
+			EnvelopePhase::Decay => {
+				self.gain *= self.decay_factor;
+				if self.gain <= self.sustain_level {
+					self.gain = self.sustain_level;
+					self.phase = EnvelopePhase::Sustain;
+				}
+				if self.gain < SILENCE {
+					self.gain = 0.0;
+				}
+			}
+			EnvelopePhase::Sustain => {}
+			EnvelopePhase::Release => {
+				self.gain *= self.release_factor;
+				if self.gain < SILENCE {
+					self.gain = 0.0;
+				}
+			}
+		}
+	}
+
+	fn release(&mut self) {
+		self.phase = EnvelopePhase::Release;
+	}
+
+	/// Whether the envelope has faded out for good, after its attack
+	fn is_silent(&self) -> bool {
+		self.phase != EnvelopePhase::Attack && self.gain == 0.0
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(i32)]
+pub(super) enum SampleType {
+	Mono = 1,
+	Right = 2,
+	Left = 4,
+	// There's also a "linked" type but I'm unsure when this would be used, usually `link` is just the other stereo channel
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A sample looping between frames 40 and 60, playing on to frame 100 if it stops looping
+	fn looping_voice(loop_mode: LoopMode, envelope: VolumeEnvelope) -> SoundFontVoice {
+		let soundfont =
+			SoundFont::new(&mut &include_bytes!("../../assets/hl4mgm.sf2")[..]).unwrap();
+		SoundFontVoice {
+			soundfont: Arc::new(soundfont),
+			samples: vec![VoiceSample {
+				speed: sample_math::speed(1.0),
+				current_sample: sample_math::position(0.0),
+				end_sample: sample_math::position(100.0),
+				start_loop: sample_math::position(40.0),
+				end_loop: sample_math::position(60.0),
+				loop_mode,
+				looping: true,
+				sample_type: SampleType::Mono,
+				volume: sample_math::gain(1.0),
+				envelope,
+			}],
+		}
+	}
+
+	fn loop_until_note_off() -> SoundFontVoice {
+		looping_voice(
+			LoopMode::LoopUntilNoteOff,
+			VolumeEnvelope::new(0.0, 0.0, 0.0, 1.0, 10.0, 44100.0),
+		)
+	}
+
+	#[test]
+	fn held_loops_stay_between_their_loop_points() {
+		let mut voice = loop_until_note_off();
+		for _ in 0..1000 {
+			voice.tick();
+			let position = voice.samples[0].current_sample;
+			assert!(position < sample_math::position(60.0));
+		}
+		assert!(!voice.is_finished());
+	}
+
+	#[test]
+	fn released_loops_play_through_to_the_end() {
+		let mut voice = loop_until_note_off();
+		for _ in 0..1000 {
+			voice.tick();
+		}
+		assert!(voice.release());
+		let mut frames = 0;
+		let mut furthest = voice.samples[0].current_sample;
+		while !voice.is_finished() {
+			voice.tick();
+			furthest = furthest.max(voice.samples[0].current_sample);
+			frames += 1;
+		}
+		// At most the rest of the loop and the 40 frames after it, long before the release fades out
+		assert!(frames <= 60, "{frames}");
+		assert!(furthest >= sample_math::position(99.0));
+	}
+
+	/// How many ticks until `done` holds, giving up after a second
+	fn frames_until(envelope: &mut VolumeEnvelope, done: impl Fn(&VolumeEnvelope) -> bool) -> u32 {
+		let mut frames = 0;
+		while !done(envelope) && frames < 44100 {
+			envelope.tick();
+			frames += 1;
+		}
+		frames
+	}
+
+	#[test]
+	fn attacks_ramp_up_over_their_time() {
+		// A hundredth of a second is 441 frames
+		let attack = || VolumeEnvelope::new(0.01, 0.0, 1.0, 1.0, 1.0, 44100.0);
+		let frames = frames_until(&mut attack(), |envelope| {
+			envelope.phase != EnvelopePhase::Attack
+		});
+		assert!((441..=442).contains(&frames), "{frames}");
+		// Rising evenly rather than jumping straight to full volume
+		let mut envelope = attack();
+		let mut last = envelope.gain;
+		for _ in 0..440 {
+			envelope.tick();
+			assert!(
+				envelope.gain - last <= 1.0 / 440.0,
+				"{last} to {}",
+				envelope.gain
+			);
+			last = envelope.gain;
+		}
+		envelope.tick();
+		assert_eq!(envelope.gain, 1.0);
+	}
+
+	#[test]
+	fn decays_settle_at_the_sustain_level() {
+		let mut envelope = VolumeEnvelope::new(0.0, 0.0, 0.1, 0.5, 1.0, 44100.0);
+		let frames = frames_until(&mut envelope, |envelope| {
+			envelope.phase == EnvelopePhase::Sustain
+		});
+		// Falling by 80 dB over a tenth of a second reaches -6 dB within a few hundred frames
+		assert!(frames < 4410, "{frames}");
+		assert_eq!(envelope.gain, 0.5);
+		for _ in 0..44100 {
+			envelope.tick();
+		}
+		assert_eq!(envelope.gain, 0.5);
+	}
+
+	#[test]
+	fn released_voices_fade_to_silence_and_finish() {
+		let envelope = VolumeEnvelope::new(0.0, 0.0, 0.0, 1.0, 0.1, 44100.0);
+		let mut voice = looping_voice(LoopMode::Continuous, envelope);
+		for _ in 0..1000 {
+			voice.tick();
+		}
+		assert!(voice.release());
+		let mut frames = 0;
+		let mut last = voice.samples[0].envelope.gain;
+		while !voice.is_finished() && frames < 44100 {
+			voice.tick();
+			let gain = voice.samples[0].envelope.gain;
+			assert!(gain <= last);
+			last = gain;
+			frames += 1;
+		}
+		// -60 dB is three quarters of the way down the release's 80 dB
+		assert!((3000..4410).contains(&frames), "{frames}");
+		assert_eq!(voice.samples[0].envelope.gain, 0.0);
+	}
+
+	#[test]
+	fn releases_mid_attack_fade_from_where_the_attack_got_to() {
+		let mut envelope = VolumeEnvelope::new(0.1, 0.0, 1.0, 1.0, 0.1, 44100.0);
+		for _ in 0..2205 {
+			envelope.tick();
+		}
+		assert!((envelope.gain - 0.5).abs() < 0.01, "{}", envelope.gain);
+		envelope.release();
+		envelope.tick();
+		assert!(
+			envelope.gain < 0.5 && envelope.gain > 0.49,
+			"{}",
+			envelope.gain
+		);
+		frames_until(&mut envelope, VolumeEnvelope::is_silent);
+		assert!(envelope.is_silent());
+	}
+}
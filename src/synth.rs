@@ -0,0 +1,143 @@
+use std::f32::consts::TAU;
+
+const ATTACK_SECONDS: f32 = 0.005;
+/// Time constant of the exponential decay after the attack
+const DECAY_SECONDS: f32 = 2.0;
+/// Below this the voice is considered finished
+const SILENCE: f32 = 0.001;
+/// Leaves headroom for a few voices before clipping
+const PEAK: f32 = i16::MAX as f32 * 0.25;
+
+/// Shape of the built-in fallback synth, used when there's no SoundFont preset to play
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Waveform {
+	#[default]
+	Sine,
+	Square,
+	Triangle,
+	Saw,
+}
+
+impl Waveform {
+	/// `phase` is in cycles, in 0..1
+	fn value(&self, phase: f32) -> f32 {
+		match self {
+			Waveform::Sine => (phase * TAU).sin(),
+			Waveform::Square => {
+				if phase < 0.5 {
+					1.0
+				} else {
+					-1.0
+				}
+			}
+			Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+			Waveform::Saw => 2.0 * phase - 1.0,
+		}
+	}
+}
+
+pub(crate) struct Oscillator {
+	waveform: Waveform,
+	phase: f32,
+	phase_step: f32,
+	volume: f32,
+	envelope: f32,
+	frames: u32,
+	samples_per_second: f32,
+}
+
+impl Oscillator {
+	pub(crate) fn new(
+		waveform: Waveform,
+		frequency: f32,
+		volume: f32,
+		samples_per_second: f32,
+	) -> Self {
+		Self {
+			waveform,
+			phase: 0.0,
+			phase_step: frequency / samples_per_second,
+			volume,
+			envelope: 0.0,
+			frames: 0,
+			samples_per_second,
+		}
+	}
+
+	pub(crate) fn detune(&mut self, ratio: f32) {
+		self.phase_step *= ratio;
+	}
+
+	pub(crate) fn tick(&mut self) {
+		self.phase = (self.phase + self.phase_step).fract();
+		self.frames += 1;
+		let seconds = self.frames as f32 / self.samples_per_second;
+		self.envelope = if seconds < ATTACK_SECONDS {
+			seconds / ATTACK_SECONDS
+		} else {
+			(-(seconds - ATTACK_SECONDS) / DECAY_SECONDS).exp()
+		};
+	}
+
+	pub(crate) fn is_finished(&self) -> bool {
+		self.frames as f32 / self.samples_per_second > ATTACK_SECONDS && self.envelope < SILENCE
+	}
+
+	pub(crate) fn sample(&self) -> i32 {
+		(self.waveform.value(self.phase) * self.envelope * self.volume * PEAK) as i32
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn waveforms_peak_at_one() {
+		for waveform in [
+			Waveform::Sine,
+			Waveform::Square,
+			Waveform::Triangle,
+			Waveform::Saw,
+		] {
+			let peak = (0..100)
+				.map(|step| waveform.value(step as f32 / 100.0).abs())
+				.fold(0.0, f32::max);
+			assert!((peak - 1.0).abs() < 0.01, "{waveform:?} {peak}");
+		}
+		assert_eq!(Waveform::Square.value(0.25), 1.0);
+		assert_eq!(Waveform::Triangle.value(0.5), 1.0);
+		assert_eq!(Waveform::Saw.value(0.0), -1.0);
+	}
+
+	#[test]
+	fn oscillates_at_its_frequency() {
+		let mut oscillator = Oscillator::new(Waveform::Square, 441.0, 1.0, 44100.0);
+		let mut crossings = 0;
+		let mut last = 0;
+		for _ in 0..44100 {
+			oscillator.tick();
+			let sample = oscillator.sample();
+			if last < 0 && sample >= 0 {
+				crossings += 1;
+			}
+			last = sample;
+		}
+		assert!((crossings - 441i32).abs() <= 1, "{crossings}");
+	}
+
+	#[test]
+	fn fades_out_and_finishes() {
+		let mut oscillator = Oscillator::new(Waveform::Sine, 440.0, 1.0, 1000.0);
+		assert!(!oscillator.is_finished());
+		for _ in 0..1000 {
+			oscillator.tick();
+		}
+		assert!(!oscillator.is_finished());
+		// Below a thousandth after about seven time constants
+		for _ in 0..14000 {
+			oscillator.tick();
+		}
+		assert!(oscillator.is_finished());
+	}
+}
@@ -0,0 +1,261 @@
+use crate::Note;
+
+/// The semitones between a root and the notes built on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Scale {
+	Major,
+	NaturalMinor,
+	HarmonicMinor,
+	/// As it's played going up
+	MelodicMinor,
+	Dorian,
+	Phrygian,
+	Lydian,
+	Mixolydian,
+	Locrian,
+	MajorPentatonic,
+	MinorPentatonic,
+	Chromatic,
+}
+
+impl Scale {
+	/// Semitones above the root of each note in one octave of the scale, starting with the root
+	pub fn semitones(self) -> &'static [i8] {
+		match self {
+			Self::Major => &[0, 2, 4, 5, 7, 9, 11],
+			Self::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+			Self::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+			Self::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+			Self::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+			Self::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+			Self::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+			Self::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+			Self::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+			Self::MajorPentatonic => &[0, 2, 4, 7, 9],
+			Self::MinorPentatonic => &[0, 3, 5, 7, 10],
+			Self::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+		}
+	}
+
+	/// One octave of the scale from `root`, spelled with sharps.
+	/// Stops at the last note below G9 rather than running out of midi notes.
+	pub fn notes(self, root: Note) -> Vec<Note> {
+		self.semitones()
+			.iter()
+			.map_while(|&semitones| root.transpose(semitones))
+			.collect()
+	}
+
+	/// The note `n` steps up the scale from `root`, so 0 is `root` itself.
+	/// Carries on into the octaves above, e.g. 7 is the octave of a seven note scale.
+	/// `None` if that's above G9.
+	pub fn degree(self, root: Note, n: usize) -> Option<Note> {
+		let semitones = self.semitones();
+		let octaves = i8::try_from(n / semitones.len()).ok()?;
+		root.transpose(
+			octaves
+				.checked_mul(12)?
+				.checked_add(semitones[n % semitones.len()])?,
+		)
+	}
+}
+
+/// The semitones between a root and the other notes played with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Chord {
+	Major,
+	Minor,
+	Diminished,
+	Augmented,
+	DominantSeventh,
+	MajorSeventh,
+	MinorSeventh,
+}
+
+impl Chord {
+	/// Semitones above the root of each note, starting with the root
+	pub fn semitones(self) -> &'static [i8] {
+		match self {
+			Self::Major => &[0, 4, 7],
+			Self::Minor => &[0, 3, 7],
+			Self::Diminished => &[0, 3, 6],
+			Self::Augmented => &[0, 4, 8],
+			Self::DominantSeventh => &[0, 4, 7, 10],
+			Self::MajorSeventh => &[0, 4, 7, 11],
+			Self::MinorSeventh => &[0, 3, 7, 10],
+		}
+	}
+
+	/// The chord in root position from `root`, spelled with sharps.
+	/// Stops at the last note below G9 rather than running out of midi notes.
+	pub fn notes(self, root: Note) -> Vec<Note> {
+		self.semitones()
+			.iter()
+			.map_while(|&semitones| root.transpose(semitones))
+			.collect()
+	}
+}
+
+/// A tonic and whether its scale is major or natural minor,
+/// for moving notes along the scale with [`Note::transpose_diatonic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+	/// Only its letter and sharp or flat matter, not its octave.
+	/// Keys still compare it as a note though, so keep to octave 4 like [`Key::from_sharps_flats`].
+	pub tonic: Note,
+	pub minor: bool,
+}
+
+impl Key {
+	pub fn major(tonic: Note) -> Self {
+		Self {
+			tonic,
+			minor: false,
+		}
+	}
+
+	pub fn minor(tonic: Note) -> Self {
+		Self { tonic, minor: true }
+	}
+
+	/// The key with `sharps_flats` sharps, or flats if negative, as MIDI key signatures give it.
+	/// `None` outside of -7 to 7.
+	pub fn from_sharps_flats(sharps_flats: i8, minor: bool) -> Option<Self> {
+		const MAJOR_TONICS: [&str; 15] = [
+			"Cb4", "Gb4", "Db4", "Ab4", "Eb4", "Bb4", "F4", "C4", "G4", "D4", "A4", "E4", "B4",
+			"F#4", "C#4",
+		];
+		const MINOR_TONICS: [&str; 15] = [
+			"Ab4", "Eb4", "Bb4", "F4", "C4", "G4", "D4", "A4", "E4", "B4", "F#4", "C#4", "G#4",
+			"D#4", "A#4",
+		];
+		let tonics = if minor { MINOR_TONICS } else { MAJOR_TONICS };
+		let tonic = tonics.get(usize::try_from(sharps_flats as i16 + 7).ok()?)?;
+		Some(Self {
+			tonic: Note::parse(tonic).ok()?,
+			minor,
+		})
+	}
+
+	pub fn scale(&self) -> Scale {
+		if self.minor {
+			Scale::NaturalMinor
+		} else {
+			Scale::Major
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn c_major_from_c4() {
+		assert_eq!(
+			Scale::Major.notes(Note::C4),
+			vec![
+				Note::C4,
+				Note::D4,
+				Note::E4,
+				Note::F4,
+				Note::G4,
+				Note::A4,
+				Note::B4
+			]
+		);
+	}
+
+	#[test]
+	fn scales_stop_at_g9() {
+		assert_eq!(Scale::Major.notes(Note::E9), vec![Note::E9, Note::FS9]);
+		assert_eq!(Scale::Chromatic.notes(Note::G9), vec![Note::G9]);
+	}
+
+	#[test]
+	fn degrees_carry_on_into_the_octaves_above() {
+		assert_eq!(Scale::Major.degree(Note::C4, 0), Some(Note::C4));
+		assert_eq!(Scale::Major.degree(Note::C4, 4), Some(Note::G4));
+		assert_eq!(Scale::Major.degree(Note::C4, 7), Some(Note::C5));
+		assert_eq!(Scale::MinorPentatonic.degree(Note::A3, 6), Some(Note::C5));
+		assert_eq!(Scale::Major.degree(Note::C9, 7), None);
+		assert_eq!(Scale::Major.degree(Note::C4, usize::MAX), None);
+	}
+
+	#[test]
+	fn chords_from_their_root() {
+		assert_eq!(
+			Chord::Minor.notes(Note::A3),
+			vec![Note::A3, Note::C4, Note::E4]
+		);
+		assert_eq!(
+			Chord::DominantSeventh.notes(Note::G3),
+			vec![Note::G3, Note::B3, Note::D4, Note::F4]
+		);
+		assert_eq!(
+			Chord::Diminished.notes(Note::B3),
+			vec![Note::B3, Note::D4, Note::F4]
+		);
+		assert_eq!(Chord::Major.notes(Note::E9), vec![Note::E9]);
+	}
+
+	#[test]
+	fn keys_from_key_signatures() {
+		assert_eq!(Key::from_sharps_flats(0, false), Some(Key::major(Note::C4)));
+		assert_eq!(Key::from_sharps_flats(2, false), Some(Key::major(Note::D4)));
+		assert_eq!(Key::from_sharps_flats(0, true), Some(Key::minor(Note::A4)));
+		assert_eq!(
+			Key::from_sharps_flats(-1, false),
+			Some(Key::major(Note::F4))
+		);
+		assert_eq!(Key::from_sharps_flats(8, false), None);
+		assert_ne!(Key::major(Note::C4), Key::minor(Note::C4));
+	}
+
+	#[test]
+	fn transpose_diatonic_spells_for_the_key() {
+		let d_major = Key::major(Note::D4);
+		assert_eq!(
+			Note::D4
+				.transpose_diatonic(&d_major, 2)
+				.unwrap()
+				.to_string(),
+			"F#4"
+		);
+		let f_major = Key::major(Note::F4);
+		assert_eq!(
+			Note::F4
+				.transpose_diatonic(&f_major, 3)
+				.unwrap()
+				.to_string(),
+			"Bb4"
+		);
+		let c_major = Key::major(Note::C4);
+		assert_eq!(Note::B4.transpose_diatonic(&c_major, 1), Some(Note::C5));
+		assert_eq!(Note::C4.transpose_diatonic(&c_major, -1), Some(Note::B3));
+		assert_eq!(Note::C4.transpose_diatonic(&c_major, 7), Some(Note::C5));
+		assert_eq!(Note::G9.transpose_diatonic(&c_major, 1), None);
+	}
+
+	#[test]
+	fn transpose_diatonic_snaps_notes_outside_the_key_down() {
+		let c_major = Key::major(Note::C4);
+		assert_eq!(Note::CS4.transpose_diatonic(&c_major, 0), Some(Note::C4));
+		assert_eq!(Note::CS4.transpose_diatonic(&c_major, 1), Some(Note::D4));
+		assert_eq!(Note::FS4.transpose_diatonic(&c_major, -1), Some(Note::E4));
+	}
+
+	#[test]
+	fn note_scales_match_scale() {
+		let scale = Note::D4.dorian_scale();
+		assert_eq!(
+			scale.map(Option::unwrap).to_vec(),
+			Scale::Dorian.notes(Note::D4)
+		);
+		let scale = Note::F9.major_scale();
+		assert_eq!(scale[..2], [Some(Note::F9), Some(Note::G9)]);
+		assert_eq!(scale[2], None);
+	}
+}
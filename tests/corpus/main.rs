@@ -0,0 +1,208 @@
+//! Renders each fixture in `fixtures/` with soundyrust and with rustysynth's own
+//! [`Synthesizer`], and fails if how far apart they are has grown past `baseline.json`.
+//!
+//! Run with `cargo test --features corpus --test corpus -- --nocapture` to see the report.
+//! After a change that's meant to move the numbers, rerun with `SOUNDYRUST_UPDATE_CORPUS=1`
+//! to write the new baseline, and commit it with the change.
+
+mod metrics;
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bevy::audio::Decodable;
+use rustysynth::{MidiFile, MidiFileSequencer, SoundFont, Synthesizer, SynthesizerSettings};
+use serde_json::{Map, Value, json};
+use soundyrust::{MidiAudio, MidiAudioTrack};
+
+use metrics::{Metrics, SAMPLE_RATE};
+
+/// Rendered past the end of the file, so releases are compared too
+const TAIL_SECONDS: f64 = 1.0;
+
+/// How much worse than the baseline each metric can get before the test fails
+const SPECTRAL_TOLERANCE: f32 = 0.02;
+const ONSET_TOLERANCE_MS: f32 = 2.0;
+const RMS_TOLERANCE_DB: f32 = 0.5;
+
+struct Fixture {
+	name: &'static str,
+	/// Rendered by rustysynth in place of the fixture, for files it can't read.
+	/// Has to play the same notes at the same times.
+	reference: Option<&'static str>,
+}
+
+const FIXTURES: &[Fixture] = &[
+	// Single piano notes, for onsets and decays
+	Fixture {
+		name: "scale",
+		reference: None,
+	},
+	// Overlapping string notes, for polyphony and sustain
+	Fixture {
+		name: "chords",
+		reference: None,
+	},
+	// The percussion bank on channel 10
+	Fixture {
+		name: "drums",
+		reference: None,
+	},
+	// A tempo change halfway through
+	Fixture {
+		name: "tempo_change",
+		reference: None,
+	},
+	// One key from very soft to full velocity
+	Fixture {
+		name: "velocities",
+		reference: None,
+	},
+	// Bass and lead on two channels at once
+	Fixture {
+		name: "ensemble",
+		reference: None,
+	},
+	// Timed in SMPTE frames at 25 fps, with a tempo change that mustn't move any notes.
+	// rustysynth only reads ticks per beat, so it plays the same notes timed that way.
+	Fixture {
+		name: "smpte",
+		reference: Some("smpte_reference"),
+	},
+];
+
+fn corpus_dir() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus")
+}
+
+/// Both renders of a fixture, mixed down to mono
+fn render(fixture: &Fixture, soundfont_bytes: &[u8]) -> (Vec<f32>, Vec<f32>) {
+	let read_fixture =
+		|name: &str| fs::read(corpus_dir().join(format!("fixtures/{name}.mid"))).unwrap();
+	let midi_bytes = read_fixture(fixture.name);
+	let reference_bytes = read_fixture(fixture.reference.unwrap_or(fixture.name));
+
+	let soundfont = Arc::new(SoundFont::new(&mut Cursor::new(soundfont_bytes)).unwrap());
+	let midi_file = Arc::new(MidiFile::new(&mut Cursor::new(&reference_bytes)).unwrap());
+	let frames = ((midi_file.get_length() + TAIL_SECONDS) * SAMPLE_RATE as f64) as usize;
+	let synthesizer =
+		Synthesizer::new(&soundfont, &SynthesizerSettings::new(SAMPLE_RATE as i32)).unwrap();
+	let mut sequencer = MidiFileSequencer::new(synthesizer);
+	sequencer.play(&midi_file, false);
+	let mut left = vec![0.0; frames];
+	let mut right = vec![0.0; frames];
+	sequencer.render(&mut left, &mut right);
+	let reference = left
+		.into_iter()
+		.zip(right)
+		.map(|(left, right)| (left + right) / 2.0)
+		.collect();
+
+	let mut audio = MidiAudio::new(soundfont).with_logical_clock();
+	assert_eq!(audio.settings().sample_rate, SAMPLE_RATE);
+	audio.add_track(MidiAudioTrack::from_bytes(&midi_bytes, 1.0).with_looping(false));
+	let mut decoder = audio.decoder();
+	let mut rendered = Vec::with_capacity(frames);
+	while rendered.len() < frames {
+		let chunk = (frames - rendered.len()).min(4096);
+		audio.advance_logical(chunk as u32);
+		rendered.extend((0..chunk).map(|_| {
+			let [left, right] = decoder.next_frame();
+			(left + right) / 2.0
+		}));
+	}
+
+	(rendered, reference)
+}
+
+fn metrics_json(metrics: &Metrics) -> Value {
+	let round = |value: f32| (value as f64 * 10000.0).round() / 10000.0;
+	json!({
+		"spectral_distance": round(metrics.spectral_distance),
+		"onset_error_ms": round(metrics.onset_error_ms),
+		"rms_difference_db": round(metrics.rms_difference_db),
+	})
+}
+
+/// Every way `metrics` is worse than `baseline` by more than the tolerance
+fn regressions(metrics: &Metrics, baseline: &Value) -> Vec<String> {
+	[
+		(
+			"spectral_distance",
+			metrics.spectral_distance,
+			SPECTRAL_TOLERANCE,
+		),
+		("onset_error_ms", metrics.onset_error_ms, ONSET_TOLERANCE_MS),
+		(
+			"rms_difference_db",
+			metrics.rms_difference_db,
+			RMS_TOLERANCE_DB,
+		),
+	]
+	.into_iter()
+	.filter_map(|(name, value, tolerance)| {
+		let Some(baseline) = baseline[name].as_f64() else {
+			return Some(format!("{name} has no baseline"));
+		};
+		(value as f64 > baseline + tolerance as f64)
+			.then(|| format!("{name} {value:.4} is worse than the baseline {baseline:.4}"))
+	})
+	.collect()
+}
+
+#[test]
+fn corpus_matches_baseline() {
+	let soundfont_bytes =
+		fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/hl4mgm.sf2")).unwrap();
+	let baseline_path = corpus_dir().join("baseline.json");
+	let baseline: Value = fs::read_to_string(&baseline_path)
+		.map(|json| serde_json::from_str(&json).unwrap())
+		.unwrap_or(Value::Null);
+	let update = std::env::var_os("SOUNDYRUST_UPDATE_CORPUS").is_some();
+
+	println!(
+		"{:<14} {:>9} {:>10} {:>9} {:>8}",
+		"fixture", "spectral", "onset ms", "rms dB", "onsets"
+	);
+	let mut report = Map::new();
+	let mut failures = vec![];
+	for fixture in FIXTURES {
+		let (rendered, reference) = render(fixture, &soundfont_bytes);
+		let metrics = Metrics::compare(&rendered, &reference);
+		println!(
+			"{:<14} {:>9.4} {:>10.2} {:>9.2} {:>4}/{:<3}",
+			fixture.name,
+			metrics.spectral_distance,
+			metrics.onset_error_ms,
+			metrics.rms_difference_db,
+			metrics.onsets,
+			metrics.reference_onsets
+		);
+		report.insert(fixture.name.to_string(), metrics_json(&metrics));
+		if !update {
+			failures.extend(
+				regressions(&metrics, &baseline[fixture.name])
+					.into_iter()
+					.map(|regression| format!("{}: {regression}", fixture.name)),
+			);
+		}
+	}
+
+	let report = serde_json::to_string_pretty(&Value::Object(report)).unwrap() + "\n";
+	fs::write(
+		Path::new(env!("CARGO_TARGET_TMPDIR")).join("corpus-report.json"),
+		&report,
+	)
+	.unwrap();
+	if update {
+		fs::write(&baseline_path, &report).unwrap();
+		println!("Wrote {}", baseline_path.display());
+	}
+	assert!(
+		failures.is_empty(),
+		"Renders moved further from rustysynth:\n{}",
+		failures.join("\n")
+	);
+}
@@ -0,0 +1,223 @@
+//! How far a render is from a reference render of the same fixture.
+//! Renders are mono, at [`SAMPLE_RATE`], from -1.0 to 1.0.
+//! The two synths' master levels differ, so both are normalized to the same peak first.
+
+use std::f32::consts::PI;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Windows quieter than this are treated as silence
+const FLOOR_DB: f32 = -60.0;
+
+const ENVELOPE_WINDOW: usize = 1024;
+
+const SPECTRUM_WINDOW: usize = 2048;
+const SPECTRUM_HOP: usize = 1024;
+
+/// Long enough to hold a cycle of a low bass note, so its waveform doesn't look like onsets
+const ONSET_WINDOW: usize = 1024;
+const ONSET_HOP: usize = 256;
+/// How much louder than the quietest of the last few windows a window has to be to start a note
+const ONSET_RISE_DB: f32 = 3.0;
+const ONSET_LOOKBACK: usize = 2;
+const ONSET_FLOOR_DB: f32 = -45.0;
+/// Onsets closer together than this are counted once
+const ONSET_REFRACTORY_SECONDS: f32 = 0.08;
+/// A reference onset with nothing this close in the render counts as this far off
+const ONSET_MATCH_SECONDS: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+	/// Half the L1 distance between normalized magnitude spectra, averaged over frames, from 0.0 to 1.0
+	pub spectral_distance: f32,
+	/// Mean distance from each reference onset to the nearest onset in the render
+	pub onset_error_ms: f32,
+	/// RMS of the difference between the loudness envelopes, in decibels
+	pub rms_difference_db: f32,
+	pub onsets: usize,
+	pub reference_onsets: usize,
+}
+
+impl Metrics {
+	pub fn compare(render: &[f32], reference: &[f32]) -> Self {
+		let render = &normalized(render);
+		let reference = &normalized(reference);
+		let render_onsets = onsets(render);
+		let reference_onsets = onsets(reference);
+		Self {
+			spectral_distance: spectral_distance(render, reference),
+			onset_error_ms: onset_error(&render_onsets, &reference_onsets) * 1000.0,
+			rms_difference_db: rms_difference(render, reference),
+			onsets: render_onsets.len(),
+			reference_onsets: reference_onsets.len(),
+		}
+	}
+}
+
+fn normalized(samples: &[f32]) -> Vec<f32> {
+	let peak = samples
+		.iter()
+		.fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+	if peak == 0.0 {
+		return samples.to_vec();
+	}
+	samples.iter().map(|sample| sample / peak).collect()
+}
+
+fn decibels(rms: f32) -> f32 {
+	(20.0 * rms.log10()).max(FLOOR_DB)
+}
+
+/// The loudness of each window, `hop` samples apart, in decibels
+fn envelope(samples: &[f32], window: usize, hop: usize) -> Vec<f32> {
+	(0..samples.len().saturating_sub(window) + 1)
+		.step_by(hop)
+		.map(|start| {
+			let window = &samples[start..(start + window).min(samples.len())];
+			decibels(
+				(window.iter().map(|sample| sample * sample).sum::<f32>() / window.len() as f32)
+					.sqrt(),
+			)
+		})
+		.collect()
+}
+
+fn rms_difference(render: &[f32], reference: &[f32]) -> f32 {
+	let differences = envelope(render, ENVELOPE_WINDOW, ENVELOPE_WINDOW)
+		.into_iter()
+		.zip(envelope(reference, ENVELOPE_WINDOW, ENVELOPE_WINDOW))
+		.filter(|&(render, reference)| render > FLOOR_DB || reference > FLOOR_DB)
+		.map(|(render, reference)| (render - reference).powi(2))
+		.collect::<Vec<_>>();
+	if differences.is_empty() {
+		return 0.0;
+	}
+	(differences.iter().sum::<f32>() / differences.len() as f32).sqrt()
+}
+
+fn spectral_distance(render: &[f32], reference: &[f32]) -> f32 {
+	let hann = (0..SPECTRUM_WINDOW)
+		.map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / SPECTRUM_WINDOW as f32).cos())
+		.collect::<Vec<_>>();
+	let length = render.len().min(reference.len());
+	let distances = (0..length.saturating_sub(SPECTRUM_WINDOW))
+		.step_by(SPECTRUM_HOP)
+		.filter_map(|start| {
+			let render = spectrum(&render[start..start + SPECTRUM_WINDOW], &hann)?;
+			let reference = spectrum(&reference[start..start + SPECTRUM_WINDOW], &hann)?;
+			Some(
+				render
+					.iter()
+					.zip(&reference)
+					.map(|(a, b)| (a - b).abs())
+					.sum::<f32>() / 2.0,
+			)
+		})
+		.collect::<Vec<_>>();
+	if distances.is_empty() {
+		return 0.0;
+	}
+	distances.iter().sum::<f32>() / distances.len() as f32
+}
+
+/// The magnitude spectrum of a frame, normalized to sum to 1. `None` if the frame is silent.
+fn spectrum(frame: &[f32], window: &[f32]) -> Option<Vec<f32>> {
+	let rms = (frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32).sqrt();
+	if decibels(rms) <= FLOOR_DB {
+		return None;
+	}
+	let mut real = frame
+		.iter()
+		.zip(window)
+		.map(|(sample, gain)| sample * gain)
+		.collect::<Vec<_>>();
+	let mut imaginary = vec![0.0; frame.len()];
+	fft(&mut real, &mut imaginary);
+	let magnitudes = real[..frame.len() / 2]
+		.iter()
+		.zip(&imaginary)
+		.map(|(re, im)| (re * re + im * im).sqrt())
+		.collect::<Vec<_>>();
+	let total = magnitudes.iter().sum::<f32>();
+	Some(
+		magnitudes
+			.into_iter()
+			.map(|magnitude| magnitude / total)
+			.collect(),
+	)
+}
+
+/// In-place radix-2 FFT, for power of two lengths
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+	let n = real.len();
+	let mut j = 0;
+	for i in 1..n {
+		let mut bit = n >> 1;
+		while j & bit != 0 {
+			j ^= bit;
+			bit >>= 1;
+		}
+		j |= bit;
+		if i < j {
+			real.swap(i, j);
+			imaginary.swap(i, j);
+		}
+	}
+	let mut length = 2;
+	while length <= n {
+		let angle = -2.0 * PI / length as f32;
+		for start in (0..n).step_by(length) {
+			for k in 0..length / 2 {
+				let (sin, cos) = (angle * k as f32).sin_cos();
+				let (a, b) = (start + k, start + k + length / 2);
+				let re = real[b] * cos - imaginary[b] * sin;
+				let im = real[b] * sin + imaginary[b] * cos;
+				real[b] = real[a] - re;
+				imaginary[b] = imaginary[a] - im;
+				real[a] += re;
+				imaginary[a] += im;
+			}
+		}
+		length <<= 1;
+	}
+}
+
+/// When each note starts, in seconds, from jumps in loudness
+fn onsets(samples: &[f32]) -> Vec<f32> {
+	let hop_seconds = ONSET_HOP as f32 / SAMPLE_RATE as f32;
+	let envelope = envelope(samples, ONSET_WINDOW, ONSET_HOP);
+	let mut onsets: Vec<f32> = vec![];
+	for (index, &level) in envelope.iter().enumerate() {
+		let time = index as f32 * hop_seconds;
+		let previous = envelope[index.saturating_sub(ONSET_LOOKBACK)..index]
+			.iter()
+			.copied()
+			.reduce(f32::min)
+			.unwrap_or(FLOOR_DB);
+		if level > ONSET_FLOOR_DB
+			&& level - previous >= ONSET_RISE_DB
+			&& onsets
+				.last()
+				.is_none_or(|&last| time - last >= ONSET_REFRACTORY_SECONDS)
+		{
+			onsets.push(time);
+		}
+	}
+	onsets
+}
+
+fn onset_error(render: &[f32], reference: &[f32]) -> f32 {
+	if reference.is_empty() {
+		return 0.0;
+	}
+	reference
+		.iter()
+		.map(|onset| {
+			render
+				.iter()
+				.map(|other| (other - onset).abs())
+				.fold(ONSET_MATCH_SECONDS, f32::min)
+		})
+		.sum::<f32>()
+		/ reference.len() as f32
+}
@@ -0,0 +1,55 @@
+//! Pins the render of a mix of looping and queued tracks, so refactors that shouldn't change
+//! what's heard can show they don't. A change that's meant to be heard updates the expected hash.
+//!
+//! The hash matched from before `source.rs` was split into modules to after it.
+
+use bevy::audio::Decodable;
+use soundyrust::prelude::*;
+
+const EXPECTED_HASH: u64 = 17_253_937_710_264_362_878;
+const EXPECTED_EVENTS: usize = 92;
+
+/// FNV-1a, which unlike std's hashers is stable across Rust releases
+fn fnv1a(samples: &[i16]) -> u64 {
+	samples
+		.iter()
+		.flat_map(|sample| sample.to_le_bytes())
+		.fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+			(hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+		})
+}
+
+fn stop_on(timing: MidiQueueTiming) -> MidiQueueEvent {
+	MidiQueueEvent {
+		event: MidiQueueEventType::Stop,
+		timing,
+		looping: MidiQueueLooping::Once,
+	}
+}
+
+#[test]
+fn render_matches_the_pinned_hash() {
+	let mut audio =
+		MidiAudio::from_bytes(include_bytes!("../assets/hl4mgm.sf2")).with_logical_clock();
+	let fray = include_bytes!("../assets/fray.mid");
+	for time_signature in [4.0 / 4.0, 3.0 / 4.0] {
+		let handle = audio.add_track(MidiAudioTrack::from_bytes(fray, time_signature));
+		audio.queue(handle, stop_on(MidiQueueTiming::Bar));
+		let handle = audio.add_track(MidiAudioTrack::from_bytes(fray, time_signature));
+		audio.queue(
+			handle,
+			MidiQueueEvent {
+				event: MidiQueueEventType::Queue(Box::new(stop_on(MidiQueueTiming::Beat))),
+				timing: MidiQueueTiming::Bar,
+				looping: MidiQueueLooping::Once,
+			},
+		);
+	}
+
+	let events = (0..200)
+		.map(|_| audio.advance_logical(4410).events.len())
+		.sum::<usize>();
+	let samples = audio.decoder().take(44100 * 40).collect::<Vec<i16>>();
+	assert_eq!(events, EXPECTED_EVENTS);
+	assert_eq!(fnv1a(&samples), EXPECTED_HASH);
+}